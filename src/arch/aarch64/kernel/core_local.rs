@@ -92,4 +92,7 @@ pub fn increment_irq_counter(irq_no: u8) {
 			warn!("Unknown core {}, is core {} already registered?", id, id);
 		}
 	}
+
+	// the timing of interrupts contributes to the entropy pool
+	crate::entropy::add_interrupt_timing(irq_no);
 }
@@ -92,6 +92,15 @@ pub fn irq_install_handler(irq_number: u8, handler: fn(state: &State)) {
 	}
 }
 
+/// Enables the shared peripheral interrupt `spi` of a device in the
+/// generic interrupt controller.
+pub fn enable_spi(spi: u32) {
+	let irqid = IntId::spi(spi);
+	let gic = unsafe { GIC.get_mut().unwrap() };
+	gic.set_interrupt_priority(irqid, 0x00);
+	gic.enable_interrupt(irqid, true);
+}
+
 #[no_mangle]
 pub extern "C" fn do_fiq(state: &State) {
 	if let Some(irqid) = GicV3::get_and_acknowledge_interrupt() {
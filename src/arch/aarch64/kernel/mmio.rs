@@ -1,7 +1,163 @@
-use hermit_sync::InterruptTicketMutex;
+use alloc::vec::Vec;
+use core::str;
 
+use align_address::Align;
+use hermit_dtb::Dtb;
+use hermit_sync::{without_interrupts, InterruptTicketMutex};
+
+use crate::arch::aarch64::kernel::boot_info;
+use crate::arch::aarch64::kernel::interrupts::enable_spi;
+use crate::arch::aarch64::mm::paging::{self, BasePageSize, PageSize, PageTableEntryFlags};
+use crate::arch::aarch64::mm::{virtualmem, PhysAddr};
+use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::net::NetworkInterface;
+use crate::drivers::virtio::transport::mmio as mmio_virtio;
+use crate::drivers::virtio::transport::mmio::{DevId, MmioRegisterLayout, VirtioDriver};
+
+pub const MAGIC_VALUE: u32 = 0x74726976;
+
+/// Interrupt type of shared peripheral interrupts in the device tree
+const GIC_SPI: u32 = 0;
+
+static mut MMIO_DRIVERS: Vec<MmioDriver> = Vec::new();
+
+pub(crate) enum MmioDriver {
+	VirtioNet(InterruptTicketMutex<VirtioNetDriver>),
+}
+
+impl MmioDriver {
+	#[allow(unreachable_patterns)]
+	fn get_network_driver(&self) -> Option<&InterruptTicketMutex<dyn NetworkInterface>> {
+		match self {
+			Self::VirtioNet(drv) => Some(drv),
+			_ => None,
+		}
+	}
+}
+
+/// Tries to find the network device within the `virtio,mmio` nodes of the device tree.
+/// Returns the registers of the device and its shared peripheral interrupt within the
+/// Ok() if successful or an Err() on failure.
+pub fn detect_network() -> Result<(&'static mut MmioRegisterLayout, u32), &'static str> {
+	let dtb = unsafe {
+		Dtb::from_raw(boot_info().hardware_info.device_tree.unwrap().get() as *const u8)
+			.expect(".dtb file has invalid header")
+	};
+
+	// The nodes share their name and differ only in their unit address,
+	// such that the complete node name has to be used.
+	for node in dtb.enum_subnodes("/") {
+		let Some(compatible) = dtb.get_property(node, "compatible") else {
+			continue;
+		};
+		if str::from_utf8(compatible)
+			.unwrap()
+			.find("virtio,mmio")
+			.is_none()
+		{
+			continue;
+		}
+
+		let reg = dtb.get_property(node, "reg").unwrap();
+		let (slice, residual_slice) = reg.split_at(core::mem::size_of::<u64>());
+		let addr = u64::from_be_bytes(slice.try_into().unwrap());
+		let (slice, _residual_slice) = residual_slice.split_at(core::mem::size_of::<u64>());
+		let size = u64::from_be_bytes(slice.try_into().unwrap());
+
+		let irq_slice = dtb.get_property(node, "interrupts").unwrap();
+		let (irqtype, irq_slice) = irq_slice.split_at(core::mem::size_of::<u32>());
+		let (irq, _irq_slice) = irq_slice.split_at(core::mem::size_of::<u32>());
+		let irqtype = u32::from_be_bytes(irqtype.try_into().unwrap());
+		let irq = u32::from_be_bytes(irq.try_into().unwrap());
+
+		trace!(
+			"try to detect MMIO device at physical address {:#X} (size {:#X})",
+			addr,
+			size
+		);
+
+		// The transports are smaller than a page and may share a page.
+		let page_offset = addr % BasePageSize::SIZE;
+		let count = (page_offset + size).align_up(BasePageSize::SIZE) / BasePageSize::SIZE;
+		let virtual_address = virtualmem::allocate_aligned(
+			(count * BasePageSize::SIZE).try_into().unwrap(),
+			BasePageSize::SIZE.try_into().unwrap(),
+		)
+		.unwrap();
+
+		let mut flags = PageTableEntryFlags::empty();
+		flags.device().writable().execute_disable();
+		paging::map::<BasePageSize>(
+			virtual_address,
+			PhysAddr(addr - page_offset),
+			count.try_into().unwrap(),
+			flags,
+		);
+
+		let mmio = unsafe {
+			&mut *((virtual_address + usize::try_from(page_offset).unwrap())
+				.as_mut_ptr::<MmioRegisterLayout>())
+		};
+
+		let magic = mmio.get_magic_value();
+		let version = mmio.get_version();
+		let id = mmio.get_device_id();
+
+		if magic != MAGIC_VALUE {
+			trace!("It's not a MMIO-device at {:#X}", addr);
+		} else if version != 2 {
+			trace!("Found a legacy device, which isn't supported");
+		} else if id != DevId::VIRTIO_DEV_ID_NET {
+			trace!("It's not a network card at {:#X}", addr);
+		} else if irqtype != GIC_SPI {
+			warn!(
+				"Network card at {:#X} does not use a shared peripheral interrupt",
+				addr
+			);
+		} else {
+			info!("Found network card at {:#X} with interrupt {}", addr, irq);
+			return Ok((mmio, irq));
+		}
+
+		// frees obsolete virtual memory region for MMIO devices
+		paging::unmap::<BasePageSize>(virtual_address, count.try_into().unwrap());
+		virtualmem::deallocate(
+			virtual_address,
+			(count * BasePageSize::SIZE).try_into().unwrap(),
+		);
+	}
+
+	Err("Network card not found!")
+}
+
+pub(crate) fn register_driver(drv: MmioDriver) {
+	unsafe {
+		MMIO_DRIVERS.push(drv);
+	}
+}
+
+pub fn get_network_drivers(
+) -> impl Iterator<Item = &'static InterruptTicketMutex<dyn NetworkInterface>> {
+	unsafe {
+		MMIO_DRIVERS
+			.iter()
+			.filter_map(|drv| drv.get_network_driver())
+	}
+}
 
-pub fn get_network_driver() -> Option<&'static InterruptTicketMutex<dyn NetworkInterface>> {
-	None
+pub fn init_drivers() {
+	// virtio: MMIO Device Discovery
+	without_interrupts(|| {
+		if let Ok((mmio, spi)) = detect_network() {
+			// Interrupt numbers start with the private peripheral interrupts at id 16,
+			// while the shared peripheral interrupts start at id 32.
+			let irq_no = u8::try_from(spi + 16).unwrap();
+			if let Ok(VirtioDriver::Network(drv)) = mmio_virtio::init_device(mmio, irq_no) {
+				register_driver(MmioDriver::VirtioNet(InterruptTicketMutex::new(drv)));
+				enable_spi(spi);
+			}
+		} else {
+			warn!("Unable to find mmio device");
+		}
+	});
 }
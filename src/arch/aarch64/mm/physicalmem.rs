@@ -41,6 +41,11 @@ pub fn total_memory_size() -> usize {
 	TOTAL_MEMORY.load(Ordering::SeqCst)
 }
 
+/// Returns the size of the physical memory, which is currently not allocated.
+pub fn free_memory_size() -> usize {
+	PHYSICAL_FREE_LIST.lock().free_space()
+}
+
 pub fn init_page_tables() {}
 
 pub fn allocate(size: usize) -> Result<PhysAddr, AllocError> {
@@ -53,13 +58,12 @@ pub fn allocate(size: usize) -> Result<PhysAddr, AllocError> {
 		BasePageSize::SIZE
 	);
 
-	Ok(PhysAddr(
-		PHYSICAL_FREE_LIST
-			.lock()
-			.allocate(size, None)?
-			.try_into()
-			.unwrap(),
-	))
+	let mut addr = PHYSICAL_FREE_LIST.lock().allocate(size, None);
+	if addr.is_err() && mm::reclaim_physical_memory(size) {
+		addr = PHYSICAL_FREE_LIST.lock().allocate(size, None);
+	}
+
+	Ok(PhysAddr(addr?.try_into().unwrap()))
 }
 
 pub fn allocate_aligned(size: usize, alignment: usize) -> Result<PhysAddr, AllocError> {
@@ -80,10 +84,33 @@ pub fn allocate_aligned(size: usize, alignment: usize) -> Result<PhysAddr, Alloc
 		BasePageSize::SIZE
 	);
 
+	let mut addr = PHYSICAL_FREE_LIST.lock().allocate(size, Some(alignment));
+	if addr.is_err() && mm::reclaim_physical_memory(size) {
+		addr = PHYSICAL_FREE_LIST.lock().allocate(size, Some(alignment));
+	}
+
+	Ok(PhysAddr(addr?.try_into().unwrap()))
+}
+
+/// Allocates an aligned region like [allocate_aligned], which starts at or above
+/// `min_address`. Physical memory is not reclaimed, if the allocation fails.
+#[cfg(feature = "pci")]
+pub fn allocate_aligned_above(
+	size: usize,
+	alignment: usize,
+	min_address: PhysAddr,
+) -> Result<PhysAddr, AllocError> {
+	assert!(size > 0);
+	assert_eq!(
+		size % alignment,
+		0,
+		"Size {size:#X} is not a multiple of the given alignment {alignment:#X}"
+	);
+
 	Ok(PhysAddr(
 		PHYSICAL_FREE_LIST
 			.lock()
-			.allocate(size, Some(alignment))?
+			.allocate_above(size, Some(alignment), min_address.as_usize())?
 			.try_into()
 			.unwrap(),
 	))
@@ -68,4 +68,7 @@ pub fn init_drivers() {
 	crate::drivers::pci::init_drivers();
 	#[cfg(all(target_arch = "x86_64", not(feature = "pci")))]
 	crate::arch::x86_64::kernel::mmio::init_drivers();
+	// Initialize the virtio-mmio drivers, which are described in the device tree
+	#[cfg(all(target_arch = "aarch64", not(feature = "pci")))]
+	crate::arch::aarch64::kernel::mmio::init_drivers();
 }
@@ -67,6 +67,9 @@ pub const TIMER_INTERRUPT_NUMBER: u8 = 123;
 const ERROR_INTERRUPT_NUMBER: u8 = 126;
 const SPURIOUS_INTERRUPT_NUMBER: u8 = 127;
 
+/// Address range, in which writes are interpreted as message signaled interrupts
+const MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+
 /// Physical and virtual memory address for our SMP boot code.
 ///
 /// While our boot processor is already in x86-64 mode, application processors boot up in 16-bit real mode
@@ -838,6 +841,20 @@ pub fn wakeup_core(core_id_to_wakeup: CoreId) {
 	}
 }
 
+/// Returns the address and the data of a message signaled interrupt,
+/// which triggers the interrupt vector `vector` on the core `core_id`.
+///
+/// See Intel SDM Vol. 3A - 11.11 Message Signalled Interrupts
+pub fn msi_message(core_id: CoreId, vector: u8) -> (u64, u32) {
+	let apic_id = CPU_LOCAL_APIC_IDS.lock()[core_id as usize];
+
+	// Fixed delivery mode and edge triggered, such that only the vector is left in the data
+	(
+		MSI_ADDRESS_BASE | (u64::from(apic_id) << 12),
+		u32::from(vector),
+	)
+}
+
 /// Translate the x2APIC MSR into an xAPIC memory address.
 #[inline]
 fn translate_x2apic_msr_to_xapic_address(x2apic_msr: u32) -> VirtAddr {
@@ -78,4 +78,6 @@ pub fn set_core_scheduler(scheduler: *mut PerCoreScheduler) {
 
 pub fn increment_irq_counter(irq_no: u8) {
 	CoreLocal::get().irq_statistics.inc(irq_no);
+	// the timing of interrupts contributes to the entropy pool
+	crate::entropy::add_interrupt_timing(irq_no);
 }
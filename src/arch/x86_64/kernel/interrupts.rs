@@ -21,6 +21,17 @@ pub const IST_SIZE: usize = 8 * BasePageSize::SIZE as usize;
 
 pub static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
 
+/// First interrupt number of message signaled interrupts. The lower numbers
+/// are used by the lines of the IOAPIC.
+const MSI_IRQ_BASE: u8 = 32;
+/// End of the interrupt numbers of message signaled interrupts. The higher
+/// numbers are used by the local APIC.
+const MSI_IRQ_END: u8 = 80;
+
+/// Interrupt numbers of message signaled interrupts, which are in use.
+/// Bit `i` stands for the interrupt number `MSI_IRQ_BASE + i`.
+static MSI_IRQS: AtomicU64 = AtomicU64::new(0);
+
 pub fn load_idt() {
 	unsafe {
 		IDT.load_unsafe();
@@ -120,6 +131,34 @@ pub extern "C" fn irq_install_handler(
 	}
 }
 
+/// Allocates an interrupt number for a message signaled interrupt.
+///
+/// Returns `None`, if all interrupt numbers are in use.
+pub fn allocate_msi_irq() -> Option<u8> {
+	let mut irq = None;
+	MSI_IRQS
+		.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+			let free = (!used).trailing_zeros();
+			if free < u32::from(MSI_IRQ_END - MSI_IRQ_BASE) {
+				irq = Some(MSI_IRQ_BASE + u8::try_from(free).unwrap());
+				Some(used | (1 << free))
+			} else {
+				None
+			}
+		})
+		.ok()?;
+
+	irq
+}
+
+/// Releases an interrupt number, which has been allocated by `allocate_msi_irq`.
+pub fn free_msi_irq(irq: u8) {
+	debug!("Free interrupt {}", irq);
+
+	MSI_IRQS.fetch_and(!(1 << (irq - MSI_IRQ_BASE)), Ordering::Relaxed);
+	IRQ_NAMES.lock().remove(&(32 + irq));
+}
+
 fn abort(stack_frame: ExceptionStackFrame, index: u8, error_code: Option<u64>) {
 	error!("Exception {index}");
 	error!("Error code: {error_code:?}");
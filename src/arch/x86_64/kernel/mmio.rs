@@ -122,8 +122,13 @@ pub(crate) fn register_driver(drv: MmioDriver) {
 	}
 }
 
-pub fn get_network_driver() -> Option<&'static InterruptTicketMutex<dyn NetworkInterface>> {
-	unsafe { MMIO_DRIVERS.iter().find_map(|drv| drv.get_network_driver()) }
+pub fn get_network_drivers(
+) -> impl Iterator<Item = &'static InterruptTicketMutex<dyn NetworkInterface>> {
+	unsafe {
+		MMIO_DRIVERS
+			.iter()
+			.filter_map(|drv| drv.get_network_driver())
+	}
 }
 
 pub fn init_drivers() {
@@ -13,5 +13,18 @@ pub(crate) const VIRTIO_MAX_QUEUE_SIZE: u16 = 1024;
 #[cfg(feature = "tcp")]
 pub(crate) const DEFAULT_KEEP_ALIVE_INTERVAL: u64 = 75000;
 
+/// Default size of the receive and send buffers of TCP sockets in bytes
+#[cfg(feature = "tcp")]
+pub(crate) const DEFAULT_TCP_BUFFER_SIZE: usize = 65_535;
+
+/// Default size of the receive and send buffers of UDP sockets in bytes
+#[cfg(feature = "tcp")]
+pub(crate) const DEFAULT_UDP_BUFFER_SIZE: usize = 65_536;
+
+/// Maximum size of the receive and send buffers of sockets in bytes. Larger
+/// values of `SO_RCVBUF` and `SO_SNDBUF` are clamped to it.
+#[cfg(feature = "tcp")]
+pub(crate) const MAX_SOCKET_BUFFER_SIZE: usize = 4_194_304;
+
 pub(crate) const HW_DESTRUCTIVE_INTERFERENCE_SIZE: usize =
 	core::mem::align_of::<crossbeam_utils::CachePadded<u8>>();
@@ -0,0 +1,58 @@
+//! A module containing hermit-rs memory balloon drivers.
+
+pub mod virtio_balloon;
+pub mod virtio_pci;
+
+use crate::arch::kernel::processor::get_timer_ticks;
+use crate::config::KERNEL_STACK_SIZE;
+use crate::core_scheduler;
+use crate::drivers::pci::get_balloon_driver;
+use crate::scheduler::task::NORMAL_PRIO;
+use crate::scheduler::PerCoreScheduler;
+
+/// Interval in microseconds, in which the balloon checks for requests of the host
+const BALLOON_INTERVAL: u64 = 1_000_000;
+
+/// Adjusts the size of the balloon to the size requested by the host and reports
+/// free memory. The device configuration is polled, as config change
+/// interrupts are not supported yet.
+extern "C" fn balloon_task(_arg: usize) {
+	let driver = get_balloon_driver().unwrap();
+
+	loop {
+		let pending = {
+			let mut balloon = driver.lock();
+			let adjusting = balloon.adjust();
+			let reporting = !adjusting && balloon.report_free_pages();
+			adjusting || reporting
+		};
+
+		let core_scheduler = core_scheduler();
+		if !pending {
+			core_scheduler.block_current_task(Some(get_timer_ticks() + BALLOON_INTERVAL));
+		}
+		core_scheduler.reschedule();
+	}
+}
+
+/// Deflates the balloon by at least `size` bytes, after an allocation of physical
+/// memory has failed. Returns `true`, if memory has been handed back to the guest.
+pub(crate) fn deflate_on_oom(size: usize) -> bool {
+	let Some(driver) = get_balloon_driver() else {
+		return false;
+	};
+	// The allocation may have been triggered by the balloon itself.
+	let Some(mut balloon) = driver.try_lock() else {
+		return false;
+	};
+
+	balloon.deflate_on_oom(size)
+}
+
+/// Starts the balloon task, if a balloon device is available.
+pub(crate) fn init() {
+	if get_balloon_driver().is_some() {
+		info!("Start memory balloon task");
+		PerCoreScheduler::spawn(balloon_task, 0, NORMAL_PRIO, 0, KERNEL_STACK_SIZE);
+	}
+}
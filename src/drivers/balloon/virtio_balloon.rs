@@ -0,0 +1,453 @@
+//! A module containing a virtio memory balloon device driver.
+//!
+//! The balloon is inflated by taking page frames out of the physical free list
+//! and handing them over to the host, which may use the memory for other guests.
+//! Deflating hands the frames back to the physical free list. Additionally, free
+//! memory is reported to the host, if the device supports free page reporting.
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cmp;
+
+use align_address::Align;
+use pci_types::InterruptLine;
+
+use self::constants::{FeatureSet, Features};
+use self::error::VirtioBalloonError;
+use crate::arch::mm::{physicalmem, PhysAddr};
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::balloon::virtio_pci::BalloonDevCfgRaw;
+use crate::drivers::virtio::features::negotiate_features;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::virtqueue::error::VirtqError;
+use crate::drivers::virtio::virtqueue::{
+	AsSliceU8, BuffSpec, Bytes, Virtq, VqIndex, VqSize, VqType,
+};
+use crate::mm;
+
+/// The balloon always works with pages of 4 KiB, independent of the page size
+/// of the guest. See Virtio specification v1.1. - 5.5.6
+const BALLOON_PAGE_SHIFT: u32 = 12;
+const BALLOON_PAGE_SIZE: usize = 1 << BALLOON_PAGE_SHIFT;
+/// Maximum number of page frames, which are passed to the device in a single request
+const PFNS_PER_REQUEST: usize = 256;
+/// Memory in bytes, which is never handed over to the host
+const MIN_FREE_MEMORY: usize = 16 * 1024 * 1024;
+/// Size of the blocks, in which free memory is reported to the host
+const REPORTING_BLOCK_SIZE: usize = 2 * 1024 * 1024;
+/// Maximum number of blocks, which are reported in a single call of
+/// [VirtioBalloonDriver::report_free_pages]. They are taken out of the
+/// physical free list until the device has acknowledged them.
+const REPORTING_BATCH: usize = 4;
+/// Free memory has to grow by this amount of bytes, before it is reported again
+const REPORTING_THRESHOLD: usize = 32 * 1024 * 1024;
+
+/// A block of free memory, which is reported to the device.
+#[repr(C)]
+struct ReportingBlock([u8; REPORTING_BLOCK_SIZE]);
+
+impl AsSliceU8 for ReportingBlock {}
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+pub(crate) struct BalloonDevCfg {
+	pub raw: &'static mut BalloonDevCfgRaw,
+	pub dev_id: u16,
+	pub features: FeatureSet,
+}
+
+/// Virtio memory balloon driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+#[allow(dead_code)]
+pub(crate) struct VirtioBalloonDriver {
+	pub(super) dev_cfg: BalloonDevCfg,
+	pub(super) com_cfg: ComCfg,
+	pub(super) isr_stat: IsrStatus,
+	pub(super) notif_cfg: NotifCfg,
+	pub(super) inflate_vq: Option<Rc<Virtq>>,
+	pub(super) deflate_vq: Option<Rc<Virtq>>,
+	pub(super) reporting_vq: Option<Rc<Virtq>>,
+	/// Page frames, which are currently owned by the host
+	pub(super) frames: Vec<PhysAddr>,
+	/// Address, above which the current reporting pass continues. Blocks below
+	/// have already been reported during the pass.
+	pub(super) reporting_cursor: Option<PhysAddr>,
+	/// Bytes, which have been reported during the current pass
+	pub(super) reported: usize,
+	/// Free memory after the last reporting pass
+	pub(super) reported_free: usize,
+	pub(super) irq: InterruptLine,
+}
+
+// Backend-independent interface for Virtio memory balloon driver
+impl VirtioBalloonDriver {
+	pub fn get_dev_id(&self) -> u16 {
+		self.dev_cfg.dev_id
+	}
+
+	pub fn set_failed(&mut self) {
+		self.com_cfg.set_failed();
+	}
+
+	/// Returns the number of pages, which the host wants to be in the balloon.
+	pub fn target_pages(&self) -> u32 {
+		self.dev_cfg.raw.get_num_pages()
+	}
+
+	/// Returns the number of pages, which are currently in the balloon.
+	pub fn actual_pages(&self) -> u32 {
+		self.frames.len() as u32
+	}
+
+	/// Inflates or deflates the balloon by at most [PFNS_PER_REQUEST] pages towards
+	/// the size requested by the host.
+	///
+	/// Returns `true`, if the balloon has not reached its target size yet.
+	pub fn adjust(&mut self) -> bool {
+		let free = physicalmem::free_memory_size();
+		let target = self.target_pages() as usize;
+		let actual = self.frames.len();
+		if target > actual {
+			// Never take the last free memory of the guest
+			let available = free.saturating_sub(MIN_FREE_MEMORY) / BALLOON_PAGE_SIZE;
+			let count = cmp::min(cmp::min(target - actual, PFNS_PER_REQUEST), available);
+			count > 0 && self.inflate(count) == count && target > actual + count
+		} else if target < actual {
+			let count = cmp::min(actual - target, PFNS_PER_REQUEST);
+			self.deflate(count) == count && target < actual - count
+		} else {
+			false
+		}
+	}
+
+	/// Deflates the balloon by at least `size` bytes, after an allocation of physical
+	/// memory has failed. The host allows it, although it requested a larger balloon,
+	/// if VIRTIO_BALLOON_F_DEFLATE_ON_OOM has been negotiated.
+	///
+	/// Returns `true`, if page frames have been handed back to the physical free list.
+	pub fn deflate_on_oom(&mut self, size: usize) -> bool {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
+		{
+			return false;
+		}
+
+		let count = cmp::max(
+			size.align_up(BALLOON_PAGE_SIZE) / BALLOON_PAGE_SIZE,
+			PFNS_PER_REQUEST,
+		);
+		let count = cmp::min(count, self.frames.len());
+		if count == 0 {
+			return false;
+		}
+
+		info!("Running out of memory, deflate balloon by {} pages", count);
+		self.deflate(count) > 0
+	}
+
+	/// Takes up to `count` page frames out of the physical free list and hands
+	/// them over to the host. Returns the number of frames added to the balloon.
+	fn inflate(&mut self, count: usize) -> usize {
+		let Some(vq) = self.inflate_vq.as_ref() else {
+			return 0;
+		};
+
+		let mut frames = Vec::with_capacity(count);
+		for _ in 0..count {
+			match physicalmem::allocate(BALLOON_PAGE_SIZE) {
+				Ok(frame) => frames.push(frame),
+				Err(_) => break,
+			}
+		}
+
+		if frames.is_empty() {
+			return 0;
+		}
+
+		if let Err(err) = Self::send_frames(vq, &frames) {
+			error!("Unable to inflate balloon: {:?}", err);
+			for frame in frames {
+				physicalmem::deallocate(frame, BALLOON_PAGE_SIZE);
+			}
+			return 0;
+		}
+
+		let count = frames.len();
+		self.frames.append(&mut frames);
+		self.update_actual();
+
+		count
+	}
+
+	/// Takes up to `count` page frames back from the host and hands them back to
+	/// the physical free list. Returns the number of frames removed from the balloon.
+	fn deflate(&mut self, count: usize) -> usize {
+		let Some(vq) = self.deflate_vq.as_ref() else {
+			return 0;
+		};
+
+		let frames = self.frames.split_off(self.frames.len() - count);
+
+		if let Err(err) = Self::send_frames(vq, &frames) {
+			error!("Unable to deflate balloon: {:?}", err);
+			// Without VIRTIO_BALLOON_F_MUST_TELL_HOST, the frames can be used
+			// without telling the host.
+			if self
+				.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_BALLOON_F_MUST_TELL_HOST)
+			{
+				self.frames.extend(frames);
+				return 0;
+			}
+		}
+
+		for frame in frames.iter() {
+			physicalmem::deallocate(*frame, BALLOON_PAGE_SIZE);
+		}
+		self.update_actual();
+
+		frames.len()
+	}
+
+	/// Passes the page frame numbers of `frames` to the device.
+	fn send_frames(vq: &Rc<Virtq>, frames: &[PhysAddr]) -> Result<(), VirtqError> {
+		let mut pfns = Vec::with_capacity(frames.len() * core::mem::size_of::<u32>());
+		for frame in frames {
+			let pfn = (frame.as_u64() >> BALLOON_PAGE_SHIFT) as u32;
+			pfns.extend_from_slice(&pfn.to_le_bytes());
+		}
+
+		let send_spec = BuffSpec::Single(Bytes::new(pfns.len()).ok_or(VirtqError::General)?);
+		let transfer = vq
+			.prep_buffer(Rc::clone(vq), Some(send_spec), None)?
+			.write(Some(pfns.as_slice()), None::<&[u8]>)?
+			.dispatch_blocking()?;
+		transfer.close();
+
+		Ok(())
+	}
+
+	/// Tells the device the number of pages, which are in the balloon.
+	fn update_actual(&mut self) {
+		let actual = self.actual_pages();
+		self.dev_cfg.raw.set_actual(actual);
+	}
+
+	/// Reports up to [REPORTING_BATCH] blocks of free memory to the host, which may
+	/// discard their content. The blocks are handed back to the physical free list,
+	/// as soon as the device has acknowledged them. A reporting pass walks through
+	/// the physical memory by increasing addresses and is started, if the free memory
+	/// has grown by [REPORTING_THRESHOLD] bytes since the last pass.
+	///
+	/// Returns `true`, if the current pass has not been finished yet.
+	pub fn report_free_pages(&mut self) -> bool {
+		let Some(vq) = self.reporting_vq.as_ref() else {
+			return false;
+		};
+
+		let cursor = match self.reporting_cursor {
+			Some(cursor) => cursor,
+			None => {
+				let free = physicalmem::free_memory_size();
+				if free < self.reported_free + REPORTING_THRESHOLD {
+					self.reported_free = cmp::min(self.reported_free, free);
+					return false;
+				}
+				PhysAddr(0)
+			}
+		};
+
+		let batch = Self::report_batch(vq, cursor);
+		self.reported += batch.len() * REPORTING_BLOCK_SIZE;
+		let next = batch.last().map(|block| *block + REPORTING_BLOCK_SIZE);
+		for block in batch.iter() {
+			physicalmem::deallocate(*block, REPORTING_BLOCK_SIZE);
+		}
+
+		if batch.len() == REPORTING_BATCH {
+			self.reporting_cursor = next;
+			true
+		} else {
+			// All free memory has been reported
+			debug!(
+				"Reported {} MiB of free memory to the host",
+				self.reported / (1024 * 1024)
+			);
+			self.reporting_cursor = None;
+			self.reported = 0;
+			self.reported_free = physicalmem::free_memory_size();
+			false
+		}
+	}
+
+	/// Takes up to [REPORTING_BATCH] blocks of free memory, which start at or above
+	/// `cursor`, out of the physical free list and reports them to the device.
+	/// Returns the reported blocks, which the caller has to hand back.
+	fn report_batch(vq: &Rc<Virtq>, cursor: PhysAddr) -> Vec<PhysAddr> {
+		let mut batch = Vec::with_capacity(REPORTING_BATCH);
+		let mut cursor = cursor;
+
+		while batch.len() < REPORTING_BATCH {
+			// The allocation of an aligned block may require twice its size
+			if physicalmem::free_memory_size() < MIN_FREE_MEMORY + 2 * REPORTING_BLOCK_SIZE {
+				break;
+			}
+			let Ok(block) = physicalmem::allocate_aligned_above(
+				REPORTING_BLOCK_SIZE,
+				REPORTING_BLOCK_SIZE,
+				cursor,
+			) else {
+				break;
+			};
+
+			if let Err(err) = Self::report_block(vq, block) {
+				error!("Unable to report free memory: {:?}", err);
+				physicalmem::deallocate(block, REPORTING_BLOCK_SIZE);
+				break;
+			}
+			batch.push(block);
+			cursor = block + REPORTING_BLOCK_SIZE;
+		}
+
+		batch
+	}
+
+	/// Passes a block of free memory to the device. See Virtio specification v1.2. - 5.5.6.7
+	fn report_block(vq: &Rc<Virtq>, block: PhysAddr) -> Result<(), VirtqError> {
+		// The virtqueue determines the physical address of the buffer by its virtual
+		// address, hence the block has to be mapped during the request.
+		let spec = BuffSpec::Single(Bytes::new(REPORTING_BLOCK_SIZE).ok_or(VirtqError::General)?);
+		let virt_addr = mm::map(block, REPORTING_BLOCK_SIZE, true, true, false);
+
+		let ret = vq
+			.prep_transfer_from_raw(
+				Rc::clone(vq),
+				None::<(*mut ReportingBlock, BuffSpec<'_>)>,
+				Some((virt_addr.as_mut_ptr::<ReportingBlock>(), spec)),
+			)
+			.and_then(|tkn| tkn.dispatch_blocking())
+			.map(|transfer| transfer.close());
+		mm::unmap(virt_addr, REPORTING_BLOCK_SIZE);
+
+		ret
+	}
+
+	/// Initializes the device in adherence to specification. Returns Some(VirtioBalloonError)
+	/// upon failure and None in case everything worked as expected.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.1. - 5.5.5
+	pub(crate) fn init_dev(&mut self) -> Result<(), VirtioBalloonError> {
+		// Optional features, which are used if the device offers them
+		let feats = negotiate_features(
+			&mut self.com_cfg,
+			&[Features::VIRTIO_F_VERSION_1],
+			&[
+				Features::VIRTIO_BALLOON_F_MUST_TELL_HOST,
+				Features::VIRTIO_BALLOON_F_DEFLATE_ON_OOM,
+				Features::VIRTIO_BALLOON_F_REPORTING,
+			],
+		)
+		.map_err(|err| VirtioBalloonError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		self.dev_cfg.features = FeatureSet::new(feats);
+		info!(
+			"Features have been negotiated between virtio balloon device {:x} and driver. Features are: {:?}",
+			self.dev_cfg.dev_id,
+			Features::from_set(self.dev_cfg.features)
+		);
+
+		// Requests are polled, hence notifications are not needed
+		self.inflate_vq = Some(Rc::new(self.create_vq(0)));
+		self.deflate_vq = Some(Rc::new(self.create_vq(1)));
+
+		if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_BALLOON_F_REPORTING)
+		{
+			// The queues of the statistics and of free page hinting precede the
+			// reporting queue. Devices create them, if they offer the respective
+			// feature, even if the driver does not use them.
+			let dev_feats = FeatureSet::new(self.com_cfg.dev_features());
+			let mut index = 2;
+			if dev_feats.is_feature(Features::VIRTIO_BALLOON_F_STATS_VQ) {
+				index += 1;
+			}
+			if dev_feats.is_feature(Features::VIRTIO_BALLOON_F_FREE_PAGE_HINT) {
+				index += 1;
+			}
+			self.reporting_vq = Some(Rc::new(self.create_vq(index)));
+		}
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		Ok(())
+	}
+
+	fn create_vq(&mut self, index: u16) -> Virtq {
+		let vq = Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			VqType::Split,
+			VqIndex::from(index),
+			self.dev_cfg.features.into(),
+		);
+		vq.disable_notifs();
+
+		vq
+	}
+}
+
+pub mod constants {
+	use crate::drivers::virtio::features::virtio_features;
+
+	virtio_features! {
+		/// Enum contains virtio's balloon device features and general features of Virtio.
+		///
+		/// See Virtio specification v1.1. - 5.5.3
+		///
+		/// See Virtio specification v1.1. - 6
+		pub enum Features {
+			VIRTIO_BALLOON_F_MUST_TELL_HOST = 1 << 0,
+			VIRTIO_BALLOON_F_STATS_VQ = 1 << 1,
+			VIRTIO_BALLOON_F_DEFLATE_ON_OOM = 1 << 2,
+			VIRTIO_BALLOON_F_FREE_PAGE_HINT = 1 << 3,
+			VIRTIO_BALLOON_F_PAGE_POISON = 1 << 4,
+			VIRTIO_BALLOON_F_REPORTING = 1 << 5,
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		}
+	}
+}
+
+/// Error module of virtios balloon driver.
+pub mod error {
+	use crate::drivers::virtio::features::FeatureError;
+
+	/// Virtio balloon error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioBalloonError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
+		Unknown,
+	}
+}
@@ -0,0 +1,44 @@
+//! A module containing hermit-rs block device drivers and the
+//! backend-independent [BlockDevice] interface.
+
+#[cfg(feature = "pci")]
+pub mod virtio_blk;
+#[cfg(feature = "pci")]
+pub mod virtio_pci;
+
+/// Errors reported by a [BlockDevice].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockError {
+	/// The request addresses sectors beyond the capacity of the device.
+	OutOfRange,
+	/// The buffer length is not a multiple of the sector size.
+	Unaligned,
+	/// The device does not accept write requests.
+	ReadOnly,
+	/// The device does not support the requested operation.
+	Unsupported,
+	/// The device reported an I/O error or the request could not be transferred.
+	Io,
+}
+
+/// A trait for accessing block devices.
+///
+/// All offsets and counts are given in sectors of [`sector_size`](BlockDevice::sector_size)
+/// bytes. Buffers passed to [`read_sectors`](BlockDevice::read_sectors) and
+/// [`write_sectors`](BlockDevice::write_sectors) must be a multiple of the sector size.
+pub trait BlockDevice {
+	/// Returns the size of a sector in bytes.
+	fn sector_size(&self) -> usize;
+	/// Returns the capacity of the device in sectors.
+	fn capacity(&self) -> u64;
+	/// Returns true, if the device does not accept write requests.
+	fn is_read_only(&self) -> bool;
+	/// Reads `buf.len() / sector_size()` sectors starting at `sector` into `buf`.
+	fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+	/// Writes `buf.len() / sector_size()` sectors starting at `sector` from `buf`.
+	fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> Result<(), BlockError>;
+	/// Makes sure that all completed writes reached persistent storage.
+	fn flush(&mut self) -> Result<(), BlockError>;
+	/// Informs the device, that `count` sectors starting at `sector` are no longer in use.
+	fn discard(&mut self, sector: u64, count: u64) -> Result<(), BlockError>;
+}
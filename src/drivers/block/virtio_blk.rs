@@ -0,0 +1,434 @@
+//! A module containing a virtio block device driver.
+//!
+//! The driver supports split and packed virtqueues and issues all
+//! requests synchronously on a single request queue.
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cmp;
+
+use pci_types::InterruptLine;
+use zerocopy::AsBytes;
+
+use self::constants::{FeatureSet, Features, ReqStatus, ReqType};
+use self::error::VirtioBlkError;
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::block::virtio_pci::BlkDevCfgRaw;
+use crate::drivers::block::{BlockDevice, BlockError};
+use crate::drivers::virtio::features::negotiate_features;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::virtqueue::{BuffSpec, Bytes, Virtq, VqIndex, VqSize, VqType};
+
+/// Virtio addresses the device always in sectors of 512 bytes,
+/// independent of the block size of the device.
+/// See Virtio specification v1.1. - 5.2.6
+const SECTOR_SIZE: usize = 512;
+
+/// Maximal number of bytes transferred by a single request.
+const MAX_REQ_LEN: usize = 64 * 1024;
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+pub(crate) struct BlkDevCfg {
+	pub raw: &'static BlkDevCfgRaw,
+	pub dev_id: u16,
+	pub features: FeatureSet,
+}
+
+/// Header of each request send to the device.
+/// See Virtio specification v1.1. - 5.2.6
+#[derive(AsBytes, Debug)]
+#[repr(C)]
+struct BlkReqHdr {
+	req_type: u32,
+	reserved: u32,
+	sector: u64,
+}
+
+/// Payload of a discard request.
+/// See Virtio specification v1.1. - 5.2.6
+#[derive(AsBytes, Debug)]
+#[repr(C)]
+struct BlkDiscardSeg {
+	sector: u64,
+	num_sectors: u32,
+	flags: u32,
+}
+
+/// Virtio block driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+#[allow(dead_code)]
+pub(crate) struct VirtioBlkDriver {
+	pub(super) dev_cfg: BlkDevCfg,
+	pub(super) com_cfg: ComCfg,
+	pub(super) isr_stat: IsrStatus,
+	pub(super) notif_cfg: NotifCfg,
+	pub(super) vqueue: Option<Rc<Virtq>>,
+	pub(super) irq: InterruptLine,
+}
+
+// Backend-independent interface for Virtio block driver
+impl VirtioBlkDriver {
+	pub fn get_dev_id(&self) -> u16 {
+		self.dev_cfg.dev_id
+	}
+
+	pub fn set_failed(&mut self) {
+		self.com_cfg.set_failed();
+	}
+
+	/// Returns the maximal number of bytes, which can be transferred by a
+	/// single request. Takes into account the maximal segment size, if
+	/// VIRTIO_BLK_F_SIZE_MAX has been negotiated.
+	fn max_req_len(&self) -> usize {
+		if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_BLK_F_SIZE_MAX)
+		{
+			let size_max = self.dev_cfg.raw.get_size_max() as usize;
+			let len = cmp::min(MAX_REQ_LEN, size_max) / SECTOR_SIZE * SECTOR_SIZE;
+			cmp::max(len, SECTOR_SIZE)
+		} else {
+			MAX_REQ_LEN
+		}
+	}
+
+	/// Sends a single request to the device and waits for its completion.
+	///
+	/// `data` is appended to the request header and read by the device, while
+	/// `recv_len` bytes are provided to the device to write the response into.
+	/// Returns the data written by the device, without the trailing status byte.
+	fn request(
+		&self,
+		req_type: ReqType,
+		sector: u64,
+		data: Option<&[u8]>,
+		recv_len: usize,
+	) -> Result<Vec<u8>, BlockError> {
+		let vq = self.vqueue.as_ref().ok_or(BlockError::Io)?;
+
+		let hdr = BlkReqHdr {
+			req_type: req_type.into(),
+			reserved: 0,
+			sector,
+		};
+
+		let mut send = Vec::with_capacity(
+			core::mem::size_of::<BlkReqHdr>() + data.map_or(0, |data| data.len()),
+		);
+		send.extend_from_slice(hdr.as_bytes());
+		if let Some(data) = data {
+			send.extend_from_slice(data);
+		}
+
+		// The status byte is written by the device after the actual data.
+		// As VIRTIO_F_VERSION_1 implies VIRTIO_F_ANY_LAYOUT, header and data as well as
+		// data and status can share a descriptor.
+		let send_spec = BuffSpec::Single(Bytes::new(send.len()).ok_or(BlockError::Io)?);
+		let recv_spec = BuffSpec::Single(Bytes::new(recv_len + 1).ok_or(BlockError::Io)?);
+
+		let buff_tkn = vq
+			.prep_buffer(Rc::clone(vq), Some(send_spec), Some(recv_spec))
+			.map_err(|_| BlockError::Io)?;
+		let transfer = buff_tkn
+			.write(Some(send.as_slice()), None::<&[u8]>)
+			.map_err(|_| BlockError::Io)?
+			.dispatch_blocking()
+			.map_err(|_| BlockError::Io)?;
+
+		let (_, response) = transfer.ret_cpy().map_err(|_| BlockError::Io)?;
+		transfer.close();
+
+		let mut response = response.ok_or(BlockError::Io)?.into_vec();
+		let status = response.get(recv_len).copied().ok_or(BlockError::Io)?;
+		response.truncate(recv_len);
+
+		match ReqStatus::from(status) {
+			ReqStatus::VIRTIO_BLK_S_OK => Ok(response),
+			ReqStatus::VIRTIO_BLK_S_UNSUPP => Err(BlockError::Unsupported),
+			ReqStatus::VIRTIO_BLK_S_IOERR => Err(BlockError::Io),
+		}
+	}
+
+	/// Checks, if the given range is inside the device and returns the number of sectors.
+	fn check_range(&self, sector: u64, len: usize) -> Result<u64, BlockError> {
+		if len % SECTOR_SIZE != 0 {
+			return Err(BlockError::Unaligned);
+		}
+
+		let count = (len / SECTOR_SIZE) as u64;
+		match sector.checked_add(count) {
+			Some(end) if end <= self.capacity() => Ok(count),
+			_ => Err(BlockError::OutOfRange),
+		}
+	}
+
+	/// Initializes the device in adherence to specification. Returns Some(VirtioBlkError)
+	/// upon failure and None in case everything worked as expected.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.1. - 5.2.5
+	pub(crate) fn init_dev(&mut self) -> Result<(), VirtioBlkError> {
+		// Optional features, which are used if the device offers them
+		let feats = negotiate_features(
+			&mut self.com_cfg,
+			&[Features::VIRTIO_F_VERSION_1],
+			&[
+				Features::VIRTIO_BLK_F_SIZE_MAX,
+				Features::VIRTIO_BLK_F_RO,
+				Features::VIRTIO_BLK_F_BLK_SIZE,
+				Features::VIRTIO_BLK_F_FLUSH,
+				Features::VIRTIO_BLK_F_DISCARD,
+				Features::VIRTIO_F_RING_PACKED,
+			],
+		)
+		.map_err(|err| VirtioBlkError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		self.dev_cfg.features = FeatureSet::new(feats);
+		info!(
+			"Features have been negotiated between virtio block device {:x} and driver. Features are: {:?}",
+			self.dev_cfg.dev_id,
+			Features::from_set(self.dev_cfg.features)
+		);
+
+		let vq_type = if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_F_RING_PACKED)
+		{
+			VqType::Packed
+		} else {
+			VqType::Split
+		};
+
+		// The driver uses only the first request queue
+		let vq = Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			vq_type,
+			VqIndex::from(0u16),
+			self.dev_cfg.features.into(),
+		);
+		// Requests are polled, hence notifications are not needed
+		vq.disable_notifs();
+		self.vqueue = Some(Rc::new(vq));
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		info!(
+			"Virtio block device {:x} has a capacity of {} MiB{}",
+			self.dev_cfg.dev_id,
+			self.capacity() * SECTOR_SIZE as u64 / (1024 * 1024),
+			if self.is_read_only() {
+				" and is read-only"
+			} else {
+				""
+			}
+		);
+
+		Ok(())
+	}
+}
+
+impl BlockDevice for VirtioBlkDriver {
+	fn sector_size(&self) -> usize {
+		SECTOR_SIZE
+	}
+
+	fn capacity(&self) -> u64 {
+		self.dev_cfg.raw.get_capacity()
+	}
+
+	fn is_read_only(&self) -> bool {
+		self.dev_cfg.features.is_feature(Features::VIRTIO_BLK_F_RO)
+	}
+
+	fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+		self.check_range(sector, buf.len())?;
+
+		let max_len = self.max_req_len();
+		let mut sector = sector;
+		for chunk in buf.chunks_mut(max_len) {
+			let data = self.request(ReqType::VIRTIO_BLK_T_IN, sector, None, chunk.len())?;
+			chunk.copy_from_slice(&data);
+			sector += (chunk.len() / SECTOR_SIZE) as u64;
+		}
+
+		Ok(())
+	}
+
+	fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> Result<(), BlockError> {
+		if self.is_read_only() {
+			return Err(BlockError::ReadOnly);
+		}
+		self.check_range(sector, buf.len())?;
+
+		let max_len = self.max_req_len();
+		let mut sector = sector;
+		for chunk in buf.chunks(max_len) {
+			self.request(ReqType::VIRTIO_BLK_T_OUT, sector, Some(chunk), 0)?;
+			sector += (chunk.len() / SECTOR_SIZE) as u64;
+		}
+
+		Ok(())
+	}
+
+	fn flush(&mut self) -> Result<(), BlockError> {
+		// Without VIRTIO_BLK_F_FLUSH the device uses a write-through cache
+		// See Virtio specification v1.1. - 5.2.6.2
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_BLK_F_FLUSH)
+		{
+			return Ok(());
+		}
+
+		self.request(ReqType::VIRTIO_BLK_T_FLUSH, 0, None, 0)
+			.map(|_| ())
+	}
+
+	fn discard(&mut self, sector: u64, count: u64) -> Result<(), BlockError> {
+		if !self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_BLK_F_DISCARD)
+		{
+			return Err(BlockError::Unsupported);
+		}
+		if self.is_read_only() {
+			return Err(BlockError::ReadOnly);
+		}
+		match sector.checked_add(count) {
+			Some(end) if end <= self.capacity() => {}
+			_ => return Err(BlockError::OutOfRange),
+		}
+
+		let max_sectors = cmp::max(u64::from(self.dev_cfg.raw.get_max_discard_sectors()), 1);
+		let mut sector = sector;
+		let mut remaining = count;
+		while remaining > 0 {
+			let num_sectors = cmp::min(remaining, max_sectors);
+			let seg = BlkDiscardSeg {
+				sector,
+				num_sectors: num_sectors as u32,
+				flags: 0,
+			};
+			self.request(ReqType::VIRTIO_BLK_T_DISCARD, 0, Some(seg.as_bytes()), 0)?;
+
+			sector += num_sectors;
+			remaining -= num_sectors;
+		}
+
+		Ok(())
+	}
+}
+
+pub mod constants {
+	use crate::drivers::virtio::features::virtio_features;
+
+	/// Enum contains the request types of virtio's block device.
+	///
+	/// See Virtio specification v1.1. - 5.2.6
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(Copy, Clone, Debug)]
+	#[repr(u32)]
+	pub enum ReqType {
+		VIRTIO_BLK_T_IN = 0,
+		VIRTIO_BLK_T_OUT = 1,
+		VIRTIO_BLK_T_FLUSH = 4,
+		VIRTIO_BLK_T_DISCARD = 11,
+		VIRTIO_BLK_T_WRITE_ZEROES = 13,
+	}
+
+	impl From<ReqType> for u32 {
+		fn from(val: ReqType) -> Self {
+			match val {
+				ReqType::VIRTIO_BLK_T_IN => 0,
+				ReqType::VIRTIO_BLK_T_OUT => 1,
+				ReqType::VIRTIO_BLK_T_FLUSH => 4,
+				ReqType::VIRTIO_BLK_T_DISCARD => 11,
+				ReqType::VIRTIO_BLK_T_WRITE_ZEROES => 13,
+			}
+		}
+	}
+
+	/// Enum contains the status values, the device returns for a request.
+	///
+	/// See Virtio specification v1.1. - 5.2.6
+	//
+	// WARN: Upon changes in the set of the enum variants
+	// one MUST adjust the associated From<u8>
+	// implementation, in order catch all cases correctly,
+	// as this function uses the catch-all "_" case!
+	#[allow(dead_code, non_camel_case_types)]
+	#[derive(Copy, Clone, Debug)]
+	#[repr(u8)]
+	pub enum ReqStatus {
+		VIRTIO_BLK_S_OK = 0,
+		VIRTIO_BLK_S_IOERR = 1,
+		VIRTIO_BLK_S_UNSUPP = 2,
+	}
+
+	impl From<u8> for ReqStatus {
+		fn from(val: u8) -> Self {
+			match val {
+				0 => ReqStatus::VIRTIO_BLK_S_OK,
+				2 => ReqStatus::VIRTIO_BLK_S_UNSUPP,
+				_ => ReqStatus::VIRTIO_BLK_S_IOERR,
+			}
+		}
+	}
+
+	virtio_features! {
+		/// Enum contains virtio's block device features and general features of Virtio.
+		///
+		/// See Virtio specification v1.1. - 5.2.3
+		///
+		/// See Virtio specification v1.1. - 6
+		pub enum Features {
+			VIRTIO_BLK_F_SIZE_MAX = 1 << 1,
+			VIRTIO_BLK_F_SEG_MAX = 1 << 2,
+			VIRTIO_BLK_F_GEOMETRY = 1 << 4,
+			VIRTIO_BLK_F_RO = 1 << 5,
+			VIRTIO_BLK_F_BLK_SIZE = 1 << 6,
+			VIRTIO_BLK_F_FLUSH = 1 << 9,
+			VIRTIO_BLK_F_TOPOLOGY = 1 << 10,
+			VIRTIO_BLK_F_CONFIG_WCE = 1 << 11,
+			VIRTIO_BLK_F_MQ = 1 << 12,
+			VIRTIO_BLK_F_DISCARD = 1 << 13,
+			VIRTIO_BLK_F_WRITE_ZEROES = 1 << 14,
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		}
+	}
+}
+
+/// Error module of virtios block driver.
+pub mod error {
+	use crate::drivers::virtio::features::FeatureError;
+
+	/// Block driver error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioBlkError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
+		Unknown,
+	}
+}
@@ -0,0 +1,18 @@
+//! A module containing hermit-rs console drivers.
+
+#[cfg(feature = "pci")]
+pub mod virtio_console;
+#[cfg(feature = "pci")]
+pub mod virtio_pci;
+
+/// Port of the console device, which is used for stdin, stdout and stderr.
+pub const CONSOLE_PORT: u32 = 0;
+
+/// Errors reported by a console driver.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConsoleError {
+	/// The port does not exist or has been removed by the device.
+	NoPort,
+	/// The data could not be transferred.
+	Io,
+}
@@ -0,0 +1,441 @@
+//! A module containing a virtio console driver.
+//!
+//! The driver supports multiple ports (VIRTIO_CONSOLE_F_MULTIPORT). Port 0 is
+//! the console, which is used for stdin, stdout and stderr. Further ports are
+//! named by the host and exposed as character devices. All queues are polled.
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::cmp;
+
+use pci_types::InterruptLine;
+use zerocopy::AsBytes;
+
+use self::constants::{FeatureSet, Features};
+use self::error::VirtioConsoleError;
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::console::virtio_pci::ConsoleDevCfgRaw;
+use crate::drivers::console::ConsoleError;
+use crate::drivers::virtio::features::negotiate_features;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::virtqueue::{
+	BuffSpec, Bytes, Transfer, Virtq, VqIndex, VqSize, VqType,
+};
+
+/// Maximal number of ports supported by the driver.
+const MAX_PORTS: u32 = 16;
+/// Number of receive buffers, which are provided to each receive queue.
+const RX_BUFFERS: usize = 16;
+/// Size of a receive buffer of a port.
+const RX_BUFFER_SIZE: usize = 4096;
+/// Size of a receive buffer of the control queue, which is large enough for port names.
+const CTRL_BUFFER_SIZE: usize = 512;
+
+/// Events of control messages.
+/// See Virtio specification v1.1. - 5.3.6.2
+const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
+const VIRTIO_CONSOLE_DEVICE_ADD: u16 = 1;
+const VIRTIO_CONSOLE_DEVICE_REMOVE: u16 = 2;
+const VIRTIO_CONSOLE_PORT_READY: u16 = 3;
+const VIRTIO_CONSOLE_CONSOLE_PORT: u16 = 4;
+const VIRTIO_CONSOLE_RESIZE: u16 = 5;
+const VIRTIO_CONSOLE_PORT_OPEN: u16 = 6;
+const VIRTIO_CONSOLE_PORT_NAME: u16 = 7;
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+pub(crate) struct ConsoleDevCfg {
+	pub raw: &'static ConsoleDevCfgRaw,
+	pub dev_id: u16,
+	pub features: FeatureSet,
+}
+
+/// Control message exchanged on the control queues.
+/// See Virtio specification v1.1. - 5.3.6.2
+#[derive(AsBytes, Debug)]
+#[repr(C)]
+struct ControlMsg {
+	id: u32,
+	event: u16,
+	value: u16,
+}
+
+impl ControlMsg {
+	fn from_bytes(buf: &[u8]) -> Option<Self> {
+		Some(Self {
+			id: u32::from_le_bytes(buf.get(0..4)?.try_into().unwrap()),
+			event: u16::from_le_bytes(buf.get(4..6)?.try_into().unwrap()),
+			value: u16::from_le_bytes(buf.get(6..8)?.try_into().unwrap()),
+		})
+	}
+}
+
+/// A receive queue, whose buffers are permanently provided to the device.
+struct RxQueue {
+	vq: Rc<Virtq>,
+	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
+}
+
+impl RxQueue {
+	fn new(vq: Virtq, buf_size: usize) -> Self {
+		let vq = Rc::new(vq);
+		let poll_queue = Rc::new(RefCell::new(VecDeque::new()));
+		let num_buff = cmp::min(RX_BUFFERS, u16::from(vq.size()).into());
+
+		for _ in 0..num_buff {
+			let spec = BuffSpec::Single(Bytes::new(buf_size).unwrap());
+			match vq.prep_buffer(Rc::clone(&vq), None, Some(spec)) {
+				Ok(tkn) => tkn.provide().dispatch_await(Rc::clone(&poll_queue), false),
+				Err(_) => {
+					error!("Setup of console queue failed, which should not happen!");
+					panic!("setup of console queue failed!");
+				}
+			}
+		}
+
+		Self { vq, poll_queue }
+	}
+
+	/// Returns the data of the next used buffer and hands the buffer back to the device.
+	fn recv(&self) -> Option<Vec<u8>> {
+		self.vq.poll();
+		let transfer = self.poll_queue.borrow_mut().pop_front()?;
+
+		let data = match transfer.as_slices() {
+			Ok((_, Some(slices))) => slices.concat(),
+			_ => Vec::new(),
+		};
+		transfer
+			.reuse()
+			.unwrap()
+			.provide()
+			.dispatch_await(Rc::clone(&self.poll_queue), false);
+
+		Some(data)
+	}
+}
+
+/// Sends `data` on the transmit queue `vq` and waits until the device consumed it.
+fn send(vq: &Rc<Virtq>, data: &[u8]) -> Result<(), ConsoleError> {
+	let spec = BuffSpec::Single(Bytes::new(data.len()).ok_or(ConsoleError::Io)?);
+
+	let transfer = vq
+		.prep_buffer(Rc::clone(vq), Some(spec), None)
+		.map_err(|_| ConsoleError::Io)?
+		.write(Some(data), None::<&[u8]>)
+		.map_err(|_| ConsoleError::Io)?
+		.dispatch_blocking()
+		.map_err(|_| ConsoleError::Io)?;
+	transfer.close();
+
+	Ok(())
+}
+
+/// A port of the console device.
+struct Port {
+	rx: RxQueue,
+	tx: Rc<Virtq>,
+	/// Received data, which has not been read yet
+	input: VecDeque<u8>,
+	/// Name of the port, which is assigned by the host
+	name: Option<String>,
+	/// The port has been announced by the device
+	added: bool,
+	/// A process on the host side has opened the port
+	host_connected: bool,
+}
+
+/// Virtio console driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+#[allow(dead_code)]
+pub(crate) struct VirtioConsoleDriver {
+	pub(super) dev_cfg: ConsoleDevCfg,
+	pub(super) com_cfg: ComCfg,
+	pub(super) isr_stat: IsrStatus,
+	pub(super) notif_cfg: NotifCfg,
+	pub(super) irq: InterruptLine,
+	pub(super) ports: Vec<Port>,
+	/// Control receive and transmit queue, if multiple ports are supported
+	pub(super) ctrl: Option<(RxQueue, Rc<Virtq>)>,
+}
+
+// Backend-independent interface for Virtio console driver
+impl VirtioConsoleDriver {
+	pub fn get_dev_id(&self) -> u16 {
+		self.dev_cfg.dev_id
+	}
+
+	pub fn set_failed(&mut self) {
+		self.com_cfg.set_failed();
+	}
+
+	/// Initializes the device in adherence to specification. Returns Some(VirtioConsoleError)
+	/// upon failure and None in case everything worked as expected.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.1. - 5.3.5
+	pub(crate) fn init_dev(&mut self) -> Result<(), VirtioConsoleError> {
+		// Multiple ports are optional, hence fall back to a single port
+		let feats = negotiate_features(
+			&mut self.com_cfg,
+			&[Features::VIRTIO_F_VERSION_1],
+			&[Features::VIRTIO_CONSOLE_F_MULTIPORT],
+		)
+		.map_err(|err| VirtioConsoleError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		self.dev_cfg.features = FeatureSet::new(feats);
+		info!(
+			"Features have been negotiated between virtio console device {:x} and driver. Features are: {:?}",
+			self.dev_cfg.dev_id,
+			Features::from_set(self.dev_cfg.features)
+		);
+
+		let multiport = self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_CONSOLE_F_MULTIPORT);
+		let num_ports = if multiport {
+			cmp::min(self.dev_cfg.raw.get_max_nr_ports(), MAX_PORTS)
+		} else {
+			1
+		};
+
+		// Queues of port 0 are followed by the control queues, if multiple ports
+		// are supported. See Virtio specification v1.1. - 5.3.2
+		for port in 0..num_ports {
+			let rx_index = if port == 0 { 0 } else { 2 * port + 2 };
+			let rx = self.create_vq(rx_index as u16);
+			let tx = self.create_vq(rx_index as u16 + 1);
+			// Transmissions are polled, hence notifications are not needed
+			tx.disable_notifs();
+
+			self.ports.push(Port {
+				rx: RxQueue::new(rx, RX_BUFFER_SIZE),
+				tx: Rc::new(tx),
+				input: VecDeque::new(),
+				name: None,
+				// Without multiple ports, the console port is always present
+				added: !multiport,
+				host_connected: !multiport,
+			});
+		}
+
+		if multiport {
+			let rx = self.create_vq(2);
+			let tx = self.create_vq(3);
+			tx.disable_notifs();
+			self.ctrl = Some((RxQueue::new(rx, CTRL_BUFFER_SIZE), Rc::new(tx)));
+		}
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		if multiport {
+			// The device announces its ports in response to DEVICE_READY
+			self.send_control(0, VIRTIO_CONSOLE_DEVICE_READY, 1)
+				.map_err(|_| VirtioConsoleError::Unknown)?;
+			self.process_control();
+		}
+
+		Ok(())
+	}
+
+	fn create_vq(&mut self, index: u16) -> Virtq {
+		let vq = Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			VqType::Split,
+			VqIndex::from(index),
+			self.dev_cfg.features.into(),
+		);
+		vq.disable_notifs();
+		vq
+	}
+
+	fn send_control(&self, id: u32, event: u16, value: u16) -> Result<(), ConsoleError> {
+		let (_, tx) = self.ctrl.as_ref().ok_or(ConsoleError::Io)?;
+		let msg = ControlMsg { id, event, value };
+
+		send(tx, msg.as_bytes())
+	}
+
+	/// Handles all pending control messages of the device.
+	///
+	/// See Virtio specification v1.1. - 5.3.6.2
+	fn process_control(&mut self) {
+		loop {
+			let Some(buf) = self.ctrl.as_ref().and_then(|(rx, _)| rx.recv()) else {
+				return;
+			};
+			let Some(msg) = ControlMsg::from_bytes(&buf) else {
+				warn!("Received malformed console control message");
+				continue;
+			};
+			let Some(port) = self.ports.get_mut(msg.id as usize) else {
+				warn!("Console control message for unknown port {}", msg.id);
+				continue;
+			};
+
+			let response = match msg.event {
+				VIRTIO_CONSOLE_DEVICE_ADD => {
+					port.added = true;
+					Some((VIRTIO_CONSOLE_PORT_READY, 1))
+				}
+				VIRTIO_CONSOLE_DEVICE_REMOVE => {
+					port.added = false;
+					port.host_connected = false;
+					port.name = None;
+					None
+				}
+				VIRTIO_CONSOLE_CONSOLE_PORT => {
+					// stdin and stdout are always connected
+					port.host_connected = true;
+					Some((VIRTIO_CONSOLE_PORT_OPEN, 1))
+				}
+				VIRTIO_CONSOLE_PORT_OPEN => {
+					port.host_connected = msg.value != 0;
+					None
+				}
+				VIRTIO_CONSOLE_PORT_NAME => {
+					let name = String::from_utf8_lossy(&buf[8..]);
+					port.name = Some(name.trim_end_matches('\0').into());
+					None
+				}
+				VIRTIO_CONSOLE_RESIZE => None,
+				event => {
+					warn!("Unknown console control event {}", event);
+					None
+				}
+			};
+
+			if let Some((event, value)) = response {
+				if self.send_control(msg.id, event, value).is_err() {
+					error!(
+						"Unable to answer console control message for port {}",
+						msg.id
+					);
+				}
+			}
+		}
+	}
+
+	fn port(&mut self, id: u32) -> Result<&mut Port, ConsoleError> {
+		self.process_control();
+
+		self.ports
+			.get_mut(id as usize)
+			.filter(|port| port.added)
+			.ok_or(ConsoleError::NoPort)
+	}
+
+	/// Returns the port with the given name.
+	pub fn find_port(&mut self, name: &str) -> Option<u32> {
+		self.process_control();
+
+		self.ports
+			.iter()
+			.position(|port| port.added && port.name.as_deref() == Some(name))
+			.map(|id| id as u32)
+	}
+
+	/// Returns the names of all named ports.
+	pub fn port_names(&mut self) -> Vec<String> {
+		self.process_control();
+
+		self.ports
+			.iter()
+			.filter(|port| port.added)
+			.filter_map(|port| port.name.clone())
+			.collect()
+	}
+
+	/// Returns true, if the host side of the port is connected.
+	pub fn is_host_connected(&mut self, id: u32) -> bool {
+		self.port(id).map_or(false, |port| port.host_connected)
+	}
+
+	/// Informs the host, that the guest side of the port has been opened or closed.
+	pub fn set_guest_connected(&mut self, id: u32, connected: bool) -> Result<(), ConsoleError> {
+		self.port(id)?;
+		if self.ctrl.is_some() {
+			self.send_control(id, VIRTIO_CONSOLE_PORT_OPEN, connected.into())?;
+		}
+
+		Ok(())
+	}
+
+	/// Reads the available data of the port into `buf` without blocking.
+	/// Returns the number of read bytes.
+	pub fn read(&mut self, id: u32, buf: &mut [u8]) -> Result<usize, ConsoleError> {
+		let port = self.port(id)?;
+		while let Some(data) = port.rx.recv() {
+			port.input.extend(data);
+		}
+
+		let len = cmp::min(buf.len(), port.input.len());
+		for (dst, src) in buf.iter_mut().zip(port.input.drain(..len)) {
+			*dst = src;
+		}
+
+		Ok(len)
+	}
+
+	/// Writes `buf` to the port.
+	pub fn write(&mut self, id: u32, buf: &[u8]) -> Result<usize, ConsoleError> {
+		let port = self.port(id)?;
+		for chunk in buf.chunks(RX_BUFFER_SIZE) {
+			send(&port.tx, chunk)?;
+		}
+
+		Ok(buf.len())
+	}
+}
+
+pub mod constants {
+	use crate::drivers::virtio::features::virtio_features;
+
+	virtio_features! {
+		/// Enum contains virtio's console device features and general features of Virtio.
+		///
+		/// See Virtio specification v1.1. - 5.3.3
+		///
+		/// See Virtio specification v1.1. - 6
+		pub enum Features {
+			VIRTIO_CONSOLE_F_SIZE = 1 << 0,
+			VIRTIO_CONSOLE_F_MULTIPORT = 1 << 1,
+			VIRTIO_CONSOLE_F_EMERG_WRITE = 1 << 2,
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		}
+	}
+}
+
+/// Error module of virtios console driver.
+pub mod error {
+	use crate::drivers::virtio::features::FeatureError;
+
+	/// Virtio console error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioConsoleError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
+		Unknown,
+	}
+}
@@ -0,0 +1,134 @@
+use alloc::vec::Vec;
+
+use crate::arch::pci::PciConfigRegion;
+use crate::drivers::console::virtio_console::constants::FeatureSet;
+use crate::drivers::console::virtio_console::{ConsoleDevCfg, VirtioConsoleDriver};
+use crate::drivers::pci::PciDevice;
+use crate::drivers::virtio::error::{self, VirtioError};
+use crate::drivers::virtio::transport::pci;
+use crate::drivers::virtio::transport::pci::{PciCap, UniCapsColl};
+
+/// Virtio's console device configuration structure.
+/// See specification v1.1. - 5.3.4
+///
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct ConsoleDevCfgRaw {
+	/// Number of columns of the console. Only valid if VIRTIO_CONSOLE_F_SIZE is set.
+	cols: u16,
+	/// Number of rows of the console. Only valid if VIRTIO_CONSOLE_F_SIZE is set.
+	rows: u16,
+	/// Maximum number of ports. Only valid if VIRTIO_CONSOLE_F_MULTIPORT is set.
+	max_nr_ports: u32,
+	/// Emergency write register. Only valid if VIRTIO_CONSOLE_F_EMERG_WRITE is set.
+	emerg_wr: u32,
+}
+
+impl ConsoleDevCfgRaw {
+	pub fn get_max_nr_ports(&self) -> u32 {
+		self.max_nr_ports
+	}
+}
+
+impl VirtioConsoleDriver {
+	fn map_cfg(cap: &PciCap) -> Option<ConsoleDevCfg> {
+		let dev_cfg: &'static ConsoleDevCfgRaw = match pci::map_dev_cfg::<ConsoleDevCfgRaw>(cap) {
+			Some(cfg) => cfg,
+			None => return None,
+		};
+
+		Some(ConsoleDevCfg {
+			raw: dev_cfg,
+			dev_id: cap.dev_id(),
+			features: FeatureSet::new(0),
+		})
+	}
+
+	/// Instantiates a new (VirtioConsoleDriver)[VirtioConsoleDriver] struct, by checking the available
+	/// configuration structures and moving them into the struct.
+	pub fn new(
+		mut caps_coll: UniCapsColl,
+		device: &PciDevice<PciConfigRegion>,
+	) -> Result<Self, error::VirtioConsoleError> {
+		let device_id = device.device_id();
+
+		let com_cfg = match caps_coll.get_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(error::VirtioConsoleError::NoComCfg(device_id));
+			}
+		};
+
+		let isr_stat = match caps_coll.get_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(error::VirtioConsoleError::NoIsrCfg(device_id));
+			}
+		};
+
+		let notif_cfg = match caps_coll.get_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(error::VirtioConsoleError::NoNotifCfg(device_id));
+			}
+		};
+
+		let dev_cfg = loop {
+			match caps_coll.get_dev_cfg() {
+				Some(cfg) => {
+					if let Some(dev_cfg) = VirtioConsoleDriver::map_cfg(&cfg) {
+						break dev_cfg;
+					}
+				}
+				None => {
+					error!("No dev config. Aborting!");
+					return Err(error::VirtioConsoleError::NoDevCfg(device_id));
+				}
+			}
+		};
+
+		Ok(VirtioConsoleDriver {
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+			irq: device.irq().unwrap(),
+			ports: Vec::new(),
+			ctrl: None,
+		})
+	}
+
+	/// Initializes virtio console device
+	pub fn init(device: &PciDevice<PciConfigRegion>) -> Result<VirtioConsoleDriver, VirtioError> {
+		let mut drv = match pci::map_caps(device) {
+			Ok(caps) => match VirtioConsoleDriver::new(caps, device) {
+				Ok(driver) => driver,
+				Err(cons_err) => {
+					error!("Initializing new console driver failed. Aborting!");
+					return Err(VirtioError::ConsoleDriver(cons_err));
+				}
+			},
+			Err(pci_error) => {
+				error!("Mapping capabilities failed. Aborting!");
+				return Err(VirtioError::FromPci(pci_error));
+			}
+		};
+
+		match drv.init_dev() {
+			Ok(_) => info!(
+				"Console device with id {:x}, has been initialized by driver!",
+				drv.get_dev_id()
+			),
+			Err(cons_err) => {
+				drv.set_failed();
+				return Err(VirtioError::ConsoleDriver(cons_err));
+			}
+		}
+
+		Ok(drv)
+	}
+}
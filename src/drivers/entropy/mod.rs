@@ -0,0 +1,6 @@
+//! A module containing hermit-rs drivers for hardware random number generators.
+
+#[cfg(feature = "pci")]
+pub mod virtio_pci;
+#[cfg(feature = "pci")]
+pub mod virtio_rng;
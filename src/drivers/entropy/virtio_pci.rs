@@ -0,0 +1,87 @@
+use crate::arch::pci::PciConfigRegion;
+use crate::drivers::entropy::virtio_rng::constants::FeatureSet;
+use crate::drivers::entropy::virtio_rng::{RngDevCfg, VirtioRngDriver};
+use crate::drivers::pci::PciDevice;
+use crate::drivers::virtio::error::{self, VirtioError};
+use crate::drivers::virtio::transport::pci;
+use crate::drivers::virtio::transport::pci::UniCapsColl;
+
+impl VirtioRngDriver {
+	/// Instantiates a new (VirtioRngDriver)[VirtioRngDriver] struct, by checking the available
+	/// configuration structures and moving them into the struct.
+	pub fn new(
+		mut caps_coll: UniCapsColl,
+		device: &PciDevice<PciConfigRegion>,
+	) -> Result<Self, error::VirtioRngError> {
+		let device_id = device.device_id();
+
+		let com_cfg = match caps_coll.get_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(error::VirtioRngError::NoComCfg(device_id));
+			}
+		};
+
+		let isr_stat = match caps_coll.get_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(error::VirtioRngError::NoIsrCfg(device_id));
+			}
+		};
+
+		let notif_cfg = match caps_coll.get_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(error::VirtioRngError::NoNotifCfg(device_id));
+			}
+		};
+
+		// The device has no device specific configuration
+		let dev_cfg = RngDevCfg {
+			dev_id: device_id,
+			features: FeatureSet::new(0),
+		};
+
+		Ok(VirtioRngDriver {
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+			vqueue: None,
+			irq: device.irq().unwrap(),
+		})
+	}
+
+	/// Initializes virtio entropy device
+	pub fn init(device: &PciDevice<PciConfigRegion>) -> Result<VirtioRngDriver, VirtioError> {
+		let mut drv = match pci::map_caps(device) {
+			Ok(caps) => match VirtioRngDriver::new(caps, device) {
+				Ok(driver) => driver,
+				Err(rng_err) => {
+					error!("Initializing new entropy driver failed. Aborting!");
+					return Err(VirtioError::RngDriver(rng_err));
+				}
+			},
+			Err(pci_error) => {
+				error!("Mapping capabilities failed. Aborting!");
+				return Err(VirtioError::FromPci(pci_error));
+			}
+		};
+
+		match drv.init_dev() {
+			Ok(_) => info!(
+				"Entropy device with id {:x}, has been initialized by driver!",
+				drv.get_dev_id()
+			),
+			Err(rng_err) => {
+				drv.set_failed();
+				return Err(VirtioError::RngDriver(rng_err));
+			}
+		}
+
+		Ok(drv)
+	}
+}
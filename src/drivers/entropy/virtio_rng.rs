@@ -0,0 +1,152 @@
+//! A module containing a virtio entropy device (virtio-rng) driver.
+//!
+//! The device provides random data of the host, which is mixed into the
+//! entropy pool of the kernel by [crate::entropy].
+
+use alloc::rc::Rc;
+
+use pci_types::InterruptLine;
+
+use self::constants::{FeatureSet, Features};
+use self::error::VirtioRngError;
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::virtio::features::negotiate_features;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::virtqueue::{BuffSpec, Bytes, Virtq, VqIndex, VqSize, VqType};
+
+/// The device has no device specific configuration structure.
+/// See Virtio specification v1.1. - 5.4.4
+pub(crate) struct RngDevCfg {
+	pub dev_id: u16,
+	pub features: FeatureSet,
+}
+
+/// Virtio entropy driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+#[allow(dead_code)]
+pub(crate) struct VirtioRngDriver {
+	pub(super) dev_cfg: RngDevCfg,
+	pub(super) com_cfg: ComCfg,
+	pub(super) isr_stat: IsrStatus,
+	pub(super) notif_cfg: NotifCfg,
+	pub(super) vqueue: Option<Rc<Virtq>>,
+	pub(super) irq: InterruptLine,
+}
+
+// Backend-independent interface for Virtio entropy driver
+impl VirtioRngDriver {
+	pub fn get_dev_id(&self) -> u16 {
+		self.dev_cfg.dev_id
+	}
+
+	pub fn set_failed(&mut self) {
+		self.com_cfg.set_failed();
+	}
+
+	/// Initializes the device in adherence to specification. Returns Some(VirtioRngError)
+	/// upon failure and None in case everything worked as expected.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.1. - 5.4.5
+	pub(crate) fn init_dev(&mut self) -> Result<(), VirtioRngError> {
+		let feats = negotiate_features(&mut self.com_cfg, &[Features::VIRTIO_F_VERSION_1], &[])
+			.map_err(|err| VirtioRngError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		info!(
+			"Features have been negotiated between virtio entropy device {:x} and driver.",
+			self.dev_cfg.dev_id
+		);
+		self.dev_cfg.features = FeatureSet::new(feats);
+
+		// The device provides a single request queue
+		let vq = Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			VqType::Split,
+			VqIndex::from(0u16),
+			self.dev_cfg.features.into(),
+		);
+		// Requests are polled, hence notifications are not needed
+		vq.disable_notifs();
+		self.vqueue = Some(Rc::new(vq));
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		Ok(())
+	}
+
+	/// Fills `buf` with random data of the device and returns the number of
+	/// written bytes. The device may provide less data than requested.
+	pub fn read(&mut self, buf: &mut [u8]) -> usize {
+		let Some(vq) = self.vqueue.as_ref() else {
+			return 0;
+		};
+		let Some(len) = Bytes::new(buf.len()) else {
+			return 0;
+		};
+
+		let transfer = match vq
+			.prep_buffer(Rc::clone(vq), None, Some(BuffSpec::Single(len)))
+			.map(|tkn| tkn.provide().dispatch_blocking())
+		{
+			Ok(Ok(transfer)) => transfer,
+			_ => {
+				error!("Unable to request random data from virtio entropy device");
+				return 0;
+			}
+		};
+
+		let len = match transfer.ret_cpy() {
+			Ok((_, Some(data))) => {
+				buf[..data.len()].copy_from_slice(&data);
+				data.len()
+			}
+			_ => 0,
+		};
+		transfer.close();
+
+		len
+	}
+}
+
+pub mod constants {
+	use crate::drivers::virtio::features::virtio_features;
+
+	virtio_features! {
+		/// Enum contains virtio's entropy device features and general features of Virtio.
+		///
+		/// See Virtio specification v1.1. - 5.4.3
+		///
+		/// See Virtio specification v1.1. - 6
+		pub enum Features {
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		}
+	}
+}
+
+/// Error module of virtios entropy driver.
+pub mod error {
+	use crate::drivers::virtio::features::FeatureError;
+
+	/// Virtio entropy error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioRngError {
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
+		Unknown,
+	}
+}
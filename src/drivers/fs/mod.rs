@@ -1,4 +1,6 @@
 #[cfg(feature = "pci")]
+pub mod virtio_9p;
+#[cfg(feature = "pci")]
 pub mod virtio_fs;
 #[cfg(feature = "pci")]
 pub mod virtio_pci;
@@ -0,0 +1,159 @@
+//! A module containing a virtio 9p transport driver.
+//!
+//! The driver only transports 9P messages. The protocol itself is
+//! implemented by the file system in [crate::fs::ninep].
+
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use pci_types::InterruptLine;
+
+use self::constants::{FeatureSet, Features};
+use self::error::Virtio9pError;
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::fs::virtio_pci::NinePDevCfgRaw;
+use crate::drivers::virtio::features::negotiate_features;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::virtqueue::{BuffSpec, Bytes, Virtq, VqIndex, VqSize, VqType};
+use crate::fs::ninep::NinePInterface;
+use crate::syscalls::fs::FileError;
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+pub(crate) struct NinePDevCfg {
+	pub raw: &'static NinePDevCfgRaw,
+	pub dev_id: u16,
+	pub features: FeatureSet,
+}
+
+/// Virtio 9p driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+#[allow(dead_code)]
+pub(crate) struct Virtio9pDriver {
+	pub(super) dev_cfg: NinePDevCfg,
+	pub(super) com_cfg: ComCfg,
+	pub(super) isr_stat: IsrStatus,
+	pub(super) notif_cfg: NotifCfg,
+	pub(super) vqueue: Option<Rc<Virtq>>,
+	pub(super) irq: InterruptLine,
+}
+
+// Backend-independent interface for Virtio 9p driver
+impl Virtio9pDriver {
+	pub fn get_dev_id(&self) -> u16 {
+		self.dev_cfg.dev_id
+	}
+
+	pub fn set_failed(&mut self) {
+		self.com_cfg.set_failed();
+	}
+
+	/// Initializes the device in adherence to specification. Returns Some(Virtio9pError)
+	/// upon failure and None in case everything worked as expected.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.1. - 5.13
+	pub(crate) fn init_dev(&mut self) -> Result<(), Virtio9pError> {
+		// The mount tag is required to mount the file system
+		let feats = negotiate_features(
+			&mut self.com_cfg,
+			&[Features::VIRTIO_F_VERSION_1, Features::VIRTIO_9P_MOUNT_TAG],
+			&[],
+		)
+		.map_err(|err| Virtio9pError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		info!(
+			"Features have been negotiated between virtio 9p device {:x} and driver.",
+			self.dev_cfg.dev_id
+		);
+		self.dev_cfg.features = FeatureSet::new(feats);
+
+		// The device provides a single request queue
+		let vq = Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			VqType::Split,
+			VqIndex::from(0u16),
+			self.dev_cfg.features.into(),
+		);
+		// Requests are polled, hence notifications are not needed
+		vq.disable_notifs();
+		self.vqueue = Some(Rc::new(vq));
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		Ok(())
+	}
+}
+
+impl NinePInterface for Virtio9pDriver {
+	fn send_request(&mut self, request: &[u8], max_response: usize) -> Result<Vec<u8>, FileError> {
+		let vq = self.vqueue.as_ref().ok_or(FileError::EIO)?;
+
+		let send_spec = BuffSpec::Single(Bytes::new(request.len()).ok_or(FileError::EIO)?);
+		let recv_spec = BuffSpec::Single(Bytes::new(max_response).ok_or(FileError::EIO)?);
+
+		let transfer = vq
+			.prep_buffer(Rc::clone(vq), Some(send_spec), Some(recv_spec))
+			.map_err(|_| FileError::EIO)?
+			.write(Some(request), None::<&[u8]>)
+			.map_err(|_| FileError::EIO)?
+			.dispatch_blocking()
+			.map_err(|_| FileError::EIO)?;
+
+		let (_, response) = transfer.ret_cpy().map_err(|_| FileError::EIO)?;
+		transfer.close();
+
+		Ok(response.ok_or(FileError::EIO)?.into_vec())
+	}
+
+	fn get_mount_point(&self) -> String {
+		self.dev_cfg.raw.get_tag().to_string()
+	}
+}
+
+pub mod constants {
+	use crate::drivers::virtio::features::virtio_features;
+
+	virtio_features! {
+		/// Enum contains virtio's 9p transport features and general features of Virtio.
+		///
+		/// See Virtio specification v1.1. - 5.13
+		///
+		/// See Virtio specification v1.1. - 6
+		pub enum Features {
+			VIRTIO_9P_MOUNT_TAG = 1 << 0,
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		}
+	}
+}
+
+/// Error module of virtios 9p driver.
+pub mod error {
+	use crate::drivers::virtio::features::FeatureError;
+
+	/// Virtio 9p error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum Virtio9pError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
+		Unknown,
+	}
+}
@@ -1,12 +1,22 @@
 use alloc::vec::Vec;
 
+#[cfg(target_arch = "x86_64")]
+use crate::arch::get_processor_count;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::kernel::apic;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::kernel::interrupts::ExceptionStackFrame;
 use crate::arch::pci::PciConfigRegion;
+use crate::drivers::fs::virtio_9p;
+use crate::drivers::fs::virtio_9p::{NinePDevCfg, Virtio9pDriver};
 use crate::drivers::fs::virtio_fs::constants::FeatureSet;
 use crate::drivers::fs::virtio_fs::{FsDevCfg, VirtioFsDriver};
 use crate::drivers::pci::PciDevice;
 use crate::drivers::virtio::error::{self, VirtioError};
 use crate::drivers::virtio::transport::pci;
 use crate::drivers::virtio::transport::pci::{PciCap, UniCapsColl};
+#[cfg(target_arch = "x86_64")]
+use crate::scheduler::CoreId;
 
 /// Virtio's network device configuration structure.
 /// See specification v1.1. - 5.11.4
@@ -114,9 +124,57 @@ impl VirtioFsDriver {
 			vqueues: Vec::new(),
 			ready_queue: Vec::new(),
 			irq: device.irq().unwrap(),
+			#[cfg(target_arch = "x86_64")]
+			msix: device.msix_table(),
 		})
 	}
 
+	/// Assigns an MSI-X vector to each virtqueue. The vectors are spread
+	/// across the available cores.
+	///
+	/// Falls back to the legacy interrupt line, if the device does not
+	/// provide enough vectors.
+	#[cfg(target_arch = "x86_64")]
+	pub(super) fn setup_msix(&mut self) {
+		let msix = match self.msix.take() {
+			Some(msix) => msix,
+			None => return,
+		};
+
+		if usize::from(msix.size()) < self.vqueues.len() {
+			info!(
+				"Virtio filesystem device {:x} provides only {} MSI-X vectors. Using the legacy interrupt.",
+				self.dev_cfg.dev_id,
+				msix.size()
+			);
+			return;
+		}
+
+		let num_vqs = u16::try_from(self.vqueues.len()).unwrap();
+		let mut irqs = Vec::with_capacity(self.vqueues.len());
+		for entry in 0..num_vqs {
+			let core_id = CoreId::from(entry) % get_processor_count();
+			let irq = pci::install_msix_handler(&msix, entry, core_id, fs_irqhandler, "virtio_fs");
+			irqs.extend(irq);
+			let vq_cfg = self.com_cfg.select_vq(entry);
+
+			if irq.is_none() || !vq_cfg.map_or(false, |mut vq_cfg| vq_cfg.set_msix_vector(entry)) {
+				warn!("Unable to assign a MSI-X vector to virtqueue {}", entry);
+				pci::free_msix_handlers(&mut self.com_cfg, 0..num_vqs, &irqs);
+				return;
+			}
+		}
+
+		msix.enable();
+		info!(
+			"Virtio filesystem device {:x} uses MSI-X with {} vectors",
+			self.dev_cfg.dev_id,
+			self.vqueues.len()
+		);
+
+		self.msix = Some(msix);
+	}
+
 	/// Initializes virtio filesystem device
 	pub fn init(device: &PciDevice<PciConfigRegion>) -> Result<VirtioFsDriver, VirtioError> {
 		let mut drv = match pci::map_caps(device) {
@@ -147,3 +205,138 @@ impl VirtioFsDriver {
 		Ok(drv)
 	}
 }
+
+/// Requests to the filesystem device are completed by polling the virtqueues,
+/// such that their interrupts only have to be acknowledged.
+#[cfg(target_arch = "x86_64")]
+extern "x86-interrupt" fn fs_irqhandler(_stack_frame: ExceptionStackFrame) {
+	debug!("Receive virtio filesystem interrupt");
+	apic::eoi();
+}
+
+/// Virtio's 9p transport configuration structure.
+/// See specification v1.1. - 5.13.4
+///
+/// The mount tag of length `tag_len` directly follows the structure.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct NinePDevCfgRaw {
+	/// Length of the mount tag in bytes.
+	tag_len: u16,
+}
+
+impl NinePDevCfgRaw {
+	pub fn get_tag(&self) -> &str {
+		// The tag is not NUL-terminated and directly follows the length field
+		let tag = unsafe {
+			core::slice::from_raw_parts(
+				(self as *const Self).add(1) as *const u8,
+				usize::from(self.tag_len),
+			)
+		};
+
+		core::str::from_utf8(tag).unwrap_or_default()
+	}
+}
+
+impl Virtio9pDriver {
+	fn map_cfg(cap: &PciCap) -> Option<NinePDevCfg> {
+		let dev_cfg: &'static NinePDevCfgRaw = match pci::map_dev_cfg::<NinePDevCfgRaw>(cap) {
+			Some(cfg) => cfg,
+			None => return None,
+		};
+
+		Some(NinePDevCfg {
+			raw: dev_cfg,
+			dev_id: cap.dev_id(),
+			features: virtio_9p::constants::FeatureSet::new(0),
+		})
+	}
+
+	/// Instantiates a new (Virtio9pDriver)[Virtio9pDriver] struct, by checking the available
+	/// configuration structures and moving them into the struct.
+	pub fn new(
+		mut caps_coll: UniCapsColl,
+		device: &PciDevice<PciConfigRegion>,
+	) -> Result<Self, error::Virtio9pError> {
+		let device_id = device.device_id();
+
+		let com_cfg = match caps_coll.get_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(error::Virtio9pError::NoComCfg(device_id));
+			}
+		};
+
+		let isr_stat = match caps_coll.get_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(error::Virtio9pError::NoIsrCfg(device_id));
+			}
+		};
+
+		let notif_cfg = match caps_coll.get_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(error::Virtio9pError::NoNotifCfg(device_id));
+			}
+		};
+
+		let dev_cfg = loop {
+			match caps_coll.get_dev_cfg() {
+				Some(cfg) => {
+					if let Some(dev_cfg) = Virtio9pDriver::map_cfg(&cfg) {
+						break dev_cfg;
+					}
+				}
+				None => {
+					error!("No dev config. Aborting!");
+					return Err(error::Virtio9pError::NoDevCfg(device_id));
+				}
+			}
+		};
+
+		Ok(Virtio9pDriver {
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+			vqueue: None,
+			irq: device.irq().unwrap(),
+		})
+	}
+
+	/// Initializes virtio 9p device
+	pub fn init(device: &PciDevice<PciConfigRegion>) -> Result<Virtio9pDriver, VirtioError> {
+		let mut drv = match pci::map_caps(device) {
+			Ok(caps) => match Virtio9pDriver::new(caps, device) {
+				Ok(driver) => driver,
+				Err(p9_err) => {
+					error!("Initializing new 9p driver failed. Aborting!");
+					return Err(VirtioError::NinePDriver(p9_err));
+				}
+			},
+			Err(pci_error) => {
+				error!("Mapping capabilities failed. Aborting!");
+				return Err(VirtioError::FromPci(pci_error));
+			}
+		};
+
+		match drv.init_dev() {
+			Ok(_) => info!(
+				"9p device with id {:x} and mount tag {}, has been initialized by driver!",
+				drv.get_dev_id(),
+				drv.dev_cfg.raw.get_tag()
+			),
+			Err(p9_err) => {
+				drv.set_failed();
+				return Err(VirtioError::NinePDriver(p9_err));
+			}
+		}
+
+		Ok(drv)
+	}
+}
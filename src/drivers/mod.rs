@@ -1,10 +1,17 @@
 //! A module containing hermit-rs driver, hermit-rs driver trait and driver specific errors.
 
+#[cfg(feature = "pci")]
+pub mod balloon;
+pub mod block;
+pub mod console;
+pub mod entropy;
 pub mod fs;
 pub mod net;
 #[cfg(feature = "pci")]
 pub mod pci;
 pub mod virtio;
+#[cfg(feature = "pci")]
+pub mod vsock;
 
 /// A common error module for drivers.
 /// [DriverError](enums.drivererror.html) values will be
@@ -12,6 +19,8 @@ pub mod virtio;
 pub mod error {
 	use core::fmt;
 
+	#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+	use crate::drivers::net::e1000::E1000Error;
 	#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
 	use crate::drivers::net::rtl8139::RTL8139Error;
 	use crate::drivers::virtio::error::VirtioError;
@@ -21,6 +30,8 @@ pub mod error {
 		InitVirtioDevFail(VirtioError),
 		#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
 		InitRTL8139DevFail(RTL8139Error),
+		#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+		InitE1000DevFail(E1000Error),
 	}
 
 	impl From<VirtioError> for DriverError {
@@ -36,6 +47,13 @@ pub mod error {
 		}
 	}
 
+	#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+	impl From<E1000Error> for DriverError {
+		fn from(err: E1000Error) -> Self {
+			DriverError::InitE1000DevFail(err)
+		}
+	}
+
 	impl fmt::Display for DriverError {
 		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 			match *self {
@@ -46,6 +64,10 @@ pub mod error {
 				DriverError::InitRTL8139DevFail(ref err) => {
 					write!(f, "RTL8139 driver failed: {err:?}")
 				}
+				#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+				DriverError::InitE1000DevFail(ref err) => {
+					write!(f, "e1000 driver failed: {err:?}")
+				}
 			}
 		}
 	}
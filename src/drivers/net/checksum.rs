@@ -0,0 +1,305 @@
+//! Helper functions to compute and verify the checksums of TCP and UDP packets,
+//! which are embedded in an Ethernet frame.
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPV6_HEADER_LEN: usize = 40;
+
+pub(super) const IP_PROTO_TCP: u8 = 6;
+pub(super) const IP_PROTO_UDP: u8 = 17;
+
+/// Location of the TCP or UDP segment inside an Ethernet frame.
+pub(super) struct Transport {
+	/// Offset of the transport header from the start of the frame
+	pub start: usize,
+	/// Length of the transport header and its payload
+	pub len: usize,
+	pub protocol: u8,
+	pub is_ipv6: bool,
+	/// Unfolded sum of the pseudo header
+	pub pseudo_sum: u32,
+}
+
+impl Transport {
+	/// Locates the TCP or UDP segment of an IPv4 or IPv6 packet.
+	///
+	/// Returns `None` for other protocols, for IPv4 fragments, for IPv6
+	/// extension headers and for truncated frames.
+	pub fn parse(frame: &[u8]) -> Option<Self> {
+		let ethertype = u16::from_be_bytes(frame.get(12..14)?.try_into().unwrap());
+		let ip = frame.get(ETHERNET_HEADER_LEN..)?;
+
+		let (ip_header_len, len, protocol, is_ipv6, addrs) = match ethertype {
+			ETHERTYPE_IPV4 => {
+				let ip_header_len = usize::from(ip.first()? & 0xf) * 4;
+				let total_len = usize::from(u16::from_be_bytes(ip.get(2..4)?.try_into().unwrap()));
+				let fragment = u16::from_be_bytes(ip.get(6..8)?.try_into().unwrap()) & 0x3fff;
+				if ip_header_len < 20 || total_len < ip_header_len || fragment != 0 {
+					return None;
+				}
+
+				(
+					ip_header_len,
+					total_len - ip_header_len,
+					*ip.get(9)?,
+					false,
+					ip.get(12..20)?,
+				)
+			}
+			ETHERTYPE_IPV6 => {
+				let payload_len =
+					usize::from(u16::from_be_bytes(ip.get(4..6)?.try_into().unwrap()));
+
+				(
+					IPV6_HEADER_LEN,
+					payload_len,
+					*ip.get(6)?,
+					true,
+					ip.get(8..40)?,
+				)
+			}
+			_ => return None,
+		};
+
+		let min_len = match protocol {
+			IP_PROTO_TCP => 20,
+			IP_PROTO_UDP => 8,
+			_ => return None,
+		};
+		let start = ETHERNET_HEADER_LEN + ip_header_len;
+		if len < min_len || frame.len() < start + len {
+			return None;
+		}
+
+		Some(Self {
+			start,
+			len,
+			protocol,
+			is_ipv6,
+			pseudo_sum: sum(addrs) + u32::from(protocol) + u32::try_from(len).unwrap(),
+		})
+	}
+
+	/// Returns the offset of the checksum field from the start of the frame.
+	pub fn checksum_offset(&self) -> usize {
+		match self.protocol {
+			IP_PROTO_TCP => 16,
+			_ => 6,
+		}
+	}
+
+	/// Verifies the checksum of the segment.
+	pub fn verify(&self, frame: &[u8]) -> bool {
+		let segment = &frame[self.start..self.start + self.len];
+
+		// Senders may omit the checksum of UDP datagrams over IPv4.
+		if self.protocol == IP_PROTO_UDP && !self.is_ipv6 && segment[6..8] == [0, 0] {
+			return true;
+		}
+
+		fold(self.pseudo_sum + sum(segment)) == 0xffff
+	}
+
+	/// Computes the checksum of the segment and stores it in the frame.
+	#[cfg(feature = "tcp")]
+	pub fn fill(&self, frame: &mut [u8]) {
+		let pos = self.start + self.checksum_offset();
+		frame[pos..pos + 2].fill(0);
+
+		let mut checksum = !fold(self.pseudo_sum + sum(&frame[self.start..self.start + self.len]));
+		// A zero checksum of UDP would mean, that the checksum has been omitted.
+		if checksum == 0 && self.protocol == IP_PROTO_UDP {
+			checksum = 0xffff;
+		}
+		frame[pos..pos + 2].copy_from_slice(&checksum.to_be_bytes());
+	}
+}
+
+/// Computes the checksum of a TCP or UDP packet, whose checksum has been left
+/// to the device, but which is delivered locally without passing the device.
+#[cfg(feature = "tcp")]
+pub(crate) fn fill_checksum(frame: &mut [u8]) {
+	if let Some(transport) = Transport::parse(frame) {
+		transport.fill(frame);
+	}
+}
+
+/// Sums up the 16 bit words of `data`, which are in network byte order.
+fn sum(data: &[u8]) -> u32 {
+	let mut chunks = data.chunks_exact(2);
+	let mut sum = chunks
+		.by_ref()
+		.map(|chunk| u32::from(u16::from_be_bytes([chunk[0], chunk[1]])))
+		.sum::<u32>();
+	if let [last] = chunks.remainder() {
+		sum += u32::from(*last) << 8;
+	}
+
+	sum
+}
+
+/// Folds a sum into the 16 bit one's complement sum.
+pub(super) fn fold(mut sum: u32) -> u16 {
+	while sum > 0xffff {
+		sum = (sum & 0xffff) + (sum >> 16);
+	}
+
+	sum as u16
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use alloc::vec::Vec;
+
+	use super::*;
+
+	/// Builds an Ethernet frame with an IPv4 header and the given transport segment.
+	fn ipv4_frame(protocol: u8, segment: &[u8]) -> Vec<u8> {
+		let mut frame = vec![0; ETHERNET_HEADER_LEN];
+		frame[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+		let total_len = u16::try_from(20 + segment.len()).unwrap();
+		let mut ip = [0u8; 20];
+		ip[0] = 0x45;
+		ip[2..4].copy_from_slice(&total_len.to_be_bytes());
+		ip[8] = 64;
+		ip[9] = protocol;
+		ip[12..16].copy_from_slice(&[10, 0, 0, 1]);
+		ip[16..20].copy_from_slice(&[10, 0, 0, 2]);
+		frame.extend_from_slice(&ip);
+		frame.extend_from_slice(segment);
+
+		frame
+	}
+
+	/// Builds an Ethernet frame with an IPv6 header and the given transport segment.
+	fn ipv6_frame(protocol: u8, segment: &[u8]) -> Vec<u8> {
+		let mut frame = vec![0; ETHERNET_HEADER_LEN];
+		frame[12..14].copy_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+
+		let payload_len = u16::try_from(segment.len()).unwrap();
+		let mut ip = [0u8; IPV6_HEADER_LEN];
+		ip[0] = 0x60;
+		ip[4..6].copy_from_slice(&payload_len.to_be_bytes());
+		ip[6] = protocol;
+		ip[7] = 64;
+		ip[8..10].copy_from_slice(&[0xfe, 0x80]);
+		ip[23] = 1;
+		ip[24..26].copy_from_slice(&[0xfe, 0x80]);
+		ip[39] = 2;
+		frame.extend_from_slice(&ip);
+		frame.extend_from_slice(segment);
+
+		frame
+	}
+
+	/// UDP header from port 1234 to port 5678 with an odd sized payload
+	fn udp_segment() -> Vec<u8> {
+		let mut segment = vec![0x04, 0xd2, 0x16, 0x2e, 0x00, 0x0d, 0x00, 0x00];
+		segment.extend_from_slice(b"hello");
+		segment
+	}
+
+	/// TCP header from port 1234 to port 5678 without options and with a payload
+	fn tcp_segment() -> Vec<u8> {
+		let mut segment = vec![0; 20];
+		segment[0..4].copy_from_slice(&[0x04, 0xd2, 0x16, 0x2e]);
+		segment[12] = 5 << 4;
+		segment[13] = 0x18;
+		segment.extend_from_slice(b"hermit");
+		segment
+	}
+
+	#[test]
+	fn sum_and_fold() {
+		// Example of RFC 1071 - 3
+		let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+		assert_eq!(sum(&data), 0x2ddf0);
+		assert_eq!(fold(sum(&data)), 0xddf2);
+
+		// A trailing byte is padded with zero.
+		assert_eq!(sum(&[0x01, 0x02, 0x03]), 0x0402);
+		assert_eq!(fold(0xffff), 0xffff);
+		assert_eq!(fold(0x1_fffe), 0xffff);
+	}
+
+	#[test]
+	fn parse_udp_ipv4() {
+		let frame = ipv4_frame(IP_PROTO_UDP, &udp_segment());
+		let transport = Transport::parse(&frame).unwrap();
+
+		assert_eq!(transport.start, ETHERNET_HEADER_LEN + 20);
+		assert_eq!(transport.len, 13);
+		assert_eq!(transport.protocol, IP_PROTO_UDP);
+		assert!(!transport.is_ipv6);
+		assert_eq!(transport.checksum_offset(), 6);
+	}
+
+	#[test]
+	fn parse_tcp_ipv6() {
+		let frame = ipv6_frame(IP_PROTO_TCP, &tcp_segment());
+		let transport = Transport::parse(&frame).unwrap();
+
+		assert_eq!(transport.start, ETHERNET_HEADER_LEN + IPV6_HEADER_LEN);
+		assert_eq!(transport.len, 26);
+		assert_eq!(transport.protocol, IP_PROTO_TCP);
+		assert!(transport.is_ipv6);
+		assert_eq!(transport.checksum_offset(), 16);
+	}
+
+	#[test]
+	fn parse_rejects_unsupported_frames() {
+		// ICMP
+		assert!(Transport::parse(&ipv4_frame(1, &udp_segment())).is_none());
+
+		// IPv4 fragment
+		let mut frame = ipv4_frame(IP_PROTO_UDP, &udp_segment());
+		frame[ETHERNET_HEADER_LEN + 6] = 0x20;
+		assert!(Transport::parse(&frame).is_none());
+
+		// Truncated segment
+		let frame = ipv4_frame(IP_PROTO_TCP, &tcp_segment());
+		assert!(Transport::parse(&frame[..frame.len() - 1]).is_none());
+
+		// Segment, which is shorter than its header
+		assert!(Transport::parse(&ipv6_frame(IP_PROTO_TCP, &[0; 8])).is_none());
+
+		// ARP
+		let mut frame = ipv4_frame(IP_PROTO_UDP, &udp_segment());
+		frame[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+		assert!(Transport::parse(&frame).is_none());
+	}
+
+	#[cfg(feature = "tcp")]
+	#[test]
+	fn fill_and_verify() {
+		for mut frame in [
+			ipv4_frame(IP_PROTO_UDP, &udp_segment()),
+			ipv4_frame(IP_PROTO_TCP, &tcp_segment()),
+			ipv6_frame(IP_PROTO_UDP, &udp_segment()),
+			ipv6_frame(IP_PROTO_TCP, &tcp_segment()),
+		] {
+			let transport = Transport::parse(&frame).unwrap();
+			let pos = transport.start + transport.checksum_offset();
+
+			fill_checksum(&mut frame);
+			assert_ne!(frame[pos..pos + 2], [0, 0]);
+			assert!(transport.verify(&frame));
+
+			// A corrupted payload is detected.
+			*frame.last_mut().unwrap() ^= 0x01;
+			assert!(!transport.verify(&frame));
+		}
+	}
+
+	#[test]
+	fn omitted_udp_checksum() {
+		let frame = ipv4_frame(IP_PROTO_UDP, &udp_segment());
+		assert!(Transport::parse(&frame).unwrap().verify(&frame));
+
+		// IPv6 requires the checksum of UDP.
+		let frame = ipv6_frame(IP_PROTO_UDP, &udp_segment());
+		assert!(!Transport::parse(&frame).unwrap().verify(&frame));
+	}
+}
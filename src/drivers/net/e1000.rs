@@ -0,0 +1,576 @@
+// The driver is based on the Intel 8254x Family of Gigabit Ethernet Controllers
+// Software Developer's Manual and the Intel 82574 GbE Controller Datasheet.
+// Both families provide the legacy descriptors, which are used by this driver.
+
+#![allow(dead_code)]
+
+use alloc::boxed::Box;
+use core::ptr;
+
+use pci_types::{Bar, InterruptLine};
+
+use crate::arch::kernel::core_local::increment_irq_counter;
+use crate::arch::kernel::interrupts::*;
+use crate::arch::mm::paging::virt_to_phys;
+use crate::arch::mm::{PhysAddr, VirtAddr};
+use crate::arch::pci::PciConfigRegion;
+use crate::drivers::error::DriverError;
+use crate::drivers::net::{network_irqhandler, NetworkInterface};
+use crate::drivers::pci::PciDevice;
+
+/// device ids of the supported controllers (82540EM, 82545EM, 82543GC, 82574L)
+pub(crate) const DEVICE_IDS: [u16; 4] = [0x100E, 0x100F, 0x1004, 0x10D3];
+
+/// number of receive descriptors
+const NUM_RX_DESCRIPTORS: usize = 32;
+/// number of transmit descriptors
+const NUM_TX_DESCRIPTORS: usize = 32;
+/// size of each receive buffer
+const RX_BUF_LEN: usize = 2048;
+/// size of each transmit buffer
+const TX_BUF_LEN: usize = 2048;
+
+/// device control register
+const CTRL: usize = 0x0000;
+/// device status register
+const STATUS: usize = 0x0008;
+/// interrupt cause read register (cleared by reading)
+const ICR: usize = 0x00C0;
+/// interrupt mask set/read register
+const IMS: usize = 0x00D0;
+/// interrupt mask clear register
+const IMC: usize = 0x00D8;
+/// receive control register
+const RCTL: usize = 0x0100;
+/// transmit control register
+const TCTL: usize = 0x0400;
+/// transmit inter packet gap register
+const TIPG: usize = 0x0410;
+/// receive descriptor base address low
+const RDBAL: usize = 0x2800;
+/// receive descriptor base address high
+const RDBAH: usize = 0x2804;
+/// receive descriptor length
+const RDLEN: usize = 0x2808;
+/// receive descriptor head
+const RDH: usize = 0x2810;
+/// receive descriptor tail
+const RDT: usize = 0x2818;
+/// transmit descriptor base address low
+const TDBAL: usize = 0x3800;
+/// transmit descriptor base address high
+const TDBAH: usize = 0x3804;
+/// transmit descriptor length
+const TDLEN: usize = 0x3808;
+/// transmit descriptor head
+const TDH: usize = 0x3810;
+/// transmit descriptor tail
+const TDT: usize = 0x3818;
+/// multicast table array (128 registers)
+const MTA: usize = 0x5200;
+/// number of registers in the multicast table array
+const MTA_LEN: usize = 128;
+/// receive address low (first entry)
+const RAL0: usize = 0x5400;
+/// receive address high (first entry)
+const RAH0: usize = 0x5404;
+
+/// Link Reset
+const CTRL_LRST: u32 = 1 << 3;
+/// Auto-Speed Detection Enable
+const CTRL_ASDE: u32 = 1 << 5;
+/// Set Link Up
+const CTRL_SLU: u32 = 1 << 6;
+/// Invert Loss-of-Signal
+const CTRL_ILOS: u32 = 1 << 7;
+/// Device Reset, self-clearing
+const CTRL_RST: u32 = 1 << 26;
+/// PHY Reset
+const CTRL_PHY_RST: u32 = 1 << 31;
+
+/// Link Up indication
+const STATUS_LU: u32 = 1 << 1;
+
+/// Receiver Enable
+const RCTL_EN: u32 = 1 << 1;
+/// Unicast Promiscuous Enabled
+const RCTL_UPE: u32 = 1 << 3;
+/// Multicast Promiscuous Enabled
+const RCTL_MPE: u32 = 1 << 4;
+/// Broadcast Accept Mode
+const RCTL_BAM: u32 = 1 << 15;
+/// Receive Buffer Size of 2048 bytes (with RCTL.BSEX cleared)
+const RCTL_BSIZE_2048: u32 = 0;
+/// Strip Ethernet CRC from incoming packet
+const RCTL_SECRC: u32 = 1 << 26;
+
+/// Transmit Enable
+const TCTL_EN: u32 = 1 << 1;
+/// Pad Short Packets
+const TCTL_PSP: u32 = 1 << 3;
+/// Collision Threshold (recommended value 0x0F)
+const TCTL_CT: u32 = 0x0F << 4;
+/// Collision Distance (recommended value 0x3F for full duplex)
+const TCTL_COLD: u32 = 0x3F << 12;
+
+/// recommended inter packet gap for IEEE 802.3 (IPGT = 10, IPGR1 = 8, IPGR2 = 6)
+const TIPG_DEFAULT: u32 = 10 | (8 << 10) | (6 << 20);
+
+/// Address Valid bit of the receive address high register
+const RAH_AV: u32 = 1 << 31;
+
+/// Transmit Descriptor Written Back
+const ICR_TXDW: u32 = 1 << 0;
+/// Link Status Change
+const ICR_LSC: u32 = 1 << 2;
+/// Receive Descriptor Minimum Threshold Reached
+const ICR_RXDMT0: u32 = 1 << 4;
+/// Receiver Overrun
+const ICR_RXO: u32 = 1 << 6;
+/// Receiver Timer Interrupt
+const ICR_RXT0: u32 = 1 << 7;
+
+/// interrupts, which are signaled by a received packet
+const INT_MASK_RX: u32 = ICR_RXT0 | ICR_RXO | ICR_RXDMT0;
+/// interrupts, which are handled by the driver
+const INT_MASK: u32 = INT_MASK_RX | ICR_LSC;
+
+/// Descriptor Done
+const DESC_STATUS_DD: u8 = 1 << 0;
+/// End of Packet
+const DESC_STATUS_EOP: u8 = 1 << 1;
+
+/// End Of Packet
+const TX_CMD_EOP: u8 = 1 << 0;
+/// Insert FCS
+const TX_CMD_IFCS: u8 = 1 << 1;
+/// Report Status
+const TX_CMD_RS: u8 = 1 << 3;
+
+/// Legacy receive descriptor
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct RxDescriptor {
+	addr: u64,
+	length: u16,
+	checksum: u16,
+	status: u8,
+	errors: u8,
+	special: u16,
+}
+
+/// Legacy transmit descriptor
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct TxDescriptor {
+	addr: u64,
+	length: u16,
+	cso: u8,
+	cmd: u8,
+	status: u8,
+	css: u8,
+	special: u16,
+}
+
+// The rings have to be aligned to 16 bytes. A stronger alignment guarantees
+// that a ring does not cross a page boundary.
+#[repr(C, align(512))]
+struct RxRing([RxDescriptor; NUM_RX_DESCRIPTORS]);
+
+#[repr(C, align(512))]
+struct TxRing([TxDescriptor; NUM_TX_DESCRIPTORS]);
+
+/// Error type of the Intel e1000 driver
+#[derive(Debug)]
+pub enum E1000Error {
+	InitFailed,
+	ResetFailed,
+	NoMacAddress,
+	Unknown,
+}
+
+/// Intel e1000 / e1000e network driver struct.
+///
+/// Struct allows to control device queues as also
+/// the device itself.
+pub(crate) struct E1000Driver {
+	base: VirtAddr,
+	mtu: u16,
+	irq: InterruptLine,
+	mac: [u8; 6],
+	rx_ring: Box<RxRing>,
+	rx_cur: usize,
+	rxbuffer: Box<[u8]>,
+	tx_ring: Box<TxRing>,
+	tx_cur: usize,
+	txbuffer: Box<[u8]>,
+	promiscuous: bool,
+	all_multicast: bool,
+	polling_mode_counter: u32,
+}
+
+impl NetworkInterface for E1000Driver {
+	/// Returns the MAC address of the network interface
+	fn get_mac_address(&self) -> [u8; 6] {
+		self.mac
+	}
+
+	/// Returns the current MTU of the device.
+	fn get_mtu(&self) -> u16 {
+		self.mtu
+	}
+
+	fn get_tx_buffer(&mut self, len: usize) -> Result<(*mut u8, usize), ()> {
+		let id = self.tx_cur;
+		let status = unsafe { ptr::read_volatile(&self.tx_ring.0[id].status) };
+
+		if status & DESC_STATUS_DD != DESC_STATUS_DD || len > TX_BUF_LEN {
+			trace!("Unable to get TX buffer");
+			Err(())
+		} else {
+			Ok((
+				self.txbuffer[id * TX_BUF_LEN..][..TX_BUF_LEN].as_mut_ptr(),
+				id,
+			))
+		}
+	}
+
+	fn free_tx_buffer(&self, _token: usize) {
+		// get_tx_buffer did not allocate
+	}
+
+	fn send_tx_buffer(&mut self, id: usize, len: usize) -> Result<(), ()> {
+		// the descriptors have to be handed over in order
+		if id != self.tx_cur {
+			return Err(());
+		}
+
+		let addr = self.tx_ring.0[id].addr;
+		unsafe {
+			ptr::write_volatile(
+				&mut self.tx_ring.0[id],
+				TxDescriptor {
+					addr,
+					length: len.try_into().unwrap(),
+					cmd: TX_CMD_EOP | TX_CMD_IFCS | TX_CMD_RS,
+					..Default::default()
+				},
+			);
+		}
+
+		self.tx_cur = (id + 1) % NUM_TX_DESCRIPTORS;
+		self.write(TDT, self.tx_cur.try_into().unwrap());
+
+		Ok(())
+	}
+
+	fn has_packet(&self) -> bool {
+		let status = unsafe { ptr::read_volatile(&self.rx_ring.0[self.rx_cur].status) };
+
+		status & DESC_STATUS_DD == DESC_STATUS_DD
+	}
+
+	/// Lends the receive buffer of the next descriptor. The descriptor is given
+	/// back to the device by `release_rx_buffer`, which has to be called in the
+	/// same order as the buffers have been received.
+	fn receive_rx_buffer(&mut self) -> Result<(*mut u8, usize, usize), ()> {
+		loop {
+			let id = self.rx_cur;
+			let desc = unsafe { ptr::read_volatile(&self.rx_ring.0[id]) };
+
+			if desc.status & DESC_STATUS_DD != DESC_STATUS_DD {
+				return Err(());
+			}
+
+			self.rx_cur = (id + 1) % NUM_RX_DESCRIPTORS;
+
+			// As the receive buffers are large enough for a complete frame,
+			// a frame without EOP is a frame, which exceeds the MTU.
+			if desc.errors != 0 || desc.status & DESC_STATUS_EOP != DESC_STATUS_EOP {
+				warn!(
+					"e1000: drop invalid packet (status {:#x}, errors {:#x})",
+					desc.status, desc.errors
+				);
+				self.release_rx_buffer(id);
+				continue;
+			}
+
+			return Ok((
+				self.rxbuffer[id * RX_BUF_LEN..][..RX_BUF_LEN].as_mut_ptr(),
+				desc.length.into(),
+				id,
+			));
+		}
+	}
+
+	fn release_rx_buffer(&mut self, handle: usize) {
+		unsafe {
+			ptr::write_volatile(&mut self.rx_ring.0[handle].status, 0);
+		}
+
+		// The descriptor at the tail is kept by the driver and marks the end of
+		// the ring. Moving the tail to the released descriptor hands the previous
+		// one over to the device.
+		self.write(RDT, handle.try_into().unwrap());
+	}
+
+	fn set_promiscuous(&mut self, value: bool) -> Result<(), ()> {
+		self.promiscuous = value;
+		self.update_rx_mode();
+		Ok(())
+	}
+
+	fn set_all_multicast(&mut self, value: bool) -> Result<(), ()> {
+		self.all_multicast = value;
+		self.update_rx_mode();
+		Ok(())
+	}
+
+	fn set_multicast_filter(&mut self, addrs: &[[u8; 6]]) -> Result<(), ()> {
+		let mut table = [0u32; MTA_LEN];
+
+		for addr in addrs {
+			// With RCTL.MO = 00b the hash consists of the bits [47:36] of the address.
+			let hash = (usize::from(addr[4]) >> 4 | usize::from(addr[5]) << 4) & 0xFFF;
+			table[hash >> 5] |= 1 << (hash & 0x1F);
+		}
+
+		for (i, value) in table.iter().enumerate() {
+			self.write(MTA + 4 * i, *value);
+		}
+
+		Ok(())
+	}
+
+	fn is_link_up(&self) -> bool {
+		self.read(STATUS) & STATUS_LU == STATUS_LU
+	}
+
+	fn set_polling_mode(&mut self, value: bool) {
+		if value {
+			if self.polling_mode_counter == 0 {
+				// disable receive interrupts from the NIC
+				self.write(IMC, INT_MASK_RX);
+			}
+			self.polling_mode_counter += 1;
+		} else {
+			self.polling_mode_counter -= 1;
+			if self.polling_mode_counter == 0 {
+				// Enable all known interrupts by setting the interrupt mask.
+				self.write(IMS, INT_MASK);
+			}
+		}
+	}
+
+	fn handle_interrupt(&mut self) -> bool {
+		increment_irq_counter(32 + self.irq);
+
+		// reading the register acknowledges the interrupts
+		let icr = self.read(ICR);
+
+		if icr & ICR_LSC == ICR_LSC {
+			info!(
+				"e1000: link is {}",
+				if self.is_link_up() { "up" } else { "down" }
+			);
+		}
+
+		if icr & ICR_RXO == ICR_RXO {
+			trace!("e1000: RX overrun detected!");
+		}
+
+		icr & INT_MASK != 0
+	}
+}
+
+impl E1000Driver {
+	fn read(&self, register: usize) -> u32 {
+		unsafe { ptr::read_volatile((self.base + register).as_ptr::<u32>()) }
+	}
+
+	fn write(&self, register: usize, value: u32) {
+		unsafe { ptr::write_volatile((self.base + register).as_mut_ptr::<u32>(), value) }
+	}
+
+	fn update_rx_mode(&self) {
+		let mut rctl = self.read(RCTL) & !(RCTL_UPE | RCTL_MPE);
+		if self.promiscuous {
+			rctl |= RCTL_UPE | RCTL_MPE;
+		} else if self.all_multicast {
+			rctl |= RCTL_MPE;
+		}
+		self.write(RCTL, rctl);
+	}
+
+	fn reset(&self) -> Result<(), E1000Error> {
+		// mask all interrupts and clear pending ones
+		self.write(IMC, u32::MAX);
+		self.read(ICR);
+
+		self.write(CTRL, self.read(CTRL) | CTRL_RST);
+
+		// The RST bit is cleared by the device after the reset has been completed.
+		crate::arch::kernel::processor::udelay(1000);
+		let mut tmp: u16 = 10000;
+		while self.read(CTRL) & CTRL_RST == CTRL_RST && tmp > 0 {
+			crate::arch::kernel::processor::udelay(10);
+			tmp -= 1;
+		}
+
+		if tmp == 0 {
+			return Err(E1000Error::ResetFailed);
+		}
+
+		// clear the interrupts, which have been raised during the reset
+		self.read(ICR);
+
+		Ok(())
+	}
+}
+
+impl Drop for E1000Driver {
+	fn drop(&mut self) {
+		debug!("Dropping E1000Driver!");
+
+		// Stop the DMA engines before the rings are freed
+		let _ = self.reset();
+	}
+}
+
+pub(crate) fn init_device(device: &PciDevice<PciConfigRegion>) -> Result<E1000Driver, DriverError> {
+	let irq = device.irq().unwrap();
+
+	// The registers are located in the first bar, which is a non-prefetchable
+	// memory bar. Consequently, the registers have to be mapped uncached.
+	let (address, size) = match device.bar(0) {
+		Some(Bar::Memory32 { address, size, .. }) => {
+			(u64::from(address), usize::try_from(size).unwrap())
+		}
+		Some(Bar::Memory64 { address, size, .. }) => (address, usize::try_from(size).unwrap()),
+		_ => {
+			error!("e1000: unable to find the memory bar");
+			return Err(DriverError::InitE1000DevFail(E1000Error::Unknown));
+		}
+	};
+
+	debug!(
+		"Found e1000 at {:#x} (size {:#x}, irq {})",
+		address, size, irq
+	);
+
+	device.make_bus_master();
+
+	let base = crate::mm::map(PhysAddr::from(address), size, true, true, true);
+
+	let mut drv = E1000Driver {
+		base,
+		mtu: 1500,
+		irq,
+		mac: [0; 6],
+		rx_ring: Box::new(RxRing([RxDescriptor::default(); NUM_RX_DESCRIPTORS])),
+		rx_cur: 0,
+		rxbuffer: vec![0; NUM_RX_DESCRIPTORS * RX_BUF_LEN].into_boxed_slice(),
+		tx_ring: Box::new(TxRing([TxDescriptor::default(); NUM_TX_DESCRIPTORS])),
+		tx_cur: 0,
+		txbuffer: vec![0; NUM_TX_DESCRIPTORS * TX_BUF_LEN].into_boxed_slice(),
+		promiscuous: false,
+		all_multicast: false,
+		polling_mode_counter: 0,
+	};
+
+	if drv.read(STATUS) == u32::MAX {
+		error!("Unable to initialize e1000");
+		return Err(DriverError::InitE1000DevFail(E1000Error::InitFailed));
+	}
+
+	if let Err(err) = drv.reset() {
+		error!("e1000 reset failed");
+		return Err(DriverError::InitE1000DevFail(err));
+	}
+
+	// set the link up and let the device detect the speed
+	drv.write(
+		CTRL,
+		(drv.read(CTRL) | CTRL_SLU | CTRL_ASDE) & !(CTRL_LRST | CTRL_PHY_RST | CTRL_ILOS),
+	);
+
+	// After a reset, the device loads the MAC address from the NVM
+	// into the first receive address registers.
+	let ral = drv.read(RAL0);
+	let rah = drv.read(RAH0);
+	if rah & RAH_AV != RAH_AV {
+		error!("e1000: unable to determine the MAC address");
+		return Err(DriverError::InitE1000DevFail(E1000Error::NoMacAddress));
+	}
+	let [mac0, mac1, mac2, mac3] = ral.to_le_bytes();
+	let [mac4, mac5, _, _] = rah.to_le_bytes();
+	drv.mac = [mac0, mac1, mac2, mac3, mac4, mac5];
+
+	debug!(
+		"MAC address {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+		drv.mac[0], drv.mac[1], drv.mac[2], drv.mac[3], drv.mac[4], drv.mac[5]
+	);
+
+	// clear the multicast table array
+	for i in 0..MTA_LEN {
+		drv.write(MTA + 4 * i, 0);
+	}
+
+	let phys_addr = |p: *const u8| virt_to_phys(VirtAddr::from_usize(p as _)).as_u64();
+
+	for (i, desc) in drv.rx_ring.0.iter_mut().enumerate() {
+		desc.addr = phys_addr(drv.rxbuffer[i * RX_BUF_LEN..].as_ptr());
+	}
+	for (i, desc) in drv.tx_ring.0.iter_mut().enumerate() {
+		desc.addr = phys_addr(drv.txbuffer[i * TX_BUF_LEN..].as_ptr());
+		// all transmit descriptors are free
+		desc.status = DESC_STATUS_DD;
+	}
+
+	debug!(
+		"Allocate TxRing at {:p} and RxRing at {:p}",
+		drv.tx_ring, drv.rx_ring
+	);
+
+	// register the receive ring and hand over all descriptors except the
+	// one at the tail
+	let rx_ring = phys_addr(drv.rx_ring.0.as_ptr().cast());
+	drv.write(RDBAL, rx_ring as u32);
+	drv.write(RDBAH, (rx_ring >> 32) as u32);
+	drv.write(RDLEN, core::mem::size_of::<RxRing>().try_into().unwrap());
+	drv.write(RDH, 0);
+	drv.write(RDT, (NUM_RX_DESCRIPTORS - 1).try_into().unwrap());
+
+	// register the transmit ring, which is initially empty
+	let tx_ring = phys_addr(drv.tx_ring.0.as_ptr().cast());
+	drv.write(TDBAL, tx_ring as u32);
+	drv.write(TDBAH, (tx_ring >> 32) as u32);
+	drv.write(TDLEN, core::mem::size_of::<TxRing>().try_into().unwrap());
+	drv.write(TDH, 0);
+	drv.write(TDT, 0);
+
+	// configure the receiver
+	// BAM - Broadcast Accept Mode: Accept broadcast packets
+	// SECRC - Strip Ethernet CRC: The CRC isn't part of the received packets
+	drv.write(RCTL, RCTL_EN | RCTL_BAM | RCTL_BSIZE_2048 | RCTL_SECRC);
+
+	// configure the transmitter
+	drv.write(TIPG, TIPG_DEFAULT);
+	drv.write(TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+
+	// Install interrupt handler for e1000
+	debug!("Install interrupt handler for e1000 at {}", irq);
+	irq_install_handler(irq, network_irqhandler);
+	add_irq_name(irq, "e1000_net");
+
+	// Enable all known interrupts by setting the interrupt mask.
+	drv.write(IMS, INT_MASK);
+
+	info!(
+		"e1000: STATUS = {:#x}, link is {}",
+		drv.read(STATUS),
+		if drv.is_link_up() { "up" } else { "down" }
+	);
+
+	Ok(drv)
+}
@@ -1,3 +1,6 @@
+pub(crate) mod checksum;
+#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+pub mod e1000;
 #[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
 pub mod rtl8139;
 #[cfg(not(feature = "pci"))]
@@ -6,7 +9,8 @@ pub mod virtio_net;
 #[cfg(feature = "pci")]
 pub mod virtio_pci;
 
-use alloc::vec::Vec;
+#[cfg(feature = "tcp")]
+use smoltcp::phy::ChecksumCapabilities;
 
 #[cfg(target_arch = "x86_64")]
 use crate::arch::kernel::apic;
@@ -37,21 +41,63 @@ pub trait NetworkInterface {
 	/// Check if a packet is available
 	fn has_packet(&self) -> bool;
 	/// Get RX buffer with an received packet
-	fn receive_rx_buffer(&mut self) -> Result<Vec<u8>, ()>;
+	///
+	/// This lends the RX buffer and returns a pointer to the packet, its length
+	/// and a handle, which gives the buffer back to the device.
+	fn receive_rx_buffer(&mut self) -> Result<(*mut u8, usize, usize), ()>;
+	/// Releases the RX buffer (takes ownership)
+	fn release_rx_buffer(&mut self, handle: usize);
+	/// Enable / disable the reception of all packets
+	fn set_promiscuous(&mut self, _value: bool) -> Result<(), ()> {
+		Err(())
+	}
+	/// Enable / disable the reception of all multicast packets
+	fn set_all_multicast(&mut self, _value: bool) -> Result<(), ()> {
+		Err(())
+	}
+	/// Set the multicast addresses, whose packets are received
+	fn set_multicast_filter(&mut self, _addrs: &[[u8; 6]]) -> Result<(), ()> {
+		Err(())
+	}
+	/// Add a VLAN id to the filter of the device
+	fn add_vlan(&mut self, _vid: u16) -> Result<(), ()> {
+		Err(())
+	}
+	/// Remove a VLAN id from the filter of the device
+	fn remove_vlan(&mut self, _vid: u16) -> Result<(), ()> {
+		Err(())
+	}
+	/// Check if the link of the device is up
+	fn is_link_up(&self) -> bool {
+		true
+	}
+	/// Check if the device asks to announce the addresses of the interface
+	fn is_announce(&self) -> bool {
+		false
+	}
+	/// Acknowledge an announce request, after the addresses have been announced
+	fn ack_announce(&mut self) {}
 	/// Enable / disable the polling mode of the network interface
 	fn set_polling_mode(&mut self, value: bool);
+	/// Returns the checksums, which the network stack has to compute and verify.
+	///
+	/// By default, the device does not offload any checksums.
+	#[cfg(feature = "tcp")]
+	fn get_checksums(&self) -> ChecksumCapabilities {
+		ChecksumCapabilities::default()
+	}
 	/// Handle interrupt and check if a packet is available
 	fn handle_interrupt(&mut self) -> bool;
 }
 
 #[inline]
 fn _irqhandler() {
-	let has_packet = if let Some(driver) = hardware::get_network_driver() {
-		driver.lock().handle_interrupt()
-	} else {
-		debug!("Unable to handle interrupt!");
-		false
-	};
+	// The network devices may share the interrupt line, such that
+	// every device has to check, if it has raised the interrupt.
+	let mut has_packet = false;
+	for driver in hardware::get_network_drivers() {
+		has_packet |= driver.lock().handle_interrupt();
+	}
 
 	if has_packet {
 		let core_scheduler = core_scheduler();
@@ -232,9 +232,6 @@ impl NetworkInterface for RTL8139Driver {
 			trace!("Unable to get TX buffer");
 			Err(())
 		} else {
-			self.tx_in_use[id] = true;
-			self.tx_counter += 1;
-
 			Ok((
 				self.txbuffer[id * TX_BUF_LEN..][..TX_BUF_LEN].as_mut_ptr(),
 				id,
@@ -243,10 +240,15 @@ impl NetworkInterface for RTL8139Driver {
 	}
 
 	fn free_tx_buffer(&self, _token: usize) {
-		// get_tx_buffer did not allocate
+		// get_tx_buffer did not allocate nor reserve the buffer
 	}
 
 	fn send_tx_buffer(&mut self, id: usize, len: usize) -> Result<(), ()> {
+		// The descriptors are used round-robin, such that the buffer is
+		// reserved not until it is sent.
+		self.tx_in_use[id] = true;
+		self.tx_counter += 1;
+
 		// send the packet
 		unsafe {
 			outl(
@@ -272,7 +274,7 @@ impl NetworkInterface for RTL8139Driver {
 		false
 	}
 
-	fn receive_rx_buffer(&mut self) -> Result<Vec<u8>, ()> {
+	fn receive_rx_buffer(&mut self) -> Result<(*mut u8, usize, usize), ()> {
 		let cmd = unsafe { inb(self.iobase + CR) };
 
 		if (cmd & CR_BUFE) != CR_BUFE {
@@ -295,7 +297,10 @@ impl NetworkInterface for RTL8139Driver {
 
 				self.consume_current_buffer();
 
-				Ok(buf)
+				// The packet has been copied out of the ring, so that the handle owns the copy.
+				let mut buf = Box::new(buf);
+				let (buf_ptr, len) = (buf.as_mut_ptr(), buf.len());
+				Ok((buf_ptr, len, Box::into_raw(buf) as usize))
 			} else {
 				error!(
 					"RTL8192: invalid header {:#x}, rx_pos {}\n",
@@ -309,6 +314,10 @@ impl NetworkInterface for RTL8139Driver {
 		}
 	}
 
+	fn release_rx_buffer(&mut self, handle: usize) {
+		unsafe { drop(Box::from_raw(handle as *mut Vec<u8>)) }
+	}
+
 	fn set_polling_mode(&mut self, value: bool) {
 		if value {
 			if self.polling_mode_counter == 0 {
@@ -12,17 +12,25 @@ use core::mem;
 use core::result::Result;
 
 use pci_types::InterruptLine;
+#[cfg(feature = "tcp")]
+use smoltcp::phy::{Checksum, ChecksumCapabilities};
 use zerocopy::AsBytes;
 
-use self::constants::{FeatureSet, Features, NetHdrGSO, Status, MAX_NUM_VQ};
+use self::constants::{FeatureSet, Features, NetHdrFlag, NetHdrGSO, Status, MAX_NUM_VQ};
 use self::error::VirtioNetError;
 use crate::arch::kernel::core_local::increment_irq_counter;
+#[cfg(all(feature = "pci", target_arch = "x86_64"))]
+use crate::arch::pci::PciConfigRegion;
 use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::net::checksum::{self, Transport, IP_PROTO_TCP};
 #[cfg(not(feature = "pci"))]
 use crate::drivers::net::virtio_mmio::NetDevCfgRaw;
 #[cfg(feature = "pci")]
 use crate::drivers::net::virtio_pci::NetDevCfgRaw;
 use crate::drivers::net::NetworkInterface;
+#[cfg(all(feature = "pci", target_arch = "x86_64"))]
+use crate::drivers::pci::MsixTable;
+use crate::drivers::virtio::features::negotiate_features;
 #[cfg(not(feature = "pci"))]
 use crate::drivers::virtio::transport::mmio::{ComCfg, IsrStatus, NotifCfg};
 #[cfg(feature = "pci")]
@@ -33,6 +41,10 @@ use crate::drivers::virtio::virtqueue::{
 
 pub const ETH_HDR: usize = 14usize;
 
+/// Number of receive buffers per queue, if the device may merge TCP segments.
+/// As each of these buffers has to hold 64 KiB, the queues are not filled completely.
+const NUM_LARGE_RX_BUFFERS: u16 = 64;
+
 /// A wrapper struct for the raw configuration structure.
 /// Handling the right access to fields, as some are read-only
 /// for the driver.
@@ -73,18 +85,6 @@ impl VirtioNetHdr {
 			num_buffers: 0,
 		}
 	}
-
-	pub fn get_rx_hdr() -> VirtioNetHdr {
-		VirtioNetHdr {
-			flags: 0,
-			gso_type: 0,
-			hdr_len: 0,
-			gso_size: 0,
-			csum_start: 0,
-			csum_offset: 0,
-			num_buffers: 0,
-		}
-	}
 }
 
 pub struct CtrlQueue(Option<Rc<Virtq>>);
@@ -93,27 +93,60 @@ impl CtrlQueue {
 	pub fn new(vq: Option<Rc<Virtq>>) -> Self {
 		CtrlQueue(vq)
 	}
+
+	/// Sends a command of the given class to the device and waits until the
+	/// device has acknowledged it.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5
+	fn send(&self, class: CtrlClass, cmd: u8, data: &[u8]) -> Result<(), VirtioNetError> {
+		let vq = self.0.as_ref().ok_or(VirtioNetError::General)?;
+
+		let mut cmd_buf = Vec::with_capacity(2 + data.len());
+		cmd_buf.push(u8::from(class));
+		cmd_buf.push(cmd);
+		cmd_buf.extend_from_slice(data);
+
+		let send_spec = BuffSpec::Single(Bytes::new(cmd_buf.len()).unwrap());
+		let recv_spec = BuffSpec::Single(Bytes::new(mem::size_of::<u8>()).unwrap());
+
+		let transfer = vq
+			.prep_buffer(Rc::clone(vq), Some(send_spec), Some(recv_spec))
+			.and_then(|tkn| tkn.write(Some(cmd_buf.as_slice()), None::<&[u8]>))
+			.and_then(|tkn| tkn.dispatch_blocking())
+			.map_err(|_| VirtioNetError::General)?;
+
+		let (_, ack) = transfer.ret_cpy().map_err(|_| VirtioNetError::General)?;
+		transfer.close();
+
+		match ack.as_deref() {
+			Some([VIRTIO_NET_OK]) => Ok(()),
+			_ => Err(VirtioNetError::FailCtrlCmd(u8::from(class), cmd)),
+		}
+	}
 }
 
+/// Acknowledgement of a command, which has been executed successfully by the device.
+const VIRTIO_NET_OK: u8 = 0;
+
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum CtrlClass {
-	VIRTIO_NET_CTRL_RX = 1 << 0,
-	VIRTIO_NET_CTRL_MAC = 1 << 1,
-	VIRTIO_NET_CTRL_VLAN = 1 << 2,
-	VIRTIO_NET_CTRL_ANNOUNCE = 1 << 3,
-	VIRTIO_NET_CTRL_MQ = 1 << 4,
+	VIRTIO_NET_CTRL_RX = 0,
+	VIRTIO_NET_CTRL_MAC = 1,
+	VIRTIO_NET_CTRL_VLAN = 2,
+	VIRTIO_NET_CTRL_ANNOUNCE = 3,
+	VIRTIO_NET_CTRL_MQ = 4,
 }
 
 impl From<CtrlClass> for u8 {
 	fn from(val: CtrlClass) -> Self {
 		match val {
-			CtrlClass::VIRTIO_NET_CTRL_RX => 1 << 0,
-			CtrlClass::VIRTIO_NET_CTRL_MAC => 1 << 1,
-			CtrlClass::VIRTIO_NET_CTRL_VLAN => 1 << 2,
-			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE => 1 << 3,
-			CtrlClass::VIRTIO_NET_CTRL_MQ => 1 << 4,
+			CtrlClass::VIRTIO_NET_CTRL_RX => 0,
+			CtrlClass::VIRTIO_NET_CTRL_MAC => 1,
+			CtrlClass::VIRTIO_NET_CTRL_VLAN => 2,
+			CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE => 3,
+			CtrlClass::VIRTIO_NET_CTRL_MQ => 4,
 		}
 	}
 }
@@ -122,61 +155,107 @@ impl From<CtrlClass> for u8 {
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum RxCmd {
-	VIRTIO_NET_CTRL_RX_PROMISC = 1 << 0,
-	VIRTIO_NET_CTRL_RX_ALLMULTI = 1 << 1,
-	VIRTIO_NET_CTRL_RX_ALLUNI = 1 << 2,
-	VIRTIO_NET_CTRL_RX_NOMULTI = 1 << 3,
-	VIRTIO_NET_CTRL_RX_NOUNI = 1 << 4,
-	VIRTIO_NET_CTRL_RX_NOBCAST = 1 << 5,
+	VIRTIO_NET_CTRL_RX_PROMISC = 0,
+	VIRTIO_NET_CTRL_RX_ALLMULTI = 1,
+	VIRTIO_NET_CTRL_RX_ALLUNI = 2,
+	VIRTIO_NET_CTRL_RX_NOMULTI = 3,
+	VIRTIO_NET_CTRL_RX_NOUNI = 4,
+	VIRTIO_NET_CTRL_RX_NOBCAST = 5,
+}
+
+impl From<RxCmd> for u8 {
+	fn from(val: RxCmd) -> Self {
+		match val {
+			RxCmd::VIRTIO_NET_CTRL_RX_PROMISC => 0,
+			RxCmd::VIRTIO_NET_CTRL_RX_ALLMULTI => 1,
+			RxCmd::VIRTIO_NET_CTRL_RX_ALLUNI => 2,
+			RxCmd::VIRTIO_NET_CTRL_RX_NOMULTI => 3,
+			RxCmd::VIRTIO_NET_CTRL_RX_NOUNI => 4,
+			RxCmd::VIRTIO_NET_CTRL_RX_NOBCAST => 5,
+		}
+	}
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum MacCmd {
-	VIRTIO_NET_CTRL_MAC_TABLE_SET = 1 << 0,
-	VIRTIO_NET_CTRL_MAC_ADDR_SET = 1 << 1,
+	VIRTIO_NET_CTRL_MAC_TABLE_SET = 0,
+	VIRTIO_NET_CTRL_MAC_ADDR_SET = 1,
+}
+
+impl From<MacCmd> for u8 {
+	fn from(val: MacCmd) -> Self {
+		match val {
+			MacCmd::VIRTIO_NET_CTRL_MAC_TABLE_SET => 0,
+			MacCmd::VIRTIO_NET_CTRL_MAC_ADDR_SET => 1,
+		}
+	}
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum VlanCmd {
-	VIRTIO_NET_CTRL_VLAN_ADD = 1 << 0,
-	VIRTIO_NET_CTRL_VLAN_DEL = 1 << 1,
+	VIRTIO_NET_CTRL_VLAN_ADD = 0,
+	VIRTIO_NET_CTRL_VLAN_DEL = 1,
+}
+
+impl From<VlanCmd> for u8 {
+	fn from(val: VlanCmd) -> Self {
+		match val {
+			VlanCmd::VIRTIO_NET_CTRL_VLAN_ADD => 0,
+			VlanCmd::VIRTIO_NET_CTRL_VLAN_DEL => 1,
+		}
+	}
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum AnceCmd {
-	VIRTIO_NET_CTRL_ANNOUNCE_ACK = 1 << 0,
+	VIRTIO_NET_CTRL_ANNOUNCE_ACK = 0,
+}
+
+impl From<AnceCmd> for u8 {
+	fn from(val: AnceCmd) -> Self {
+		match val {
+			AnceCmd::VIRTIO_NET_CTRL_ANNOUNCE_ACK => 0,
+		}
+	}
 }
 
 #[allow(dead_code, non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 enum MqCmd {
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET = 1 << 0,
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN = 1 << 1,
-	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MAX = 0x80,
+	VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET = 0,
+}
+
+/// Checks, if TCP packets of IPv4 and IPv6, which exceed the MTU, can be
+/// segmented by the device.
+fn is_tso_enabled(features: FeatureSet) -> bool {
+	features.is_feature(Features::VIRTIO_NET_F_HOST_TSO4)
+		&& features.is_feature(Features::VIRTIO_NET_F_HOST_TSO6)
 }
 
 pub struct RxQueues {
 	vqs: Vec<Rc<Virtq>>,
-	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
+	/// Finished transfers of each virtqueue. The poll queue at index i
+	/// belongs to the virtqueue at index i.
+	poll_queues: Vec<Rc<RefCell<VecDeque<Transfer>>>>,
 	is_multi: bool,
 }
 
 impl RxQueues {
 	pub fn new(
 		vqs: Vec<Rc<Virtq>>,
-		poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
+		poll_queues: Vec<Rc<RefCell<VecDeque<Transfer>>>>,
 		is_multi: bool,
 	) -> Self {
 		Self {
 			vqs,
-			poll_queue,
+			poll_queues,
 			is_multi,
 		}
 	}
@@ -203,6 +282,7 @@ impl RxQueues {
 		// Safe virtqueue
 		let rc_vq = Rc::new(vq);
 		let vq = &rc_vq;
+		let poll_queue = Rc::new(RefCell::new(VecDeque::new()));
 
 		if dev_cfg
 			.features
@@ -233,7 +313,7 @@ impl RxQueues {
 				BuffSpec::Single(Bytes::new(mem::size_of::<VirtioNetHdr>() + 65550).unwrap())
 			};
 
-			let num_buff: u16 = vq.size().into();
+			let num_buff = u16::from(vq.size()).min(NUM_LARGE_RX_BUFFERS);
 
 			for _ in 0..num_buff {
 				let buff_tkn = match vq.prep_buffer(Rc::clone(vq), None, Some(spec.clone())) {
@@ -249,7 +329,7 @@ impl RxQueues {
 				// Transfers will be awaited at the queue
 				buff_tkn
 					.provide()
-					.dispatch_await(Rc::clone(&self.poll_queue), false);
+					.dispatch_await(Rc::clone(&poll_queue), false);
 			}
 		} else {
 			// If above features not set, buffers must be at least 1526 bytes large.
@@ -284,29 +364,52 @@ impl RxQueues {
 				// Transfers will be awaited at the queue
 				buff_tkn
 					.provide()
-					.dispatch_await(Rc::clone(&self.poll_queue), false);
+					.dispatch_await(Rc::clone(&poll_queue), false);
 			}
 		}
 
 		// Safe virtqueue
 		self.vqs.push(rc_vq);
+		self.poll_queues.push(poll_queue);
 
 		if self.vqs.len() > 1 {
 			self.is_multi = true;
 		}
 	}
 
-	fn get_next(&mut self) -> Option<Transfer> {
-		let transfer = self.poll_queue.borrow_mut().pop_front();
+	/// Returns the next received transfer and the index of the virtqueue it
+	/// is coming from.
+	fn get_next(&mut self) -> Option<(Transfer, usize)> {
+		(0..self.vqs.len()).find_map(|index| {
+			let transfer = self.poll_queues[index].borrow_mut().pop_front();
 
-		transfer.or_else(|| {
-			// Check if any not yet provided transfers are in the queue.
-			self.poll();
+			transfer
+				.or_else(|| {
+					// Check if any not yet provided transfers are in the queue.
+					self.vqs[index].poll();
 
-			self.poll_queue.borrow_mut().pop_front()
+					self.poll_queues[index].borrow_mut().pop_front()
+				})
+				.map(|transfer| (transfer, index))
 		})
 	}
 
+	/// Provides the buffer of a processed transfer to its virtqueue again.
+	fn recycle(&self, transfer: Transfer, vq_index: usize) {
+		transfer
+			.reuse()
+			.unwrap()
+			.provide()
+			.dispatch_await(Rc::clone(&self.poll_queues[vq_index]), false);
+	}
+
+	fn has_packet(&self) -> bool {
+		self.poll();
+		self.poll_queues
+			.iter()
+			.any(|poll_queue| !poll_queue.borrow().is_empty())
+	}
+
 	fn poll(&self) {
 		if self.is_multi {
 			for vq in &self.vqs {
@@ -342,8 +445,11 @@ impl RxQueues {
 /// to the respective queue structures.
 pub struct TxQueues {
 	vqs: Vec<Rc<Virtq>>,
-	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
-	ready_queue: Vec<BufferToken>,
+	/// Finished transfers of each virtqueue. The poll queue at index i
+	/// belongs to the virtqueue at index i.
+	poll_queues: Vec<Rc<RefCell<VecDeque<Transfer>>>>,
+	/// Prepared buffer tokens of each virtqueue.
+	ready_queues: Vec<Vec<BufferToken>>,
 	/// Indicates, whether the Driver/Device are using multiple
 	/// queues for communication.
 	is_multi: bool,
@@ -352,14 +458,14 @@ pub struct TxQueues {
 impl TxQueues {
 	pub fn new(
 		vqs: Vec<Rc<Virtq>>,
-		poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
-		ready_queue: Vec<BufferToken>,
+		poll_queues: Vec<Rc<RefCell<VecDeque<Transfer>>>>,
+		ready_queues: Vec<Vec<BufferToken>>,
 		is_multi: bool,
 	) -> Self {
 		Self {
 			vqs,
-			poll_queue,
-			ready_queue,
+			poll_queues,
+			ready_queues,
 			is_multi,
 		}
 	}
@@ -385,47 +491,42 @@ impl TxQueues {
 		}
 	}
 
-	fn poll(&self) {
-		if self.is_multi {
-			for vq in &self.vqs {
-				vq.poll();
-			}
-		} else {
-			self.vqs[0].poll();
-		}
-	}
-
 	fn add(&mut self, vq: Virtq, dev_cfg: &NetDevCfg) {
 		// Safe virtqueue
-		self.vqs.push(Rc::new(vq));
-		if self.vqs.len() == 1 {
-			// Unwrapping is safe, as one virtq will be definitely in the vector.
-			let vq = self.vqs.get(0).unwrap();
-
-			// Virtio specification v1.1. - 5.1.6.2 point 5.
-			//      Header and data are added as ONE output descriptor to the transmitvq.
-			//      Hence we are interpreting this, as the fact, that send packets must be inside a single descriptor.
-			// As usize is currently safe as the minimal usize is defined as 16bit in rust.
-			let buff_def = Bytes::new(
-				mem::size_of::<VirtioNetHdr>() + (dev_cfg.raw.get_mtu() as usize) + ETH_HDR,
-			)
-			.unwrap();
-			let spec = BuffSpec::Single(buff_def);
-
-			let num_buff: u16 = vq.size().into();
+		let vq = Rc::new(vq);
 
-			for _ in 0..num_buff {
-				self.ready_queue.push(
-					vq.prep_buffer(Rc::clone(vq), Some(spec.clone()), None)
-						.unwrap()
-						.write_seq(Some(&VirtioNetHdr::get_tx_hdr()), None::<&VirtioNetHdr>)
-						.unwrap(),
-				)
-			}
+		// Virtio specification v1.1. - 5.1.6.2 point 5.
+		//      Header and data are added as ONE output descriptor to the transmitvq.
+		//      Hence we are interpreting this, as the fact, that send packets must be inside a single descriptor.
+		// As usize is currently safe as the minimal usize is defined as 16bit in rust.
+		//
+		// With TCP segmentation offload, the network stack passes TCP packets of up
+		// to 64 KiB to the device, which splits them into segments.
+		let frame_len = if is_tso_enabled(dev_cfg.features) {
+			65550
 		} else {
+			usize::from(dev_cfg.raw.get_mtu()) + ETH_HDR
+		};
+		let buff_def = Bytes::new(mem::size_of::<VirtioNetHdr>() + frame_len).unwrap();
+		let spec = BuffSpec::Single(buff_def);
+
+		let num_buff: u16 = vq.size().into();
+		let ready_queue = (0..num_buff)
+			.map(|_| {
+				vq.prep_buffer(Rc::clone(&vq), Some(spec.clone()), None)
+					.unwrap()
+					.write_seq(Some(&VirtioNetHdr::get_tx_hdr()), None::<&VirtioNetHdr>)
+					.unwrap()
+			})
+			.collect();
+
+		self.vqs.push(vq);
+		self.poll_queues
+			.push(Rc::new(RefCell::new(VecDeque::new())));
+		self.ready_queues.push(ready_queue);
+
+		if self.vqs.len() > 1 {
 			self.is_multi = true;
-			// Currently we are doing nothing with the additional queues. They are inactive and might be used in the
-			// future
 		}
 	}
 
@@ -434,37 +535,38 @@ impl TxQueues {
 	///
 	/// OR returns None, if no Buffertoken could be generated
 	fn get_tkn(&mut self, len: usize) -> Option<(BufferToken, usize)> {
+		// All packets are sent via the main queue with index 0.
+		let index = 0;
+
 		// Check all ready token, for correct size.
 		// Drop token if not so
-		//
-		// All Tokens inside the ready_queue are coming from the main queue with index 0.
-		while let Some(mut tkn) = self.ready_queue.pop() {
+		while let Some(mut tkn) = self.ready_queues[index].pop() {
 			let (send_len, _) = tkn.len();
 
 			match send_len.cmp(&len) {
 				Ordering::Less => {}
-				Ordering::Equal => return Some((tkn, 0)),
+				Ordering::Equal => return Some((tkn, index)),
 				Ordering::Greater => {
 					tkn.restr_size(Some(len), None).unwrap();
-					return Some((tkn, 0));
+					return Some((tkn, index));
 				}
 			}
 		}
 
-		if self.poll_queue.borrow().is_empty() {
-			self.poll();
+		if self.poll_queues[index].borrow().is_empty() {
+			self.vqs[index].poll();
 		}
 
-		while let Some(transfer) = self.poll_queue.borrow_mut().pop_back() {
+		while let Some(transfer) = self.poll_queues[index].borrow_mut().pop_back() {
 			let mut tkn = transfer.reuse().unwrap();
 			let (send_len, _) = tkn.len();
 
 			match send_len.cmp(&len) {
 				Ordering::Less => {}
-				Ordering::Equal => return Some((tkn, 0)),
+				Ordering::Equal => return Some((tkn, index)),
 				Ordering::Greater => {
 					tkn.restr_size(Some(len), None).unwrap();
-					return Some((tkn, 0));
+					return Some((tkn, index));
 				}
 			}
 		}
@@ -472,14 +574,10 @@ impl TxQueues {
 		// As usize is currently safe as the minimal usize is defined as 16bit in rust.
 		let spec = BuffSpec::Single(Bytes::new(len).unwrap());
 
-		match self.vqs[0].prep_buffer(Rc::clone(&self.vqs[0]), Some(spec), None) {
-			Ok(tkn) => Some((tkn, 0)),
-			Err(_) => {
-				// Here it is possible if multiple queues are enabled to get another buffertoken from them!
-				// Info the queues are disabled upon initialization and should be enabled somehow!
-				None
-			}
-		}
+		self.vqs[index]
+			.prep_buffer(Rc::clone(&self.vqs[index]), Some(spec), None)
+			.ok()
+			.map(|tkn| (tkn, index))
 	}
 }
 
@@ -499,6 +597,9 @@ pub(crate) struct VirtioNetDriver {
 
 	pub(super) num_vqs: u16,
 	pub(super) irq: InterruptLine,
+	/// MSI-X table of the device, if the queues signal their own interrupts
+	#[cfg(all(feature = "pci", target_arch = "x86_64"))]
+	pub(super) msix: Option<MsixTable<PciConfigRegion>>,
 	pub(super) polling_mode_counter: u32,
 }
 
@@ -513,14 +614,15 @@ impl NetworkInterface for VirtioNetDriver {
 		}
 	}
 
-	/// Returns the current MTU of the device.
-	/// Currently, if VIRTIO_NET_F_MAC is not set
-	//  MTU is set static to 1500 bytes.
+	/// Returns the MTU, which is used by the network stack.
+	///
+	/// If TCP segmentation offload has been negotiated, the network stack
+	/// may send frames of up to 64 KiB, which are segmented by the device.
 	fn get_mtu(&self) -> u16 {
-		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MTU) {
-			self.dev_cfg.raw.get_mtu()
+		if is_tso_enabled(self.dev_cfg.features) {
+			u16::MAX
 		} else {
-			1500
+			self.get_dev_mtu()
 		}
 	}
 
@@ -537,7 +639,7 @@ impl NetworkInterface for VirtioNetDriver {
 		let len = len + core::mem::size_of::<VirtioNetHdr>();
 
 		match self.send_vqs.get_tkn(len) {
-			Some((mut buff_tkn, _vq_index)) => {
+			Some((mut buff_tkn, vq_index)) => {
 				let (send_ptrs, _) = buff_tkn.raw_ptrs();
 				// Currently we have single Buffers in the TxQueue of size: MTU + ETH_HDR + VIRTIO_NET_HDR
 				// see TxQueue.add()
@@ -548,95 +650,204 @@ impl NetworkInterface for VirtioNetDriver {
 					buff_ptr.offset(isize::try_from(core::mem::size_of::<VirtioNetHdr>()).unwrap())
 				};
 
-				Ok((buff_ptr, Box::into_raw(Box::new(buff_tkn)) as usize))
+				// The handle remembers the virtqueue of the token.
+				Ok((
+					buff_ptr,
+					Box::into_raw(Box::new((buff_tkn, vq_index))) as usize,
+				))
 			}
 			None => Err(()),
 		}
 	}
 
 	fn free_tx_buffer(&self, token: usize) {
-		unsafe { drop(Box::from_raw(token as *mut BufferToken)) }
+		unsafe { drop(Box::from_raw(token as *mut (BufferToken, usize))) }
 	}
 
-	fn send_tx_buffer(&mut self, tkn_handle: usize, _len: usize) -> Result<(), ()> {
+	fn send_tx_buffer(&mut self, tkn_handle: usize, len: usize) -> Result<(), ()> {
 		// This does not result in a new assignment, or in a drop of the BufferToken, which
 		// would be dangerous, as the memory is freed then.
-		let tkn = *unsafe { Box::from_raw(tkn_handle as *mut BufferToken) };
+		let (mut tkn, vq_index) =
+			*unsafe { Box::from_raw(tkn_handle as *mut (BufferToken, usize)) };
+
+		// The header precedes the frame, which has been written by the "user-space".
+		// Reused tokens still contain the header of their previous packet.
+		let (send_ptrs, _) = tkn.raw_ptrs();
+		let (buff_ptr, _) = send_ptrs.unwrap()[0];
+		let frame = unsafe {
+			core::slice::from_raw_parts_mut(buff_ptr.add(mem::size_of::<VirtioNetHdr>()), len)
+		};
+		let hdr = self.tx_hdr(frame);
+		if hdr.gso_type == u8::from(NetHdrGSO::VIRTIO_NET_HDR_GSO_NONE)
+			&& len > usize::from(self.get_dev_mtu()) + ETH_HDR
+		{
+			// Only TCP packets are segmented by the device. Other packets,
+			// which exceed the MTU of the link, are dropped like on the wire.
+			warn!("Drop packet of {} bytes, which exceeds the MTU", len);
+			return Ok(());
+		}
+		unsafe { (buff_ptr as *mut VirtioNetHdr).write_unaligned(hdr) };
 
 		tkn.provide()
-			.dispatch_await(Rc::clone(&self.send_vqs.poll_queue), false);
+			.dispatch_await(Rc::clone(&self.send_vqs.poll_queues[vq_index]), false);
 
 		Ok(())
 	}
 
 	fn has_packet(&self) -> bool {
-		self.recv_vqs.poll();
-		!self.recv_vqs.poll_queue.borrow().is_empty()
-	}
-
-	fn receive_rx_buffer(&mut self) -> Result<Vec<u8>, ()> {
-		match self.recv_vqs.get_next() {
-			Some(transfer) => {
-				let transfer = match RxQueues::post_processing(transfer) {
-					Ok(trf) => trf,
-					Err(vnet_err) => {
-						error!("Post processing failed. Err: {:?}", vnet_err);
-						return Err(());
-					}
-				};
+		self.recv_vqs.has_packet()
+	}
+
+	/// Lends the buffer of a received frame to the network stack, which returns
+	/// it via `release_rx_buffer` after the frame has been processed.
+	fn receive_rx_buffer(&mut self) -> Result<(*mut u8, usize, usize), ()> {
+		while let Some((transfer, vq_index)) = self.recv_vqs.get_next() {
+			let transfer = match RxQueues::post_processing(transfer) {
+				Ok(trf) => trf,
+				Err(vnet_err) => {
+					error!("Post processing failed. Err: {:?}", vnet_err);
+					return Err(());
+				}
+			};
+
+			let (_, recv_data_opt) = transfer.as_slices().unwrap();
+			let recv_data = recv_data_opt.unwrap();
 
-				let (_, recv_data_opt) = transfer.as_slices().unwrap();
-				let mut recv_data = recv_data_opt.unwrap();
-
-				// If the given length is zero, we currently fail.
-				if recv_data.len() == 2 {
-					let recv_payload = recv_data.pop().unwrap();
-					// Create static reference for the user-space
-					// As long as we keep the Transfer in a raw reference this reference is static,
-					// so this is fine.
-					let recv_ref = (recv_payload as *const [u8]) as *mut [u8];
-					let ref_data: &'static mut [u8] = unsafe { &mut *(recv_ref) };
-					let vec_data = ref_data.to_vec();
-					transfer
-						.reuse()
-						.unwrap()
-						.provide()
-						.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
-
-					Ok(vec_data)
-				} else if recv_data.len() == 1 {
-					let packet = recv_data.pop().unwrap();
-					let payload_ptr =
-						(&packet[mem::size_of::<VirtioNetHdr>()] as *const u8) as *mut u8;
-
-					let ref_data: &'static mut [u8] = unsafe {
-						core::slice::from_raw_parts_mut(
-							payload_ptr,
-							packet.len() - mem::size_of::<VirtioNetHdr>(),
-						)
-					};
-					let vec_data = ref_data.to_vec();
-					transfer
-						.reuse()
-						.unwrap()
-						.provide()
-						.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
-
-					Ok(vec_data)
-				} else {
-					error!("Empty transfer, or with wrong buffer layout. Reusing and returning error to user-space network driver...");
-					transfer
-						.reuse()
-						.unwrap()
-						.write_seq(None::<&VirtioNetHdr>, Some(&VirtioNetHdr::get_rx_hdr()))
-						.unwrap()
-						.provide()
-						.dispatch_await(Rc::clone(&self.recv_vqs.poll_queue), false);
-
-					Err(())
+			// The header and the frame are either placed in separate buffers
+			// or in a single one. See RxQueues.add()
+			let packet = match recv_data.as_slice() {
+				[hdr, frame] => Some((*hdr, *frame)),
+				[packet] if packet.len() > mem::size_of::<VirtioNetHdr>() => {
+					Some(packet.split_at(mem::size_of::<VirtioNetHdr>()))
+				}
+				_ => None,
+			};
+
+			let frame = match packet {
+				Some((hdr, frame)) if self.rx_checksum_ok(hdr, frame) => {
+					Some((frame.as_ptr() as *mut u8, frame.len()))
+				}
+				Some(_) => {
+					debug!("Dropping received packet with invalid checksum");
+					None
+				}
+				None => {
+					error!("Empty transfer, or with wrong buffer layout. Reusing buffer...");
+					None
 				}
+			};
+
+			match frame {
+				// The buffer remains valid, as long as the transfer is kept alive by the handle.
+				Some((buff_ptr, len)) => {
+					let handle = Box::into_raw(Box::new((transfer, vq_index))) as usize;
+					return Ok((buff_ptr, len, handle));
+				}
+				None => self.recv_vqs.recycle(transfer, vq_index),
 			}
-			None => Err(()),
+		}
+
+		Err(())
+	}
+
+	fn release_rx_buffer(&mut self, handle: usize) {
+		let (transfer, vq_index) = *unsafe { Box::from_raw(handle as *mut (Transfer, usize)) };
+		self.recv_vqs.recycle(transfer, vq_index);
+	}
+
+	fn set_promiscuous(&mut self, value: bool) -> Result<(), ()> {
+		self.send_rx_cmd(RxCmd::VIRTIO_NET_CTRL_RX_PROMISC, value)
+			.map_err(|vnet_err| warn!("Unable to set promiscuous mode: {:?}", vnet_err))
+	}
+
+	fn set_all_multicast(&mut self, value: bool) -> Result<(), ()> {
+		self.send_rx_cmd(RxCmd::VIRTIO_NET_CTRL_RX_ALLMULTI, value)
+			.map_err(|vnet_err| warn!("Unable to set all-multicast mode: {:?}", vnet_err))
+	}
+
+	/// Programs the MAC filter table of the device. The unicast table remains
+	/// empty, as the device always accepts packets for its own address.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.2
+	fn set_multicast_filter(&mut self, addrs: &[[u8; 6]]) -> Result<(), ()> {
+		let mut tables = Vec::with_capacity(2 * mem::size_of::<u32>() + addrs.len() * 6);
+		tables.extend_from_slice(&0u32.to_le_bytes());
+		tables.extend_from_slice(&u32::try_from(addrs.len()).unwrap().to_le_bytes());
+		for addr in addrs {
+			tables.extend_from_slice(addr);
+		}
+
+		self.require_feature(Features::VIRTIO_NET_F_CTRL_RX)
+			.and_then(|_| {
+				self.ctrl_vq.send(
+					CtrlClass::VIRTIO_NET_CTRL_MAC,
+					MacCmd::VIRTIO_NET_CTRL_MAC_TABLE_SET.into(),
+					&tables,
+				)
+			})
+			.map_err(|vnet_err| warn!("Unable to set multicast filter: {:?}", vnet_err))
+	}
+
+	/// Adds a VLAN id to the filter of the device. If VIRTIO_NET_F_CTRL_VLAN has
+	/// been negotiated, the device only delivers tagged packets of VLANs in the filter.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.3
+	fn add_vlan(&mut self, vid: u16) -> Result<(), ()> {
+		self.send_vlan_cmd(VlanCmd::VIRTIO_NET_CTRL_VLAN_ADD, vid)
+			.map_err(|vnet_err| warn!("Unable to add VLAN {}: {:?}", vid, vnet_err))
+	}
+
+	fn remove_vlan(&mut self, vid: u16) -> Result<(), ()> {
+		self.send_vlan_cmd(VlanCmd::VIRTIO_NET_CTRL_VLAN_DEL, vid)
+			.map_err(|vnet_err| warn!("Unable to remove VLAN {}: {:?}", vid, vnet_err))
+	}
+
+	/// Returns the links status.
+	/// If feature VIRTIO_NET_F_STATUS has not been negotiated, then we assume the link is up!
+	fn is_link_up(&self) -> bool {
+		if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_STATUS)
+		{
+			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_LINK_UP)
+				== u16::from(Status::VIRTIO_NET_S_LINK_UP)
+		} else {
+			true
+		}
+	}
+
+	/// Returns, whether the device asks the driver to announce its addresses,
+	/// e.g. after live migration. The request is only possible, if
+	/// VIRTIO_NET_F_GUEST_ANNOUNCE has been negotiated.
+	fn is_announce(&self) -> bool {
+		if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_GUEST_ANNOUNCE)
+		{
+			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
+				== u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
+		} else {
+			false
+		}
+	}
+
+	/// Acknowledges an announce request of the device, after the addresses have
+	/// been announced. This clears VIRTIO_NET_S_ANNOUNCE in the status.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.4
+	fn ack_announce(&mut self) {
+		if let Err(vnet_err) = self
+			.require_feature(Features::VIRTIO_NET_F_GUEST_ANNOUNCE)
+			.and_then(|_| {
+				self.ctrl_vq.send(
+					CtrlClass::VIRTIO_NET_CTRL_ANNOUNCE,
+					AnceCmd::VIRTIO_NET_CTRL_ANNOUNCE_ACK.into(),
+					&[],
+				)
+			}) {
+			warn!("Unable to acknowledge announce request: {:?}", vnet_err);
 		}
 	}
 
@@ -654,14 +865,44 @@ impl NetworkInterface for VirtioNetDriver {
 		}
 	}
 
+	#[cfg(feature = "tcp")]
+	fn get_checksums(&self) -> ChecksumCapabilities {
+		let offload = match (
+			self.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_NET_F_CSUM),
+			self.dev_cfg
+				.features
+				.is_feature(Features::VIRTIO_NET_F_GUEST_CSUM),
+		) {
+			(true, true) => Checksum::None,
+			(true, false) => Checksum::Rx,
+			(false, true) => Checksum::Tx,
+			(false, false) => Checksum::Both,
+		};
+
+		let mut checksums = ChecksumCapabilities::default();
+		checksums.tcp = offload;
+		checksums.udp = offload;
+		checksums
+	}
+
 	fn handle_interrupt(&mut self) -> bool {
 		increment_irq_counter(32 + self.irq);
 
+		// MSI-X vectors are not shared, such that the ISR status is not used.
+		#[cfg(all(feature = "pci", target_arch = "x86_64"))]
+		if self.msix.is_some() {
+			return true;
+		}
+
 		let result = if self.isr_stat.is_interrupt() {
 			true
 		} else if self.isr_stat.is_cfg_change() {
-			info!("Configuration changes are not possible! Aborting");
-			todo!("Implement possibiity to change config on the fly...")
+			// The network stack follows the link status and announce requests
+			// by itself, but has to be woken up.
+			info!("Configuration of virtio network device has changed");
+			true
 		} else {
 			false
 		};
@@ -699,34 +940,121 @@ impl VirtioNetDriver {
 		}
 	}
 
-	/// Returns the links status.
-	/// If feature VIRTIO_NET_F_STATUS has not been negotiated, then we assume the link is up!
-	#[cfg(feature = "pci")]
-	pub fn is_link_up(&self) -> bool {
-		if self
+	/// Switches a receive mode of the device on or off.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.5.1
+	fn send_rx_cmd(&self, cmd: RxCmd, value: bool) -> Result<(), VirtioNetError> {
+		self.require_feature(Features::VIRTIO_NET_F_CTRL_RX)?;
+		self.ctrl_vq
+			.send(CtrlClass::VIRTIO_NET_CTRL_RX, cmd.into(), &[value.into()])
+	}
+
+	fn send_vlan_cmd(&self, cmd: VlanCmd, vid: u16) -> Result<(), VirtioNetError> {
+		self.require_feature(Features::VIRTIO_NET_F_CTRL_VLAN)?;
+		self.ctrl_vq.send(
+			CtrlClass::VIRTIO_NET_CTRL_VLAN,
+			cmd.into(),
+			&vid.to_le_bytes(),
+		)
+	}
+
+	/// Returns an error, if the feature has not been negotiated.
+	fn require_feature(&self, feat: Features) -> Result<(), VirtioNetError> {
+		if self.dev_cfg.features.is_feature(feat) {
+			Ok(())
+		} else {
+			Err(VirtioNetError::FeatNotNeg(feat))
+		}
+	}
+
+	/// Returns the MTU of the link.
+	/// Currently, if VIRTIO_NET_F_MTU is not set
+	//  MTU is set static to 1500 bytes.
+	fn get_dev_mtu(&self) -> u16 {
+		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MTU) {
+			self.dev_cfg.raw.get_mtu()
+		} else {
+			1500
+		}
+	}
+
+	/// Creates the header of a frame, which is going to be sent.
+	///
+	/// If VIRTIO_NET_F_CSUM has been negotiated, the device computes the checksum
+	/// of TCP and UDP packets. If TCP segmentation offload has been negotiated, too,
+	/// the device splits TCP packets, which exceed the MTU.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.2
+	fn tx_hdr(&self, frame: &mut [u8]) -> VirtioNetHdr {
+		let mut hdr = VirtioNetHdr::get_tx_hdr();
+
+		if !self
 			.dev_cfg
 			.features
-			.is_feature(Features::VIRTIO_NET_F_STATUS)
+			.is_feature(Features::VIRTIO_NET_F_CSUM)
 		{
-			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_LINK_UP)
-				== u16::from(Status::VIRTIO_NET_S_LINK_UP)
-		} else {
-			true
+			return hdr;
 		}
+		let Some(transport) = Transport::parse(frame) else {
+			return hdr;
+		};
+
+		// The device expects the checksum of the pseudo header in the checksum field.
+		let csum_pos = transport.start + transport.checksum_offset();
+		frame[csum_pos..csum_pos + 2]
+			.copy_from_slice(&checksum::fold(transport.pseudo_sum).to_be_bytes());
+
+		hdr.flags = NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM.into();
+		hdr.csum_start = transport.start.try_into().unwrap();
+		hdr.csum_offset = transport.checksum_offset().try_into().unwrap();
+
+		let mtu = usize::from(self.get_dev_mtu());
+		if transport.protocol == IP_PROTO_TCP
+			&& frame.len() > mtu + ETH_HDR
+			&& is_tso_enabled(self.dev_cfg.features)
+		{
+			let gso_type = if transport.is_ipv6 {
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV6
+			} else {
+				NetHdrGSO::VIRTIO_NET_HDR_GSO_TCPV4
+			};
+			let tcp_hdr_len = usize::from(frame[transport.start + 12] >> 4) * 4;
+			let hdr_len = transport.start + tcp_hdr_len;
+
+			hdr.gso_type = gso_type.into();
+			hdr.hdr_len = hdr_len.try_into().unwrap();
+			// The maximal segment size follows the MTU, which includes the IP header.
+			hdr.gso_size = (mtu + ETH_HDR - hdr_len).try_into().unwrap();
+		}
+
+		hdr
 	}
 
-	#[allow(dead_code)]
-	pub fn is_announce(&self) -> bool {
-		if self
+	/// Checks the TCP or UDP checksum of a received frame, if VIRTIO_NET_F_GUEST_CSUM
+	/// has been negotiated. Then, the network stack does not verify it anymore.
+	///
+	/// Frames, which have been validated by the device or which come with a partial
+	/// checksum from the host, are accepted without further checks.
+	///
+	/// See Virtio specification v1.1. - 5.1.6.4
+	fn rx_checksum_ok(&self, hdr: &[u8], frame: &[u8]) -> bool {
+		if !self
 			.dev_cfg
 			.features
-			.is_feature(Features::VIRTIO_NET_F_STATUS)
+			.is_feature(Features::VIRTIO_NET_F_GUEST_CSUM)
 		{
-			self.dev_cfg.raw.get_status() & u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
-				== u16::from(Status::VIRTIO_NET_S_ANNOUNCE)
-		} else {
-			false
+			return true;
 		}
+
+		let flags = hdr[0];
+		if flags
+			& (NetHdrFlag::VIRTIO_NET_HDR_F_NEEDS_CSUM | NetHdrFlag::VIRTIO_NET_HDR_F_DATA_VALID)
+			!= 0
+		{
+			return true;
+		}
+
+		Transport::parse(frame).map_or(true, |transport| transport.verify(frame))
 	}
 
 	/// Returns the maximal number of virtqueue pairs allowed. This is the
@@ -734,7 +1062,6 @@ impl VirtioNetDriver {
 	/// device and overrides the num_vq field in the common config.
 	///
 	/// Returns 1 (i.e. minimum number of pairs) if VIRTIO_NET_F_MQ is not set.
-	#[allow(dead_code)]
 	pub fn get_max_vq_pairs(&self) -> u16 {
 		if self.dev_cfg.features.is_feature(Features::VIRTIO_NET_F_MQ) {
 			self.dev_cfg.raw.get_max_virtqueue_pairs()
@@ -761,115 +1088,56 @@ impl VirtioNetDriver {
 	/// See Virtio specification v1.1. - 3.1.1.
 	///                      and v1.1. - 5.1.5
 	pub fn init_dev(&mut self) -> Result<(), VirtioNetError> {
-		// Reset
-		self.com_cfg.reset_dev();
-
-		// Indiacte device, that OS noticed it
-		self.com_cfg.ack_dev();
-
-		// Indicate device, that driver is able to handle it
-		self.com_cfg.set_drv();
-
 		// Define minimal feature set
-		let min_feats: Vec<Features> = vec![
+		let min_feats = [
 			Features::VIRTIO_F_VERSION_1,
 			Features::VIRTIO_NET_F_MAC,
 			Features::VIRTIO_NET_F_STATUS,
 		];
 
-		let mut min_feat_set = FeatureSet::new(0);
-		min_feat_set.set_features(&min_feats);
-		let mut feats: Vec<Features> = min_feats;
-
 		// If wanted, push new features into feats here:
-		//
-		// Indirect descriptors can be used
-		feats.push(Features::VIRTIO_F_RING_INDIRECT_DESC);
-		// MTU setting can be used
-		feats.push(Features::VIRTIO_NET_F_MTU);
-		// Packed Vq can be used
-		feats.push(Features::VIRTIO_F_RING_PACKED);
-
-		// Currently the driver does NOT support the features below.
-		// In order to provide functionality for these, the driver
-		// needs to take care of calculating checksum in
-		// RxQueues.post_processing()
-		// feats.push(Features::VIRTIO_NET_F_GUEST_CSUM);
-		// feats.push(Features::VIRTIO_NET_F_GUEST_TSO4);
-		// feats.push(Features::VIRTIO_NET_F_GUEST_TSO6);
+		let feats = [
+			// Indirect descriptors can be used
+			Features::VIRTIO_F_RING_INDIRECT_DESC,
+			// MTU setting can be used
+			Features::VIRTIO_NET_F_MTU,
+			// Packed Vq can be used
+			Features::VIRTIO_F_RING_PACKED,
+			// The device is configured via the control queue
+			Features::VIRTIO_NET_F_CTRL_VQ,
+			// Receive modes, the MAC filter and the VLAN filter can be controlled
+			Features::VIRTIO_NET_F_CTRL_RX,
+			Features::VIRTIO_NET_F_CTRL_VLAN,
+			// Addresses are announced on request of the device, e.g. after live migration
+			Features::VIRTIO_NET_F_GUEST_ANNOUNCE,
+			// Checksums can be computed by the device and unverified checksums
+			// of received packets are checked by the driver
+			Features::VIRTIO_NET_F_CSUM,
+			Features::VIRTIO_NET_F_GUEST_CSUM,
+			// TCP segmentation can be offloaded in both directions
+			Features::VIRTIO_NET_F_HOST_TSO4,
+			Features::VIRTIO_NET_F_HOST_TSO6,
+			Features::VIRTIO_NET_F_GUEST_TSO4,
+			Features::VIRTIO_NET_F_GUEST_TSO6,
+		];
 
-		// Negotiate features with device. Automatically reduces selected feats in order to meet device capabilities.
-		// Aborts in case incompatible features are selected by the dricer or the device does not support min_feat_set.
-		match self.negotiate_features(&feats) {
-			Ok(_) => info!(
-				"Driver found a subset of features for virtio device {:x}. Features are: {:?}",
-				self.dev_cfg.dev_id, &feats
-			),
-			Err(vnet_err) => {
-				match vnet_err {
-					VirtioNetError::FeatReqNotMet(feat_set) => {
-						error!("Network drivers feature set {:x} does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!", u64::from(feat_set));
-						return Err(vnet_err);
-					}
-					VirtioNetError::IncompFeatsSet(drv_feats, dev_feats) => {
-						// Create a new matching feature set for device and driver if the minimal set is met!
-						if (min_feat_set & dev_feats) != min_feat_set {
-							error!("Device features set, does not satisfy minimal features needed. Aborting!");
-							return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
-						} else {
-							feats = match Features::from_set(dev_feats & drv_feats) {
-								Some(feats) => feats,
-								None => {
-									error!("Feature negotiation failed with minimal feature set. Aborting!");
-									return Err(VirtioNetError::FailFeatureNeg(
-										self.dev_cfg.dev_id,
-									));
-								}
-							};
-
-							match self.negotiate_features(&feats) {
-                                Ok(_) => info!("Driver found a subset of features for virtio device {:x}. Features are: {:?}", self.dev_cfg.dev_id, &feats),
-                                Err(vnet_err) => {
-                                    match vnet_err {
-                                        VirtioNetError::FeatReqNotMet(feat_set) => {
-                                            error!("Network device offers a feature set {:x} when used completely does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!", u64::from(feat_set));
-                                            return Err(vnet_err);
-                                        },
-                                        _ => {
-                                            error!("Feature Set after reduction still not usable. Set: {:?}. Aborting!", feats);
-                                            return Err(vnet_err);
-                                        }
-                                    }
-                                }
-                            }
-						}
-					}
-					_ => {
-						error!(
-							"Wanted set of features is NOT supported by device. Set: {:?}",
-							feats
-						);
-						return Err(vnet_err);
-					}
-				}
-			}
+		// Checks if the selected feature set is compatible with requirements for
+		// features according to Virtio spec. v1.1 - 5.1.3.1.
+		let wanted = [&min_feats[..], &feats[..]].concat();
+		if let Err(vnet_err) = FeatureSet::check_features(&wanted) {
+			error!("Network drivers feature set does not satisfy rules in section 5.1.3.1 of specification v1.1. Aborting!");
+			return Err(vnet_err);
 		}
 
-		// Indicates the device, that the current feature set is final for the driver
-		// and will not be changed.
-		self.com_cfg.features_ok();
-
-		// Checks if the device has accepted final set. This finishes feature negotiation.
-		if self.com_cfg.check_features() {
-			info!(
-				"Features have been negotiated between virtio network device {:x} and driver.",
-				self.dev_cfg.dev_id
-			);
-			// Set feature set in device config fur future use.
-			self.dev_cfg.features.set_features(&feats);
-		} else {
-			return Err(VirtioNetError::FailFeatureNeg(self.dev_cfg.dev_id));
-		}
+		// Negotiate features with device. Automatically reduces selected feats in order to meet device capabilities.
+		let feats = negotiate_features(&mut self.com_cfg, &min_feats, &feats)
+			.map_err(|err| VirtioNetError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		self.dev_cfg.features = FeatureSet::new(feats);
+		info!(
+			"Features have been negotiated between virtio network device {:x} and driver. Features are: {:?}",
+			self.dev_cfg.dev_id,
+			Features::from_set(self.dev_cfg.features)
+		);
 
 		match self.dev_spec_init() {
 			Ok(_) => info!(
@@ -878,41 +1146,16 @@ impl VirtioNetDriver {
 			),
 			Err(vnet_err) => return Err(vnet_err),
 		}
+
+		#[cfg(all(feature = "pci", target_arch = "x86_64"))]
+		self.setup_msix();
+
 		// At this point the device is "live"
 		self.com_cfg.drv_ok();
 
 		Ok(())
 	}
 
-	/// Negotiates a subset of features, understood and wanted by both the OS
-	/// and the device.
-	fn negotiate_features(&mut self, wanted_feats: &[Features]) -> Result<(), VirtioNetError> {
-		let mut drv_feats = FeatureSet::new(0);
-
-		for feat in wanted_feats.iter() {
-			drv_feats |= *feat;
-		}
-
-		let dev_feats = FeatureSet::new(self.com_cfg.dev_features());
-
-		// Checks if the selected feature set is compatible with requirements for
-		// features according to Virtio spec. v1.1 - 5.1.3.1.
-		match FeatureSet::check_features(wanted_feats) {
-			Ok(_) => {
-				info!("Feature set wanted by network driver are in conformance with specification.")
-			}
-			Err(vnet_err) => return Err(vnet_err),
-		}
-
-		if (dev_feats & drv_feats) == drv_feats {
-			// If device supports subset of features write feature set to common config
-			self.com_cfg.set_drv_features(drv_feats.into());
-			Ok(())
-		} else {
-			Err(VirtioNetError::IncompFeatsSet(drv_feats, dev_feats))
-		}
-	}
-
 	/// Device Specific initialization according to Virtio specifictation v1.1. - 5.1.5
 	fn dev_spec_init(&mut self) -> Result<(), VirtioNetError> {
 		match self.virtqueue_init() {
@@ -920,7 +1163,10 @@ impl VirtioNetDriver {
 			Err(vnet_err) => return Err(vnet_err),
 		}
 
-		// Add a control if feature is negotiated
+		// Add a control if feature is negotiated. The control queue follows
+		// the maximal number of queue pairs, even if fewer pairs are used.
+		//
+		// See Virtio specification v1.1. - 5.1.2
 		if self
 			.dev_cfg
 			.features
@@ -936,7 +1182,7 @@ impl VirtioNetDriver {
 					&self.notif_cfg,
 					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
 					VqType::Packed,
-					VqIndex::from(self.num_vqs),
+					VqIndex::from(2 * self.get_max_vq_pairs()),
 					self.dev_cfg.features.into(),
 				))));
 			} else {
@@ -945,7 +1191,7 @@ impl VirtioNetDriver {
 					&self.notif_cfg,
 					VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
 					VqType::Split,
-					VqIndex::from(self.num_vqs),
+					VqIndex::from(2 * self.get_max_vq_pairs()),
 					self.dev_cfg.features.into(),
 				))));
 			}
@@ -1058,10 +1304,10 @@ impl VirtioNetDriver {
 }
 
 pub mod constants {
-	use alloc::vec::Vec;
 	use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
 
 	pub use super::error::VirtioNetError;
+	use crate::drivers::virtio::features::virtio_features;
 
 	// Configuration constants
 	pub const MAX_NUM_VQ: u16 = 2;
@@ -1211,312 +1457,48 @@ pub mod constants {
 		}
 	}
 
-	/// Enum contains virtio's network device features and general features of Virtio.
-	///
-	/// See Virtio specification v1.1. - 5.1.3
-	///
-	/// See Virtio specification v1.1. - 6
-	//
-	// WARN: In case the enum is changed, the static function of features `into_features(feat: u64) ->
-	// Option<Vec<Features>>` must also be adjusted to return a correct vector of features.
-	#[allow(dead_code, non_camel_case_types)]
-	#[derive(Copy, Clone, Debug)]
-	#[repr(u64)]
-	pub enum Features {
-		VIRTIO_NET_F_CSUM = 1 << 0,
-		VIRTIO_NET_F_GUEST_CSUM = 1 << 1,
-		VIRTIO_NET_F_CTRL_GUEST_OFFLOADS = 1 << 2,
-		VIRTIO_NET_F_MTU = 1 << 3,
-		VIRTIO_NET_F_MAC = 1 << 5,
-		VIRTIO_NET_F_GUEST_TSO4 = 1 << 7,
-		VIRTIO_NET_F_GUEST_TSO6 = 1 << 8,
-		VIRTIO_NET_F_GUEST_ECN = 1 << 9,
-		VIRTIO_NET_F_GUEST_UFO = 1 << 10,
-		VIRTIO_NET_F_HOST_TSO4 = 1 << 11,
-		VIRTIO_NET_F_HOST_TSO6 = 1 << 12,
-		VIRTIO_NET_F_HOST_ECN = 1 << 13,
-		VIRTIO_NET_F_HOST_UFO = 1 << 14,
-		VIRTIO_NET_F_MRG_RXBUF = 1 << 15,
-		VIRTIO_NET_F_STATUS = 1 << 16,
-		VIRTIO_NET_F_CTRL_VQ = 1 << 17,
-		VIRTIO_NET_F_CTRL_RX = 1 << 18,
-		VIRTIO_NET_F_CTRL_VLAN = 1 << 19,
-		VIRTIO_NET_F_GUEST_ANNOUNCE = 1 << 21,
-		VIRTIO_NET_F_MQ = 1 << 22,
-		VIRTIO_NET_F_CTRL_MAC_ADDR = 1 << 23,
-		VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
-		VIRTIO_F_RING_EVENT_IDX = 1 << 29,
-		VIRTIO_F_VERSION_1 = 1 << 32,
-		VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
-		VIRTIO_F_RING_PACKED = 1 << 34,
-		VIRTIO_F_IN_ORDER = 1 << 35,
-		VIRTIO_F_ORDER_PLATFORM = 1 << 36,
-		VIRTIO_F_SR_IOV = 1 << 37,
-		VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
-		VIRTIO_NET_F_GUEST_HDRLEN = 1 << 59,
-		VIRTIO_NET_F_RSC_EXT = 1 << 61,
-		VIRTIO_NET_F_STANDBY = 1 << 62,
-		// INTERNAL DOCUMENTATION TO KNOW WHICH FEATURES HAVE REQUIREMENTS
-		//
-		// 5.1.3.1 Feature bit requirements
-		// Some networking feature bits require other networking feature bits (see 2.2.1):
-		// VIRTIO_NET_F_GUEST_TSO4 Requires VIRTIO_NET_F_GUEST_CSUM.
-		// VIRTIO_NET_F_GUEST_TSO6 Requires VIRTIO_NET_F_GUEST_CSUM.
-		// VIRTIO_NET_F_GUEST_ECN Requires VIRTIO_NET_F_GUEST_TSO4orVIRTIO_NET_F_GUEST_TSO6.
-		// VIRTIO_NET_F_GUEST_UFO Requires VIRTIO_NET_F_GUEST_CSUM.
-		// VIRTIO_NET_F_HOST_TSO4 Requires VIRTIO_NET_F_CSUM.
-		// VIRTIO_NET_F_HOST_TSO6 Requires VIRTIO_NET_F_CSUM.
-		// VIRTIO_NET_F_HOST_ECN Requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6.
-		// VIRTIO_NET_F_HOST_UFO Requires VIRTIO_NET_F_CSUM.
-		// VIRTIO_NET_F_CTRL_RX Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_CTRL_VLAN Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_GUEST_ANNOUNCE Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_MQ Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_CTRL_MAC_ADDR Requires VIRTIO_NET_F_CTRL_VQ.
-		// VIRTIO_NET_F_RSC_EXT Requires VIRTIO_NET_F_HOST_TSO4 or VIRTIO_NET_F_HOST_TSO6.
-	}
-
-	impl From<Features> for u64 {
-		fn from(val: Features) -> Self {
-			match val {
-				Features::VIRTIO_NET_F_CSUM => 1 << 0,
-				Features::VIRTIO_NET_F_GUEST_CSUM => 1 << 1,
-				Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS => 1 << 2,
-				Features::VIRTIO_NET_F_MTU => 1 << 3,
-				Features::VIRTIO_NET_F_MAC => 1 << 5,
-				Features::VIRTIO_NET_F_GUEST_TSO4 => 1 << 7,
-				Features::VIRTIO_NET_F_GUEST_TSO6 => 1 << 8,
-				Features::VIRTIO_NET_F_GUEST_ECN => 1 << 9,
-				Features::VIRTIO_NET_F_GUEST_UFO => 1 << 10,
-				Features::VIRTIO_NET_F_HOST_TSO4 => 1 << 11,
-				Features::VIRTIO_NET_F_HOST_TSO6 => 1 << 12,
-				Features::VIRTIO_NET_F_HOST_ECN => 1 << 13,
-				Features::VIRTIO_NET_F_HOST_UFO => 1 << 14,
-				Features::VIRTIO_NET_F_MRG_RXBUF => 1 << 15,
-				Features::VIRTIO_NET_F_STATUS => 1 << 16,
-				Features::VIRTIO_NET_F_CTRL_VQ => 1 << 17,
-				Features::VIRTIO_NET_F_CTRL_RX => 1 << 18,
-				Features::VIRTIO_NET_F_CTRL_VLAN => 1 << 19,
-				Features::VIRTIO_NET_F_GUEST_ANNOUNCE => 1 << 21,
-				Features::VIRTIO_NET_F_MQ => 1 << 22,
-				Features::VIRTIO_NET_F_CTRL_MAC_ADDR => 1 << 23,
-				Features::VIRTIO_F_RING_INDIRECT_DESC => 1 << 28,
-				Features::VIRTIO_F_RING_EVENT_IDX => 1 << 29,
-				Features::VIRTIO_F_VERSION_1 => 1 << 32,
-				Features::VIRTIO_F_ACCESS_PLATFORM => 1 << 33,
-				Features::VIRTIO_F_RING_PACKED => 1 << 34,
-				Features::VIRTIO_F_IN_ORDER => 1 << 35,
-				Features::VIRTIO_F_ORDER_PLATFORM => 1 << 36,
-				Features::VIRTIO_F_SR_IOV => 1 << 37,
-				Features::VIRTIO_F_NOTIFICATION_DATA => 1 << 38,
-				Features::VIRTIO_NET_F_GUEST_HDRLEN => 1 << 59,
-				Features::VIRTIO_NET_F_RSC_EXT => 1 << 61,
-				Features::VIRTIO_NET_F_STANDBY => 1 << 62,
-			}
-		}
-	}
-
-	impl BitOr for Features {
-		type Output = u64;
-
-		fn bitor(self, rhs: Self) -> Self::Output {
-			u64::from(self) | u64::from(rhs)
-		}
-	}
-
-	impl BitOr<Features> for u64 {
-		type Output = u64;
-
-		fn bitor(self, rhs: Features) -> Self::Output {
-			self | u64::from(rhs)
-		}
-	}
-
-	impl BitOrAssign<Features> for u64 {
-		fn bitor_assign(&mut self, rhs: Features) {
-			*self |= u64::from(rhs);
-		}
-	}
-
-	impl BitAnd for Features {
-		type Output = u64;
-
-		fn bitand(self, rhs: Features) -> Self::Output {
-			u64::from(self) & u64::from(rhs)
-		}
-	}
-
-	impl BitAnd<Features> for u64 {
-		type Output = u64;
-
-		fn bitand(self, rhs: Features) -> Self::Output {
-			self & u64::from(rhs)
-		}
-	}
-
-	impl BitAndAssign<Features> for u64 {
-		fn bitand_assign(&mut self, rhs: Features) {
-			*self &= u64::from(rhs);
-		}
-	}
-
-	impl core::fmt::Display for Features {
-		fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-			match *self {
-				Features::VIRTIO_NET_F_CSUM => write!(f, "VIRTIO_NET_F_CSUM"),
-				Features::VIRTIO_NET_F_GUEST_CSUM => write!(f, "VIRTIO_NET_F_GUEST_CSUM"),
-				Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS => {
-					write!(f, "VIRTIO_NET_F_CTRL_GUEST_OFFLOADS")
-				}
-				Features::VIRTIO_NET_F_MTU => write!(f, "VIRTIO_NET_F_MTU"),
-				Features::VIRTIO_NET_F_MAC => write!(f, "VIRTIO_NET_F_MAC"),
-				Features::VIRTIO_NET_F_GUEST_TSO4 => write!(f, "VIRTIO_NET_F_GUEST_TSO4"),
-				Features::VIRTIO_NET_F_GUEST_TSO6 => write!(f, "VIRTIO_NET_F_GUEST_TSO6"),
-				Features::VIRTIO_NET_F_GUEST_ECN => write!(f, "VIRTIO_NET_F_GUEST_ECN"),
-				Features::VIRTIO_NET_F_GUEST_UFO => write!(f, "VIRTIO_NET_FGUEST_UFO"),
-				Features::VIRTIO_NET_F_HOST_TSO4 => write!(f, "VIRTIO_NET_F_HOST_TSO4"),
-				Features::VIRTIO_NET_F_HOST_TSO6 => write!(f, "VIRTIO_NET_F_HOST_TSO6"),
-				Features::VIRTIO_NET_F_HOST_ECN => write!(f, "VIRTIO_NET_F_HOST_ECN"),
-				Features::VIRTIO_NET_F_HOST_UFO => write!(f, "VIRTIO_NET_F_HOST_UFO"),
-				Features::VIRTIO_NET_F_MRG_RXBUF => write!(f, "VIRTIO_NET_F_MRG_RXBUF"),
-				Features::VIRTIO_NET_F_STATUS => write!(f, "VIRTIO_NET_F_STATUS"),
-				Features::VIRTIO_NET_F_CTRL_VQ => write!(f, "VIRTIO_NET_F_CTRL_VQ"),
-				Features::VIRTIO_NET_F_CTRL_RX => write!(f, "VIRTIO_NET_F_CTRL_RX"),
-				Features::VIRTIO_NET_F_CTRL_VLAN => write!(f, "VIRTIO_NET_F_CTRL_VLAN"),
-				Features::VIRTIO_NET_F_GUEST_ANNOUNCE => write!(f, "VIRTIO_NET_F_GUEST_ANNOUNCE"),
-				Features::VIRTIO_NET_F_MQ => write!(f, "VIRTIO_NET_F_MQ"),
-				Features::VIRTIO_NET_F_CTRL_MAC_ADDR => write!(f, "VIRTIO_NET_F_CTRL_MAC_ADDR"),
-				Features::VIRTIO_F_RING_INDIRECT_DESC => write!(f, "VIRTIO_F_RING_INDIRECT_DESC"),
-				Features::VIRTIO_F_RING_EVENT_IDX => write!(f, "VIRTIO_F_RING_EVENT_IDX"),
-				Features::VIRTIO_F_VERSION_1 => write!(f, "VIRTIO_F_VERSION_1"),
-				Features::VIRTIO_F_ACCESS_PLATFORM => write!(f, "VIRTIO_F_ACCESS_PLATFORM"),
-				Features::VIRTIO_F_RING_PACKED => write!(f, "VIRTIO_F_RING_PACKED"),
-				Features::VIRTIO_F_IN_ORDER => write!(f, "VIRTIO_F_IN_ORDER"),
-				Features::VIRTIO_F_ORDER_PLATFORM => write!(f, "VIRTIO_F_ORDER_PLATFORM"),
-				Features::VIRTIO_F_SR_IOV => write!(f, "VIRTIO_F_SR_IOV"),
-				Features::VIRTIO_F_NOTIFICATION_DATA => write!(f, "VIRTIO_F_NOTIFICATION_DATA"),
-				Features::VIRTIO_NET_F_GUEST_HDRLEN => write!(f, "VIRTIO_NET_F_GUEST_HDRLEN"),
-				Features::VIRTIO_NET_F_RSC_EXT => write!(f, "VIRTIO_NET_F_RSC_EXT"),
-				Features::VIRTIO_NET_F_STANDBY => write!(f, "VIRTIO_NET_F_STANDBY"),
-			}
-		}
-	}
-
-	impl Features {
-		/// Return a vector of [Features](Features) for a given input of a u64 representation.
+	virtio_features! {
+		/// Enum contains virtio's network device features and general features of Virtio.
 		///
-		/// INFO: In case the FEATURES enum is changed, this function MUST also be adjusted to the new set!
+		/// See Virtio specification v1.1. - 5.1.3
+		///
+		/// See Virtio specification v1.1. - 6
 		//
-		// Really UGLY function, but currently the most convenienvt one to reduce the set of features for the driver easily!
-		pub fn from_set(feat_set: FeatureSet) -> Option<Vec<Features>> {
-			let mut vec_of_feats: Vec<Features> = Vec::new();
-			let feats = feat_set.0;
-
-			if feats & (1 << 0) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CSUM)
-			}
-			if feats & (1 << 1) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_CSUM)
-			}
-			if feats & (1 << 2) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_GUEST_OFFLOADS)
-			}
-			if feats & (1 << 3) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MTU)
-			}
-			if feats & (1 << 5) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MAC)
-			}
-			if feats & (1 << 7) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_TSO4)
-			}
-			if feats & (1 << 8) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_TSO6)
-			}
-			if feats & (1 << 9) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_ECN)
-			}
-			if feats & (1 << 10) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_UFO)
-			}
-			if feats & (1 << 11) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_TSO4)
-			}
-			if feats & (1 << 12) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_TSO6)
-			}
-			if feats & (1 << 13) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_ECN)
-			}
-			if feats & (1 << 14) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_HOST_UFO)
-			}
-			if feats & (1 << 15) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MRG_RXBUF)
-			}
-			if feats & (1 << 16) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_STATUS)
-			}
-			if feats & (1 << 17) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_VQ)
-			}
-			if feats & (1 << 18) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_RX)
-			}
-			if feats & (1 << 19) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_VLAN)
-			}
-			if feats & (1 << 21) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_ANNOUNCE)
-			}
-			if feats & (1 << 22) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_MQ)
-			}
-			if feats & (1 << 23) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_CTRL_MAC_ADDR)
-			}
-			if feats & (1 << 28) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_RING_INDIRECT_DESC)
-			}
-			if feats & (1 << 29) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_RING_EVENT_IDX)
-			}
-			if feats & (1 << 32) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_VERSION_1)
-			}
-			if feats & (1 << 33) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_ACCESS_PLATFORM)
-			}
-			if feats & (1 << 34) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_RING_PACKED)
-			}
-			if feats & (1 << 35) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_IN_ORDER)
-			}
-			if feats & (1 << 36) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_ORDER_PLATFORM)
-			}
-			if feats & (1 << 37) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_SR_IOV)
-			}
-			if feats & (1 << 38) != 0 {
-				vec_of_feats.push(Features::VIRTIO_F_NOTIFICATION_DATA)
-			}
-			if feats & (1 << 59) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_GUEST_HDRLEN)
-			}
-			if feats & (1 << 61) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_RSC_EXT)
-			}
-			if feats & (1 << 62) != 0 {
-				vec_of_feats.push(Features::VIRTIO_NET_F_STANDBY)
-			}
-
-			if vec_of_feats.is_empty() {
-				None
-			} else {
-				Some(vec_of_feats)
-			}
+		// 5.1.3.1 Feature bit requirements are checked by `FeatureSet::check_features`.
+		pub enum Features {
+			VIRTIO_NET_F_CSUM = 1 << 0,
+			VIRTIO_NET_F_GUEST_CSUM = 1 << 1,
+			VIRTIO_NET_F_CTRL_GUEST_OFFLOADS = 1 << 2,
+			VIRTIO_NET_F_MTU = 1 << 3,
+			VIRTIO_NET_F_MAC = 1 << 5,
+			VIRTIO_NET_F_GUEST_TSO4 = 1 << 7,
+			VIRTIO_NET_F_GUEST_TSO6 = 1 << 8,
+			VIRTIO_NET_F_GUEST_ECN = 1 << 9,
+			VIRTIO_NET_F_GUEST_UFO = 1 << 10,
+			VIRTIO_NET_F_HOST_TSO4 = 1 << 11,
+			VIRTIO_NET_F_HOST_TSO6 = 1 << 12,
+			VIRTIO_NET_F_HOST_ECN = 1 << 13,
+			VIRTIO_NET_F_HOST_UFO = 1 << 14,
+			VIRTIO_NET_F_MRG_RXBUF = 1 << 15,
+			VIRTIO_NET_F_STATUS = 1 << 16,
+			VIRTIO_NET_F_CTRL_VQ = 1 << 17,
+			VIRTIO_NET_F_CTRL_RX = 1 << 18,
+			VIRTIO_NET_F_CTRL_VLAN = 1 << 19,
+			VIRTIO_NET_F_GUEST_ANNOUNCE = 1 << 21,
+			VIRTIO_NET_F_MQ = 1 << 22,
+			VIRTIO_NET_F_CTRL_MAC_ADDR = 1 << 23,
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+			VIRTIO_NET_F_GUEST_HDRLEN = 1 << 59,
+			VIRTIO_NET_F_RSC_EXT = 1 << 61,
+			VIRTIO_NET_F_STANDBY = 1 << 62,
 		}
 	}
 
@@ -1542,70 +1524,6 @@ pub mod constants {
 		}
 	}
 
-	/// FeatureSet is new type whicih holds features for virito network devices indicated by the virtio specification
-	/// v1.1. - 5.1.3. and all General Features defined in Virtio specification v1.1. - 6
-	/// wrapping a u64.
-	///
-	/// The main functionality of this type are functions implemented on it.
-	#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
-	pub struct FeatureSet(u64);
-
-	impl BitOr for FeatureSet {
-		type Output = FeatureSet;
-
-		fn bitor(self, rhs: Self) -> Self::Output {
-			FeatureSet(self.0 | rhs.0)
-		}
-	}
-
-	impl BitOr<FeatureSet> for u64 {
-		type Output = u64;
-
-		fn bitor(self, rhs: FeatureSet) -> Self::Output {
-			self | u64::from(rhs)
-		}
-	}
-
-	impl BitOrAssign<FeatureSet> for u64 {
-		fn bitor_assign(&mut self, rhs: FeatureSet) {
-			*self |= u64::from(rhs);
-		}
-	}
-
-	impl BitOrAssign<Features> for FeatureSet {
-		fn bitor_assign(&mut self, rhs: Features) {
-			self.0 = self.0 | u64::from(rhs);
-		}
-	}
-
-	impl BitAnd for FeatureSet {
-		type Output = FeatureSet;
-
-		fn bitand(self, rhs: FeatureSet) -> Self::Output {
-			FeatureSet(self.0 & rhs.0)
-		}
-	}
-
-	impl BitAnd<FeatureSet> for u64 {
-		type Output = u64;
-
-		fn bitand(self, rhs: FeatureSet) -> Self::Output {
-			self & u64::from(rhs)
-		}
-	}
-
-	impl BitAndAssign<FeatureSet> for u64 {
-		fn bitand_assign(&mut self, rhs: FeatureSet) {
-			*self &= u64::from(rhs);
-		}
-	}
-
-	impl From<FeatureSet> for u64 {
-		fn from(feature_set: FeatureSet) -> Self {
-			feature_set.0
-		}
-	}
-
 	impl FeatureSet {
 		/// Checks if a given set of features is compatible and adheres to the
 		/// specfification v1.1. - 5.1.3.1
@@ -1755,33 +1673,14 @@ pub mod constants {
 
 			Ok(())
 		}
-
-		/// Checks if a given feature is set.
-		pub fn is_feature(self, feat: Features) -> bool {
-			self.0 & feat != 0
-		}
-
-		/// Sets features contained in feats to true.
-		///
-		/// WARN: Features should be checked before using this function via the [`FeatureSet::check_features`] function.
-		pub fn set_features(&mut self, feats: &[Features]) {
-			for feat in feats {
-				self.0 |= *feat;
-			}
-		}
-
-		/// Returns a new instance of (FeatureSet)[FeatureSet] with all features
-		/// initialized to false.
-		pub fn new(val: u64) -> Self {
-			FeatureSet(val)
-		}
 	}
 }
 
 /// Error module of virtios network driver. Containing the (VirtioNetError)[VirtioNetError]
 /// enum.
 pub mod error {
-	use super::constants::FeatureSet;
+	use super::constants::{FeatureSet, Features};
+	use crate::drivers::virtio::features::FeatureError;
 	/// Network drivers error enum.
 	#[derive(Debug, Copy, Clone)]
 	pub enum VirtioNetError {
@@ -1790,16 +1689,19 @@ pub mod error {
 		NoComCfg(u16),
 		NoIsrCfg(u16),
 		NoNotifCfg(u16),
-		FailFeatureNeg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
 		/// Set of features does not adhere to the requirements of features
 		/// indicated by the specification
 		FeatReqNotMet(FeatureSet),
-		/// The first u64 contains the feature bits wanted by the driver.
-		/// but which are incompatible with the device feature set, second u64.
-		IncompFeatsSet(FeatureSet, FeatureSet),
 		/// Indicates that an operation for finished Transfers, was performed on
 		/// an ongoing transfer
 		ProcessOngoing,
+		/// The device did not acknowledge a command of the class (first u8)
+		/// sent via the control queue (second u8).
+		FailCtrlCmd(u8, u8),
+		/// The command requires a feature, which has not been negotiated.
+		FeatNotNeg(Features),
 		Unknown,
 	}
 }
@@ -2,19 +2,24 @@
 //!
 //! The module contains ...
 
-use alloc::collections::VecDeque;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
-use core::cell::RefCell;
 
 use crate::arch::pci::PciConfigRegion;
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::net::network_irqhandler;
 use crate::drivers::net::virtio_net::constants::FeatureSet;
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::net::virtio_net::constants::Features;
 use crate::drivers::net::virtio_net::{CtrlQueue, NetDevCfg, RxQueues, TxQueues, VirtioNetDriver};
+use crate::drivers::net::NetworkInterface;
 use crate::drivers::pci::PciDevice;
 use crate::drivers::virtio::error::{self, VirtioError};
 use crate::drivers::virtio::transport::pci;
 use crate::drivers::virtio::transport::pci::{PciCap, UniCapsColl};
 use crate::drivers::virtio::virtqueue::Virtq;
+#[cfg(target_arch = "x86_64")]
+use crate::scheduler::CoreId;
 
 /// Virtio's network device configuration structure.
 /// See specification v1.1. - 5.1.4
@@ -137,23 +142,92 @@ impl VirtioNetDriver {
 			notif_cfg,
 
 			ctrl_vq: CtrlQueue::new(None),
-			recv_vqs: RxQueues::new(
-				Vec::<Rc<Virtq>>::new(),
-				Rc::new(RefCell::new(VecDeque::new())),
-				false,
-			),
-			send_vqs: TxQueues::new(
-				Vec::<Rc<Virtq>>::new(),
-				Rc::new(RefCell::new(VecDeque::new())),
-				Vec::new(),
-				false,
-			),
+			recv_vqs: RxQueues::new(Vec::<Rc<Virtq>>::new(), Vec::new(), false),
+			send_vqs: TxQueues::new(Vec::<Rc<Virtq>>::new(), Vec::new(), Vec::new(), false),
 			num_vqs: 0,
 			irq: device.irq().unwrap(),
+			#[cfg(target_arch = "x86_64")]
+			msix: device.msix_table(),
 			polling_mode_counter: 0,
 		})
 	}
 
+	/// Returns true, if the device signals its interrupts via MSI-X.
+	#[cfg(target_arch = "x86_64")]
+	pub(crate) fn is_msix_enabled(&self) -> bool {
+		self.msix.is_some()
+	}
+
+	/// Assigns an MSI-X vector to configuration changes and one to each
+	/// virtqueue. The vectors of the i-th queue pair are delivered to core i.
+	///
+	/// Falls back to the legacy interrupt line, if the device does not
+	/// provide enough vectors.
+	#[cfg(target_arch = "x86_64")]
+	pub(super) fn setup_msix(&mut self) {
+		let msix = match self.msix.take() {
+			Some(msix) => msix,
+			None => return,
+		};
+
+		// The first entry is used for configuration changes
+		let mut vectors: Vec<(u16, CoreId)> = (0..self.num_vqs / 2)
+			.flat_map(|i| [(2 * i, CoreId::from(i)), (2 * i + 1, CoreId::from(i))])
+			.collect();
+		if self
+			.dev_cfg
+			.features
+			.is_feature(Features::VIRTIO_NET_F_CTRL_VQ)
+		{
+			vectors.push((2 * self.get_max_vq_pairs(), 0));
+		}
+
+		let num_vectors = vectors.len() + 1;
+		if usize::from(msix.size()) < num_vectors {
+			info!(
+				"Virtio network device {:x} provides only {} MSI-X vectors. Using the legacy interrupt.",
+				self.dev_cfg.dev_id,
+				msix.size()
+			);
+			return;
+		}
+
+		let vq_indices = || vectors.iter().map(|(vq_index, _)| *vq_index);
+		let mut irqs = Vec::with_capacity(num_vectors);
+
+		let irq = pci::install_msix_handler(&msix, 0, 0, network_irqhandler, "virtio_net");
+		irqs.extend(irq);
+		if irq.is_none() || !self.com_cfg.set_config_vector(0) {
+			warn!("Unable to assign a MSI-X vector to configuration changes");
+			pci::free_msix_handlers(&mut self.com_cfg, [], &irqs);
+			return;
+		}
+		let irq = irq.unwrap();
+
+		for (entry, (vq_index, core_id)) in (1..).zip(vectors.iter().copied()) {
+			let vq_irq =
+				pci::install_msix_handler(&msix, entry, core_id, network_irqhandler, "virtio_net");
+			irqs.extend(vq_irq);
+			let vq_cfg = self.com_cfg.select_vq(vq_index);
+
+			if vq_irq.is_none() || !vq_cfg.map_or(false, |mut vq_cfg| vq_cfg.set_msix_vector(entry))
+			{
+				warn!("Unable to assign a MSI-X vector to virtqueue {}", vq_index);
+				pci::free_msix_handlers(&mut self.com_cfg, vq_indices(), &irqs);
+				return;
+			}
+		}
+
+		msix.enable();
+		info!(
+			"Virtio network device {:x} uses MSI-X with {} vectors",
+			self.dev_cfg.dev_id, num_vectors
+		);
+
+		self.irq = irq;
+		self.msix = Some(msix);
+	}
+
 	/// Initializes virtio network device by mapping configuration layout to
 	/// respective structs (configuration structs are:
 	/// [ComCfg](structs.comcfg.html), [NotifCfg](structs.notifcfg.html)
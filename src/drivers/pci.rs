@@ -2,6 +2,8 @@
 
 use alloc::vec::Vec;
 use core::fmt;
+#[cfg(target_arch = "x86_64")]
+use core::ptr;
 
 use hermit_sync::{without_interrupts, InterruptTicketMutex};
 use pci_types::{
@@ -9,15 +11,30 @@ use pci_types::{
 	VendorId, MAX_BARS,
 };
 
+#[cfg(target_arch = "x86_64")]
+use crate::arch::kernel::apic;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::mm::paging::{BasePageSize, PageSize};
 use crate::arch::mm::{PhysAddr, VirtAddr};
 use crate::arch::pci::PciConfigRegion;
+use crate::drivers::balloon::virtio_balloon::VirtioBalloonDriver;
+use crate::drivers::block::virtio_blk::VirtioBlkDriver;
+use crate::drivers::block::BlockDevice;
+use crate::drivers::console::virtio_console::VirtioConsoleDriver;
+use crate::drivers::entropy::virtio_rng::VirtioRngDriver;
+use crate::drivers::fs::virtio_9p::Virtio9pDriver;
 use crate::drivers::fs::virtio_fs::VirtioFsDriver;
 #[cfg(not(target_arch = "aarch64"))]
+use crate::drivers::net::e1000::{self, E1000Driver};
+#[cfg(not(target_arch = "aarch64"))]
 use crate::drivers::net::rtl8139::{self, RTL8139Driver};
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::net::NetworkInterface;
 use crate::drivers::virtio::transport::pci as pci_virtio;
 use crate::drivers::virtio::transport::pci::VirtioDriver;
+use crate::drivers::vsock::virtio_vsock::VirtioVsockDriver;
+#[cfg(target_arch = "x86_64")]
+use crate::scheduler::CoreId;
 
 /// Converts a given little endian coded u32 to native endian coded.
 //
@@ -46,6 +63,16 @@ pub(crate) mod constants {
 	pub(crate) const PCI_CAP_ID_VNDR_VIRTIO: u32 = 0x09;
 	pub(crate) const PCI_MASK_IS_DEV_BUS_MASTER: u32 = 0x0000_0004u32;
 	pub(crate) const PCI_COMMAND_BUSMASTER: u32 = 1 << 2;
+	pub(crate) const PCI_CAP_ID_MSIX: u8 = 0x11;
+
+	// Bits of the message control register of the MSI-X capability.
+	// See PCI Local Bus Specification 3.0 - 6.8.2.3
+	pub(crate) const PCI_MSIX_CONTROL_TABLE_SIZE: u32 = 0x7FF;
+	pub(crate) const PCI_MSIX_CONTROL_FUNCTION_MASK: u32 = 1 << 14;
+	pub(crate) const PCI_MSIX_CONTROL_ENABLE: u32 = 1 << 15;
+	pub(crate) const PCI_MSIX_BIR_MASK: u32 = 0x7;
+	/// Size of an entry of the MSI-X table in bytes
+	pub(crate) const PCI_MSIX_ENTRY_SIZE: usize = 16;
 
 	/// PCI registers offset inside header,
 	/// if PCI header is of type 00h.
@@ -213,6 +240,37 @@ impl<T: ConfigRegionAccess> PciDevice<T> {
 		Some((virtual_address, size))
 	}
 
+	/// Returns the offset of the capability with the id `cap_id` inside the
+	/// configuration space, if the device provides such a capability.
+	pub fn find_capability(&self, cap_id: u8) -> Option<u16> {
+		use crate::drivers::pci::constants::{Masks, RegisterHeader};
+
+		let status = self.read_register(RegisterHeader::PCI_COMMAND_REGISTER.into()) >> 16;
+		if status & u32::from(Masks::PCI_MASK_STATUS_CAPABILITIES_LIST) == 0 {
+			return None;
+		}
+
+		let mut next = self.read_register(RegisterHeader::PCI_CAPABILITY_LIST_REGISTER.into())
+			& u32::from(Masks::PCI_MASK_CAPLIST_POINTER);
+
+		// A capability takes at least 4 of the 192 bytes behind the header,
+		// which bounds the walk through a malformed list.
+		for _ in 0..48 {
+			if next == 0 {
+				break;
+			}
+
+			let header = self.read_register(next.try_into().unwrap());
+			if header & 0xFF == u32::from(cap_id) {
+				return Some(next.try_into().unwrap());
+			}
+
+			next = (header >> 8) & u32::from(Masks::PCI_MASK_CAPLIST_POINTER);
+		}
+
+		None
+	}
+
 	pub fn irq(&self) -> Option<InterruptLine> {
 		let header = PciHeader::new(self.address);
 		if let Some(endpoint) = EndpointHeader::from_header(header, &self.access) {
@@ -249,6 +307,102 @@ impl<T: ConfigRegionAccess> PciDevice<T> {
 	}
 }
 
+#[cfg(target_arch = "x86_64")]
+impl<T: ConfigRegionAccess + Copy> PciDevice<T> {
+	/// Maps the MSI-X table of the device, if the device supports MSI-X.
+	///
+	/// See PCI Local Bus Specification 3.0 - 6.8.2
+	pub fn msix_table(&self) -> Option<MsixTable<T>> {
+		use crate::drivers::pci::constants::*;
+
+		let cap_offset = self.find_capability(PCI_CAP_ID_MSIX)?;
+		let control = self.read_register(cap_offset) >> 16;
+		let size = u16::try_from(control & PCI_MSIX_CONTROL_TABLE_SIZE).unwrap() + 1;
+
+		let table_register = self.read_register(cap_offset + 4);
+		let bar_index = u8::try_from(table_register & PCI_MSIX_BIR_MASK).unwrap();
+		let bar_address = match self.bar(bar_index)? {
+			Bar::Memory32 { address, .. } => u64::from(address),
+			Bar::Memory64 { address, .. } => address,
+			Bar::Io { .. } => {
+				warn!("MSI-X table is located in an IO bar!");
+				return None;
+			}
+		};
+
+		let physical_address = bar_address + u64::from(table_register & !PCI_MSIX_BIR_MASK);
+		let page_offset = usize::try_from(physical_address % BasePageSize::SIZE).unwrap();
+		let virtual_address = crate::mm::map(
+			PhysAddr::from(physical_address - physical_address % BasePageSize::SIZE),
+			page_offset + usize::from(size) * PCI_MSIX_ENTRY_SIZE,
+			true,
+			true,
+			true,
+		);
+
+		Some(MsixTable {
+			device: *self,
+			cap_offset,
+			table: virtual_address + page_offset,
+			size,
+		})
+	}
+}
+
+/// The MSI-X table of a PCI device, which routes each interrupt of the
+/// device to an interrupt vector of a core.
+///
+/// See PCI Local Bus Specification 3.0 - 6.8.2
+#[cfg(target_arch = "x86_64")]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct MsixTable<T: ConfigRegionAccess> {
+	device: PciDevice<T>,
+	/// Offset of the MSI-X capability inside the configuration space
+	cap_offset: u16,
+	/// Virtual address of the mapped table
+	table: VirtAddr,
+	/// Number of entries of the table
+	size: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl<T: ConfigRegionAccess> MsixTable<T> {
+	/// Returns the number of entries of the table.
+	pub fn size(&self) -> u16 {
+		self.size
+	}
+
+	/// Routes the entry `entry` to the interrupt `irq` of the core `core_id`
+	/// and unmasks the entry.
+	pub fn set_entry(&self, entry: u16, core_id: CoreId, irq: u8) {
+		assert!(entry < self.size);
+
+		let (address, data) = apic::msi_message(core_id, 32 + irq);
+		let entry_ptr =
+			(self.table + usize::from(entry) * constants::PCI_MSIX_ENTRY_SIZE).as_mut_ptr::<u32>();
+
+		unsafe {
+			ptr::write_volatile(entry_ptr, address as u32);
+			ptr::write_volatile(entry_ptr.add(1), (address >> 32) as u32);
+			ptr::write_volatile(entry_ptr.add(2), data);
+			// vector control: clear the mask bit
+			ptr::write_volatile(entry_ptr.add(3), 0);
+		}
+	}
+
+	/// Enables MSI-X, which disables the legacy interrupt line of the device.
+	pub fn enable(&self) {
+		use crate::drivers::pci::constants::{
+			PCI_MSIX_CONTROL_ENABLE, PCI_MSIX_CONTROL_FUNCTION_MASK,
+		};
+
+		let mut register = self.device.read_register(self.cap_offset);
+		register |= PCI_MSIX_CONTROL_ENABLE << 16;
+		register &= !(PCI_MSIX_CONTROL_FUNCTION_MASK << 16);
+		self.device.write_register(self.cap_offset, register);
+	}
+}
+
 impl<T: ConfigRegionAccess> fmt::Display for PciDevice<T> {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let header = PciHeader::new(self.address);
@@ -357,8 +511,16 @@ pub(crate) fn print_information() {
 pub(crate) enum PciDriver {
 	VirtioFs(InterruptTicketMutex<VirtioFsDriver>),
 	VirtioNet(InterruptTicketMutex<VirtioNetDriver>),
+	VirtioBlk(InterruptTicketMutex<VirtioBlkDriver>),
+	Virtio9p(InterruptTicketMutex<Virtio9pDriver>),
+	VirtioConsole(InterruptTicketMutex<VirtioConsoleDriver>),
+	VirtioRng(InterruptTicketMutex<VirtioRngDriver>),
+	VirtioBalloon(InterruptTicketMutex<VirtioBalloonDriver>),
+	VirtioVsock(InterruptTicketMutex<VirtioVsockDriver>),
 	#[cfg(not(target_arch = "aarch64"))]
 	RTL8139Net(InterruptTicketMutex<RTL8139Driver>),
+	#[cfg(not(target_arch = "aarch64"))]
+	E1000Net(InterruptTicketMutex<E1000Driver>),
 }
 
 impl PciDriver {
@@ -367,6 +529,8 @@ impl PciDriver {
 			Self::VirtioNet(drv) => Some(drv),
 			#[cfg(not(target_arch = "aarch64"))]
 			Self::RTL8139Net(drv) => Some(drv),
+			#[cfg(not(target_arch = "aarch64"))]
+			Self::E1000Net(drv) => Some(drv),
 			_ => None,
 		}
 	}
@@ -377,6 +541,48 @@ impl PciDriver {
 			_ => None,
 		}
 	}
+
+	fn get_block_driver(&self) -> Option<&InterruptTicketMutex<dyn BlockDevice>> {
+		match self {
+			Self::VirtioBlk(drv) => Some(drv),
+			_ => None,
+		}
+	}
+
+	fn get_9p_driver(&self) -> Option<&InterruptTicketMutex<Virtio9pDriver>> {
+		match self {
+			Self::Virtio9p(drv) => Some(drv),
+			_ => None,
+		}
+	}
+
+	fn get_console_driver(&self) -> Option<&InterruptTicketMutex<VirtioConsoleDriver>> {
+		match self {
+			Self::VirtioConsole(drv) => Some(drv),
+			_ => None,
+		}
+	}
+
+	fn get_entropy_driver(&self) -> Option<&InterruptTicketMutex<VirtioRngDriver>> {
+		match self {
+			Self::VirtioRng(drv) => Some(drv),
+			_ => None,
+		}
+	}
+
+	fn get_balloon_driver(&self) -> Option<&InterruptTicketMutex<VirtioBalloonDriver>> {
+		match self {
+			Self::VirtioBalloon(drv) => Some(drv),
+			_ => None,
+		}
+	}
+
+	fn get_vsock_driver(&self) -> Option<&InterruptTicketMutex<VirtioVsockDriver>> {
+		match self {
+			Self::VirtioVsock(drv) => Some(drv),
+			_ => None,
+		}
+	}
 }
 
 pub(crate) fn register_driver(drv: PciDriver) {
@@ -385,8 +591,13 @@ pub(crate) fn register_driver(drv: PciDriver) {
 	}
 }
 
-pub(crate) fn get_network_driver() -> Option<&'static InterruptTicketMutex<dyn NetworkInterface>> {
-	unsafe { PCI_DRIVERS.iter().find_map(|drv| drv.get_network_driver()) }
+pub(crate) fn get_network_drivers(
+) -> impl Iterator<Item = &'static InterruptTicketMutex<dyn NetworkInterface>> {
+	unsafe {
+		PCI_DRIVERS
+			.iter()
+			.filter_map(|drv| drv.get_network_driver())
+	}
 }
 
 pub(crate) fn get_filesystem_driver() -> Option<&'static InterruptTicketMutex<VirtioFsDriver>> {
@@ -397,6 +608,46 @@ pub(crate) fn get_filesystem_driver() -> Option<&'static InterruptTicketMutex<Vi
 	}
 }
 
+/// Returns all block devices in the order, in which they were found on the PCI bus.
+pub(crate) fn get_block_drivers() -> Vec<&'static InterruptTicketMutex<dyn BlockDevice>> {
+	unsafe {
+		PCI_DRIVERS
+			.iter()
+			.filter_map(|drv| drv.get_block_driver())
+			.collect()
+	}
+}
+
+/// Returns all 9p devices in the order, in which they were found on the PCI bus.
+pub(crate) fn get_9p_drivers() -> Vec<&'static InterruptTicketMutex<Virtio9pDriver>> {
+	unsafe {
+		PCI_DRIVERS
+			.iter()
+			.filter_map(|drv| drv.get_9p_driver())
+			.collect()
+	}
+}
+
+/// Returns the first virtio console found on the PCI bus.
+pub(crate) fn get_console_driver() -> Option<&'static InterruptTicketMutex<VirtioConsoleDriver>> {
+	unsafe { PCI_DRIVERS.iter().find_map(|drv| drv.get_console_driver()) }
+}
+
+/// Returns the first virtio entropy device found on the PCI bus.
+pub(crate) fn get_entropy_driver() -> Option<&'static InterruptTicketMutex<VirtioRngDriver>> {
+	unsafe { PCI_DRIVERS.iter().find_map(|drv| drv.get_entropy_driver()) }
+}
+
+/// Returns the first virtio balloon device found on the PCI bus.
+pub(crate) fn get_balloon_driver() -> Option<&'static InterruptTicketMutex<VirtioBalloonDriver>> {
+	unsafe { PCI_DRIVERS.iter().find_map(|drv| drv.get_balloon_driver()) }
+}
+
+/// Returns the first virtio socket device found on the PCI bus.
+pub(crate) fn get_vsock_driver() -> Option<&'static InterruptTicketMutex<VirtioVsockDriver>> {
+	unsafe { PCI_DRIVERS.iter().find_map(|drv| drv.get_vsock_driver()) }
+}
+
 #[cfg(not(target_arch = "aarch64"))]
 pub(crate) fn init_drivers() {
 	let mut nic_available = false;
@@ -422,13 +673,47 @@ pub(crate) fn init_drivers() {
 				Ok(VirtioDriver::FileSystem(drv)) => {
 					register_driver(PciDriver::VirtioFs(InterruptTicketMutex::new(drv)))
 				}
+				Ok(VirtioDriver::Block(drv)) => {
+					register_driver(PciDriver::VirtioBlk(InterruptTicketMutex::new(drv)))
+				}
+				Ok(VirtioDriver::NineP(drv)) => {
+					register_driver(PciDriver::Virtio9p(InterruptTicketMutex::new(drv)))
+				}
+				Ok(VirtioDriver::Console(drv)) => {
+					register_driver(PciDriver::VirtioConsole(InterruptTicketMutex::new(drv)))
+				}
+				Ok(VirtioDriver::Entropy(drv)) => {
+					register_driver(PciDriver::VirtioRng(InterruptTicketMutex::new(drv)))
+				}
+				Ok(VirtioDriver::Balloon(drv)) => {
+					register_driver(PciDriver::VirtioBalloon(InterruptTicketMutex::new(drv)))
+				}
+				Ok(VirtioDriver::Vsock(drv)) => {
+					register_driver(PciDriver::VirtioVsock(InterruptTicketMutex::new(drv)))
+				}
 				_ => {}
 			}
 		}
 
 		// do we already found a network interface?
-		#[cfg(not(target_arch = "aarch64"))]
 		if !nic_available {
+			// Searching for Intel e1000 and e1000e, which are supported by Qemu
+			for adapter in unsafe {
+				PCI_DEVICES.iter().filter(|x| {
+					let (vendor_id, device_id) = x.id();
+					vendor_id == 0x8086 && e1000::DEVICE_IDS.contains(&device_id)
+				})
+			} {
+				info!(
+					"Found Intel network device with device id {:#x}",
+					adapter.device_id()
+				);
+
+				if let Ok(drv) = e1000::init_device(adapter) {
+					register_driver(PciDriver::E1000Net(InterruptTicketMutex::new(drv)));
+				}
+			}
+
 			// Searching for Realtek RTL8139, which is supported by Qemu
 			for adapter in unsafe {
 				PCI_DEVICES.iter().filter(|x| {
@@ -0,0 +1,260 @@
+//! Feature bits and the feature negotiation, which are shared by the virtio
+//! device drivers.
+
+use core::fmt;
+
+#[cfg(not(feature = "pci"))]
+use crate::drivers::virtio::transport::mmio::ComCfg;
+#[cfg(feature = "pci")]
+use crate::drivers::virtio::transport::pci::ComCfg;
+
+/// Errors of the feature negotiation, which is shared by the virtio drivers.
+#[derive(Debug, Copy, Clone)]
+pub enum FeatureError {
+	/// The device does not offer all feature bits, which the driver requires.
+	/// The first u64 contains the required bits, the second one the bits of the device.
+	Incompatible(u64, u64),
+	/// The device has not accepted the negotiated feature bits.
+	NotAccepted(u64),
+}
+
+impl fmt::Display for FeatureError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			FeatureError::Incompatible(drv_feats, dev_feats) => write!(
+				f,
+				"required feature set {drv_feats:x} is incompatible with the device features {dev_feats:x}!"
+			),
+			FeatureError::NotAccepted(feats) => write!(
+				f,
+				"device did not acknowledge negotiated feature set {feats:x}!"
+			),
+		}
+	}
+}
+
+/// Resets the device and negotiates its features. The device has to offer
+/// the features `required`, whereas the features `optional` are only used,
+/// if the device offers them. Returns the negotiated feature bits.
+///
+/// This covers the steps up to FEATURES_OK of the device initialization.
+/// The driver finishes it by setting DRIVER_OK.
+///
+/// See Virtio specification v1.1. - 3.1.1
+pub(crate) fn negotiate_features<F: Copy + Into<u64>>(
+	com_cfg: &mut ComCfg,
+	required: &[F],
+	optional: &[F],
+) -> Result<u64, FeatureError> {
+	// Reset
+	com_cfg.reset_dev();
+
+	// Indicate device, that OS noticed it
+	com_cfg.ack_dev();
+
+	// Indicate device, that driver is able to handle it
+	com_cfg.set_drv();
+
+	let required = required
+		.iter()
+		.fold(0u64, |bits, feat| bits | (*feat).into());
+	let optional = optional
+		.iter()
+		.fold(0u64, |bits, feat| bits | (*feat).into());
+	let dev_feats = com_cfg.dev_features();
+	if dev_feats & required != required {
+		return Err(FeatureError::Incompatible(required, dev_feats));
+	}
+
+	let drv_feats = (required | optional) & dev_feats;
+	com_cfg.set_drv_features(drv_feats);
+
+	// Indicates the device, that the current feature set is final for the driver
+	// and will not be changed.
+	com_cfg.features_ok();
+
+	// Checks if the device has accepted final set. This finishes feature negotiation.
+	if com_cfg.check_features() {
+		Ok(drv_feats)
+	} else {
+		Err(FeatureError::NotAccepted(drv_feats))
+	}
+}
+
+/// Defines the enum `Features` of a virtio device and the new type `FeatureSet`,
+/// which wraps a set of these features as u64.
+///
+/// The enum lists the device specific features and the general features of
+/// Virtio, see Virtio specification v1.1. - 6. Both types get the conversions
+/// and bit operations, which are common to all drivers. The driver checks the
+/// dependencies between its features in `FeatureSet::check_features`.
+macro_rules! virtio_features {
+	(
+		$(#[$attr:meta])*
+		pub enum Features {
+			$($feat:ident = $bit:expr,)*
+		}
+	) => {
+		$(#[$attr])*
+		#[allow(dead_code, non_camel_case_types)]
+		#[derive(Copy, Clone, Debug)]
+		#[repr(u64)]
+		pub enum Features {
+			$($feat = $bit,)*
+		}
+
+		impl From<Features> for u64 {
+			fn from(val: Features) -> Self {
+				val as u64
+			}
+		}
+
+		impl core::ops::BitOr for Features {
+			type Output = u64;
+
+			fn bitor(self, rhs: Self) -> Self::Output {
+				u64::from(self) | u64::from(rhs)
+			}
+		}
+
+		impl core::ops::BitOr<Features> for u64 {
+			type Output = u64;
+
+			fn bitor(self, rhs: Features) -> Self::Output {
+				self | u64::from(rhs)
+			}
+		}
+
+		impl core::ops::BitOrAssign<Features> for u64 {
+			fn bitor_assign(&mut self, rhs: Features) {
+				*self |= u64::from(rhs);
+			}
+		}
+
+		impl core::ops::BitAnd for Features {
+			type Output = u64;
+
+			fn bitand(self, rhs: Features) -> Self::Output {
+				u64::from(self) & u64::from(rhs)
+			}
+		}
+
+		impl core::ops::BitAnd<Features> for u64 {
+			type Output = u64;
+
+			fn bitand(self, rhs: Features) -> Self::Output {
+				self & u64::from(rhs)
+			}
+		}
+
+		impl core::ops::BitAndAssign<Features> for u64 {
+			fn bitand_assign(&mut self, rhs: Features) {
+				*self &= u64::from(rhs);
+			}
+		}
+
+		impl core::fmt::Display for Features {
+			fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+				match *self {
+					$(Features::$feat => f.write_str(stringify!($feat)),)*
+				}
+			}
+		}
+
+		impl Features {
+			/// Returns a vector of the [Features](Features), which are contained
+			/// in a feature set. Returns `None` for an empty set.
+			pub fn from_set(feat_set: FeatureSet) -> Option<alloc::vec::Vec<Features>> {
+				const ALL: &[Features] = &[$(Features::$feat,)*];
+
+				let vec_of_feats: alloc::vec::Vec<Features> = ALL
+					.iter()
+					.copied()
+					.filter(|feat| feat_set.is_feature(*feat))
+					.collect();
+
+				if vec_of_feats.is_empty() {
+					None
+				} else {
+					Some(vec_of_feats)
+				}
+			}
+		}
+
+		/// FeatureSet is a new type which holds a set of [Features](Features)
+		/// wrapping a u64.
+		#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
+		pub struct FeatureSet(u64);
+
+		impl core::ops::BitOr for FeatureSet {
+			type Output = FeatureSet;
+
+			fn bitor(self, rhs: Self) -> Self::Output {
+				FeatureSet(self.0 | rhs.0)
+			}
+		}
+
+		impl core::ops::BitOr<FeatureSet> for u64 {
+			type Output = u64;
+
+			fn bitor(self, rhs: FeatureSet) -> Self::Output {
+				self | u64::from(rhs)
+			}
+		}
+
+		impl core::ops::BitOrAssign<FeatureSet> for u64 {
+			fn bitor_assign(&mut self, rhs: FeatureSet) {
+				*self |= u64::from(rhs);
+			}
+		}
+
+		impl core::ops::BitOrAssign<Features> for FeatureSet {
+			fn bitor_assign(&mut self, rhs: Features) {
+				self.0 |= u64::from(rhs);
+			}
+		}
+
+		impl core::ops::BitAnd for FeatureSet {
+			type Output = FeatureSet;
+
+			fn bitand(self, rhs: FeatureSet) -> Self::Output {
+				FeatureSet(self.0 & rhs.0)
+			}
+		}
+
+		impl core::ops::BitAnd<FeatureSet> for u64 {
+			type Output = u64;
+
+			fn bitand(self, rhs: FeatureSet) -> Self::Output {
+				self & u64::from(rhs)
+			}
+		}
+
+		impl core::ops::BitAndAssign<FeatureSet> for u64 {
+			fn bitand_assign(&mut self, rhs: FeatureSet) {
+				*self &= u64::from(rhs);
+			}
+		}
+
+		impl From<FeatureSet> for u64 {
+			fn from(feature_set: FeatureSet) -> Self {
+				feature_set.0
+			}
+		}
+
+		impl FeatureSet {
+			/// Checks if a given feature is set.
+			pub fn is_feature(self, feat: Features) -> bool {
+				self.0 & feat != 0
+			}
+
+			/// Returns a new instance of (FeatureSet)[FeatureSet] with all features
+			/// initialized to false.
+			pub fn new(val: u64) -> Self {
+				FeatureSet(val)
+			}
+		}
+	};
+}
+
+pub(crate) use virtio_features;
@@ -2,17 +2,30 @@
 //!
 //! The module contains virtios transport mechanisms, virtqueues and virtio specific errors
 pub mod env;
+pub(crate) mod features;
 pub mod transport;
 pub mod virtqueue;
 
 pub mod error {
 	use core::fmt;
 
+	#[cfg(feature = "pci")]
+	pub use crate::drivers::balloon::virtio_balloon::error::VirtioBalloonError;
+	#[cfg(feature = "pci")]
+	pub use crate::drivers::block::virtio_blk::error::VirtioBlkError;
+	#[cfg(feature = "pci")]
+	pub use crate::drivers::console::virtio_console::error::VirtioConsoleError;
+	#[cfg(feature = "pci")]
+	pub use crate::drivers::entropy::virtio_rng::error::VirtioRngError;
+	#[cfg(feature = "pci")]
+	pub use crate::drivers::fs::virtio_9p::error::Virtio9pError;
 	#[cfg(feature = "pci")]
 	pub use crate::drivers::fs::virtio_fs::error::VirtioFsError;
 	pub use crate::drivers::net::virtio_net::error::VirtioNetError;
 	#[cfg(feature = "pci")]
 	use crate::drivers::pci::error::PciError;
+	#[cfg(feature = "pci")]
+	pub use crate::drivers::vsock::virtio_vsock::error::VirtioVsockError;
 
 	#[derive(Debug)]
 	pub enum VirtioError {
@@ -22,6 +35,18 @@ pub mod error {
 		NetDriver(VirtioNetError),
 		#[cfg(feature = "pci")]
 		FsDriver(VirtioFsError),
+		#[cfg(feature = "pci")]
+		BlkDriver(VirtioBlkError),
+		#[cfg(feature = "pci")]
+		NinePDriver(Virtio9pError),
+		#[cfg(feature = "pci")]
+		ConsoleDriver(VirtioConsoleError),
+		#[cfg(feature = "pci")]
+		RngDriver(VirtioRngError),
+		#[cfg(feature = "pci")]
+		BalloonDriver(VirtioBalloonError),
+		#[cfg(feature = "pci")]
+		VsockDriver(VirtioVsockError),
 		#[cfg(not(feature = "pci"))]
 		Unknown,
 	}
@@ -46,10 +71,11 @@ pub mod error {
                     VirtioNetError::NoComCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed common config!"),
                     VirtioNetError::NoIsrCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
                     VirtioNetError::NoNotifCfg(id) =>  write!(f, "Virtio network driver failed, for device {id:x}, due to a missing or malformed notification config!"),
-                    VirtioNetError::FailFeatureNeg(id) => write!(f, "Virtio network driver failed, for device {id:x}, device did not acknowledge negotiated feature set!"),
+                    VirtioNetError::FeatureNeg(id, err) => write!(f, "Virtio network driver failed, for device {id:x}, {err}"),
                     VirtioNetError::FeatReqNotMet(feats) => write!(f, "Virtio network driver tried to set feature bit without setting dependency feature. Feat set: {:x}", u64::from(*feats)),
-                    VirtioNetError::IncompFeatsSet(drv_feats, dev_feats) => write!(f, "Feature set: {:x} , is incompatible with the device features: {:x}", u64::from(*drv_feats), u64::from(*dev_feats)),
                     VirtioNetError::ProcessOngoing => write!(f, "Virtio network performed an unsuitable operation upon an ongoging transfer."),
+                    VirtioNetError::FailCtrlCmd(class, cmd) => write!(f, "Virtio network device did not acknowledge command {cmd} of class {class} on the control queue."),
+                    VirtioNetError::FeatNotNeg(feat) => write!(f, "Virtio network driver requires feature {feat}, which has not been negotiated."),
 					VirtioNetError::Unknown => write!(f, "Virtio network driver failed due unknown reason!"),
                 },
 				#[cfg(feature = "pci")]
@@ -58,10 +84,62 @@ pub mod error {
 					VirtioFsError::NoComCfg(id) =>  write!(f, "Virtio filesystem driver failed, for device {id:x}, due to a missing or malformed common config!"),
 					VirtioFsError::NoIsrCfg(id) =>  write!(f, "Virtio filesystem driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
                     VirtioFsError::NoNotifCfg(id) =>  write!(f, "Virtio filesystem driver failed, for device {id:x}, due to a missing or malformed notification config!"),
-					VirtioFsError::FailFeatureNeg(id) => write!(f, "Virtio filesystem driver failed, for device {id:x}, device did not acknowledge negotiated feature set!"),
-					VirtioFsError::IncompFeatsSet(drv_feats, dev_feats) => write!(f, "Feature set: {:x} , is incompatible with the device features: {:x}", u64::from(*drv_feats), u64::from(*dev_feats)),
+					VirtioFsError::FeatureNeg(id, err) => write!(f, "Virtio filesystem driver failed, for device {id:x}, {err}"),
 					VirtioFsError::Unknown => write!(f, "Virtio filesystem failed, driver failed due unknown reason!"),
 				},
+				#[cfg(feature = "pci")]
+				VirtioError::BlkDriver(blk_error) => match blk_error {
+					VirtioBlkError::NoDevCfg(id) => write!(f, "Virtio block driver failed, for device {id:x}, due to a missing or malformed device config!"),
+					VirtioBlkError::NoComCfg(id) =>  write!(f, "Virtio block driver failed, for device {id:x}, due to a missing or malformed common config!"),
+					VirtioBlkError::NoIsrCfg(id) =>  write!(f, "Virtio block driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+					VirtioBlkError::NoNotifCfg(id) =>  write!(f, "Virtio block driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+					VirtioBlkError::FeatureNeg(id, err) => write!(f, "Virtio block driver failed, for device {id:x}, {err}"),
+					VirtioBlkError::Unknown => write!(f, "Virtio block driver failed due unknown reason!"),
+				},
+				#[cfg(feature = "pci")]
+				VirtioError::NinePDriver(p9_error) => match p9_error {
+					Virtio9pError::NoDevCfg(id) => write!(f, "Virtio 9p driver failed, for device {id:x}, due to a missing or malformed device config!"),
+					Virtio9pError::NoComCfg(id) =>  write!(f, "Virtio 9p driver failed, for device {id:x}, due to a missing or malformed common config!"),
+					Virtio9pError::NoIsrCfg(id) =>  write!(f, "Virtio 9p driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+					Virtio9pError::NoNotifCfg(id) =>  write!(f, "Virtio 9p driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+					Virtio9pError::FeatureNeg(id, err) => write!(f, "Virtio 9p driver failed, for device {id:x}, {err}"),
+					Virtio9pError::Unknown => write!(f, "Virtio 9p driver failed due unknown reason!"),
+				},
+				#[cfg(feature = "pci")]
+				VirtioError::ConsoleDriver(cons_error) => match cons_error {
+					VirtioConsoleError::NoDevCfg(id) => write!(f, "Virtio console driver failed, for device {id:x}, due to a missing or malformed device config!"),
+					VirtioConsoleError::NoComCfg(id) =>  write!(f, "Virtio console driver failed, for device {id:x}, due to a missing or malformed common config!"),
+					VirtioConsoleError::NoIsrCfg(id) =>  write!(f, "Virtio console driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+					VirtioConsoleError::NoNotifCfg(id) =>  write!(f, "Virtio console driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+					VirtioConsoleError::FeatureNeg(id, err) => write!(f, "Virtio console driver failed, for device {id:x}, {err}"),
+					VirtioConsoleError::Unknown => write!(f, "Virtio console driver failed due unknown reason!"),
+				},
+				#[cfg(feature = "pci")]
+				VirtioError::RngDriver(rng_error) => match rng_error {
+					VirtioRngError::NoComCfg(id) =>  write!(f, "Virtio entropy driver failed, for device {id:x}, due to a missing or malformed common config!"),
+					VirtioRngError::NoIsrCfg(id) =>  write!(f, "Virtio entropy driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+					VirtioRngError::NoNotifCfg(id) =>  write!(f, "Virtio entropy driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+					VirtioRngError::FeatureNeg(id, err) => write!(f, "Virtio entropy driver failed, for device {id:x}, {err}"),
+					VirtioRngError::Unknown => write!(f, "Virtio entropy driver failed due unknown reason!"),
+				},
+				#[cfg(feature = "pci")]
+				VirtioError::BalloonDriver(balloon_error) => match balloon_error {
+					VirtioBalloonError::NoDevCfg(id) => write!(f, "Virtio balloon driver failed, for device {id:x}, due to a missing or malformed device config!"),
+					VirtioBalloonError::NoComCfg(id) =>  write!(f, "Virtio balloon driver failed, for device {id:x}, due to a missing or malformed common config!"),
+					VirtioBalloonError::NoIsrCfg(id) =>  write!(f, "Virtio balloon driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+					VirtioBalloonError::NoNotifCfg(id) =>  write!(f, "Virtio balloon driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+					VirtioBalloonError::FeatureNeg(id, err) => write!(f, "Virtio balloon driver failed, for device {id:x}, {err}"),
+					VirtioBalloonError::Unknown => write!(f, "Virtio balloon driver failed due unknown reason!"),
+				},
+				#[cfg(feature = "pci")]
+				VirtioError::VsockDriver(vsock_error) => match vsock_error {
+					VirtioVsockError::NoDevCfg(id) => write!(f, "Virtio vsock driver failed, for device {id:x}, due to a missing or malformed device config!"),
+					VirtioVsockError::NoComCfg(id) =>  write!(f, "Virtio vsock driver failed, for device {id:x}, due to a missing or malformed common config!"),
+					VirtioVsockError::NoIsrCfg(id) =>  write!(f, "Virtio vsock driver failed, for device {id:x}, due to a missing or malformed ISR status config!"),
+					VirtioVsockError::NoNotifCfg(id) =>  write!(f, "Virtio vsock driver failed, for device {id:x}, due to a missing or malformed notification config!"),
+					VirtioVsockError::FeatureNeg(id, err) => write!(f, "Virtio vsock driver failed, for device {id:x}, {err}"),
+					VirtioVsockError::Unknown => write!(f, "Virtio vsock driver failed due unknown reason!"),
+				},
             }
 		}
 	}
@@ -10,15 +10,31 @@ use core::result::Result;
 use crate::arch::kernel::interrupts::*;
 use crate::arch::mm::PhysAddr;
 use crate::arch::pci::PciConfigRegion;
+use crate::drivers::balloon::virtio_balloon::VirtioBalloonDriver;
+use crate::drivers::block::virtio_blk::VirtioBlkDriver;
+use crate::drivers::console::virtio_console::VirtioConsoleDriver;
+use crate::drivers::entropy::virtio_rng::VirtioRngDriver;
 use crate::drivers::error::DriverError;
+use crate::drivers::fs::virtio_9p::Virtio9pDriver;
 use crate::drivers::fs::virtio_fs::VirtioFsDriver;
 use crate::drivers::net::network_irqhandler;
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::pci::error::PciError;
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::pci::MsixTable;
 use crate::drivers::pci::PciDevice;
 use crate::drivers::virtio::device;
 use crate::drivers::virtio::env::memory::{MemLen, MemOff, VirtMemAddr};
 use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::vsock::virtio_vsock::VirtioVsockDriver;
+use crate::drivers::vsock::vsock_irqhandler;
+#[cfg(target_arch = "x86_64")]
+use crate::scheduler::CoreId;
+
+/// Indicates, that no MSI-X vector is assigned to an event.
+///
+/// See Virtio specification v1.1. - 4.1.5.1.2
+pub const VIRTIO_MSI_NO_VECTOR: u16 = 0xFFFF;
 
 /// Virtio device ID's
 /// See Virtio specification v1.1. - 5
@@ -40,6 +56,12 @@ pub enum DevId {
 	VIRTIO_TRANS_DEV_ID_ENTROPY = 0x1005,
 	VIRTIO_TRANS_DEV_ID_9P = 0x1009,
 	VIRTIO_DEV_ID_NET = 0x1041,
+	VIRTIO_DEV_ID_BLK = 0x1042,
+	VIRTIO_DEV_ID_CONS = 0x1043,
+	VIRTIO_DEV_ID_ENTROPY = 0x1044,
+	VIRTIO_DEV_ID_MEM_BALL = 0x1045,
+	VIRTIO_DEV_ID_9P = 0x1049,
+	VIRTIO_DEV_ID_VSOCK = 0x1053,
 	VIRTIO_DEV_ID_FS = 0x105A,
 }
 
@@ -54,6 +76,12 @@ impl From<DevId> for u16 {
 			DevId::VIRTIO_TRANS_DEV_ID_ENTROPY => 0x1005,
 			DevId::VIRTIO_TRANS_DEV_ID_9P => 0x1009,
 			DevId::VIRTIO_DEV_ID_NET => 0x1041,
+			DevId::VIRTIO_DEV_ID_BLK => 0x1042,
+			DevId::VIRTIO_DEV_ID_CONS => 0x1043,
+			DevId::VIRTIO_DEV_ID_ENTROPY => 0x1044,
+			DevId::VIRTIO_DEV_ID_MEM_BALL => 0x1045,
+			DevId::VIRTIO_DEV_ID_9P => 0x1049,
+			DevId::VIRTIO_DEV_ID_VSOCK => 0x1053,
 			DevId::VIRTIO_DEV_ID_FS => 0x105A,
 			DevId::INVALID => 0x0,
 		}
@@ -71,6 +99,12 @@ impl From<u16> for DevId {
 			0x1005 => DevId::VIRTIO_TRANS_DEV_ID_ENTROPY,
 			0x1009 => DevId::VIRTIO_TRANS_DEV_ID_9P,
 			0x1041 => DevId::VIRTIO_DEV_ID_NET,
+			0x1042 => DevId::VIRTIO_DEV_ID_BLK,
+			0x1043 => DevId::VIRTIO_DEV_ID_CONS,
+			0x1044 => DevId::VIRTIO_DEV_ID_ENTROPY,
+			0x1045 => DevId::VIRTIO_DEV_ID_MEM_BALL,
+			0x1049 => DevId::VIRTIO_DEV_ID_9P,
+			0x1053 => DevId::VIRTIO_DEV_ID_VSOCK,
 			0x105A => DevId::VIRTIO_DEV_ID_FS,
 			_ => DevId::INVALID,
 		}
@@ -435,6 +469,16 @@ impl<'a> VqCfgHandler<'a> {
 		self.raw.queue_select = self.vq_index;
 		self.raw.queue_enable = 1;
 	}
+
+	/// Assigns the MSI-X table entry `vector` to the notifications of the queue.
+	///
+	/// Returns `false`, if the device was unable to assign the vector.
+	pub fn set_msix_vector(&mut self, vector: u16) -> bool {
+		self.raw.queue_select = self.vq_index;
+		self.raw.queue_msix_vector = vector;
+
+		self.raw.queue_msix_vector == vector
+	}
 }
 
 // Public Interface of ComCfg
@@ -456,6 +500,15 @@ impl ComCfg {
 		}
 	}
 
+	/// Assigns the MSI-X table entry `vector` to configuration changes.
+	///
+	/// Returns `false`, if the device was unable to assign the vector.
+	pub fn set_config_vector(&mut self, vector: u16) -> bool {
+		self.com_cfg.config_msix_vector = vector;
+
+		self.com_cfg.config_msix_vector == vector
+	}
+
 	/// Returns the device status field.
 	pub fn dev_status(&self) -> u8 {
 		self.com_cfg.device_status
@@ -1213,6 +1266,47 @@ pub(crate) fn map_caps(device: &PciDevice<PciConfigRegion>) -> Result<UniCapsCol
 	check_caps(caps)
 }
 
+/// Routes the MSI-X table entry `entry` to a newly allocated interrupt, which
+/// is delivered to the core `core_id` and handled by `handler`.
+///
+/// Returns the number of the interrupt or `None`, if all interrupts are in use.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn install_msix_handler(
+	msix: &MsixTable<PciConfigRegion>,
+	entry: u16,
+	core_id: CoreId,
+	handler: extern "x86-interrupt" fn(ExceptionStackFrame),
+	name: &'static str,
+) -> Option<u8> {
+	let irq = allocate_msi_irq()?;
+	irq_install_handler(irq, handler);
+	add_irq_name(irq, name);
+	msix.set_entry(entry, core_id, irq);
+
+	Some(irq)
+}
+
+/// Detaches the MSI-X vectors from configuration changes and from the virtqueues
+/// `vq_indices` and releases the interrupts `irqs`, after the setup of the vectors
+/// has failed. The device then uses the legacy interrupt.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn free_msix_handlers(
+	com_cfg: &mut ComCfg,
+	vq_indices: impl IntoIterator<Item = u16>,
+	irqs: &[u8],
+) {
+	com_cfg.set_config_vector(VIRTIO_MSI_NO_VECTOR);
+	for vq_index in vq_indices {
+		if let Some(mut vq_cfg) = com_cfg.select_vq(vq_index) {
+			vq_cfg.set_msix_vector(VIRTIO_MSI_NO_VECTOR);
+		}
+	}
+
+	for irq in irqs {
+		free_msi_irq(*irq);
+	}
+}
+
 /// Checks existing drivers for support of given device. Upon match, provides
 /// driver with a [Caplist](struct.Caplist.html) struct, holding the structures of the capabilities
 /// list of the given device.
@@ -1222,13 +1316,7 @@ pub(crate) fn init_device(
 	let device_id = device.device_id();
 
 	let virt_drv = match DevId::from(device_id) {
-		DevId::VIRTIO_TRANS_DEV_ID_NET
-		| DevId::VIRTIO_TRANS_DEV_ID_BLK
-		| DevId::VIRTIO_TRANS_DEV_ID_MEM_BALL
-		| DevId::VIRTIO_TRANS_DEV_ID_CONS
-		| DevId::VIRTIO_TRANS_DEV_ID_SCSI
-		| DevId::VIRTIO_TRANS_DEV_ID_ENTROPY
-		| DevId::VIRTIO_TRANS_DEV_ID_9P => {
+		DevId::VIRTIO_TRANS_DEV_ID_NET | DevId::VIRTIO_TRANS_DEV_ID_SCSI => {
 			warn!(
 				"Legacy/transitional Virtio device, with id: {:#x} is NOT supported, skipping!",
 				device_id
@@ -1252,6 +1340,101 @@ pub(crate) fn init_device(
 				Err(DriverError::InitVirtioDevFail(virtio_error))
 			}
 		},
+		// Transitional block devices (e.g. Qemu's default virtio-blk-pci) provide the
+		// modern virtio capabilities as well, which are used by the driver.
+		DevId::VIRTIO_TRANS_DEV_ID_BLK | DevId::VIRTIO_DEV_ID_BLK => {
+			match VirtioBlkDriver::init(device) {
+				Ok(virt_blk_drv) => {
+					info!("Virtio block driver initialized.");
+					Ok(VirtioDriver::Block(virt_blk_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio block driver could not be initialized with device: {:x}",
+						device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
+				}
+			}
+		}
+		// Like block devices, transitional console devices provide the modern capabilities.
+		DevId::VIRTIO_TRANS_DEV_ID_CONS | DevId::VIRTIO_DEV_ID_CONS => {
+			match VirtioConsoleDriver::init(device) {
+				Ok(virt_cons_drv) => {
+					info!("Virtio console driver initialized.");
+					Ok(VirtioDriver::Console(virt_cons_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio console driver could not be initialized with device: {:x}",
+						device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
+				}
+			}
+		}
+		// Like block devices, transitional entropy devices provide the modern capabilities.
+		DevId::VIRTIO_TRANS_DEV_ID_ENTROPY | DevId::VIRTIO_DEV_ID_ENTROPY => {
+			match VirtioRngDriver::init(device) {
+				Ok(virt_rng_drv) => {
+					info!("Virtio entropy driver initialized.");
+					Ok(VirtioDriver::Entropy(virt_rng_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio entropy driver could not be initialized with device: {:x}",
+						device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
+				}
+			}
+		}
+		// Like block devices, transitional balloon devices provide the modern capabilities.
+		DevId::VIRTIO_TRANS_DEV_ID_MEM_BALL | DevId::VIRTIO_DEV_ID_MEM_BALL => {
+			match VirtioBalloonDriver::init(device) {
+				Ok(virt_balloon_drv) => {
+					info!("Virtio balloon driver initialized.");
+					Ok(VirtioDriver::Balloon(virt_balloon_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio balloon driver could not be initialized with device: {:x}",
+						device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
+				}
+			}
+		}
+		// Like block devices, transitional 9p devices provide the modern capabilities.
+		DevId::VIRTIO_TRANS_DEV_ID_9P | DevId::VIRTIO_DEV_ID_9P => {
+			match Virtio9pDriver::init(device) {
+				Ok(virt_9p_drv) => {
+					info!("Virtio 9p driver initialized.");
+					Ok(VirtioDriver::NineP(virt_9p_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio 9p driver could not be initialized with device: {:x}",
+						device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
+				}
+			}
+		}
+		// Socket devices have no transitional id.
+		DevId::VIRTIO_DEV_ID_VSOCK => match VirtioVsockDriver::init(device) {
+			Ok(virt_vsock_drv) => {
+				info!("Virtio vsock driver initialized.");
+				Ok(VirtioDriver::Vsock(virt_vsock_drv))
+			}
+			Err(virtio_error) => {
+				error!(
+					"Virtio vsock driver could not be initialized with device: {:x}",
+					device_id
+				);
+				Err(DriverError::InitVirtioDevFail(virtio_error))
+			}
+		},
 		DevId::VIRTIO_DEV_ID_FS => {
 			// TODO: check subclass
 			// TODO: proper error handling on driver creation fail
@@ -1286,6 +1469,12 @@ pub(crate) fn init_device(
 		Ok(drv) => {
 			match &drv {
 				VirtioDriver::Network(_) => {
+					// The driver has already installed the handlers of its MSI-X vectors.
+					#[cfg(target_arch = "x86_64")]
+					if matches!(&drv, VirtioDriver::Network(net_drv) if net_drv.is_msix_enabled()) {
+						return Ok(drv);
+					}
+
 					let irq = device.irq().unwrap();
 					info!("Install virtio interrupt handler at line {}", irq);
 					// Install interrupt handler
@@ -1295,6 +1484,26 @@ pub(crate) fn init_device(
 					Ok(drv)
 				}
 				VirtioDriver::FileSystem(_) => Ok(drv),
+				VirtioDriver::Block(_) => Ok(drv),
+				VirtioDriver::NineP(_) => Ok(drv),
+				VirtioDriver::Console(_) => Ok(drv),
+				VirtioDriver::Entropy(_) => Ok(drv),
+				VirtioDriver::Balloon(_) => Ok(drv),
+				VirtioDriver::Vsock(_) => {
+					// The driver has already installed the handler of its MSI-X vector.
+					#[cfg(target_arch = "x86_64")]
+					if matches!(&drv, VirtioDriver::Vsock(vsock_drv) if vsock_drv.is_msix_enabled())
+					{
+						return Ok(drv);
+					}
+
+					let irq = device.irq().unwrap();
+					info!("Install virtio vsock interrupt handler at line {}", irq);
+					irq_install_handler(irq, vsock_irqhandler);
+					add_irq_name(irq, "virtio_vsock");
+
+					Ok(drv)
+				}
 			}
 		}
 		Err(virt_err) => Err(virt_err),
@@ -1304,4 +1513,10 @@ pub(crate) fn init_device(
 pub(crate) enum VirtioDriver {
 	Network(VirtioNetDriver),
 	FileSystem(VirtioFsDriver),
+	Block(VirtioBlkDriver),
+	NineP(Virtio9pDriver),
+	Console(VirtioConsoleDriver),
+	Entropy(VirtioRngDriver),
+	Balloon(VirtioBalloonDriver),
+	Vsock(VirtioVsockDriver),
 }
@@ -561,6 +561,10 @@ pub trait AsSliceU8 {
 	}
 }
 
+/// Plain byte slices can be provided to the queue as they are, e.g. in order to write
+/// payloads into a [BufferToken](BufferToken) via `BufferToken.write()`.
+impl AsSliceU8 for [u8] {}
+
 /// The [Transfer](Transfer) will be received when a [TransferToken](TransferToken) is dispatched via `TransferToken.dispatch()` or
 /// via `TransferToken.dispatch_blocking()`.
 ///
@@ -0,0 +1,64 @@
+//! A module containing hermit-rs socket device drivers.
+
+pub mod virtio_pci;
+pub mod virtio_vsock;
+
+#[cfg(target_arch = "x86_64")]
+use crate::arch::kernel::apic;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::kernel::interrupts::ExceptionStackFrame;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::scheduler::State;
+use crate::core_scheduler;
+use crate::drivers::pci::get_vsock_driver;
+
+/// Identifies a stream connection by the local port and the address of the peer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConnectionId {
+	pub local_port: u32,
+	pub peer_cid: u64,
+	pub peer_port: u32,
+}
+
+/// Errors reported by a socket device driver.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VsockError {
+	/// The operation cannot be completed without blocking.
+	WouldBlock,
+	/// The port is already in use.
+	AddrInUse,
+	/// No free port is available.
+	AddrNotAvailable,
+	/// The peer refused the connection.
+	ConnectionRefused,
+	/// The connection has been reset by the peer or the device.
+	ConnectionReset,
+	/// The connection does not exist or has not been established.
+	NotConnected,
+	/// The connection has been shut down for sending.
+	BrokenPipe,
+	/// The data could not be transferred.
+	Io,
+}
+
+#[inline]
+fn _irqhandler() {
+	let has_packet = get_vsock_driver().map_or(false, |driver| driver.lock().handle_interrupt());
+
+	if has_packet {
+		core_scheduler().scheduler();
+	}
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn vsock_irqhandler(_state: &State) {
+	debug!("Receive vsock interrupt");
+	_irqhandler();
+}
+
+#[cfg(target_arch = "x86_64")]
+pub extern "x86-interrupt" fn vsock_irqhandler(_stack_frame: ExceptionStackFrame) {
+	debug!("Receive vsock interrupt");
+	apic::eoi();
+	_irqhandler();
+}
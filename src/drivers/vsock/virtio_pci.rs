@@ -0,0 +1,179 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::ptr;
+
+use crate::arch::pci::PciConfigRegion;
+use crate::drivers::pci::PciDevice;
+use crate::drivers::virtio::error::{self, VirtioError};
+use crate::drivers::virtio::transport::pci;
+use crate::drivers::virtio::transport::pci::{PciCap, UniCapsColl};
+use crate::drivers::vsock::virtio_vsock::constants::FeatureSet;
+use crate::drivers::vsock::virtio_vsock::{VirtioVsockDriver, VsockDevCfg, EPHEMERAL_PORTS};
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::vsock::vsock_irqhandler;
+
+/// Virtio's socket device configuration structure.
+/// See specification v1.2. - 5.10.4
+///
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct VsockDevCfgRaw {
+	/// Context id of the guest, which is assigned by the host.
+	guest_cid: u64,
+}
+
+impl VsockDevCfgRaw {
+	pub fn get_guest_cid(&self) -> u64 {
+		// The host changes the value after a transport reset
+		unsafe { ptr::read_volatile(&self.guest_cid) }
+	}
+}
+
+impl VirtioVsockDriver {
+	fn map_cfg(cap: &PciCap) -> Option<VsockDevCfg> {
+		let dev_cfg: &'static VsockDevCfgRaw = match pci::map_dev_cfg::<VsockDevCfgRaw>(cap) {
+			Some(cfg) => cfg,
+			None => return None,
+		};
+
+		Some(VsockDevCfg {
+			raw: dev_cfg,
+			dev_id: cap.dev_id(),
+			features: FeatureSet::new(0),
+		})
+	}
+
+	/// Instantiates a new (VirtioVsockDriver)[VirtioVsockDriver] struct, by checking the available
+	/// configuration structures and moving them into the struct.
+	pub fn new(
+		mut caps_coll: UniCapsColl,
+		device: &PciDevice<PciConfigRegion>,
+	) -> Result<Self, error::VirtioVsockError> {
+		let device_id = device.device_id();
+
+		let com_cfg = match caps_coll.get_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(error::VirtioVsockError::NoComCfg(device_id));
+			}
+		};
+
+		let isr_stat = match caps_coll.get_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(error::VirtioVsockError::NoIsrCfg(device_id));
+			}
+		};
+
+		let notif_cfg = match caps_coll.get_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(error::VirtioVsockError::NoNotifCfg(device_id));
+			}
+		};
+
+		let dev_cfg = loop {
+			match caps_coll.get_dev_cfg() {
+				Some(cfg) => {
+					if let Some(dev_cfg) = VirtioVsockDriver::map_cfg(&cfg) {
+						break dev_cfg;
+					}
+				}
+				None => {
+					error!("No dev config. Aborting!");
+					return Err(error::VirtioVsockError::NoDevCfg(device_id));
+				}
+			}
+		};
+
+		Ok(VirtioVsockDriver {
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+			irq: device.irq().unwrap(),
+			#[cfg(target_arch = "x86_64")]
+			msix: device.msix_table(),
+			rx: None,
+			tx: None,
+			event: None,
+			guest_cid: 0,
+			bound: BTreeSet::new(),
+			listeners: BTreeMap::new(),
+			connections: BTreeMap::new(),
+			wakers: BTreeMap::new(),
+			next_port: *EPHEMERAL_PORTS.start(),
+		})
+	}
+
+	/// Returns true, if the device signals its interrupts via MSI-X.
+	#[cfg(target_arch = "x86_64")]
+	pub(crate) fn is_msix_enabled(&self) -> bool {
+		self.msix.is_some()
+	}
+
+	/// Assigns a single MSI-X vector to the receive and the event queue, which
+	/// are the only queues with enabled notifications.
+	///
+	/// Falls back to the legacy interrupt line, if the vector cannot be assigned.
+	#[cfg(target_arch = "x86_64")]
+	pub(super) fn setup_msix(&mut self) {
+		let msix = match self.msix.take() {
+			Some(msix) => msix,
+			None => return,
+		};
+
+		let vq_indices = [0, 2];
+		let irq = pci::install_msix_handler(&msix, 0, 0, vsock_irqhandler, "virtio_vsock");
+		let irqs: Vec<u8> = irq.into_iter().collect();
+
+		for vq_index in vq_indices {
+			let vq_cfg = self.com_cfg.select_vq(vq_index);
+
+			if irq.is_none() || !vq_cfg.map_or(false, |mut vq_cfg| vq_cfg.set_msix_vector(0)) {
+				warn!("Unable to assign a MSI-X vector to virtqueue {}", vq_index);
+				pci::free_msix_handlers(&mut self.com_cfg, vq_indices, &irqs);
+				return;
+			}
+		}
+
+		msix.enable();
+		info!("Virtio socket device {:x} uses MSI-X", self.dev_cfg.dev_id);
+
+		self.irq = irq.unwrap();
+		self.msix = Some(msix);
+	}
+
+	/// Initializes virtio socket device
+	pub fn init(device: &PciDevice<PciConfigRegion>) -> Result<VirtioVsockDriver, VirtioError> {
+		let mut drv = match pci::map_caps(device) {
+			Ok(caps) => match VirtioVsockDriver::new(caps, device) {
+				Ok(driver) => driver,
+				Err(vsock_err) => {
+					error!("Initializing new vsock driver failed. Aborting!");
+					return Err(VirtioError::VsockDriver(vsock_err));
+				}
+			},
+			Err(pci_error) => {
+				error!("Mapping capabilities failed. Aborting!");
+				return Err(VirtioError::FromPci(pci_error));
+			}
+		};
+
+		match drv.init_dev() {
+			Ok(_) => info!(
+				"Socket device with id {:x}, has been initialized by driver!",
+				drv.get_dev_id()
+			),
+			Err(vsock_err) => {
+				drv.set_failed();
+				return Err(VirtioError::VsockDriver(vsock_err));
+			}
+		}
+
+		Ok(drv)
+	}
+}
@@ -0,0 +1,863 @@
+//! A module containing a virtio socket device driver.
+//!
+//! The driver implements stream sockets, which allow the communication with
+//! services of the host by context id (CID) and port without an IP network.
+//! Connections are multiplexed over a single pair of receive and transmit queues
+//! and use the credit based flow control of the device. The transmit queue is
+//! polled, while received packets and events are signaled by an interrupt,
+//! which wakes up the tasks waiting on the addressed port.
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ops::RangeInclusive;
+use core::task::Waker;
+use core::{cmp, mem};
+
+use pci_types::InterruptLine;
+use zerocopy::AsBytes;
+
+use self::constants::{FeatureSet, Features};
+use self::error::VirtioVsockError;
+use crate::arch::kernel::core_local::increment_irq_counter;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::pci::PciConfigRegion;
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+#[cfg(target_arch = "x86_64")]
+use crate::drivers::pci::MsixTable;
+use crate::drivers::virtio::features::negotiate_features;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg};
+use crate::drivers::virtio::virtqueue::{
+	BuffSpec, Bytes, Transfer, Virtq, VqIndex, VqSize, VqType,
+};
+use crate::drivers::vsock::virtio_pci::VsockDevCfgRaw;
+use crate::drivers::vsock::{ConnectionId, VsockError};
+
+/// Number of receive buffers, which are provided to the receive queue.
+const RX_BUFFERS: usize = 32;
+/// Maximal payload of a receive buffer.
+const RX_PAYLOAD_SIZE: usize = 4096;
+/// Number of buffers, which are provided to the event queue.
+const EVENT_BUFFERS: usize = 4;
+/// Size of an event. See Virtio specification v1.2. - 5.10.6.5
+const EVENT_SIZE: usize = 4;
+/// Receive buffer space of each connection, which is announced to the peer.
+const BUF_ALLOC: u32 = 256 * 1024;
+/// Maximal payload of a transmitted packet.
+const TX_PAYLOAD_SIZE: usize = 64 * 1024;
+/// Ports, which are assigned to sockets without an explicitly bound port.
+pub(super) const EPHEMERAL_PORTS: RangeInclusive<u32> = 49152..=65535;
+
+/// Socket type of stream sockets.
+/// See Virtio specification v1.2. - 5.10.6
+const VIRTIO_VSOCK_TYPE_STREAM: u16 = 1;
+
+/// Operations of packets.
+/// See Virtio specification v1.2. - 5.10.6
+const VIRTIO_VSOCK_OP_REQUEST: u16 = 1;
+const VIRTIO_VSOCK_OP_RESPONSE: u16 = 2;
+const VIRTIO_VSOCK_OP_RST: u16 = 3;
+const VIRTIO_VSOCK_OP_SHUTDOWN: u16 = 4;
+const VIRTIO_VSOCK_OP_RW: u16 = 5;
+const VIRTIO_VSOCK_OP_CREDIT_UPDATE: u16 = 6;
+const VIRTIO_VSOCK_OP_CREDIT_REQUEST: u16 = 7;
+
+/// Flags of the shutdown operation.
+/// See Virtio specification v1.2. - 5.10.6.6
+pub const VIRTIO_VSOCK_SHUTDOWN_RCV: u32 = 1;
+pub const VIRTIO_VSOCK_SHUTDOWN_SEND: u32 = 2;
+
+/// Event, which is sent by the device, if the communication has been interrupted,
+/// e.g., by a migration of the guest.
+/// See Virtio specification v1.2. - 5.10.6.5
+const VIRTIO_VSOCK_EVENT_TRANSPORT_RESET: u32 = 0;
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+pub(crate) struct VsockDevCfg {
+	pub raw: &'static VsockDevCfgRaw,
+	pub dev_id: u16,
+	pub features: FeatureSet,
+}
+
+/// Header of each packet exchanged on the receive and transmit queues.
+/// See Virtio specification v1.2. - 5.10.6
+#[derive(AsBytes, Debug, Default, Copy, Clone)]
+#[repr(C, packed)]
+struct Header {
+	src_cid: u64,
+	dst_cid: u64,
+	src_port: u32,
+	dst_port: u32,
+	len: u32,
+	type_: u16,
+	op: u16,
+	flags: u32,
+	buf_alloc: u32,
+	fwd_cnt: u32,
+}
+
+const HEADER_SIZE: usize = mem::size_of::<Header>();
+
+impl Header {
+	fn from_bytes(buf: &[u8]) -> Option<Self> {
+		let u32_at = |i: usize| -> Option<u32> {
+			Some(u32::from_le_bytes(buf.get(i..i + 4)?.try_into().unwrap()))
+		};
+		let u16_at = |i: usize| -> Option<u16> {
+			Some(u16::from_le_bytes(buf.get(i..i + 2)?.try_into().unwrap()))
+		};
+
+		Some(Self {
+			src_cid: u64::from_le_bytes(buf.get(0..8)?.try_into().unwrap()),
+			dst_cid: u64::from_le_bytes(buf.get(8..16)?.try_into().unwrap()),
+			src_port: u32_at(16)?,
+			dst_port: u32_at(20)?,
+			len: u32_at(24)?,
+			type_: u16_at(28)?,
+			op: u16_at(30)?,
+			flags: u32_at(32)?,
+			buf_alloc: u32_at(36)?,
+			fwd_cnt: u32_at(40)?,
+		})
+	}
+}
+
+/// A receive queue, whose buffers are permanently provided to the device.
+struct RxQueue {
+	vq: Rc<Virtq>,
+	poll_queue: Rc<RefCell<VecDeque<Transfer>>>,
+}
+
+impl RxQueue {
+	fn new(vq: Virtq, num_buff: usize, buf_size: usize) -> Self {
+		let vq = Rc::new(vq);
+		let poll_queue = Rc::new(RefCell::new(VecDeque::new()));
+		let num_buff = cmp::min(num_buff, u16::from(vq.size()).into());
+
+		for _ in 0..num_buff {
+			let spec = BuffSpec::Single(Bytes::new(buf_size).unwrap());
+			match vq.prep_buffer(Rc::clone(&vq), None, Some(spec)) {
+				Ok(tkn) => tkn.provide().dispatch_await(Rc::clone(&poll_queue), false),
+				Err(_) => {
+					error!("Setup of vsock queue failed, which should not happen!");
+					panic!("setup of vsock queue failed!");
+				}
+			}
+		}
+
+		Self { vq, poll_queue }
+	}
+
+	/// Returns the data of the next used buffer and hands the buffer back to the device.
+	fn recv(&self) -> Option<Vec<u8>> {
+		self.vq.poll();
+		let transfer = self.poll_queue.borrow_mut().pop_front()?;
+
+		let data = match transfer.as_slices() {
+			Ok((_, Some(slices))) => slices.concat(),
+			_ => Vec::new(),
+		};
+		transfer
+			.reuse()
+			.unwrap()
+			.provide()
+			.dispatch_await(Rc::clone(&self.poll_queue), false);
+
+		Some(data)
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+	/// A connection request has been sent, but not answered yet.
+	Connecting,
+	Connected,
+	/// The peer has shut down both directions of the connection.
+	Closed,
+	/// The connection has been reset by the peer or the device.
+	Reset,
+}
+
+/// A stream connection.
+struct Connection {
+	state: State,
+	/// Received data, which has not been read yet
+	input: VecDeque<u8>,
+	/// Number of bytes, which have been read by the application
+	fwd_cnt: u32,
+	/// Value of `fwd_cnt`, which has been announced to the peer
+	announced_fwd_cnt: u32,
+	/// Number of bytes, which have been sent to the peer
+	tx_cnt: u32,
+	/// Receive buffer space of the peer
+	peer_buf_alloc: u32,
+	/// Number of bytes, which have been read by the peer
+	peer_fwd_cnt: u32,
+	/// The peer has been asked for its credit, but has not answered yet
+	credit_requested: bool,
+	/// Shutdown flags received from the peer
+	peer_shutdown: u32,
+	/// Shutdown flags sent to the peer
+	local_shutdown: u32,
+}
+
+impl Connection {
+	fn new(state: State, hdr: Option<&Header>) -> Self {
+		Self {
+			state,
+			input: VecDeque::new(),
+			fwd_cnt: 0,
+			announced_fwd_cnt: 0,
+			tx_cnt: 0,
+			peer_buf_alloc: hdr.map_or(0, |hdr| hdr.buf_alloc),
+			peer_fwd_cnt: hdr.map_or(0, |hdr| hdr.fwd_cnt),
+			credit_requested: false,
+			peer_shutdown: 0,
+			local_shutdown: 0,
+		}
+	}
+
+	/// Returns the number of bytes, which the peer is able to receive.
+	fn peer_credit(&self) -> u32 {
+		self.peer_buf_alloc
+			.saturating_sub(self.tx_cnt.wrapping_sub(self.peer_fwd_cnt))
+	}
+}
+
+/// A listening port.
+struct Listener {
+	backlog: usize,
+	/// Established connections, which have not been accepted yet
+	pending: VecDeque<ConnectionId>,
+}
+
+/// Virtio socket driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+#[allow(dead_code)]
+pub(crate) struct VirtioVsockDriver {
+	pub(super) dev_cfg: VsockDevCfg,
+	pub(super) com_cfg: ComCfg,
+	pub(super) isr_stat: IsrStatus,
+	pub(super) notif_cfg: NotifCfg,
+	pub(super) irq: InterruptLine,
+	/// MSI-X table of the device, if the queues signal their own interrupt
+	#[cfg(target_arch = "x86_64")]
+	pub(super) msix: Option<MsixTable<PciConfigRegion>>,
+	pub(super) rx: Option<RxQueue>,
+	pub(super) tx: Option<Rc<Virtq>>,
+	pub(super) event: Option<RxQueue>,
+	/// Context id of the guest, which is assigned by the host
+	pub(super) guest_cid: u64,
+	/// Ports, which are bound by a socket
+	pub(super) bound: BTreeSet<u32>,
+	pub(super) listeners: BTreeMap<u32, Listener>,
+	pub(super) connections: BTreeMap<ConnectionId, Connection>,
+	/// Tasks, which wait for packets to a local port
+	pub(super) wakers: BTreeMap<u32, Vec<Waker>>,
+	/// Next candidate for an ephemeral port
+	pub(super) next_port: u32,
+}
+
+// Backend-independent interface for Virtio socket driver
+impl VirtioVsockDriver {
+	pub fn get_dev_id(&self) -> u16 {
+		self.dev_cfg.dev_id
+	}
+
+	pub fn set_failed(&mut self) {
+		self.com_cfg.set_failed();
+	}
+
+	/// Returns the context id of the guest.
+	pub fn guest_cid(&self) -> u64 {
+		self.guest_cid
+	}
+
+	/// Registers `waker` to be woken up, as soon as a packet to the local `port`
+	/// has been received or the transport has been reset.
+	pub fn register_waker(&mut self, port: u32, waker: &Waker) {
+		let wakers = self.wakers.entry(port).or_default();
+		if !wakers.iter().any(|w| w.will_wake(waker)) {
+			wakers.push(waker.clone());
+		}
+	}
+
+	/// Wakes up all tasks, which wait on the local `port`.
+	fn wake(&mut self, port: u32) {
+		for waker in self.wakers.remove(&port).unwrap_or_default() {
+			waker.wake();
+		}
+	}
+
+	/// Handles the interrupt of the device. Returns true, if the device
+	/// has raised the interrupt.
+	pub fn handle_interrupt(&mut self) -> bool {
+		increment_irq_counter(32 + self.irq);
+
+		// MSI-X vectors are not shared, such that the ISR status is not used.
+		#[cfg(target_arch = "x86_64")]
+		let result = self.msix.is_some() || self.isr_stat.is_interrupt();
+		#[cfg(not(target_arch = "x86_64"))]
+		let result = self.isr_stat.is_interrupt();
+		self.isr_stat.acknowledge();
+		if result {
+			self.process();
+		}
+
+		result
+	}
+
+	/// Reserves `port` for a socket. If no port is given, a free ephemeral
+	/// port is chosen. Returns the reserved port.
+	pub fn bind(&mut self, port: Option<u32>) -> Result<u32, VsockError> {
+		if let Some(port) = port {
+			return if self.bound.insert(port) {
+				Ok(port)
+			} else {
+				Err(VsockError::AddrInUse)
+			};
+		}
+
+		for _ in EPHEMERAL_PORTS {
+			let port = self.next_port;
+			self.next_port = if port == *EPHEMERAL_PORTS.end() {
+				*EPHEMERAL_PORTS.start()
+			} else {
+				port + 1
+			};
+
+			if self.bound.insert(port) {
+				return Ok(port);
+			}
+		}
+
+		Err(VsockError::AddrNotAvailable)
+	}
+
+	/// Releases `port`. Connections, which are still pending on the port, are reset.
+	pub fn unbind(&mut self, port: u32) {
+		self.bound.remove(&port);
+		if let Some(listener) = self.listeners.remove(&port) {
+			for id in listener.pending {
+				self.reset(id);
+			}
+		}
+	}
+
+	/// Accepts connection requests on `port`, which has to be bound before.
+	pub fn listen(&mut self, port: u32, backlog: usize) {
+		let backlog = cmp::max(backlog, 1);
+		self.listeners
+			.entry(port)
+			.and_modify(|listener| listener.backlog = backlog)
+			.or_insert_with(|| Listener {
+				backlog,
+				pending: VecDeque::new(),
+			});
+	}
+
+	/// Returns an established connection of the listening `port` without blocking.
+	pub fn accept(&mut self, port: u32) -> Option<ConnectionId> {
+		self.process();
+
+		self.listeners.get_mut(&port)?.pending.pop_front()
+	}
+
+	/// Sends a connection request from the bound `local_port` to the peer.
+	/// The connection is established, as soon as [Self::poll_connect] returns true.
+	pub fn connect(
+		&mut self,
+		local_port: u32,
+		peer_cid: u64,
+		peer_port: u32,
+	) -> Result<ConnectionId, VsockError> {
+		let id = ConnectionId {
+			local_port,
+			peer_cid,
+			peer_port,
+		};
+		if self.connections.contains_key(&id) {
+			return Err(VsockError::AddrInUse);
+		}
+
+		self.connections
+			.insert(id, Connection::new(State::Connecting, None));
+		if let Err(err) = self.send_packet(id, VIRTIO_VSOCK_OP_REQUEST, 0, &[]) {
+			self.connections.remove(&id);
+			return Err(err);
+		}
+
+		Ok(id)
+	}
+
+	/// Returns true, if the connection has been established, and false, if the
+	/// peer has not answered yet. Refused connections are removed.
+	pub fn poll_connect(&mut self, id: ConnectionId) -> Result<bool, VsockError> {
+		self.process();
+
+		let state = self
+			.connections
+			.get(&id)
+			.map(|conn| conn.state)
+			.ok_or(VsockError::NotConnected)?;
+		match state {
+			State::Connecting => Ok(false),
+			State::Connected => Ok(true),
+			State::Closed | State::Reset => {
+				self.connections.remove(&id);
+				Err(VsockError::ConnectionRefused)
+			}
+		}
+	}
+
+	/// Reads the available data of the connection into `buf` without blocking.
+	/// Returns the number of read bytes or 0, if the peer will not send any data.
+	pub fn recv(&mut self, id: ConnectionId, buf: &mut [u8]) -> Result<usize, VsockError> {
+		self.process();
+
+		let conn = self
+			.connections
+			.get_mut(&id)
+			.ok_or(VsockError::NotConnected)?;
+
+		if conn.input.is_empty() {
+			return match conn.state {
+				State::Connecting => Err(VsockError::WouldBlock),
+				State::Reset => Err(VsockError::ConnectionReset),
+				State::Closed => Ok(0),
+				State::Connected if conn.peer_shutdown & VIRTIO_VSOCK_SHUTDOWN_SEND != 0 => Ok(0),
+				State::Connected => Err(VsockError::WouldBlock),
+			};
+		}
+
+		let len = cmp::min(buf.len(), conn.input.len());
+		for (dst, src) in buf.iter_mut().zip(conn.input.drain(..len)) {
+			*dst = src;
+		}
+		conn.fwd_cnt = conn.fwd_cnt.wrapping_add(len as u32);
+
+		// Announce the free buffer space, if a considerable part of the buffer
+		// has been freed or the peer might wait for credit.
+		let consumed = conn.fwd_cnt.wrapping_sub(conn.announced_fwd_cnt);
+		if (consumed >= BUF_ALLOC / 4 || conn.input.is_empty())
+			&& conn.state == State::Connected
+			&& consumed > 0
+		{
+			self.send_packet(id, VIRTIO_VSOCK_OP_CREDIT_UPDATE, 0, &[])?;
+		}
+
+		Ok(len)
+	}
+
+	/// Sends as much of `buf` as the peer is able to receive without blocking.
+	/// Returns the number of sent bytes.
+	pub fn send(&mut self, id: ConnectionId, buf: &[u8]) -> Result<usize, VsockError> {
+		self.process();
+
+		let conn = self
+			.connections
+			.get_mut(&id)
+			.ok_or(VsockError::NotConnected)?;
+
+		match conn.state {
+			State::Connecting => return Err(VsockError::WouldBlock),
+			State::Reset => return Err(VsockError::ConnectionReset),
+			State::Closed => return Err(VsockError::BrokenPipe),
+			State::Connected => {}
+		}
+		if conn.local_shutdown & VIRTIO_VSOCK_SHUTDOWN_SEND != 0
+			|| conn.peer_shutdown & VIRTIO_VSOCK_SHUTDOWN_RCV != 0
+		{
+			return Err(VsockError::BrokenPipe);
+		}
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		let credit = conn.peer_credit() as usize;
+		if credit == 0 {
+			if !conn.credit_requested {
+				conn.credit_requested = true;
+				self.send_packet(id, VIRTIO_VSOCK_OP_CREDIT_REQUEST, 0, &[])?;
+			}
+			return Err(VsockError::WouldBlock);
+		}
+
+		let len = cmp::min(cmp::min(buf.len(), credit), TX_PAYLOAD_SIZE);
+		self.send_packet(id, VIRTIO_VSOCK_OP_RW, 0, &buf[..len])?;
+		let conn = self.connections.get_mut(&id).unwrap();
+		conn.tx_cnt = conn.tx_cnt.wrapping_add(len as u32);
+
+		Ok(len)
+	}
+
+	/// Shuts down the receiving and/or sending direction of the connection.
+	/// `flags` is a combination of [VIRTIO_VSOCK_SHUTDOWN_RCV] and [VIRTIO_VSOCK_SHUTDOWN_SEND].
+	pub fn shutdown(&mut self, id: ConnectionId, flags: u32) -> Result<(), VsockError> {
+		let conn = self
+			.connections
+			.get_mut(&id)
+			.ok_or(VsockError::NotConnected)?;
+		if conn.state != State::Connected {
+			return Err(VsockError::NotConnected);
+		}
+
+		conn.local_shutdown |= flags;
+		let flags = conn.local_shutdown;
+		self.send_packet(id, VIRTIO_VSOCK_OP_SHUTDOWN, flags, &[])
+	}
+
+	/// Closes the connection and releases its resources.
+	pub fn close(&mut self, id: ConnectionId) {
+		let Some(state) = self.connections.get(&id).map(|conn| conn.state) else {
+			return;
+		};
+
+		let ret = match state {
+			State::Connecting => self.send_packet(id, VIRTIO_VSOCK_OP_RST, 0, &[]),
+			State::Connected => self.send_packet(
+				id,
+				VIRTIO_VSOCK_OP_SHUTDOWN,
+				VIRTIO_VSOCK_SHUTDOWN_RCV | VIRTIO_VSOCK_SHUTDOWN_SEND,
+				&[],
+			),
+			State::Closed | State::Reset => Ok(()),
+		};
+		if ret.is_err() {
+			warn!("Unable to inform the peer of vsock connection {:?}", id);
+		}
+
+		self.connections.remove(&id);
+	}
+
+	/// Resets the connection and informs the peer.
+	fn reset(&mut self, id: ConnectionId) {
+		if self.send_packet(id, VIRTIO_VSOCK_OP_RST, 0, &[]).is_err() {
+			warn!("Unable to reset vsock connection {:?}", id);
+		}
+		self.connections.remove(&id);
+	}
+
+	/// Sends a packet of the connection `id` with the current credit of the connection.
+	fn send_packet(
+		&mut self,
+		id: ConnectionId,
+		op: u16,
+		flags: u32,
+		data: &[u8],
+	) -> Result<(), VsockError> {
+		let (buf_alloc, fwd_cnt) = match self.connections.get_mut(&id) {
+			Some(conn) => {
+				conn.announced_fwd_cnt = conn.fwd_cnt;
+				(BUF_ALLOC, conn.fwd_cnt)
+			}
+			None => (0, 0),
+		};
+
+		let hdr = Header {
+			src_cid: self.guest_cid,
+			dst_cid: id.peer_cid,
+			src_port: id.local_port,
+			dst_port: id.peer_port,
+			len: data.len() as u32,
+			type_: VIRTIO_VSOCK_TYPE_STREAM,
+			op,
+			flags,
+			buf_alloc,
+			fwd_cnt,
+		};
+
+		self.transmit(&hdr, data)
+	}
+
+	/// Answers a packet, which does not belong to a connection, with a reset.
+	fn send_reset(&self, hdr: &Header) -> Result<(), VsockError> {
+		let reply = Header {
+			src_cid: hdr.dst_cid,
+			dst_cid: hdr.src_cid,
+			src_port: hdr.dst_port,
+			dst_port: hdr.src_port,
+			type_: hdr.type_,
+			op: VIRTIO_VSOCK_OP_RST,
+			..Default::default()
+		};
+
+		self.transmit(&reply, &[])
+	}
+
+	/// Sends a packet on the transmit queue and waits until the device consumed it.
+	fn transmit(&self, hdr: &Header, data: &[u8]) -> Result<(), VsockError> {
+		let tx = self.tx.as_ref().ok_or(VsockError::Io)?;
+
+		let mut buf = Vec::with_capacity(HEADER_SIZE + data.len());
+		buf.extend_from_slice(hdr.as_bytes());
+		buf.extend_from_slice(data);
+		let spec = BuffSpec::Single(Bytes::new(buf.len()).ok_or(VsockError::Io)?);
+
+		let transfer = tx
+			.prep_buffer(Rc::clone(tx), Some(spec), None)
+			.map_err(|_| VsockError::Io)?
+			.write(Some(buf.as_slice()), None::<&[u8]>)
+			.map_err(|_| VsockError::Io)?
+			.dispatch_blocking()
+			.map_err(|_| VsockError::Io)?;
+		transfer.close();
+
+		Ok(())
+	}
+
+	/// Handles all pending events and received packets of the device.
+	fn process(&mut self) {
+		while let Some(event) = self.event.as_ref().and_then(|event| event.recv()) {
+			let id = event
+				.get(..EVENT_SIZE)
+				.map(|id| u32::from_le_bytes(id.try_into().unwrap()));
+			if id == Some(VIRTIO_VSOCK_EVENT_TRANSPORT_RESET) {
+				self.reset_transport();
+			}
+		}
+
+		while let Some(packet) = self.rx.as_ref().and_then(|rx| rx.recv()) {
+			self.handle_packet(&packet);
+		}
+	}
+
+	/// Resets all connections and updates the context id, which might have
+	/// changed, e.g., after a migration of the guest.
+	///
+	/// See Virtio specification v1.2. - 5.10.6.5
+	fn reset_transport(&mut self) {
+		info!("Reset of the vsock transport");
+
+		self.guest_cid = self.dev_cfg.raw.get_guest_cid();
+		for conn in self.connections.values_mut() {
+			conn.state = State::Reset;
+		}
+		for listener in self.listeners.values_mut() {
+			for id in listener.pending.drain(..) {
+				self.connections.remove(&id);
+			}
+		}
+		for waker in mem::take(&mut self.wakers).into_values().flatten() {
+			waker.wake();
+		}
+	}
+
+	/// Handles a received packet.
+	///
+	/// See Virtio specification v1.2. - 5.10.6.3
+	fn handle_packet(&mut self, packet: &[u8]) {
+		let Some(hdr) = Header::from_bytes(packet) else {
+			warn!("Received malformed vsock packet");
+			return;
+		};
+		let payload = packet
+			.get(HEADER_SIZE..HEADER_SIZE + hdr.len as usize)
+			.unwrap_or_default();
+		let op = hdr.op;
+
+		if hdr.dst_cid != self.guest_cid {
+			return;
+		}
+		if hdr.type_ != VIRTIO_VSOCK_TYPE_STREAM {
+			if op != VIRTIO_VSOCK_OP_RST && self.send_reset(&hdr).is_err() {
+				warn!("Unable to reset vsock connection");
+			}
+			return;
+		}
+		self.wake(hdr.dst_port);
+
+		let id = ConnectionId {
+			local_port: hdr.dst_port,
+			peer_cid: hdr.src_cid,
+			peer_port: hdr.src_port,
+		};
+
+		let Some(conn) = self.connections.get_mut(&id) else {
+			if op == VIRTIO_VSOCK_OP_REQUEST {
+				self.handle_request(id, &hdr);
+			} else if op != VIRTIO_VSOCK_OP_RST && self.send_reset(&hdr).is_err() {
+				warn!("Unable to reset vsock connection {:?}", id);
+			}
+			return;
+		};
+
+		conn.peer_buf_alloc = hdr.buf_alloc;
+		conn.peer_fwd_cnt = hdr.fwd_cnt;
+		conn.credit_requested = false;
+
+		let response = match (op, conn.state) {
+			(VIRTIO_VSOCK_OP_RST, _) => {
+				conn.state = State::Reset;
+				None
+			}
+			(_, State::Reset) => None,
+			(VIRTIO_VSOCK_OP_RESPONSE, State::Connecting) => {
+				conn.state = State::Connected;
+				None
+			}
+			(VIRTIO_VSOCK_OP_RW, State::Connected) => {
+				if conn.input.len() + payload.len() > BUF_ALLOC as usize {
+					warn!("Peer of vsock connection {:?} exceeded its credit", id);
+				}
+				conn.input.extend(payload);
+				None
+			}
+			(VIRTIO_VSOCK_OP_CREDIT_UPDATE, State::Connected | State::Closed) => None,
+			(VIRTIO_VSOCK_OP_CREDIT_REQUEST, State::Connected) => {
+				Some((VIRTIO_VSOCK_OP_CREDIT_UPDATE, 0))
+			}
+			(VIRTIO_VSOCK_OP_SHUTDOWN, State::Connected) => {
+				conn.peer_shutdown |=
+					hdr.flags & (VIRTIO_VSOCK_SHUTDOWN_RCV | VIRTIO_VSOCK_SHUTDOWN_SEND);
+				// The connection is closed, if the peer shut down both directions.
+				if conn.peer_shutdown == VIRTIO_VSOCK_SHUTDOWN_RCV | VIRTIO_VSOCK_SHUTDOWN_SEND {
+					conn.state = State::Closed;
+					Some((VIRTIO_VSOCK_OP_RST, 0))
+				} else {
+					None
+				}
+			}
+			_ => {
+				conn.state = State::Reset;
+				Some((VIRTIO_VSOCK_OP_RST, 0))
+			}
+		};
+
+		if let Some((op, flags)) = response {
+			if self.send_packet(id, op, flags, &[]).is_err() {
+				warn!("Unable to answer packet of vsock connection {:?}", id);
+			}
+		}
+	}
+
+	/// Handles a connection request, which is accepted, if a socket listens on
+	/// the port and its backlog is not exhausted.
+	fn handle_request(&mut self, id: ConnectionId, hdr: &Header) {
+		let accepted = match self.listeners.get_mut(&id.local_port) {
+			Some(listener) if listener.pending.len() < listener.backlog => {
+				listener.pending.push_back(id);
+				true
+			}
+			_ => false,
+		};
+
+		let ret = if accepted {
+			self.connections
+				.insert(id, Connection::new(State::Connected, Some(hdr)));
+			self.send_packet(id, VIRTIO_VSOCK_OP_RESPONSE, 0, &[])
+		} else {
+			self.send_reset(hdr)
+		};
+		if ret.is_err() {
+			warn!("Unable to answer request of vsock connection {:?}", id);
+		}
+	}
+
+	/// Initializes the device in adherence to specification. Returns Some(VirtioVsockError)
+	/// upon failure and None in case everything worked as expected.
+	///
+	/// See Virtio specification v1.1. - 3.1.1.
+	///                      and v1.2. - 5.10.5
+	pub(crate) fn init_dev(&mut self) -> Result<(), VirtioVsockError> {
+		// Devices, which support further socket types, offer VIRTIO_VSOCK_F_STREAM.
+		// Otherwise, stream sockets are supported implicitly.
+		let feats = negotiate_features(
+			&mut self.com_cfg,
+			&[Features::VIRTIO_F_VERSION_1],
+			&[Features::VIRTIO_VSOCK_F_STREAM],
+		)
+		.map_err(|err| VirtioVsockError::FeatureNeg(self.dev_cfg.dev_id, err))?;
+		self.dev_cfg.features = FeatureSet::new(feats);
+		info!(
+			"Features have been negotiated between virtio socket device {:x} and driver. Features are: {:?}",
+			self.dev_cfg.dev_id,
+			Features::from_set(self.dev_cfg.features)
+		);
+
+		self.guest_cid = self.dev_cfg.raw.get_guest_cid();
+
+		// See Virtio specification v1.2. - 5.10.2
+		let rx = self.create_vq(0);
+		let tx = self.create_vq(1);
+		let event = self.create_vq(2);
+		self.rx = Some(RxQueue::new(rx, RX_BUFFERS, HEADER_SIZE + RX_PAYLOAD_SIZE));
+		self.tx = Some(Rc::new(tx));
+		self.event = Some(RxQueue::new(event, EVENT_BUFFERS, EVENT_SIZE));
+
+		// Waiting sockets are woken up by the interrupt of the receive and event queues.
+		for queue in [&self.rx, &self.event].into_iter().flatten() {
+			queue.vq.enable_notifs();
+		}
+		#[cfg(target_arch = "x86_64")]
+		self.setup_msix();
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		info!("Context id of the guest is {}", self.guest_cid);
+
+		Ok(())
+	}
+
+	fn create_vq(&mut self, index: u16) -> Virtq {
+		let vq = Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			VqType::Split,
+			VqIndex::from(index),
+			self.dev_cfg.features.into(),
+		);
+		vq.disable_notifs();
+
+		vq
+	}
+}
+
+pub mod constants {
+	use crate::drivers::virtio::features::virtio_features;
+
+	virtio_features! {
+		/// Enum contains virtio's socket device features and general features of Virtio.
+		///
+		/// See Virtio specification v1.2. - 5.10.3
+		///
+		/// See Virtio specification v1.1. - 6
+		pub enum Features {
+			VIRTIO_VSOCK_F_STREAM = 1 << 0,
+			VIRTIO_VSOCK_F_SEQPACKET = 1 << 1,
+			VIRTIO_F_RING_INDIRECT_DESC = 1 << 28,
+			VIRTIO_F_RING_EVENT_IDX = 1 << 29,
+			VIRTIO_F_VERSION_1 = 1 << 32,
+			VIRTIO_F_ACCESS_PLATFORM = 1 << 33,
+			VIRTIO_F_RING_PACKED = 1 << 34,
+			VIRTIO_F_IN_ORDER = 1 << 35,
+			VIRTIO_F_ORDER_PLATFORM = 1 << 36,
+			VIRTIO_F_SR_IOV = 1 << 37,
+			VIRTIO_F_NOTIFICATION_DATA = 1 << 38,
+		}
+	}
+}
+
+/// Error module of virtios socket driver.
+pub mod error {
+	use crate::drivers::virtio::features::FeatureError;
+
+	/// Virtio socket error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioVsockError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		/// The features of the device (u16) could not be negotiated.
+		FeatureNeg(u16, FeatureError),
+		Unknown,
+	}
+}
@@ -1,52 +1,205 @@
 //! Cryptographically secure random data generation.
 //!
-//! This currently uses a ChaCha-based generator (the same one Linux uses!) seeded
-//! with random data provided by the processor.
+//! This currently uses a ChaCha-based generator (the same one Linux uses!), which
+//! is periodically reseeded from an input pool. All available entropy sources are
+//! mixed into the input pool:
+//!
+//! * random data provided by the processor (RDSEED on x86_64),
+//! * random data of the host, if a virtio entropy device is available, and
+//! * the timing of interrupts.
+//!
+//! The pool is mixed by using ChaCha20 as a keyed permutation: each block of input
+//! is XORed into the state, which is then replaced by the first 32 bytes of the
+//! keystream generated with the state as key.
 
 use hermit_sync::InterruptTicketMutex;
 use rand_chacha::rand_core::{RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 
-use crate::arch::kernel::processor::{get_timer_ticks, seed_entropy};
+use crate::arch::kernel::processor::{get_timer_ticks, get_timestamp, seed_entropy};
+#[cfg(feature = "pci")]
+use crate::drivers::pci::get_entropy_driver;
 use crate::errno::ENOSYS;
 
 // Reseed every second for increased security while maintaining the performance of
 // the PRNG.
 const RESEED_INTERVAL: u64 = 1000000;
 
+/// Size of the pool state and of a seed in bytes
+const SEED_SIZE: usize = 32;
+/// Entropy in bits, which has to be collected before the generator is (re)seeded
+const MIN_SEED_ENTROPY: usize = 8 * SEED_SIZE;
+/// Number of interrupts, which are accumulated before they are mixed into the input pool
+const INTERRUPT_SAMPLES: u32 = 64;
+/// Entropy in bits, which is credited for [INTERRUPT_SAMPLES] interrupts
+const INTERRUPT_ENTROPY: usize = 8;
+
 bitflags! {
 	pub struct Flags: u32 {}
 }
 
+/// Accumulates the input of all entropy sources.
+struct InputPool {
+	state: [u8; SEED_SIZE],
+	/// Estimated entropy of the state in bits
+	entropy: usize,
+}
+
+impl InputPool {
+	const fn new() -> Self {
+		Self {
+			state: [0; SEED_SIZE],
+			entropy: 0,
+		}
+	}
+
+	fn mix(&mut self, data: &[u8]) {
+		for block in data.chunks(SEED_SIZE) {
+			for (state, byte) in self.state.iter_mut().zip(block) {
+				*state ^= byte;
+			}
+			ChaCha20Rng::from_seed(self.state).fill_bytes(&mut self.state);
+		}
+	}
+
+	/// Mixes `data` into the pool and credits it with `entropy` bits.
+	fn add(&mut self, data: &[u8], entropy: usize) {
+		self.mix(data);
+		self.entropy = usize::min(self.entropy + entropy, 8 * SEED_SIZE);
+	}
+
+	/// Returns a seed and replaces the state, such that the seed cannot be
+	/// reconstructed from the new state.
+	fn extract(&mut self) -> [u8; SEED_SIZE] {
+		let mut rng = ChaCha20Rng::from_seed(self.state);
+		let mut seed = [0; SEED_SIZE];
+		rng.fill_bytes(&mut seed);
+		rng.fill_bytes(&mut self.state);
+		self.entropy = 0;
+
+		seed
+	}
+}
+
 struct Pool {
-	rng: ChaCha20Rng,
+	input: InputPool,
+	rng: Option<ChaCha20Rng>,
 	last_reseed: u64,
 }
 
-static POOL: InterruptTicketMutex<Option<Pool>> = InterruptTicketMutex::new(None);
+impl Pool {
+	fn needs_reseed(&self, now: u64) -> bool {
+		self.rng.is_none() || now.saturating_sub(self.last_reseed) > RESEED_INTERVAL
+	}
+}
+
+static POOL: InterruptTicketMutex<Pool> = InterruptTicketMutex::new(Pool {
+	input: InputPool::new(),
+	rng: None,
+	last_reseed: 0,
+});
+
+/// Timings of recent interrupts, which have not been mixed into the input pool yet.
+struct InterruptPool {
+	state: [u64; 2],
+	count: u32,
+}
+
+static INTERRUPT_POOL: InterruptTicketMutex<InterruptPool> =
+	InterruptTicketMutex::new(InterruptPool {
+		state: [0; 2],
+		count: 0,
+	});
+
+/// Records the timing of an interrupt. This is called on each interrupt
+/// and therefore only accumulates the samples.
+pub fn add_interrupt_timing(irq: u8) {
+	// Interrupts on other cores are allowed to get lost.
+	let Some(mut pool) = INTERRUPT_POOL.try_lock() else {
+		return;
+	};
+
+	let timestamp = get_timestamp();
+	pool.state[0] = (pool.state[0] ^ timestamp).rotate_left(17);
+	pool.state[1] = pool.state[1].wrapping_add(pool.state[0]).rotate_left(29) ^ u64::from(irq);
+	pool.state[0] = pool.state[0].wrapping_add(pool.state[1]);
+	pool.count += 1;
+
+	if pool.count >= INTERRUPT_SAMPLES {
+		if let Some(mut main) = POOL.try_lock() {
+			let mut data = [0u8; 16];
+			data[..8].copy_from_slice(&pool.state[0].to_ne_bytes());
+			data[8..].copy_from_slice(&pool.state[1].to_ne_bytes());
+			main.input.add(&data, INTERRUPT_ENTROPY);
+			pool.count = 0;
+		}
+	}
+}
+
+/// Collects the data of the processor and of the host in a separate pool, which
+/// is mixed into the input pool afterwards. The sources may block, e.g., the
+/// virtio entropy device, such that they are read without holding [POOL].
+fn collect() -> InputPool {
+	let mut input = InputPool::new();
+
+	if let Some(seed) = seed_entropy() {
+		input.add(&seed, 8 * seed.len());
+	}
+
+	#[cfg(feature = "pci")]
+	if let Some(driver) = get_entropy_driver() {
+		let mut buf = [0u8; SEED_SIZE];
+		let len = driver.lock().read(&mut buf);
+		input.add(&buf[..len], 8 * len);
+	}
+
+	// The exact time of the request is a bit of entropy, but is not credited.
+	input.mix(&get_timestamp().to_ne_bytes());
+
+	input
+}
 
 /// Fills `buf` with random data, respecting the options in `flags`.
 ///
 /// Returns the number of bytes written or `-ENOSYS` if the system does not support
 /// random data generation.
 pub fn read(buf: &mut [u8], _flags: Flags) -> isize {
+	// The guard has to be dropped before the sources are read.
+	let needs_reseed = POOL.lock().needs_reseed(get_timer_ticks());
+	let input = needs_reseed.then(collect);
+
 	let pool = &mut *POOL.lock();
 	let now = get_timer_ticks();
-	let pool = match pool {
-		Some(pool) if now.saturating_sub(pool.last_reseed) <= RESEED_INTERVAL => pool,
-		pool => {
-			if let Some(seed) = seed_entropy() {
-				pool.insert(Pool {
-					rng: ChaCha20Rng::from_seed(seed),
-					last_reseed: now,
-				})
-			} else {
-				return -ENOSYS as isize;
+
+	if let Some(input) = input {
+		pool.input.add(&input.state, input.entropy);
+	}
+
+	if pool.needs_reseed(now) {
+		if pool.input.entropy >= MIN_SEED_ENTROPY {
+			let mut seed = pool.input.extract();
+			// Keep the entropy of the previous seed
+			if let Some(rng) = pool.rng.as_mut() {
+				let mut old = [0u8; SEED_SIZE];
+				rng.fill_bytes(&mut old);
+				for (byte, old) in seed.iter_mut().zip(old) {
+					*byte ^= old;
+				}
 			}
+
+			pool.rng = Some(ChaCha20Rng::from_seed(seed));
+			pool.last_reseed = now;
+		} else if pool.rng.is_some() {
+			// Continue with the current seed and try again later
+			pool.last_reseed = now;
 		}
+	}
+
+	let Some(rng) = pool.rng.as_mut() else {
+		return -ENOSYS as isize;
 	};
 
-	pool.rng.fill_bytes(buf);
+	rng.fill_bytes(buf);
 	// Slice lengths are always <= isize::MAX so this return value cannot conflict
 	// with error numbers.
 	buf.len() as isize
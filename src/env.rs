@@ -27,6 +27,8 @@ struct Cli {
 	#[allow(dead_code)]
 	image_path: Option<String>,
 	freq: Option<u16>,
+	#[cfg_attr(not(feature = "pci"), allow(dead_code))]
+	mounts: Vec<String>,
 	env_vars: HashMap<String, String, RandomState>,
 	args: Vec<String>,
 }
@@ -52,6 +54,7 @@ impl Default for Cli {
 	fn default() -> Self {
 		let mut image_path = None;
 		let mut freq = None;
+		let mut mounts = Vec::new();
 		let mut env_vars = HashMap::<String, String, RandomState>::with_hasher(
 			RandomState::with_seeds(0, 0, 0, 0),
 		);
@@ -72,6 +75,10 @@ impl Default for Cli {
 					let s = expect_arg(words.next(), word.as_str());
 					freq = Some(s.parse().unwrap());
 				}
+				"-mount" => {
+					let mount = expect_arg(words.next(), word.as_str());
+					mounts.push(mount);
+				}
 				"-ip" => {
 					let ip = expect_arg(words.next(), word.as_str());
 					env_vars.insert(String::from("HERMIT_IP"), ip);
@@ -84,6 +91,18 @@ impl Default for Cli {
 					let gateway = expect_arg(words.next(), word.as_str());
 					env_vars.insert(String::from("HERMIT_GATEWAY"), gateway);
 				}
+				"-ipv6" => {
+					let ip = expect_arg(words.next(), word.as_str());
+					env_vars.insert(String::from("HERMIT_IPV6"), ip);
+				}
+				"-ipv6-prefix" => {
+					let prefix = expect_arg(words.next(), word.as_str());
+					env_vars.insert(String::from("HERMIT_IPV6_PREFIX"), prefix);
+				}
+				"-ipv6-gateway" => {
+					let gateway = expect_arg(words.next(), word.as_str());
+					env_vars.insert(String::from("HERMIT_IPV6_GATEWAY"), gateway);
+				}
 				"--" => args.extend(&mut words),
 				_ if image_path.is_none() => image_path = Some(word),
 				word => panic!(
@@ -97,6 +116,7 @@ impl Default for Cli {
 		Self {
 			image_path,
 			freq,
+			mounts,
 			env_vars,
 			args,
 		}
@@ -108,7 +128,17 @@ pub fn freq() -> Option<u16> {
 	CLI.get().unwrap().freq
 }
 
-#[cfg(all(feature = "tcp", not(feature = "dhcpv4")))]
+/// Disk-based file systems, which are given through the -mount command-line parameter.
+///
+/// Each entry has the form `<mount point>=<file system>:<disk>`, where `<disk>` is the
+/// index of the block device (e.g. `0`) optionally followed by a partition number (e.g. `0p1`).
+/// Supported file systems are `fat` and the read-only `ext2`/`ext3`/`ext4`.
+#[cfg(feature = "pci")]
+pub fn mounts() -> &'static [String] {
+	CLI.get().unwrap().mounts.as_slice()
+}
+
+#[cfg(feature = "tcp")]
 pub fn var(key: &str) -> Option<&String> {
 	CLI.get().unwrap().env_vars.get(key)
 }
@@ -204,10 +204,14 @@ pub trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 		-EINVAL
 	}
 
-	/// `accept` a connection on a socket
+	/// `accept` a connection on a socket and return a new socket of the connection
 	#[cfg(all(feature = "tcp", not(feature = "newlib")))]
-	fn accept(&self, _addr: *mut sockaddr, _addrlen: *mut socklen_t) -> i32 {
-		-EINVAL
+	fn accept(
+		&self,
+		_addr: *mut sockaddr,
+		_addrlen: *mut socklen_t,
+	) -> Result<Arc<dyn ObjectInterface>, i32> {
+		Err(-EINVAL)
 	}
 
 	/// initiate a connection on a socket
@@ -252,6 +256,30 @@ pub trait ObjectInterface: Sync + Send + core::fmt::Debug + DynClone {
 		-EINVAL
 	}
 
+	/// `sendto` sends a message to the address `addr`
+	#[cfg(all(feature = "tcp", not(feature = "newlib")))]
+	fn sendto(
+		&self,
+		_buf: *const u8,
+		_len: usize,
+		_addr: *const sockaddr,
+		_addrlen: socklen_t,
+	) -> isize {
+		(-EINVAL).try_into().unwrap()
+	}
+
+	/// `recvfrom` receives a message and stores the address of its sender in `addr`
+	#[cfg(all(feature = "tcp", not(feature = "newlib")))]
+	fn recvfrom(
+		&self,
+		_buf: *mut u8,
+		_len: usize,
+		_addr: *mut sockaddr,
+		_addrlen: *mut socklen_t,
+	) -> isize {
+		(-EINVAL).try_into().unwrap()
+	}
+
 	/// `getsockname` gets socket name
 	#[cfg(all(feature = "tcp", not(feature = "newlib")))]
 	fn getsockname(&self, _name: *mut sockaddr, _namelen: *mut socklen_t) -> i32 {
@@ -0,0 +1,582 @@
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use core::task::Poll;
+
+use futures_lite::future;
+use hermit_sync::InterruptTicketMutex;
+use smoltcp::socket::icmp;
+use smoltcp::socket::icmp::SendError;
+use smoltcp::time::Duration;
+use smoltcp::wire::{IpAddress, Ipv6Address};
+
+use crate::errno::*;
+use crate::fd::socket::get_ephemeral_port;
+use crate::fd::socket::tcp::{IPv4, IPv6};
+use crate::fd::ObjectInterface;
+use crate::net::executor::block_on;
+use crate::net::{echo_socket, now, Handle, NetworkInterface, NetworkState, ICMP_BUFFER_SIZE, NIC};
+use crate::syscalls::net::*;
+
+/// Type of an ICMPv4 echo request
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+/// Type of an ICMPv6 echo request
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+/// Length of the header of an echo request
+const ECHO_HEADER_LEN: usize = 8;
+
+/// ICMP datagram socket, which sends echo requests and receives their replies.
+///
+/// The application writes the echo requests including the ICMP header. Like the
+/// port of a UDP socket, the socket has an identifier, which replaces the identifier
+/// of the requests. Only the replies with this identifier are received.
+#[derive(Debug)]
+pub struct Socket<T> {
+	handle: Handle,
+	nonblocking: AtomicBool,
+	/// Identifier of the echo requests, which is 0 as long as the socket is not bound
+	ident: AtomicU16,
+	/// Remote address of a connected socket
+	endpoint: InterruptTicketMutex<Option<IpAddress>>,
+	phantom: PhantomData<T>,
+}
+
+impl<T> Socket<T> {
+	pub fn new(handle: Handle) -> Self {
+		Self {
+			handle,
+			nonblocking: AtomicBool::new(false),
+			ident: AtomicU16::new(0),
+			endpoint: InterruptTicketMutex::new(None),
+			phantom: PhantomData,
+		}
+	}
+
+	fn with_nic<R>(&self, f: impl FnOnce(&mut NetworkInterface<'_>) -> R) -> R {
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().unwrap();
+		let result = f(nic);
+		nic.poll_common(now());
+
+		result
+	}
+
+	fn with<R>(&self, f: impl FnOnce(&mut icmp::Socket<'_>) -> R) -> R {
+		self.with_nic(|nic| f(nic.get_mut_socket::<icmp::Socket<'_>>(self.handle)))
+	}
+
+	/// Binds the socket to the identifier `ident` or, if `ident` is 0, to an ephemeral one.
+	fn bind_ident(&self, socket: &mut icmp::Socket<'_>, ident: u16) -> Result<u16, i32> {
+		let ident = if ident == 0 {
+			get_ephemeral_port()
+		} else {
+			ident
+		};
+
+		socket
+			.bind(icmp::Endpoint::Ident(ident))
+			.map_err(|_| -crate::errno::EINVAL)?;
+		self.ident.store(ident, Ordering::Release);
+
+		Ok(ident)
+	}
+
+	/// Binds the socket to an ephemeral identifier, if it is not bound yet.
+	fn bind_ephemeral(&self, socket: &mut icmp::Socket<'_>) -> Result<u16, i32> {
+		if socket.is_open() {
+			Ok(self.ident.load(Ordering::Acquire))
+		} else {
+			self.bind_ident(socket, 0)
+		}
+	}
+
+	fn bind_endpoint(&self, address: IpAddress, ident: u16) -> i32 {
+		self.with_nic(|nic| {
+			nic.bind_addr(self.handle, address)?;
+			self.bind_ident(nic.get_mut_socket::<icmp::Socket<'_>>(self.handle), ident)
+		})
+		.map(|_| 0)
+		.unwrap_or_else(|x| x)
+	}
+
+	fn connect_endpoint(&self, address: IpAddress) -> i32 {
+		let result = self.with_nic(|nic| {
+			nic.route_socket(self.handle, address);
+			self.bind_ephemeral(nic.get_mut_socket::<icmp::Socket<'_>>(self.handle))
+		});
+
+		match result {
+			Ok(_) => {
+				*self.endpoint.lock() = Some(address);
+				0
+			}
+			Err(x) => x,
+		}
+	}
+
+	async fn async_recvfrom(&self, buffer: &mut [u8]) -> Result<(isize, IpAddress), i32> {
+		let connected = *self.endpoint.lock();
+
+		future::poll_fn(|cx| {
+			self.with(|socket| {
+				while socket.can_recv() {
+					let (n, address) = socket.recv_slice(buffer).map_err(|_| -crate::errno::EIO)?;
+
+					// a connected socket receives only the replies of its peer
+					if connected.map_or(true, |connected| connected == address) {
+						return Poll::Ready(Ok((n.try_into().unwrap(), address)));
+					}
+				}
+
+				socket.register_recv_waker(cx.waker());
+				Poll::Pending
+			})
+		})
+		.await
+	}
+
+	async fn async_sendto(&self, buffer: &[u8], address: IpAddress) -> Result<isize, i32> {
+		let echo_request = match address {
+			IpAddress::Ipv4(_) => ICMPV4_ECHO_REQUEST,
+			IpAddress::Ipv6(_) => ICMPV6_ECHO_REQUEST,
+		};
+		if buffer.len() > ICMP_BUFFER_SIZE {
+			return Err(-crate::errno::EMSGSIZE);
+		} else if buffer.len() < ECHO_HEADER_LEN || buffer[0] != echo_request {
+			return Err(-crate::errno::EINVAL);
+		}
+
+		let ident = self.with_nic(|nic| {
+			nic.route_socket(self.handle, address);
+			self.bind_ephemeral(nic.get_mut_socket::<icmp::Socket<'_>>(self.handle))
+		})?;
+
+		// smoltcp recalculates the checksum of the request
+		let mut packet = buffer.to_vec();
+		packet[4..6].copy_from_slice(&ident.to_be_bytes());
+
+		future::poll_fn(|cx| {
+			self.with(|socket| match socket.send_slice(&packet, address) {
+				Ok(_) => Poll::Ready(Ok(buffer.len().try_into().unwrap())),
+				Err(SendError::BufferFull) => {
+					socket.register_send_waker(cx.waker());
+					Poll::Pending
+				}
+				Err(SendError::Unaddressable) => Poll::Ready(Err(-crate::errno::EINVAL)),
+			})
+		})
+		.await
+	}
+
+	fn recvfrom_address(&self, buf: *mut u8, len: usize) -> Result<(isize, IpAddress), i32> {
+		let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+
+		if self.nonblocking.load(Ordering::Acquire) {
+			block_on(self.async_recvfrom(slice), Some(Duration::ZERO)).map_err(|x| {
+				if x == -ETIME {
+					-EAGAIN
+				} else {
+					x
+				}
+			})
+		} else {
+			block_on(self.async_recvfrom(slice), None)
+		}
+	}
+
+	fn sendto_address(&self, buf: *const u8, len: usize, address: IpAddress) -> isize {
+		let slice = unsafe { core::slice::from_raw_parts(buf, len) };
+
+		if self.nonblocking.load(Ordering::Acquire) {
+			block_on(self.async_sendto(slice, address), Some(Duration::ZERO)).unwrap_or_else(|x| {
+				if x == -ETIME {
+					(-EAGAIN).try_into().unwrap()
+				} else {
+					x.try_into().unwrap()
+				}
+			})
+		} else {
+			block_on(self.async_sendto(slice, address), None)
+				.unwrap_or_else(|x| x.try_into().unwrap())
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		self.recvfrom_address(buf, len)
+			.map(|(n, _)| n)
+			.unwrap_or_else(|x| x.try_into().unwrap())
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		let endpoint = *self.endpoint.lock();
+		if let Some(address) = endpoint {
+			self.sendto_address(buf, len, address)
+		} else {
+			(-EDESTADDRREQ).try_into().unwrap()
+		}
+	}
+
+	fn setsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *const c_void,
+		optlen: socklen_t,
+	) -> i32 {
+		if level == SOL_SOCKET && optname == SO_BINDTODEVICE {
+			let name = unsafe { core::slice::from_raw_parts(optval as *const u8, optlen as usize) };
+			// the name of the interface may be terminated by a null byte
+			let name = name.split(|c| *c == 0).next().unwrap_or_default();
+
+			match core::str::from_utf8(name) {
+				Ok(name) => self.with_nic(|nic| {
+					nic.bind_to_device(self.handle, name)
+						.map(|_| 0)
+						.unwrap_or_else(|x| x)
+				}),
+				Err(_) => -EINVAL,
+			}
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		if cmd == FIONBIO {
+			let value = unsafe { *(argp as *const i32) };
+			if value != 0 {
+				info!("set device to nonblocking mode");
+				self.nonblocking.store(true, Ordering::Release);
+			} else {
+				info!("set device to blocking mode");
+				self.nonblocking.store(false, Ordering::Release);
+			}
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+}
+
+impl<T> Clone for Socket<T> {
+	fn clone(&self) -> Self {
+		let mut guard = NIC.lock();
+
+		let handle = if let NetworkState::Initialized(nic) = guard.deref_mut() {
+			nic.create_icmp_handle().unwrap()
+		} else {
+			panic!("Unable to create handle");
+		};
+
+		Self {
+			handle,
+			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+			ident: AtomicU16::new(0),
+			endpoint: InterruptTicketMutex::new(*self.endpoint.lock()),
+			phantom: PhantomData,
+		}
+	}
+}
+
+impl<T> Drop for Socket<T> {
+	fn drop(&mut self) {
+		// smoltcp is unable to unbind an ICMP socket => replace it by an unbound one
+		self.with(|socket| *socket = echo_socket());
+	}
+}
+
+impl ObjectInterface for Socket<IPv4> {
+	fn bind(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in>().try_into().unwrap() {
+			let addr = unsafe { *(name as *const sockaddr_in) };
+			let ident = u16::from_be(addr.sin_port);
+			let address = IpAddress::v4(
+				addr.sin_addr.s_addr[0],
+				addr.sin_addr.s_addr[1],
+				addr.sin_addr.s_addr[2],
+				addr.sin_addr.s_addr[3],
+			);
+
+			self.bind_endpoint(address, ident)
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn connect(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in>().try_into().unwrap() {
+			let saddr = unsafe { *(name as *const sockaddr_in) };
+			let address = IpAddress::v4(
+				saddr.sin_addr.s_addr[0],
+				saddr.sin_addr.s_addr[1],
+				saddr.sin_addr.s_addr[2],
+				saddr.sin_addr.s_addr[3],
+			);
+
+			self.connect_endpoint(address)
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getpeername(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in) };
+
+			let Some(IpAddress::Ipv4(ip)) = *self.endpoint.lock() else {
+				return -ENOTCONN;
+			};
+			addr.sin_family = AF_INET.try_into().unwrap();
+			addr.sin_port = 0;
+			addr.sin_addr.s_addr.copy_from_slice(ip.as_bytes());
+
+			*namelen = size_of::<sockaddr_in>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getsockname(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in) };
+			addr.sin_family = AF_INET.try_into().unwrap();
+			addr.sin_port = self.ident.load(Ordering::Acquire).to_be();
+
+			*namelen = size_of::<sockaddr_in>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn sendto(
+		&self,
+		buf: *const u8,
+		len: usize,
+		addr: *const sockaddr,
+		addrlen: socklen_t,
+	) -> isize {
+		if addr.is_null() {
+			return self.write(buf, len);
+		}
+
+		if addrlen == size_of::<sockaddr_in>().try_into().unwrap() {
+			let saddr = unsafe { *(addr as *const sockaddr_in) };
+			let address = IpAddress::v4(
+				saddr.sin_addr.s_addr[0],
+				saddr.sin_addr.s_addr[1],
+				saddr.sin_addr.s_addr[2],
+				saddr.sin_addr.s_addr[3],
+			);
+
+			self.sendto_address(buf, len, address)
+		} else {
+			(-EINVAL).try_into().unwrap()
+		}
+	}
+
+	fn recvfrom(
+		&self,
+		buf: *mut u8,
+		len: usize,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> isize {
+		match self.recvfrom_address(buf, len) {
+			Ok((n, remote)) => {
+				if !addr.is_null() && !addrlen.is_null() {
+					let addrlen = unsafe { &mut *addrlen };
+					if *addrlen >= size_of::<sockaddr_in>().try_into().unwrap() {
+						let addr = unsafe { &mut *(addr as *mut sockaddr_in) };
+						addr.sin_family = AF_INET.try_into().unwrap();
+						addr.sin_port = 0;
+						if let IpAddress::Ipv4(ip) = remote {
+							addr.sin_addr.s_addr.copy_from_slice(ip.as_bytes());
+						}
+					}
+					*addrlen = size_of::<sockaddr_in>().try_into().unwrap();
+				}
+
+				n
+			}
+			Err(x) => x.try_into().unwrap(),
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		self.read(buf, len)
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		self.write(buf, len)
+	}
+
+	fn setsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *const c_void,
+		optlen: socklen_t,
+	) -> i32 {
+		self.setsockopt(level, optname, optval, optlen)
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		self.ioctl(cmd, argp)
+	}
+}
+
+impl ObjectInterface for Socket<IPv6> {
+	fn bind(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in6>().try_into().unwrap() {
+			let addr = unsafe { *(name as *const sockaddr_in6) };
+			let ident = u16::from_be(addr.sin6_port);
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&addr.sin6_addr.s6_addr));
+
+			self.bind_endpoint(address, ident)
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn connect(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in6>().try_into().unwrap() {
+			let saddr = unsafe { *(name as *const sockaddr_in6) };
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&saddr.sin6_addr.s6_addr));
+
+			self.connect_endpoint(address)
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getpeername(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in6>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in6) };
+
+			let Some(IpAddress::Ipv6(ip)) = *self.endpoint.lock() else {
+				return -ENOTCONN;
+			};
+			addr.sin6_family = AF_INET6.try_into().unwrap();
+			addr.sin6_port = 0;
+			addr.sin6_addr.s6_addr.copy_from_slice(ip.as_bytes());
+
+			*namelen = size_of::<sockaddr_in6>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getsockname(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in6>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in6) };
+			addr.sin6_family = AF_INET6.try_into().unwrap();
+			addr.sin6_port = self.ident.load(Ordering::Acquire).to_be();
+
+			*namelen = size_of::<sockaddr_in6>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn sendto(
+		&self,
+		buf: *const u8,
+		len: usize,
+		addr: *const sockaddr,
+		addrlen: socklen_t,
+	) -> isize {
+		if addr.is_null() {
+			return self.write(buf, len);
+		}
+
+		if addrlen == size_of::<sockaddr_in6>().try_into().unwrap() {
+			let saddr = unsafe { *(addr as *const sockaddr_in6) };
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&saddr.sin6_addr.s6_addr));
+
+			self.sendto_address(buf, len, address)
+		} else {
+			(-EINVAL).try_into().unwrap()
+		}
+	}
+
+	fn recvfrom(
+		&self,
+		buf: *mut u8,
+		len: usize,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> isize {
+		match self.recvfrom_address(buf, len) {
+			Ok((n, remote)) => {
+				if !addr.is_null() && !addrlen.is_null() {
+					let addrlen = unsafe { &mut *addrlen };
+					if *addrlen >= size_of::<sockaddr_in6>().try_into().unwrap() {
+						let addr = unsafe { &mut *(addr as *mut sockaddr_in6) };
+						addr.sin6_family = AF_INET6.try_into().unwrap();
+						addr.sin6_port = 0;
+						if let IpAddress::Ipv6(ip) = remote {
+							addr.sin6_addr.s6_addr.copy_from_slice(ip.as_bytes());
+						}
+					}
+					*addrlen = size_of::<sockaddr_in6>().try_into().unwrap();
+				}
+
+				n
+			}
+			Err(x) => x.try_into().unwrap(),
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		self.read(buf, len)
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		self.write(buf, len)
+	}
+
+	fn setsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *const c_void,
+		optlen: socklen_t,
+	) -> i32 {
+		self.setsockopt(level, optname, optval, optlen)
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		self.ioctl(cmd, argp)
+	}
+}
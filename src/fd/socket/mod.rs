@@ -1,15 +1,40 @@
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::ffi::c_void;
+#[cfg(feature = "dns")]
+use core::ffi::{c_char, CStr};
 use core::ops::DerefMut;
-use core::sync::atomic::Ordering;
+use core::ptr;
+use core::sync::atomic::{AtomicU16, Ordering};
+use core::task::Poll;
 
+use futures_lite::future;
+use smoltcp::time::Duration;
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv6Address};
+
+#[cfg(feature = "pci")]
+use crate::drivers::pci::get_vsock_driver;
 use crate::errno::*;
 use crate::fd::{get_object, insert_object, FD_COUNTER, OBJECT_MAP};
-use crate::net::{NetworkState, NIC};
+#[cfg(feature = "dns")]
+use crate::net::dns::{self, Family};
+use crate::net::executor::block_on;
+use crate::net::{NetifInfo, NetworkInterface, NetworkState, NIC};
 use crate::syscalls::net::*;
 
+mod icmp;
 mod tcp;
 mod udp;
+#[cfg(feature = "pci")]
+mod vsock;
+
+/// Returns the next local port, which is used if a socket is not bound to a port.
+pub(crate) fn get_ephemeral_port() -> u16 {
+	static LOCAL_ENDPOINT: AtomicU16 = AtomicU16::new(49152);
+
+	LOCAL_ENDPOINT.fetch_add(1, Ordering::SeqCst)
+}
 
 pub(crate) extern "C" fn __sys_socket(domain: i32, type_: i32, protocol: i32) -> i32 {
 	debug!(
@@ -17,9 +42,33 @@ pub(crate) extern "C" fn __sys_socket(domain: i32, type_: i32, protocol: i32) ->
 		domain, type_, protocol
 	);
 
+	// Stream sockets of the address family AF_VSOCK do not depend on the network interface.
+	#[cfg(feature = "pci")]
+	if domain == AF_VSOCK {
+		return if type_ != SOCK_STREAM || protocol != 0 {
+			-EINVAL
+		} else if get_vsock_driver().is_none() {
+			-EAFNOSUPPORT
+		} else {
+			let fd = FD_COUNTER.fetch_add(1, Ordering::SeqCst);
+			let socket = self::vsock::Socket::default();
+			if OBJECT_MAP.write().try_insert(fd, Arc::new(socket)).is_err() {
+				-EINVAL
+			} else {
+				fd
+			}
+		};
+	}
+
+	// Datagram sockets of the ICMP protocols send echo requests, e.g. for ping.
+	let icmp = type_ == SOCK_DGRAM
+		&& ((domain == AF_INET && protocol == IPPROTO_ICMP)
+			|| (domain == AF_INET6 && protocol == IPPROTO_ICMPV6));
+
 	if (domain != AF_INET && domain != AF_INET6)
-		|| type_ != SOCK_STREAM
-		|| (protocol != 0 && protocol != IPPROTO_UDP && protocol != IPPROTO_TCP)
+		|| (type_ != SOCK_STREAM && type_ != SOCK_DGRAM)
+		|| (type_ == SOCK_STREAM && protocol != 0 && protocol != IPPROTO_TCP)
+		|| (type_ == SOCK_DGRAM && !icmp && protocol != 0 && protocol != IPPROTO_UDP)
 	{
 		-EINVAL
 	} else {
@@ -28,13 +77,39 @@ pub(crate) extern "C" fn __sys_socket(domain: i32, type_: i32, protocol: i32) ->
 		if let NetworkState::Initialized(nic) = guard.deref_mut() {
 			let fd = FD_COUNTER.fetch_add(1, Ordering::SeqCst);
 
-			if protocol == IPPROTO_UDP {
+			if icmp {
+				let handle = nic.create_icmp_handle().unwrap();
+				if domain == AF_INET {
+					let socket = self::icmp::Socket::<self::tcp::IPv4>::new(handle);
+					if OBJECT_MAP.write().try_insert(fd, Arc::new(socket)).is_err() {
+						-EINVAL
+					} else {
+						fd
+					}
+				} else {
+					let socket = self::icmp::Socket::<self::tcp::IPv6>::new(handle);
+					if OBJECT_MAP.write().try_insert(fd, Arc::new(socket)).is_err() {
+						-EINVAL
+					} else {
+						fd
+					}
+				}
+			} else if type_ == SOCK_DGRAM {
 				let handle = nic.create_udp_handle().unwrap();
-				let socket = self::udp::Socket::new(handle);
-				if OBJECT_MAP.write().try_insert(fd, Arc::new(socket)).is_err() {
-					-EINVAL
+				if domain == AF_INET {
+					let socket = self::udp::Socket::<self::tcp::IPv4>::new(handle);
+					if OBJECT_MAP.write().try_insert(fd, Arc::new(socket)).is_err() {
+						-EINVAL
+					} else {
+						fd
+					}
 				} else {
-					fd
+					let socket = self::udp::Socket::<self::tcp::IPv6>::new(handle);
+					if OBJECT_MAP.write().try_insert(fd, Arc::new(socket)).is_err() {
+						-EINVAL
+					} else {
+						fd
+					}
 				}
 			} else {
 				let handle = nic.create_tcp_handle().unwrap();
@@ -69,17 +144,14 @@ pub(crate) extern "C" fn __sys_accept(
 	obj.map_or_else(
 		|e| e,
 		|v| {
-			let result = (*v).accept(addr, addrlen);
-			if result >= 0 {
-				let new_obj = dyn_clone::clone_box(&*v);
-				insert_object(fd, Arc::from(new_obj));
-				let new_fd = FD_COUNTER.fetch_add(1, Ordering::SeqCst);
-				(*v).listen(1);
-				insert_object(new_fd, v.clone());
-				new_fd
-			} else {
-				result
-			}
+			(*v).accept(addr, addrlen).map_or_else(
+				|e| e,
+				|new_obj| {
+					let new_fd = FD_COUNTER.fetch_add(1, Ordering::SeqCst);
+					insert_object(new_fd, new_obj);
+					new_fd
+				},
+			)
 		},
 	)
 }
@@ -149,15 +221,424 @@ pub(crate) extern "C" fn __sys_getpeername(
 	obj.map_or_else(|e| e, |v| (*v).getpeername(name, namelen))
 }
 
+/// Storage of an entry of the list, which is returned by `getaddrinfo`. The socket
+/// address is part of the entry, so that `freeaddrinfo` releases both at once.
+#[cfg(feature = "dns")]
+#[repr(C)]
+struct AddrInfo {
+	info: addrinfo,
+	addr: SockAddr,
+}
+
+#[repr(C)]
+union SockAddr {
+	v4: sockaddr_in,
+	v6: sockaddr_in6,
+}
+
+impl SockAddr {
+	/// Returns the socket address of `addr` and `port` together with its
+	/// address family and its length.
+	fn new(addr: IpAddress, port: u16) -> (i32, usize, Self) {
+		match addr {
+			IpAddress::Ipv4(ip) => (
+				AF_INET,
+				core::mem::size_of::<sockaddr_in>(),
+				SockAddr {
+					v4: sockaddr_in {
+						sin_len: core::mem::size_of::<sockaddr_in>().try_into().unwrap(),
+						sin_family: AF_INET.try_into().unwrap(),
+						sin_port: port.to_be(),
+						sin_addr: in_addr { s_addr: ip.0 },
+						sin_zero: [0; 8],
+					},
+				},
+			),
+			IpAddress::Ipv6(ip) => (
+				AF_INET6,
+				core::mem::size_of::<sockaddr_in6>(),
+				SockAddr {
+					v6: sockaddr_in6 {
+						sin6_family: AF_INET6.try_into().unwrap(),
+						sin6_port: port.to_be(),
+						sin6_addr: in6_addr { s6_addr: ip.0 },
+						sin6_flowinfo: 0,
+						sin6_scope_id: 0,
+					},
+				},
+			),
+		}
+	}
+}
+
+#[cfg(feature = "dns")]
+impl AddrInfo {
+	fn new(addr: IpAddress, port: u16, socktype: i32, protocol: i32) -> Self {
+		let (family, addrlen, addr) = SockAddr::new(addr, port);
+
+		Self {
+			info: addrinfo {
+				ai_flags: 0,
+				ai_family: family,
+				ai_socktype: socktype,
+				ai_protocol: protocol,
+				ai_addrlen: addrlen.try_into().unwrap(),
+				ai_addr: ptr::null_mut(),
+				ai_canonname: ptr::null_mut(),
+				ai_next: ptr::null_mut(),
+			},
+			addr,
+		}
+	}
+}
+
+#[cfg(feature = "dns")]
+pub extern "C" fn __sys_freeaddrinfo(ai: *mut addrinfo) {
+	let mut ai = ai;
+
+	while !ai.is_null() {
+		// Each entry is allocated by `__sys_getaddrinfo` as `AddrInfo`.
+		let entry = unsafe { Box::from_raw(ai as *mut AddrInfo) };
+		ai = entry.info.ai_next;
+	}
+}
+
+#[cfg(not(feature = "dns"))]
 pub extern "C" fn __sys_freeaddrinfo(_ai: *mut addrinfo) {}
 
+/// Merges the addresses, which have been resolved for the requested families.
+/// The host is resolved, if it has an address of any of the families. Otherwise,
+/// the error of the last failed query is returned.
+#[cfg(feature = "dns")]
+fn merge_resolved(
+	results: impl IntoIterator<Item = Result<Vec<IpAddress>, i32>>,
+) -> Result<Vec<IpAddress>, i32> {
+	let mut addrs = Vec::new();
+	let mut error = EAI_NONAME;
+	for result in results {
+		match result {
+			Ok(resolved) => addrs.extend(resolved),
+			Err(err) => error = err,
+		}
+	}
+
+	if addrs.is_empty() {
+		Err(error)
+	} else {
+		Ok(addrs)
+	}
+}
+
+/// Resolves the host `nodename` and the port `servname`. Without `hints`
+/// or with the family `AF_UNSPEC`, IPv4 and IPv6 addresses are returned.
+#[cfg(feature = "dns")]
+pub extern "C" fn __sys_getaddrinfo(
+	nodename: *const u8,
+	servname: *const u8,
+	hints: *const addrinfo,
+	res: *mut *mut addrinfo,
+) -> i32 {
+	if res.is_null() || (nodename.is_null() && servname.is_null()) {
+		return EAI_NONAME;
+	}
+
+	let (flags, family, socktype, protocol) =
+		unsafe { hints.as_ref() }.map_or((0, AF_UNSPEC, 0, 0), |hints| {
+			(
+				hints.ai_flags,
+				hints.ai_family,
+				hints.ai_socktype,
+				hints.ai_protocol,
+			)
+		});
+	let families: &[Family] = match family {
+		AF_INET => &[Family::Ipv4],
+		AF_INET6 => &[Family::Ipv6],
+		AF_UNSPEC => &[Family::Ipv4, Family::Ipv6],
+		_ => return EAI_FAMILY,
+	};
+
+	let port = if servname.is_null() {
+		0
+	} else {
+		let servname = unsafe { CStr::from_ptr(servname as *const c_char) };
+		match servname.to_str().ok().and_then(|s| s.parse::<u16>().ok()) {
+			Some(port) => port,
+			None => return EAI_SERVICE,
+		}
+	};
+
+	let addrs = if nodename.is_null() {
+		// Without a host, the address is used either to accept connections
+		// or to connect to a local service.
+		families
+			.iter()
+			.map(|family| match (family, flags & AI_PASSIVE != 0) {
+				(Family::Ipv4, true) => IpAddress::Ipv4(Ipv4Address::UNSPECIFIED),
+				(Family::Ipv4, false) => IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1)),
+				(Family::Ipv6, true) => IpAddress::Ipv6(Ipv6Address::UNSPECIFIED),
+				(Family::Ipv6, false) => IpAddress::Ipv6(Ipv6Address::LOOPBACK),
+			})
+			.collect()
+	} else {
+		let nodename = unsafe { CStr::from_ptr(nodename as *const c_char) };
+		let Ok(nodename) = nodename.to_str() else {
+			return EAI_NONAME;
+		};
+
+		let results = families
+			.iter()
+			.map(|family| block_on(dns::resolve(nodename, *family), None));
+		match merge_resolved(results) {
+			Ok(addrs) => addrs,
+			Err(err) => return err,
+		}
+	};
+
+	// build the list backwards, so that it keeps the order of the addresses
+	let mut list: *mut addrinfo = ptr::null_mut();
+	for addr in addrs.into_iter().rev() {
+		let entry = Box::into_raw(Box::new(AddrInfo::new(addr, port, socktype, protocol)));
+		unsafe {
+			(*entry).info.ai_addr = ptr::addr_of_mut!((*entry).addr) as *mut sockaddr;
+			(*entry).info.ai_next = list;
+		}
+		list = entry as *mut addrinfo;
+	}
+
+	unsafe {
+		*res = list;
+	}
+
+	0
+}
+
+#[cfg(not(feature = "dns"))]
 pub extern "C" fn __sys_getaddrinfo(
 	_nodename: *const u8,
 	_servname: *const u8,
 	_hints: *const addrinfo,
 	_res: *mut *mut addrinfo,
 ) -> i32 {
-	-EINVAL
+	EAI_FAIL
+}
+
+/// Storage of an entry of the list, which is returned by `getifaddrs`. The name and
+/// the socket addresses are part of the entry, so that `freeifaddrs` releases them at once.
+#[repr(C)]
+struct IfAddrs {
+	info: ifaddrs,
+	addr: SockAddr,
+	netmask: SockAddr,
+	name: [u8; IFNAMSIZ],
+}
+
+/// Returns the name of the interface as null-terminated string.
+fn netif_name(name: &str) -> [u8; IFNAMSIZ] {
+	let mut buffer = [0; IFNAMSIZ];
+	let len = name.len().min(IFNAMSIZ - 1);
+	buffer[..len].copy_from_slice(&name.as_bytes()[..len]);
+	buffer
+}
+
+fn netif_flags(netif: &NetifInfo) -> u32 {
+	let mut flags = IFF_UP | IFF_MULTICAST;
+	if netif.loopback {
+		flags |= IFF_LOOPBACK;
+	}
+	if netif.link_up {
+		flags |= IFF_RUNNING;
+	}
+	if netif.promiscuous {
+		flags |= IFF_PROMISC;
+	}
+	if netif.all_multicast {
+		flags |= IFF_ALLMULTI;
+	}
+	flags
+}
+
+/// Returns the netmask of the network `cidr`.
+fn netmask(cidr: &IpCidr) -> IpAddress {
+	match cidr {
+		IpCidr::Ipv4(cidr) => {
+			let mask = u32::MAX
+				.checked_shl(32 - u32::from(cidr.prefix_len()))
+				.unwrap_or(0);
+			IpAddress::Ipv4(Ipv4Address::from_bytes(&mask.to_be_bytes()))
+		}
+		IpCidr::Ipv6(cidr) => {
+			let mask = u128::MAX
+				.checked_shl(128 - u32::from(cidr.prefix_len()))
+				.unwrap_or(0);
+			IpAddress::Ipv6(Ipv6Address::from_bytes(&mask.to_be_bytes()))
+		}
+	}
+}
+
+fn netif_info() -> Result<Vec<NetifInfo>, i32> {
+	NIC.lock()
+		.as_nic_mut()
+		.map(|nic| nic.netif_info())
+		.map_err(|_| -ENETDOWN)
+}
+
+pub extern "C" fn __sys_getifaddrs(ifap: *mut *mut ifaddrs) -> i32 {
+	if ifap.is_null() {
+		return -EINVAL;
+	}
+	let netifs = match netif_info() {
+		Ok(netifs) => netifs,
+		Err(err) => return err,
+	};
+
+	// build the list backwards, so that it keeps the order of the interfaces
+	let mut list: *mut ifaddrs = ptr::null_mut();
+	for netif in netifs.iter().rev() {
+		for cidr in netif.addrs.iter().rev() {
+			let mut flags = netif_flags(netif);
+			let loopback = match cidr.address() {
+				IpAddress::Ipv4(addr) => addr.is_loopback(),
+				IpAddress::Ipv6(addr) => addr.is_loopback(),
+			};
+			if loopback {
+				flags |= IFF_LOOPBACK;
+			}
+
+			let entry = Box::into_raw(Box::new(IfAddrs {
+				info: ifaddrs {
+					ifa_next: list,
+					ifa_name: ptr::null_mut(),
+					ifa_flags: flags,
+					ifa_addr: ptr::null_mut(),
+					ifa_netmask: ptr::null_mut(),
+					ifa_broadaddr: ptr::null_mut(),
+					ifa_data: ptr::null_mut(),
+				},
+				addr: SockAddr::new(cidr.address(), 0).2,
+				netmask: SockAddr::new(netmask(cidr), 0).2,
+				name: netif_name(&netif.name),
+			}));
+			unsafe {
+				(*entry).info.ifa_name = ptr::addr_of_mut!((*entry).name) as *mut u8;
+				(*entry).info.ifa_addr = ptr::addr_of_mut!((*entry).addr) as *mut sockaddr;
+				(*entry).info.ifa_netmask = ptr::addr_of_mut!((*entry).netmask) as *mut sockaddr;
+			}
+			list = entry as *mut ifaddrs;
+		}
+	}
+
+	unsafe {
+		*ifap = list;
+	}
+
+	0
+}
+
+pub extern "C" fn __sys_freeifaddrs(ifa: *mut ifaddrs) {
+	let mut ifa = ifa;
+
+	while !ifa.is_null() {
+		// Each entry is allocated by `__sys_getifaddrs` as `IfAddrs`.
+		let entry = unsafe { Box::from_raw(ifa as *mut IfAddrs) };
+		ifa = entry.info.ifa_next;
+	}
+}
+
+pub extern "C" fn __sys_getifinfo(index: u32, info: *mut ifinfo) -> i32 {
+	let Some(info) = (unsafe { info.as_mut() }) else {
+		return -EINVAL;
+	};
+	let netifs = match netif_info() {
+		Ok(netifs) => netifs,
+		Err(err) => return err,
+	};
+	let Some(netif) = netifs.iter().find(|netif| netif.index == index) else {
+		return -ENODEV;
+	};
+
+	let mut dns = [in6_addr { s6_addr: [0; 16] }; IFI_DNS_MAX];
+	for (dst, server) in dns.iter_mut().zip(netif.dns_servers.iter()) {
+		match server {
+			IpAddress::Ipv4(addr) => {
+				dst.s6_addr[10..12].copy_from_slice(&[0xff, 0xff]);
+				dst.s6_addr[12..].copy_from_slice(addr.as_bytes());
+			}
+			IpAddress::Ipv6(addr) => dst.s6_addr = addr.0,
+		}
+	}
+
+	*info = ifinfo {
+		ifi_name: netif_name(&netif.name),
+		ifi_index: netif.index,
+		ifi_flags: netif_flags(netif),
+		ifi_mtu: netif.mtu.into(),
+		ifi_hwaddr: netif.mac.0,
+		ifi_gateway: in_addr {
+			s_addr: netif.gateway.map_or([0; 4], |gateway| gateway.0),
+		},
+		ifi_gateway6: in6_addr {
+			s6_addr: netif.gateway6.map_or([0; 16], |gateway| gateway.0),
+		},
+		ifi_dns_count: netif.dns_servers.len().min(IFI_DNS_MAX).try_into().unwrap(),
+		ifi_dns: dns,
+	};
+
+	0
+}
+
+/// Runs `f` with the network interface and returns 0 on success or the negative error number.
+fn with_nic(f: impl FnOnce(&mut NetworkInterface<'_>) -> Result<(), i32>) -> i32 {
+	let mut guard = NIC.lock();
+	let Ok(nic) = guard.as_nic_mut() else {
+		return -ENETDOWN;
+	};
+
+	f(nic).map_or_else(|err| err, |()| 0)
+}
+
+pub extern "C" fn __sys_setifflags(index: u32, flags: u32) -> i32 {
+	with_nic(|nic| nic.set_netif_modes(index, flags & IFF_PROMISC != 0, flags & IFF_ALLMULTI != 0))
+}
+
+pub extern "C" fn __sys_addifvlan(index: u32, vid: u16) -> i32 {
+	with_nic(|nic| nic.add_vlan(index, vid))
+}
+
+pub extern "C" fn __sys_delifvlan(index: u32, vid: u16) -> i32 {
+	with_nic(|nic| nic.remove_vlan(index, vid))
+}
+
+pub extern "C" fn __sys_wait_netconfig(version: *mut u32, timeout: i32) -> i32 {
+	let Some(version) = (unsafe { version.as_mut() }) else {
+		return -EINVAL;
+	};
+	let current = *version;
+	let timeout = u64::try_from(timeout).ok().map(Duration::from_millis);
+
+	let result = block_on(
+		future::poll_fn(|cx| {
+			let mut guard = NIC.lock();
+			let Ok(nic) = guard.as_nic_mut() else {
+				return Poll::Ready(Err(-ENETDOWN));
+			};
+			if nic.config_version() != current {
+				Poll::Ready(Ok(nic.config_version()))
+			} else {
+				nic.register_config_waker(cx.waker());
+				Poll::Pending
+			}
+		}),
+		timeout,
+	);
+
+	match result {
+		Ok(new_version) => {
+			*version = new_version;
+			0
+		}
+		Err(err) => err,
+	}
 }
 
 pub extern "C" fn __sys_shutdown_socket(fd: i32, how: i32) -> i32 {
@@ -169,3 +650,57 @@ pub extern "C" fn __sys_recv(fd: i32, buf: *mut u8, len: usize) -> isize {
 	let obj = get_object(fd);
 	obj.map_or_else(|e| e as isize, |v| (*v).read(buf, len))
 }
+
+pub extern "C" fn __sys_recvfrom(
+	fd: i32,
+	buf: *mut u8,
+	len: usize,
+	addr: *mut sockaddr,
+	addrlen: *mut socklen_t,
+) -> isize {
+	let obj = get_object(fd);
+	obj.map_or_else(|e| e as isize, |v| (*v).recvfrom(buf, len, addr, addrlen))
+}
+
+pub extern "C" fn __sys_sendto(
+	fd: i32,
+	buf: *const u8,
+	len: usize,
+	addr: *const sockaddr,
+	addrlen: socklen_t,
+) -> isize {
+	let obj = get_object(fd);
+	obj.map_or_else(|e| e as isize, |v| (*v).sendto(buf, len, addr, addrlen))
+}
+
+#[cfg(all(test, not(target_os = "none"), feature = "dns"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn merge_families() {
+		let v4 = IpAddress::v4(10, 0, 5, 3);
+		let v6 = IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 3);
+
+		// The order of the families is kept.
+		assert_eq!(
+			merge_resolved([Ok(vec![v4]), Ok(vec![v6])]),
+			Ok(vec![v4, v6])
+		);
+		// A host without an address of one family is still resolved.
+		assert_eq!(
+			merge_resolved([Err(EAI_NONAME), Ok(vec![v6])]),
+			Ok(vec![v6])
+		);
+		assert_eq!(merge_resolved([Ok(vec![v4]), Ok(vec![])]), Ok(vec![v4]));
+	}
+
+	#[test]
+	fn merge_failures() {
+		assert_eq!(
+			merge_resolved([Err(EAI_NONAME), Err(EAI_FAIL)]),
+			Err(EAI_FAIL)
+		);
+		assert_eq!(merge_resolved([Ok(vec![]), Ok(vec![])]), Err(EAI_NONAME));
+	}
+}
@@ -1,28 +1,28 @@
+use alloc::sync::Arc;
 use core::ffi::c_void;
 use core::marker::PhantomData;
 use core::mem::size_of;
 use core::ops::DerefMut;
-use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
 use core::task::Poll;
 
 use futures_lite::future;
+use hermit_sync::InterruptTicketMutex;
 use smoltcp::iface;
 use smoltcp::socket::tcp;
 use smoltcp::time::Duration;
-use smoltcp::wire::IpAddress;
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv6Address};
 
 use crate::errno::*;
+use crate::fd::socket::get_ephemeral_port;
 use crate::fd::ObjectInterface;
 use crate::net::executor::block_on;
-use crate::net::{now, Handle, NetworkState, NIC};
+use crate::net::{copy_tcp_socket, now, Handle, NetworkInterface, NetworkState, NIC};
 use crate::syscalls::net::*;
-use crate::DEFAULT_KEEP_ALIVE_INTERVAL;
+use crate::{DEFAULT_KEEP_ALIVE_INTERVAL, MAX_SOCKET_BUFFER_SIZE};
 
-fn get_ephemeral_port() -> u16 {
-	static LOCAL_ENDPOINT: AtomicU16 = AtomicU16::new(49152);
-
-	LOCAL_ENDPOINT.fetch_add(1, Ordering::SeqCst)
-}
+/// Hop limit of the packets, if the socket does not set one, see smoltcp
+const DEFAULT_HOP_LIMIT: u8 = 64;
 
 #[derive(Debug)]
 pub struct IPv4;
@@ -35,6 +35,10 @@ pub struct Socket<T> {
 	handle: Handle,
 	port: AtomicU16,
 	nonblocking: AtomicBool,
+	/// Interval of the keep-alive packets in milliseconds, if `SO_KEEPALIVE` is set
+	keep_alive_interval: AtomicU64,
+	/// Time, which closing the socket waits for the connection to shut down, see `SO_LINGER`
+	linger: InterruptTicketMutex<Option<Duration>>,
 	phantom: PhantomData<T>,
 }
 
@@ -44,27 +48,42 @@ impl<T> Socket<T> {
 			handle,
 			port: AtomicU16::new(0),
 			nonblocking: AtomicBool::new(false),
+			keep_alive_interval: AtomicU64::new(DEFAULT_KEEP_ALIVE_INTERVAL),
+			linger: InterruptTicketMutex::new(None),
 			phantom: PhantomData,
 		}
 	}
 
-	fn with<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>) -> R) -> R {
+	/// Creates a socket with `handle`, which inherits the options of this socket.
+	fn with_options(&self, handle: Handle) -> Self {
+		Self {
+			handle,
+			port: AtomicU16::new(self.port.load(Ordering::Acquire)),
+			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+			keep_alive_interval: AtomicU64::new(self.keep_alive_interval.load(Ordering::Acquire)),
+			linger: InterruptTicketMutex::new(*self.linger.lock()),
+			phantom: PhantomData,
+		}
+	}
+
+	fn with_nic<R>(&self, f: impl FnOnce(&mut NetworkInterface<'_>) -> R) -> R {
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().unwrap();
-		let result = f(nic.get_mut_socket::<tcp::Socket<'_>>(self.handle));
+		let result = f(nic);
 		nic.poll_common(now());
 
 		result
 	}
 
-	fn with_context<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>, &mut iface::Context) -> R) -> R {
-		let mut guard = NIC.lock();
-		let nic = guard.as_nic_mut().unwrap();
-		let (s, cx) = nic.get_socket_and_context::<tcp::Socket<'_>>(self.handle);
-		let result = f(s, cx);
-		nic.poll_common(now());
+	fn with<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>) -> R) -> R {
+		self.with_nic(|nic| f(nic.get_mut_socket::<tcp::Socket<'_>>(self.handle)))
+	}
 
-		result
+	fn with_context<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>, &mut iface::Context) -> R) -> R {
+		self.with_nic(|nic| {
+			let (s, cx) = nic.get_socket_and_context::<tcp::Socket<'_>>(self.handle);
+			f(s, cx)
+		})
 	}
 
 	async fn async_read(&self, buffer: &mut [u8]) -> Result<isize, i32> {
@@ -152,29 +171,37 @@ impl<T> Socket<T> {
 	}
 
 	async fn async_connect(&self, address: IpAddress, port: u16) -> Result<i32, i32> {
+		self.with_nic(|nic| nic.route_socket(self.handle, address));
 		self.with_context(|socket, cx| socket.connect(cx, (address, port), get_ephemeral_port()))
 			.map_err(|x| {
 				info!("x {:?}", x);
 				-crate::errno::EIO
 			})?;
 
+		let remote = IpEndpoint::new(address, port);
 		future::poll_fn(|cx| {
-			self.with(|socket| match socket.state() {
-				tcp::State::Closed | tcp::State::TimeWait => {
-					Poll::Ready(Err(-crate::errno::EFAULT))
-				}
-				tcp::State::Listen => Poll::Ready(Err(-crate::errno::EIO)),
-				tcp::State::SynSent | tcp::State::SynReceived => {
-					socket.register_send_waker(cx.waker());
-					Poll::Pending
+			self.with_nic(|nic| {
+				let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
+				match socket.state() {
+					// ICMP may have reported an unreachable destination
+					tcp::State::Closed | tcp::State::TimeWait => Poll::Ready(Err(nic
+						.take_socket_error(self.handle, remote)
+						.unwrap_or(-crate::errno::EFAULT))),
+					tcp::State::Listen => Poll::Ready(Err(-crate::errno::EIO)),
+					tcp::State::SynSent | tcp::State::SynReceived => {
+						socket.register_send_waker(cx.waker());
+						Poll::Pending
+					}
+					_ => Poll::Ready(Ok(0)),
 				}
-				_ => Poll::Ready(Ok(0)),
 			})
 		})
 		.await
 	}
 
 	async fn async_close(&self) -> Result<(), i32> {
+		self.with_nic(|nic| nic.stop_listening(self.handle));
+
 		future::poll_fn(|cx| {
 			self.with(|socket| match socket.state() {
 				tcp::State::FinWait1
@@ -215,54 +242,45 @@ impl<T> Socket<T> {
 		&self,
 		_addr: *mut sockaddr,
 		_addrlen: *mut socklen_t,
-	) -> Result<(), i32> {
-		future::poll_fn(|cx| {
-			self.with(|socket| match socket.state() {
-				tcp::State::Closed => {
-					let _ = socket.listen(self.port.load(Ordering::Acquire));
-					Poll::Ready(())
-				}
-				tcp::State::Listen => Poll::Ready(()),
-				_ => {
-					socket.register_recv_waker(cx.waker());
+	) -> Result<Handle, i32> {
+		let connection = future::poll_fn(|cx| {
+			self.with_nic(|nic| match nic.accept(self.handle) {
+				Ok(Some(connection)) => Poll::Ready(Ok(connection)),
+				Ok(None) => {
+					nic.register_accept_waker(self.handle, cx.waker());
 					Poll::Pending
 				}
-			})
-		})
-		.await;
-
-		future::poll_fn(|cx| {
-			self.with(|socket| {
-				if socket.is_active() {
-					Poll::Ready(Ok(()))
-				} else {
-					match socket.state() {
-						tcp::State::Closed
-						| tcp::State::Closing
-						| tcp::State::FinWait1
-						| tcp::State::FinWait2 => Poll::Ready(Err(-crate::errno::EIO)),
-						_ => {
-							socket.register_recv_waker(cx.waker());
-							Poll::Pending
-						}
-					}
-				}
+				Err(x) => Poll::Ready(Err(x)),
 			})
 		})
 		.await?;
 
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().map_err(|_| -crate::errno::EIO)?;
-		let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.handle);
-		socket.set_keep_alive(Some(Duration::from_millis(DEFAULT_KEEP_ALIVE_INTERVAL)));
+		let socket = nic.get_mut_socket::<tcp::Socket<'_>>(connection);
+		socket.set_keep_alive(Some(Duration::from_millis(
+			self.keep_alive_interval.load(Ordering::Acquire),
+		)));
 
-		Ok(())
+		Ok(connection)
 	}
 
-	fn accept(&self, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32 {
-		block_on(self.async_accept(addr, addrlen), None)
-			.map(|_| 0)
-			.unwrap_or_else(|x| x)
+	/// Accepts a connection and returns a new socket of it, while this socket
+	/// continues to listen.
+	fn accept(&self, addr: *mut sockaddr, addrlen: *mut socklen_t) -> Result<Self, i32> {
+		let connection = if self.nonblocking.load(Ordering::Acquire) {
+			block_on(self.async_accept(addr, addrlen), Some(Duration::ZERO)).map_err(|x| {
+				if x == -ETIME {
+					-EAGAIN
+				} else {
+					x
+				}
+			})?
+		} else {
+			block_on(self.async_accept(addr, addrlen), None)?
+		};
+
+		Ok(self.with_options(connection))
 	}
 
 	fn read(&self, buf: *mut u8, len: usize) -> isize {
@@ -305,16 +323,31 @@ impl<T> Socket<T> {
 		}
 	}
 
-	fn listen(&self, _backlog: i32) -> i32 {
+	fn listen(&self, backlog: i32) -> i32 {
+		let backlog = usize::try_from(backlog).unwrap_or_default();
+
+		self.with_nic(|nic| {
+			nic.listen(self.handle, self.port.load(Ordering::Acquire), backlog)
+				.map(|_| 0)
+				.unwrap_or_else(|x| x)
+		})
+	}
+
+	/// Replaces the buffers of the socket, which is impossible after it has
+	/// connected or started to listen.
+	fn resize_buffers(&self, rx_size: Option<usize>, tx_size: Option<usize>) -> i32 {
 		self.with(|socket| {
-			if !socket.is_open() {
-				socket
-					.listen(self.port.load(Ordering::Acquire))
-					.map(|_| 0)
-					.unwrap_or_else(|_| -crate::errno::EIO)
-			} else {
-				-crate::errno::EIO
+			if socket.is_open() {
+				return -EINVAL;
 			}
+
+			*socket = copy_tcp_socket(
+				socket,
+				rx_size.unwrap_or(socket.recv_capacity()),
+				tx_size.unwrap_or(socket.send_capacity()),
+			);
+
+			0
 		})
 	}
 
@@ -325,25 +358,89 @@ impl<T> Socket<T> {
 		optval: *const c_void,
 		optlen: socklen_t,
 	) -> i32 {
-		if level == IPPROTO_TCP
-			&& optname == TCP_NODELAY
-			&& optlen == size_of::<i32>().try_into().unwrap()
-		{
-			let value = unsafe { *(optval as *const i32) };
-			self.with(|socket| {
-				socket.set_nagle_enabled(value != 0);
-				if value == 0 {
-					socket.set_ack_delay(None);
+		if level == SOL_SOCKET && optname == SO_BINDTODEVICE {
+			let name = unsafe { core::slice::from_raw_parts(optval as *const u8, optlen as usize) };
+			// the name of the interface may be terminated by a null byte
+			let name = name.split(|c| *c == 0).next().unwrap_or_default();
+
+			return match core::str::from_utf8(name) {
+				Ok(name) => self.with_nic(|nic| {
+					nic.bind_to_device(self.handle, name)
+						.map(|_| 0)
+						.unwrap_or_else(|x| x)
+				}),
+				Err(_) => -EINVAL,
+			};
+		}
+
+		if level == SOL_SOCKET && optname == SO_LINGER {
+			if optlen != size_of::<linger>().try_into().unwrap() {
+				return -EINVAL;
+			}
+
+			let value = unsafe { *(optval as *const linger) };
+			*self.linger.lock() = (value.l_onoff != 0)
+				.then(|| Duration::from_secs(value.l_linger.max(0).try_into().unwrap()));
+
+			return 0;
+		}
+
+		if optlen != size_of::<i32>().try_into().unwrap() {
+			return -EINVAL;
+		}
+
+		let value = unsafe { *(optval as *const i32) };
+		match (level, optname) {
+			(IPPROTO_TCP, TCP_NODELAY) => {
+				self.with(|socket| {
+					socket.set_nagle_enabled(value != 0);
+					if value == 0 {
+						socket.set_ack_delay(None);
+					} else {
+						socket.set_ack_delay(Some(Duration::from_millis(10)));
+					}
+				});
+				0
+			}
+			(IPPROTO_TCP, TCP_KEEPIDLE | TCP_KEEPINTVL) if value <= 0 => -EINVAL,
+			// smoltcp sends the keep-alive packets with a single interval, which
+			// is also the idle time before the first packet
+			(IPPROTO_TCP, TCP_KEEPIDLE | TCP_KEEPINTVL) => {
+				let interval = Duration::from_secs(value.try_into().unwrap());
+				self.keep_alive_interval
+					.store(interval.total_millis(), Ordering::Release);
+				self.with(|socket| {
+					if socket.keep_alive().is_some() {
+						socket.set_keep_alive(Some(interval));
+					}
+				});
+				0
+			}
+			(SOL_SOCKET, SO_KEEPALIVE) => {
+				let interval =
+					Duration::from_millis(self.keep_alive_interval.load(Ordering::Acquire));
+				self.with(|socket| socket.set_keep_alive((value != 0).then_some(interval)));
+				0
+			}
+			(SOL_SOCKET, SO_RCVBUF | SO_SNDBUF) if value <= 0 => -EINVAL,
+			(SOL_SOCKET, SO_RCVBUF | SO_SNDBUF) => {
+				let size = usize::try_from(value).unwrap().min(MAX_SOCKET_BUFFER_SIZE);
+				if optname == SO_RCVBUF {
+					self.resize_buffers(Some(size), None)
 				} else {
-					socket.set_ack_delay(Some(Duration::from_millis(10)));
+					self.resize_buffers(None, Some(size))
 				}
-			});
-			0
-		} else if level == SOL_SOCKET && optname == SO_REUSEADDR {
+			}
 			// smoltcp is always able to reuse the addr
-			0
-		} else {
-			-EINVAL
+			(SOL_SOCKET, SO_REUSEADDR) => 0,
+			(IPPROTO_IP, IP_TTL) => match u8::try_from(value) {
+				Ok(hop_limit) if hop_limit > 0 => {
+					self.with(|socket| socket.set_hop_limit(Some(hop_limit)));
+					0
+				}
+				_ => -EINVAL,
+			},
+			_ => -EINVAL,
 		}
 	}
 
@@ -354,26 +451,62 @@ impl<T> Socket<T> {
 		optval: *mut c_void,
 		optlen: *mut socklen_t,
 	) -> i32 {
-		if level == IPPROTO_TCP && optname == TCP_NODELAY {
-			let optlen = unsafe { &mut *optlen };
-			if *optlen >= size_of::<i32>().try_into().unwrap() {
-				let optval = unsafe { &mut *(optval as *mut i32) };
-				self.with(|socket| {
-					if socket.nagle_enabled() {
-						*optval = 0;
-					} else {
-						*optval = 1;
-					}
-				});
-				*optlen = size_of::<i32>().try_into().unwrap();
+		if optval.is_null() || optlen.is_null() {
+			return -EINVAL;
+		}
 
-				0
-			} else {
-				-EINVAL
+		let optlen = unsafe { &mut *optlen };
+		if level == SOL_SOCKET && optname == SO_LINGER {
+			if *optlen < size_of::<linger>().try_into().unwrap() {
+				return -EINVAL;
 			}
-		} else {
-			-EINVAL
+
+			let timeout = *self.linger.lock();
+			unsafe {
+				*(optval as *mut linger) = linger {
+					l_onoff: timeout.is_some().into(),
+					l_linger: timeout.map_or(0, |timeout| timeout.secs().try_into().unwrap()),
+				};
+			}
+			*optlen = size_of::<linger>().try_into().unwrap();
+
+			return 0;
 		}
+
+		if *optlen < size_of::<i32>().try_into().unwrap() {
+			return -EINVAL;
+		}
+
+		let value = match (level, optname) {
+			(IPPROTO_TCP, TCP_NODELAY) => self.with(|socket| (!socket.nagle_enabled()).into()),
+			(IPPROTO_TCP, TCP_KEEPIDLE | TCP_KEEPINTVL) => {
+				(self.keep_alive_interval.load(Ordering::Acquire) / 1000)
+					.try_into()
+					.unwrap()
+			}
+			(SOL_SOCKET, SO_KEEPALIVE) => self.with(|socket| socket.keep_alive().is_some().into()),
+			(SOL_SOCKET, SO_RCVBUF) => {
+				self.with(|socket| socket.recv_capacity().try_into().unwrap())
+			}
+			(SOL_SOCKET, SO_SNDBUF) => {
+				self.with(|socket| socket.send_capacity().try_into().unwrap())
+			}
+			(SOL_SOCKET, SO_REUSEADDR) => 1,
+			(SOL_SOCKET, SO_ERROR) => self
+				.with_nic(|nic| nic.take_pending_error(self.handle))
+				.map_or(0, |errno| -errno),
+			(IPPROTO_IP, IP_TTL) => {
+				self.with(|socket| socket.hop_limit().unwrap_or(DEFAULT_HOP_LIMIT).into())
+			}
+			_ => return -EINVAL,
+		};
+
+		unsafe {
+			*(optval as *mut i32) = value;
+		}
+		*optlen = size_of::<i32>().try_into().unwrap();
+
+		0
 	}
 
 	fn shutdown(&self, how: i32) -> i32 {
@@ -413,18 +546,22 @@ impl<T> Clone for Socket<T> {
 			panic!("Unable to create handle");
 		};
 
-		Self {
-			handle,
-			port: AtomicU16::new(self.port.load(Ordering::Acquire)),
-			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
-			phantom: PhantomData,
-		}
+		self.with_options(handle)
 	}
 }
 
 impl<T> Drop for Socket<T> {
 	fn drop(&mut self) {
-		let _ = block_on(self.async_close(), None);
+		let linger = *self.linger.lock();
+
+		// The connection is reset, if the linger time is zero or runs out
+		// before the connection has shut down.
+		if linger == Some(Duration::ZERO) || block_on(self.async_close(), linger) == Err(-ETIME) {
+			self.with_nic(|nic| {
+				nic.stop_listening(self.handle);
+				nic.get_mut_socket::<tcp::Socket<'_>>(self.handle).abort();
+			});
+		}
 	}
 }
 
@@ -433,8 +570,16 @@ impl ObjectInterface for Socket<IPv4> {
 		if namelen == size_of::<sockaddr_in>().try_into().unwrap() {
 			let addr = unsafe { *(name as *const sockaddr_in) };
 			let port = u16::from_be(addr.sin_port);
+			let address = IpAddress::v4(
+				addr.sin_addr.s_addr[0],
+				addr.sin_addr.s_addr[1],
+				addr.sin_addr.s_addr[2],
+				addr.sin_addr.s_addr[3],
+			);
 			self.port.store(port, Ordering::Release);
-			0
+			self.with_nic(|nic| nic.bind_addr(self.handle, address))
+				.map(|_| 0)
+				.unwrap_or_else(|x| x)
 		} else {
 			-EINVAL
 		}
@@ -527,8 +672,12 @@ impl ObjectInterface for Socket<IPv4> {
 		}
 	}
 
-	fn accept(&self, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32 {
-		self.accept(addr, addrlen)
+	fn accept(
+		&self,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> Result<Arc<dyn ObjectInterface>, i32> {
+		Ok(Arc::new(self.accept(addr, addrlen)?))
 	}
 
 	fn read(&self, buf: *mut u8, len: usize) -> isize {
@@ -576,8 +725,11 @@ impl ObjectInterface for Socket<IPv6> {
 	fn bind(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
 		if namelen == size_of::<sockaddr_in6>().try_into().unwrap() {
 			let addr = unsafe { *(name as *const sockaddr_in6) };
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&addr.sin6_addr.s6_addr));
 			self.port.store(addr.sin6_port, Ordering::Release);
-			0
+			self.with_nic(|nic| nic.bind_addr(self.handle, address))
+				.map(|_| 0)
+				.unwrap_or_else(|x| x)
 		} else {
 			-EINVAL
 		}
@@ -676,8 +828,12 @@ impl ObjectInterface for Socket<IPv6> {
 		}
 	}
 
-	fn accept(&self, addr: *mut sockaddr, addrlen: *mut socklen_t) -> i32 {
-		self.accept(addr, addrlen)
+	fn accept(
+		&self,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> Result<Arc<dyn ObjectInterface>, i32> {
+		Ok(Arc::new(self.accept(addr, addrlen)?))
 	}
 
 	fn read(&self, buf: *mut u8, len: usize) -> isize {
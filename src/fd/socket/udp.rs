@@ -1,13 +1,771 @@
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use futures_lite::future;
+use hermit_sync::InterruptTicketMutex;
+use smoltcp::socket::udp;
+use smoltcp::socket::udp::SendError;
+use smoltcp::time::Duration;
+use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address, Ipv6Address};
+
+use crate::errno::*;
+use crate::fd::socket::get_ephemeral_port;
+use crate::fd::socket::tcp::{IPv4, IPv6};
 use crate::fd::ObjectInterface;
-use crate::net::Handle;
+use crate::net::executor::block_on;
+use crate::net::{now, udp_socket, Handle, NetworkInterface, NetworkState, NIC};
+use crate::syscalls::net::*;
+use crate::MAX_SOCKET_BUFFER_SIZE;
 
-#[derive(Debug, Clone)]
-pub struct Socket(Handle);
+#[derive(Debug)]
+pub struct Socket<T> {
+	handle: Handle,
+	nonblocking: AtomicBool,
+	/// Permits sending datagrams to the broadcast address
+	broadcast: AtomicBool,
+	/// Remote endpoint of a connected socket
+	endpoint: InterruptTicketMutex<Option<IpEndpoint>>,
+	/// Multicast groups, which the socket has joined
+	groups: InterruptTicketMutex<Vec<IpAddress>>,
+	phantom: PhantomData<T>,
+}
 
-impl Socket {
+impl<T> Socket<T> {
 	pub fn new(handle: Handle) -> Self {
-		Self(handle)
+		Self {
+			handle,
+			nonblocking: AtomicBool::new(false),
+			broadcast: AtomicBool::new(false),
+			endpoint: InterruptTicketMutex::new(None),
+			groups: InterruptTicketMutex::new(Vec::new()),
+			phantom: PhantomData,
+		}
+	}
+
+	fn with_nic<R>(&self, f: impl FnOnce(&mut NetworkInterface<'_>) -> R) -> R {
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().unwrap();
+		let result = f(nic);
+		nic.poll_common(now());
+
+		result
+	}
+
+	fn with<R>(&self, f: impl FnOnce(&mut udp::Socket<'_>) -> R) -> R {
+		self.with_nic(|nic| f(nic.get_mut_socket::<udp::Socket<'_>>(self.handle)))
+	}
+
+	/// Moves the socket to the interface of the destination `addr`, if the socket
+	/// has no local port yet. Afterwards, it stays on its interface, because moving
+	/// it would drop the buffered datagrams. Hence, it receives only the datagrams
+	/// of this interface.
+	fn route(&self, nic: &mut NetworkInterface<'_>, addr: IpAddress) {
+		if !nic.get_socket::<udp::Socket<'_>>(self.handle).is_open() {
+			nic.route_socket(self.handle, addr);
+		}
+	}
+
+	/// Binds the socket to an ephemeral port, if it is not bound yet.
+	fn bind_ephemeral(socket: &mut udp::Socket<'_>) -> Result<(), i32> {
+		if socket.is_open() {
+			Ok(())
+		} else {
+			socket
+				.bind(get_ephemeral_port())
+				.map_err(|_| -crate::errno::EINVAL)
+		}
+	}
+
+	fn bind_endpoint(&self, address: IpAddress, port: u16) -> i32 {
+		let port = if port == 0 {
+			get_ephemeral_port()
+		} else {
+			port
+		};
+
+		self.with_nic(|nic| {
+			nic.bind_addr(self.handle, address)?;
+
+			let endpoint = IpListenEndpoint {
+				addr: (!address.is_unspecified()).then_some(address),
+				port,
+			};
+			nic.get_mut_socket::<udp::Socket<'_>>(self.handle)
+				.bind(endpoint)
+				.map_err(|_| -crate::errno::EINVAL)
+		})
+		.map(|_| 0)
+		.unwrap_or_else(|x| x)
+	}
+
+	fn connect_endpoint(&self, endpoint: IpEndpoint) -> i32 {
+		let result = self.with_nic(|nic| {
+			self.route(nic, endpoint.addr);
+			Self::bind_ephemeral(nic.get_mut_socket::<udp::Socket<'_>>(self.handle))
+		});
+
+		match result {
+			Ok(_) => {
+				*self.endpoint.lock() = Some(endpoint);
+				0
+			}
+			Err(x) => x,
+		}
+	}
+
+	async fn async_recvfrom(&self, buffer: &mut [u8]) -> Result<(isize, IpEndpoint), i32> {
+		let connected = *self.endpoint.lock();
+
+		future::poll_fn(|cx| {
+			self.with_nic(|nic| {
+				// a connected socket reports the errors of ICMP
+				if let Some(connected) = connected {
+					if let Some(err) = nic.take_socket_error(self.handle, connected) {
+						return Poll::Ready(Err(err));
+					}
+					nic.register_error_waker(self.handle, cx.waker());
+				}
+
+				let socket = nic.get_mut_socket::<udp::Socket<'_>>(self.handle);
+				while socket.can_recv() {
+					let (n, endpoint) =
+						socket.recv_slice(buffer).map_err(|_| -crate::errno::EIO)?;
+
+					// a connected socket receives only the datagrams of its peer
+					if connected.map_or(true, |connected| connected == endpoint) {
+						return Poll::Ready(Ok((n.try_into().unwrap(), endpoint)));
+					}
+				}
+
+				socket.register_recv_waker(cx.waker());
+				Poll::Pending
+			})
+		})
+		.await
+	}
+
+	async fn async_sendto(&self, buffer: &[u8], endpoint: IpEndpoint) -> Result<isize, i32> {
+		if matches!(endpoint.addr, IpAddress::Ipv4(addr) if addr.is_broadcast())
+			&& !self.broadcast.load(Ordering::Acquire)
+		{
+			return Err(-crate::errno::EACCES);
+		}
+
+		let connected = *self.endpoint.lock();
+		self.with_nic(|nic| {
+			// a connected socket reports the errors of ICMP
+			if connected == Some(endpoint) {
+				if let Some(err) = nic.take_socket_error(self.handle, endpoint) {
+					return Err(err);
+				}
+			}
+
+			self.route(nic, endpoint.addr);
+			let socket = nic.get_mut_socket::<udp::Socket<'_>>(self.handle);
+			if buffer.len() > socket.payload_send_capacity() {
+				Err(-crate::errno::EMSGSIZE)
+			} else {
+				Self::bind_ephemeral(socket)
+			}
+		})?;
+
+		future::poll_fn(|cx| {
+			self.with(|socket| match socket.send_slice(buffer, endpoint) {
+				Ok(_) => Poll::Ready(Ok(buffer.len().try_into().unwrap())),
+				Err(SendError::BufferFull) => {
+					socket.register_send_waker(cx.waker());
+					Poll::Pending
+				}
+				Err(SendError::Unaddressable) => Poll::Ready(Err(-crate::errno::EINVAL)),
+			})
+		})
+		.await
+	}
+
+	fn recvfrom_endpoint(&self, buf: *mut u8, len: usize) -> Result<(isize, IpEndpoint), i32> {
+		let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+
+		if self.nonblocking.load(Ordering::Acquire) {
+			block_on(self.async_recvfrom(slice), Some(Duration::ZERO)).map_err(|x| {
+				if x == -ETIME {
+					-EAGAIN
+				} else {
+					x
+				}
+			})
+		} else {
+			block_on(self.async_recvfrom(slice), None)
+		}
+	}
+
+	fn sendto_endpoint(&self, buf: *const u8, len: usize, endpoint: IpEndpoint) -> isize {
+		let slice = unsafe { core::slice::from_raw_parts(buf, len) };
+
+		if self.nonblocking.load(Ordering::Acquire) {
+			block_on(self.async_sendto(slice, endpoint), Some(Duration::ZERO)).unwrap_or_else(|x| {
+				if x == -ETIME {
+					(-EAGAIN).try_into().unwrap()
+				} else {
+					x.try_into().unwrap()
+				}
+			})
+		} else {
+			block_on(self.async_sendto(slice, endpoint), None)
+				.unwrap_or_else(|x| x.try_into().unwrap())
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		self.recvfrom_endpoint(buf, len)
+			.map(|(n, _)| n)
+			.unwrap_or_else(|x| x.try_into().unwrap())
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		let endpoint = *self.endpoint.lock();
+		if let Some(endpoint) = endpoint {
+			self.sendto_endpoint(buf, len, endpoint)
+		} else {
+			(-EDESTADDRREQ).try_into().unwrap()
+		}
+	}
+
+	/// Replaces the buffers of the socket. Datagrams, which are still buffered, are dropped.
+	fn resize_buffers(&self, rx_size: Option<usize>, tx_size: Option<usize>) {
+		self.with(|socket| {
+			let mut new_socket = udp_socket(
+				rx_size.unwrap_or(socket.payload_recv_capacity()),
+				tx_size.unwrap_or(socket.payload_send_capacity()),
+			);
+			new_socket.set_hop_limit(socket.hop_limit());
+			if socket.is_open() {
+				new_socket.bind(socket.endpoint()).unwrap();
+			}
+
+			*socket = new_socket;
+		});
+	}
+
+	/// Joins or leaves the multicast group `addr`. The socket leaves its groups,
+	/// when it is closed.
+	fn update_membership(&self, addr: IpAddress, join: bool) -> i32 {
+		if !addr.is_multicast() {
+			return -EINVAL;
+		}
+
+		let mut groups = self.groups.lock();
+		let pos = groups.iter().position(|group| *group == addr);
+		let result = match (join, pos) {
+			(true, None) => self
+				.with_nic(|nic| nic.join_multicast_group(addr))
+				.map(|_| groups.push(addr)),
+			(false, Some(pos)) => self
+				.with_nic(|nic| nic.leave_multicast_group(addr))
+				.map(|_| {
+					groups.remove(pos);
+				}),
+			(true, Some(_)) => Err(-EADDRINUSE),
+			(false, None) => Err(-EADDRNOTAVAIL),
+		};
+
+		result.map(|_| 0).unwrap_or_else(|x| x)
+	}
+
+	fn setsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *const c_void,
+		optlen: socklen_t,
+	) -> i32 {
+		if level == IPPROTO_IP && (optname == IP_ADD_MEMBERSHIP || optname == IP_DROP_MEMBERSHIP) {
+			if optlen != size_of::<ip_mreq>().try_into().unwrap() {
+				return -EINVAL;
+			}
+
+			let mreq = unsafe { *(optval as *const ip_mreq) };
+			let addr = IpAddress::Ipv4(Ipv4Address::from_bytes(&mreq.imr_multiaddr.s_addr));
+
+			return self.update_membership(addr, optname == IP_ADD_MEMBERSHIP);
+		}
+
+		if level == IPPROTO_IPV6
+			&& (optname == IPV6_ADD_MEMBERSHIP || optname == IPV6_DROP_MEMBERSHIP)
+		{
+			if optlen != size_of::<ipv6_mreq>().try_into().unwrap() {
+				return -EINVAL;
+			}
+
+			let mreq = unsafe { *(optval as *const ipv6_mreq) };
+			let addr = IpAddress::Ipv6(Ipv6Address::from_bytes(&mreq.ipv6mr_multiaddr.s6_addr));
+
+			return self.update_membership(addr, optname == IPV6_ADD_MEMBERSHIP);
+		}
+
+		if level == SOL_SOCKET && optname == SO_BINDTODEVICE {
+			let name = unsafe { core::slice::from_raw_parts(optval as *const u8, optlen as usize) };
+			// the name of the interface may be terminated by a null byte
+			let name = name.split(|c| *c == 0).next().unwrap_or_default();
+
+			return match core::str::from_utf8(name) {
+				Ok(name) => self.with_nic(|nic| {
+					nic.bind_to_device(self.handle, name)
+						.map(|_| 0)
+						.unwrap_or_else(|x| x)
+				}),
+				Err(_) => -EINVAL,
+			};
+		}
+
+		if level != SOL_SOCKET || optlen != size_of::<i32>().try_into().unwrap() {
+			return -EINVAL;
+		}
+
+		let value = unsafe { *(optval as *const i32) };
+		match optname {
+			SO_BROADCAST => {
+				self.broadcast.store(value != 0, Ordering::Release);
+				0
+			}
+			SO_RCVBUF | SO_SNDBUF if value <= 0 => -EINVAL,
+			SO_RCVBUF | SO_SNDBUF => {
+				let size = usize::try_from(value).unwrap().min(MAX_SOCKET_BUFFER_SIZE);
+				if optname == SO_RCVBUF {
+					self.resize_buffers(Some(size), None);
+				} else {
+					self.resize_buffers(None, Some(size));
+				}
+				0
+			}
+			// smoltcp is always able to reuse the addr
+			SO_REUSEADDR => 0,
+			_ => -EINVAL,
+		}
+	}
+
+	fn getsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *mut c_void,
+		optlen: *mut socklen_t,
+	) -> i32 {
+		if level != SOL_SOCKET || optval.is_null() || optlen.is_null() {
+			return -EINVAL;
+		}
+
+		let optlen = unsafe { &mut *optlen };
+		if *optlen < size_of::<i32>().try_into().unwrap() {
+			return -EINVAL;
+		}
+
+		let value = match optname {
+			SO_BROADCAST => self.broadcast.load(Ordering::Acquire).into(),
+			SO_RCVBUF => self.with(|socket| socket.payload_recv_capacity().try_into().unwrap()),
+			SO_SNDBUF => self.with(|socket| socket.payload_send_capacity().try_into().unwrap()),
+			SO_ERROR => self
+				.with_nic(|nic| nic.take_pending_error(self.handle))
+				.map_or(0, |errno| -errno),
+			_ => return -EINVAL,
+		};
+
+		unsafe {
+			*(optval as *mut i32) = value;
+		}
+		*optlen = size_of::<i32>().try_into().unwrap();
+
+		0
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		if cmd == FIONBIO {
+			let value = unsafe { *(argp as *const i32) };
+			if value != 0 {
+				info!("set device to nonblocking mode");
+				self.nonblocking.store(true, Ordering::Release);
+			} else {
+				info!("set device to blocking mode");
+				self.nonblocking.store(false, Ordering::Release);
+			}
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+}
+
+impl<T> Clone for Socket<T> {
+	fn clone(&self) -> Self {
+		let mut guard = NIC.lock();
+
+		let handle = if let NetworkState::Initialized(nic) = guard.deref_mut() {
+			nic.create_udp_handle().unwrap()
+		} else {
+			panic!("Unable to create handle");
+		};
+
+		Self {
+			handle,
+			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+			broadcast: AtomicBool::new(self.broadcast.load(Ordering::Acquire)),
+			endpoint: InterruptTicketMutex::new(*self.endpoint.lock()),
+			groups: InterruptTicketMutex::new(Vec::new()),
+			phantom: PhantomData,
+		}
+	}
+}
+
+impl<T> Drop for Socket<T> {
+	fn drop(&mut self) {
+		let groups = core::mem::take(&mut *self.groups.lock());
+		self.with_nic(|nic| {
+			for addr in groups {
+				let _ = nic.leave_multicast_group(addr);
+			}
+			nic.get_mut_socket::<udp::Socket<'_>>(self.handle).close();
+		});
+	}
+}
+
+impl ObjectInterface for Socket<IPv4> {
+	fn bind(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in>().try_into().unwrap() {
+			let addr = unsafe { *(name as *const sockaddr_in) };
+			let port = u16::from_be(addr.sin_port);
+			let address = IpAddress::v4(
+				addr.sin_addr.s_addr[0],
+				addr.sin_addr.s_addr[1],
+				addr.sin_addr.s_addr[2],
+				addr.sin_addr.s_addr[3],
+			);
+
+			self.bind_endpoint(address, port)
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn connect(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in>().try_into().unwrap() {
+			let saddr = unsafe { *(name as *const sockaddr_in) };
+			let port = u16::from_be(saddr.sin_port);
+			let address = IpAddress::v4(
+				saddr.sin_addr.s_addr[0],
+				saddr.sin_addr.s_addr[1],
+				saddr.sin_addr.s_addr[2],
+				saddr.sin_addr.s_addr[3],
+			);
+
+			self.connect_endpoint(IpEndpoint::new(address, port))
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getpeername(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in) };
+
+			let Some(remote) = *self.endpoint.lock() else {
+				return -ENOTCONN;
+			};
+			addr.sin_family = AF_INET.try_into().unwrap();
+			addr.sin_port = remote.port.to_be();
+			if let IpAddress::Ipv4(ip) = remote.addr {
+				addr.sin_addr.s_addr.copy_from_slice(ip.as_bytes());
+			}
+
+			*namelen = size_of::<sockaddr_in>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getsockname(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in) };
+			addr.sin_family = AF_INET.try_into().unwrap();
+
+			self.with(|socket| {
+				let local = socket.endpoint();
+				addr.sin_port = local.port.to_be();
+
+				if let Some(IpAddress::Ipv4(ip)) = local.addr {
+					addr.sin_addr.s_addr.copy_from_slice(ip.as_bytes());
+				}
+			});
+
+			*namelen = size_of::<sockaddr_in>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn sendto(
+		&self,
+		buf: *const u8,
+		len: usize,
+		addr: *const sockaddr,
+		addrlen: socklen_t,
+	) -> isize {
+		if addr.is_null() {
+			return self.write(buf, len);
+		}
+
+		if addrlen == size_of::<sockaddr_in>().try_into().unwrap() {
+			let saddr = unsafe { *(addr as *const sockaddr_in) };
+			let port = u16::from_be(saddr.sin_port);
+			let address = IpAddress::v4(
+				saddr.sin_addr.s_addr[0],
+				saddr.sin_addr.s_addr[1],
+				saddr.sin_addr.s_addr[2],
+				saddr.sin_addr.s_addr[3],
+			);
+
+			self.sendto_endpoint(buf, len, IpEndpoint::new(address, port))
+		} else {
+			(-EINVAL).try_into().unwrap()
+		}
+	}
+
+	fn recvfrom(
+		&self,
+		buf: *mut u8,
+		len: usize,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> isize {
+		match self.recvfrom_endpoint(buf, len) {
+			Ok((n, remote)) => {
+				if !addr.is_null() && !addrlen.is_null() {
+					let addrlen = unsafe { &mut *addrlen };
+					if *addrlen >= size_of::<sockaddr_in>().try_into().unwrap() {
+						let addr = unsafe { &mut *(addr as *mut sockaddr_in) };
+						addr.sin_family = AF_INET.try_into().unwrap();
+						addr.sin_port = remote.port.to_be();
+						if let IpAddress::Ipv4(ip) = remote.addr {
+							addr.sin_addr.s_addr.copy_from_slice(ip.as_bytes());
+						}
+					}
+					*addrlen = size_of::<sockaddr_in>().try_into().unwrap();
+				}
+
+				n
+			}
+			Err(x) => x.try_into().unwrap(),
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		self.read(buf, len)
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		self.write(buf, len)
+	}
+
+	fn setsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *const c_void,
+		optlen: socklen_t,
+	) -> i32 {
+		self.setsockopt(level, optname, optval, optlen)
+	}
+
+	fn getsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *mut c_void,
+		optlen: *mut socklen_t,
+	) -> i32 {
+		self.getsockopt(level, optname, optval, optlen)
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		self.ioctl(cmd, argp)
 	}
 }
 
-impl ObjectInterface for Socket {}
+impl ObjectInterface for Socket<IPv6> {
+	fn bind(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in6>().try_into().unwrap() {
+			let addr = unsafe { *(name as *const sockaddr_in6) };
+			let port = u16::from_be(addr.sin6_port);
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&addr.sin6_addr.s6_addr));
+
+			self.bind_endpoint(address, port)
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn connect(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		if namelen == size_of::<sockaddr_in6>().try_into().unwrap() {
+			let saddr = unsafe { *(name as *const sockaddr_in6) };
+			let port = u16::from_be(saddr.sin6_port);
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&saddr.sin6_addr.s6_addr));
+
+			self.connect_endpoint(IpEndpoint::new(address, port))
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getpeername(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in6>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in6) };
+
+			let Some(remote) = *self.endpoint.lock() else {
+				return -ENOTCONN;
+			};
+			addr.sin6_family = AF_INET6.try_into().unwrap();
+			addr.sin6_port = remote.port.to_be();
+			if let IpAddress::Ipv6(ip) = remote.addr {
+				addr.sin6_addr.s6_addr.copy_from_slice(ip.as_bytes());
+			}
+
+			*namelen = size_of::<sockaddr_in6>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn getsockname(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		if namelen.is_null() {
+			return -ENOBUFS;
+		}
+
+		let namelen = unsafe { &mut *namelen };
+		if *namelen >= size_of::<sockaddr_in6>().try_into().unwrap() {
+			let addr = unsafe { &mut *(name as *mut sockaddr_in6) };
+			addr.sin6_family = AF_INET6.try_into().unwrap();
+
+			self.with(|socket| {
+				let local = socket.endpoint();
+				addr.sin6_port = local.port.to_be();
+
+				if let Some(IpAddress::Ipv6(ip)) = local.addr {
+					addr.sin6_addr.s6_addr.copy_from_slice(ip.as_bytes());
+				}
+			});
+
+			*namelen = size_of::<sockaddr_in6>().try_into().unwrap();
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+
+	fn sendto(
+		&self,
+		buf: *const u8,
+		len: usize,
+		addr: *const sockaddr,
+		addrlen: socklen_t,
+	) -> isize {
+		if addr.is_null() {
+			return self.write(buf, len);
+		}
+
+		if addrlen == size_of::<sockaddr_in6>().try_into().unwrap() {
+			let saddr = unsafe { *(addr as *const sockaddr_in6) };
+			let port = u16::from_be(saddr.sin6_port);
+			let address = IpAddress::Ipv6(Ipv6Address::from_bytes(&saddr.sin6_addr.s6_addr));
+
+			self.sendto_endpoint(buf, len, IpEndpoint::new(address, port))
+		} else {
+			(-EINVAL).try_into().unwrap()
+		}
+	}
+
+	fn recvfrom(
+		&self,
+		buf: *mut u8,
+		len: usize,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> isize {
+		match self.recvfrom_endpoint(buf, len) {
+			Ok((n, remote)) => {
+				if !addr.is_null() && !addrlen.is_null() {
+					let addrlen = unsafe { &mut *addrlen };
+					if *addrlen >= size_of::<sockaddr_in6>().try_into().unwrap() {
+						let addr = unsafe { &mut *(addr as *mut sockaddr_in6) };
+						addr.sin6_family = AF_INET6.try_into().unwrap();
+						addr.sin6_port = remote.port.to_be();
+						if let IpAddress::Ipv6(ip) = remote.addr {
+							addr.sin6_addr.s6_addr.copy_from_slice(ip.as_bytes());
+						}
+					}
+					*addrlen = size_of::<sockaddr_in6>().try_into().unwrap();
+				}
+
+				n
+			}
+			Err(x) => x.try_into().unwrap(),
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		self.read(buf, len)
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		self.write(buf, len)
+	}
+
+	fn setsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *const c_void,
+		optlen: socklen_t,
+	) -> i32 {
+		self.setsockopt(level, optname, optval, optlen)
+	}
+
+	fn getsockopt(
+		&self,
+		level: i32,
+		optname: i32,
+		optval: *mut c_void,
+		optlen: *mut socklen_t,
+	) -> i32 {
+		self.getsockopt(level, optname, optval, optlen)
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		self.ioctl(cmd, argp)
+	}
+}
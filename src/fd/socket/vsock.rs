@@ -0,0 +1,400 @@
+//! Stream sockets of the address family `AF_VSOCK`, which are provided by a
+//! virtio socket device and address the host and other guests by context id
+//! and port.
+
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::ffi::c_void;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+
+use hermit_sync::InterruptTicketMutex;
+
+use crate::core_scheduler;
+use crate::drivers::pci::get_vsock_driver;
+use crate::drivers::vsock::virtio_vsock::{
+	VirtioVsockDriver, VIRTIO_VSOCK_SHUTDOWN_RCV, VIRTIO_VSOCK_SHUTDOWN_SEND,
+};
+use crate::drivers::vsock::{ConnectionId, VsockError};
+use crate::errno::*;
+use crate::fd::ObjectInterface;
+use crate::scheduler::task::TaskHandle;
+use crate::syscalls::net::*;
+
+fn errno(err: VsockError) -> i32 {
+	match err {
+		VsockError::WouldBlock => EAGAIN,
+		VsockError::AddrInUse => EADDRINUSE,
+		VsockError::AddrNotAvailable => EADDRNOTAVAIL,
+		VsockError::ConnectionRefused => ECONNREFUSED,
+		VsockError::ConnectionReset => ECONNRESET,
+		VsockError::NotConnected => ENOTCONN,
+		VsockError::BrokenPipe => EPIPE,
+		VsockError::Io => EIO,
+	}
+}
+
+/// Wakes up a task, which waits for packets to a port.
+struct TaskWaker(TaskHandle);
+
+impl Wake for TaskWaker {
+	fn wake(self: Arc<Self>) {
+		core_scheduler().custom_wakeup(self.0);
+	}
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+enum State {
+	#[default]
+	Unbound,
+	Bound(u32),
+	Listening(u32),
+	Connected {
+		id: ConnectionId,
+		/// The port has been bound by this socket and not by a listening socket
+		owns_port: bool,
+	},
+}
+
+#[derive(Debug, Default)]
+pub struct Socket {
+	state: InterruptTicketMutex<State>,
+	nonblocking: AtomicBool,
+}
+
+impl Socket {
+	/// Binds an ephemeral port, if the socket is not bound yet.
+	fn bound_port(state: &mut State) -> Result<u32, i32> {
+		match *state {
+			State::Unbound => {
+				let driver = get_vsock_driver().ok_or(ENODEV)?;
+				let port = driver.lock().bind(None).map_err(errno)?;
+				*state = State::Bound(port);
+				Ok(port)
+			}
+			State::Bound(port) => Ok(port),
+			State::Listening(_) => Err(EINVAL),
+			State::Connected { .. } => Err(EISCONN),
+		}
+	}
+
+	/// Calls `f` until it does not return [VsockError::WouldBlock]. In between,
+	/// the task is blocked until a packet to the local `port` has been received.
+	/// In nonblocking mode, `f` is called only once.
+	fn poll<T>(
+		&self,
+		port: u32,
+		mut f: impl FnMut(&mut VirtioVsockDriver) -> Result<T, VsockError>,
+	) -> Result<T, i32> {
+		let driver = get_vsock_driver().ok_or(ENODEV)?;
+		let waker = Waker::from(Arc::new(TaskWaker(
+			core_scheduler().get_current_task_handle(),
+		)));
+
+		loop {
+			let mut guard = driver.lock();
+			match f(&mut guard) {
+				Err(VsockError::WouldBlock) if !self.nonblocking.load(Ordering::Acquire) => {
+					// The lock prevents a wakeup before the task is blocked.
+					guard.register_waker(port, &waker);
+					core_scheduler().block_current_task(None);
+					drop(guard);
+					core_scheduler().reschedule();
+				}
+				ret => return ret.map_err(errno),
+			}
+		}
+	}
+
+	fn connection(&self) -> Result<ConnectionId, i32> {
+		match *self.state.lock() {
+			State::Connected { id, .. } => Ok(id),
+			_ => Err(ENOTCONN),
+		}
+	}
+}
+
+/// Writes the address of a socket to `name`.
+fn write_addr(name: *mut sockaddr, namelen: *mut socklen_t, cid: u64, port: u32) -> i32 {
+	if name.is_null() || namelen.is_null() {
+		return -ENOBUFS;
+	}
+
+	let namelen = unsafe { &mut *namelen };
+	if *namelen < size_of::<sockaddr_vm>().try_into().unwrap() {
+		return -EINVAL;
+	}
+
+	let addr = unsafe { &mut *(name as *mut sockaddr_vm) };
+	*addr = sockaddr_vm {
+		svm_len: size_of::<sockaddr_vm>().try_into().unwrap(),
+		svm_family: AF_VSOCK.try_into().unwrap(),
+		svm_reserved1: 0,
+		svm_port: port,
+		svm_cid: cid as u32,
+		svm_zero: [0; 4],
+	};
+	*namelen = size_of::<sockaddr_vm>().try_into().unwrap();
+
+	0
+}
+
+/// Reads the address of a socket from `name`.
+fn read_addr(name: *const sockaddr, namelen: socklen_t) -> Result<sockaddr_vm, i32> {
+	if name.is_null() || namelen != size_of::<sockaddr_vm>().try_into().unwrap() {
+		return Err(EINVAL);
+	}
+
+	let addr = unsafe { *(name as *const sockaddr_vm) };
+	if i32::from(addr.svm_family) != AF_VSOCK {
+		return Err(EAFNOSUPPORT);
+	}
+
+	Ok(addr)
+}
+
+impl ObjectInterface for Socket {
+	fn bind(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		let addr = match read_addr(name, namelen) {
+			Ok(addr) => addr,
+			Err(err) => return -err,
+		};
+		let Some(driver) = get_vsock_driver() else {
+			return -ENODEV;
+		};
+
+		let mut driver = driver.lock();
+		if addr.svm_cid != VMADDR_CID_ANY && u64::from(addr.svm_cid) != driver.guest_cid() {
+			return -EADDRNOTAVAIL;
+		}
+
+		let mut state = self.state.lock();
+		if !matches!(*state, State::Unbound) {
+			return -EINVAL;
+		}
+
+		let port = (addr.svm_port != VMADDR_PORT_ANY).then_some(addr.svm_port);
+		match driver.bind(port) {
+			Ok(port) => {
+				*state = State::Bound(port);
+				0
+			}
+			Err(err) => -errno(err),
+		}
+	}
+
+	fn listen(&self, backlog: i32) -> i32 {
+		let mut state = self.state.lock();
+		let port = match *state {
+			State::Listening(port) => port,
+			_ => match Self::bound_port(&mut state) {
+				Ok(port) => port,
+				Err(err) => return -err,
+			},
+		};
+
+		let driver = get_vsock_driver().unwrap();
+		driver
+			.lock()
+			.listen(port, backlog.try_into().unwrap_or_default());
+		*state = State::Listening(port);
+
+		0
+	}
+
+	fn accept(
+		&self,
+		addr: *mut sockaddr,
+		addrlen: *mut socklen_t,
+	) -> Result<Arc<dyn ObjectInterface>, i32> {
+		let State::Listening(port) = *self.state.lock() else {
+			return Err(-EINVAL);
+		};
+		let id = self
+			.poll(port, |driver| {
+				driver.accept(port).ok_or(VsockError::WouldBlock)
+			})
+			.map_err(|err| -err)?;
+
+		if !addr.is_null() {
+			write_addr(addr, addrlen, id.peer_cid, id.peer_port);
+		}
+
+		// The listening socket keeps the port, the connection only uses it.
+		Ok(Arc::new(Self {
+			state: InterruptTicketMutex::new(State::Connected {
+				id,
+				owns_port: false,
+			}),
+			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+		}))
+	}
+
+	fn connect(&self, name: *const sockaddr, namelen: socklen_t) -> i32 {
+		let addr = match read_addr(name, namelen) {
+			Ok(addr) => addr,
+			Err(err) => return -err,
+		};
+
+		let id = {
+			let mut state = self.state.lock();
+			let port = match Self::bound_port(&mut state) {
+				Ok(port) => port,
+				Err(err) => return -err,
+			};
+
+			let driver = get_vsock_driver().unwrap();
+			let id = match driver
+				.lock()
+				.connect(port, addr.svm_cid.into(), addr.svm_port)
+			{
+				Ok(id) => id,
+				Err(err) => return -errno(err),
+			};
+			*state = State::Connected {
+				id,
+				owns_port: true,
+			};
+
+			id
+		};
+
+		let ret = self.poll(id.local_port, |driver| match driver.poll_connect(id) {
+			Ok(true) => Ok(()),
+			Ok(false) => Err(VsockError::WouldBlock),
+			Err(err) => Err(err),
+		});
+
+		match ret {
+			Ok(()) => 0,
+			Err(EAGAIN) => -EINPROGRESS,
+			Err(err) => {
+				// The connection has been refused, but the port remains bound.
+				*self.state.lock() = State::Bound(id.local_port);
+				-err
+			}
+		}
+	}
+
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		let id = match self.connection() {
+			Ok(id) => id,
+			Err(err) => return -err as isize,
+		};
+		if len == 0 {
+			return 0;
+		}
+
+		let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+
+		match self.poll(id.local_port, |driver| driver.recv(id, slice)) {
+			Ok(len) => len.try_into().unwrap(),
+			Err(err) => -err as isize,
+		}
+	}
+
+	fn write(&self, buf: *const u8, len: usize) -> isize {
+		let id = match self.connection() {
+			Ok(id) => id,
+			Err(err) => return -err as isize,
+		};
+
+		let slice = unsafe { core::slice::from_raw_parts(buf, len) };
+		let mut pos = 0;
+
+		while pos < slice.len() {
+			match self.poll(id.local_port, |driver| driver.send(id, &slice[pos..])) {
+				Ok(len) => pos += len,
+				// Report the data, which has already been sent.
+				Err(_) if pos > 0 => break,
+				Err(err) => return -err as isize,
+			}
+		}
+
+		pos.try_into().unwrap()
+	}
+
+	fn getsockname(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		let Some(driver) = get_vsock_driver() else {
+			return -ENODEV;
+		};
+
+		let port = match *self.state.lock() {
+			State::Unbound => VMADDR_PORT_ANY,
+			State::Bound(port) | State::Listening(port) => port,
+			State::Connected { id, .. } => id.local_port,
+		};
+		let cid = driver.lock().guest_cid();
+
+		write_addr(name, namelen, cid, port)
+	}
+
+	fn getpeername(&self, name: *mut sockaddr, namelen: *mut socklen_t) -> i32 {
+		match self.connection() {
+			Ok(id) => write_addr(name, namelen, id.peer_cid, id.peer_port),
+			Err(err) => -err,
+		}
+	}
+
+	fn shutdown(&self, how: i32) -> i32 {
+		let flags = match how {
+			SHUT_RD => VIRTIO_VSOCK_SHUTDOWN_RCV,
+			SHUT_WR => VIRTIO_VSOCK_SHUTDOWN_SEND,
+			SHUT_RDWR => VIRTIO_VSOCK_SHUTDOWN_RCV | VIRTIO_VSOCK_SHUTDOWN_SEND,
+			_ => return -EINVAL,
+		};
+		let id = match self.connection() {
+			Ok(id) => id,
+			Err(err) => return -err,
+		};
+
+		let driver = get_vsock_driver().unwrap();
+		match driver.lock().shutdown(id, flags) {
+			Ok(()) => 0,
+			Err(err) => -errno(err),
+		}
+	}
+
+	fn ioctl(&self, cmd: i32, argp: *mut c_void) -> i32 {
+		if cmd == FIONBIO {
+			let value = unsafe { *(argp as *const i32) };
+			self.nonblocking.store(value != 0, Ordering::Release);
+
+			0
+		} else {
+			-EINVAL
+		}
+	}
+}
+
+impl Clone for Socket {
+	/// The clone is an unbound socket with the options of this socket, because
+	/// ports and connections are owned by a single socket.
+	fn clone(&self) -> Self {
+		Self {
+			state: InterruptTicketMutex::new(State::Unbound),
+			nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Acquire)),
+		}
+	}
+}
+
+impl Drop for Socket {
+	fn drop(&mut self) {
+		let Some(driver) = get_vsock_driver() else {
+			return;
+		};
+
+		let mut driver = driver.lock();
+		match *self.state.get_mut() {
+			State::Unbound => {}
+			State::Bound(port) | State::Listening(port) => driver.unbind(port),
+			State::Connected { id, owns_port } => {
+				driver.close(id);
+				if owns_port {
+					driver.unbind(id.local_port);
+				}
+			}
+		}
+	}
+}
@@ -1,14 +1,53 @@
 use core::{isize, slice};
 
 use crate::console::CONSOLE;
+#[cfg(feature = "pci")]
+use crate::core_scheduler;
+#[cfg(feature = "pci")]
+use crate::drivers::console::CONSOLE_PORT;
+#[cfg(feature = "pci")]
+use crate::drivers::pci::get_console_driver;
+#[cfg(feature = "pci")]
+use crate::errno::{EIO, ENOSYS};
 use crate::fd::{
 	uhyve_send, ObjectInterface, SysWrite, STDERR_FILENO, STDOUT_FILENO, UHYVE_PORT_WRITE,
 };
 
+/// Writes to the virtio console, if available, and to the kernel console otherwise.
+fn write_console(buf: &[u8]) {
+	#[cfg(feature = "pci")]
+	if let Some(console) = get_console_driver() {
+		if console.lock().write(CONSOLE_PORT, buf).is_ok() {
+			return;
+		}
+	}
+
+	CONSOLE.lock().write_all(buf);
+}
+
 #[derive(Debug, Clone)]
 pub struct GenericStdin;
 
-impl ObjectInterface for GenericStdin {}
+impl ObjectInterface for GenericStdin {
+	#[cfg(feature = "pci")]
+	fn read(&self, buf: *mut u8, len: usize) -> isize {
+		assert!(len <= isize::MAX as usize);
+		let Some(console) = get_console_driver() else {
+			return (-ENOSYS).try_into().unwrap();
+		};
+		let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+
+		// block until input is available
+		loop {
+			let ret = console.lock().read(CONSOLE_PORT, buf);
+			match ret {
+				Ok(0) if len > 0 => core_scheduler().reschedule(),
+				Ok(n) => return n as isize,
+				Err(_) => return (-EIO).try_into().unwrap(),
+			}
+		}
+	}
+}
 
 impl GenericStdin {
 	pub const fn new() -> Self {
@@ -25,7 +64,7 @@ impl ObjectInterface for GenericStdout {
 		let buf = unsafe { slice::from_raw_parts(buf, len) };
 
 		// stdin/err/out all go to console
-		CONSOLE.lock().write_all(buf);
+		write_console(buf);
 
 		len as isize
 	}
@@ -46,7 +85,7 @@ impl ObjectInterface for GenericStderr {
 		let buf = unsafe { slice::from_raw_parts(buf, len) };
 
 		// stdin/err/out all go to console
-		CONSOLE.lock().write_all(buf);
+		write_console(buf);
 
 		len as isize
 	}
@@ -0,0 +1,218 @@
+//! Byte-granular access to block devices and their partitions.
+//!
+//! The disk-based file systems do not care about the sector size of the
+//! underlying device. [Disk] translates their byte-oriented requests into
+//! sector requests and performs read-modify-write cycles on partial sectors.
+
+use alloc::vec::Vec;
+
+use hermit_sync::InterruptTicketMutex;
+
+use crate::drivers::block::{BlockDevice, BlockError};
+use crate::drivers::pci::get_block_drivers;
+use crate::syscalls::fs::FileError;
+
+/// Size of a sector as it is used by the MBR partition table.
+const MBR_SECTOR_SIZE: u64 = 512;
+/// Offset of the first partition entry in the MBR.
+const MBR_PARTITION_TABLE: usize = 446;
+/// Number of primary partitions in the MBR.
+const MBR_PARTITIONS: usize = 4;
+/// Partition type of an extended partition in the MBR (CHS and LBA variant).
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+
+impl From<BlockError> for FileError {
+	fn from(err: BlockError) -> Self {
+		match err {
+			BlockError::ReadOnly => FileError::EROFS,
+			BlockError::OutOfRange | BlockError::Unaligned => FileError::EINVAL,
+			BlockError::Unsupported | BlockError::Io => FileError::EIO,
+		}
+	}
+}
+
+/// A block device or a partition on a block device.
+///
+/// The device is referenced by its index in [get_block_drivers] and is
+/// looked up on each request, as the drivers are not `Send`.
+#[derive(Debug, Clone)]
+pub(crate) struct Disk {
+	/// Index of the device in the list of block devices
+	index: usize,
+	/// Sector size of the device in bytes
+	sector_size: u64,
+	/// First sector of the partition
+	start: u64,
+	/// Number of sectors of the partition
+	sectors: u64,
+	read_only: bool,
+}
+
+impl Disk {
+	/// Opens the whole block device with the given index.
+	pub fn new(index: usize) -> Option<Self> {
+		let dev = get_block_drivers().get(index).copied()?;
+		let dev = dev.lock();
+
+		Some(Self {
+			index,
+			sector_size: dev.sector_size() as u64,
+			start: 0,
+			sectors: dev.capacity(),
+			read_only: dev.is_read_only(),
+		})
+	}
+
+	/// Parses a device specification of the form `<index>` or `<index>p<partition>`,
+	/// where partitions are numbered from 1 in the order of the MBR partition table.
+	pub fn from_spec(spec: &str) -> Result<Self, FileError> {
+		let (index, partition) = match spec.split_once('p') {
+			Some((index, partition)) => (index, Some(partition)),
+			None => (spec, None),
+		};
+		let index = index.parse::<usize>().map_err(|_| FileError::EINVAL)?;
+		let disk = Self::new(index).ok_or(FileError::ENODEV)?;
+
+		match partition {
+			Some(partition) => {
+				let partition = partition.parse::<usize>().map_err(|_| FileError::EINVAL)?;
+				disk.partitions()?
+					.into_iter()
+					.nth(partition.wrapping_sub(1))
+					.ok_or(FileError::ENODEV)
+			}
+			None => Ok(disk),
+		}
+	}
+
+	/// Returns the primary partitions, which are listed in the MBR of the device.
+	///
+	/// Empty and extended partitions are skipped.
+	pub fn partitions(&self) -> Result<Vec<Self>, FileError> {
+		let mut mbr = [0u8; MBR_SECTOR_SIZE as usize];
+		self.read_at(0, &mut mbr)?;
+		if mbr[510..512] != [0x55, 0xaa] {
+			return Ok(Vec::new());
+		}
+
+		let mut partitions = Vec::new();
+		for i in 0..MBR_PARTITIONS {
+			let entry = &mbr[MBR_PARTITION_TABLE + 16 * i..MBR_PARTITION_TABLE + 16 * (i + 1)];
+			let kind = entry[4];
+			let lba = u64::from(u32::from_le_bytes(entry[8..12].try_into().unwrap()));
+			let len = u64::from(u32::from_le_bytes(entry[12..16].try_into().unwrap()));
+			if kind == 0 || MBR_EXTENDED.contains(&kind) || len == 0 {
+				continue;
+			}
+
+			let start = lba * MBR_SECTOR_SIZE / self.sector_size;
+			let sectors = len * MBR_SECTOR_SIZE / self.sector_size;
+			if start + sectors > self.sectors {
+				warn!(
+					"Partition {} exceeds the size of the disk, ignoring it",
+					i + 1
+				);
+				continue;
+			}
+
+			partitions.push(Self {
+				start: self.start + start,
+				sectors,
+				..self.clone()
+			});
+		}
+
+		Ok(partitions)
+	}
+
+	/// Returns the size of the disk in bytes.
+	pub fn size(&self) -> u64 {
+		self.sectors * self.sector_size
+	}
+
+	/// Returns true, if the disk does not accept write requests.
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
+	fn device(&self) -> Result<&'static InterruptTicketMutex<dyn BlockDevice>, FileError> {
+		get_block_drivers()
+			.get(self.index)
+			.copied()
+			.ok_or(FileError::ENODEV)
+	}
+
+	/// Returns the range of sectors covering `len` bytes at byte offset `offset`.
+	fn sector_range(&self, offset: u64, len: usize) -> Result<(u64, u64), FileError> {
+		let end = offset
+			.checked_add(len as u64)
+			.filter(|end| *end <= self.size())
+			.ok_or(FileError::EIO)?;
+		let first = offset / self.sector_size;
+		let last = (end + self.sector_size - 1) / self.sector_size;
+
+		Ok((first, last))
+	}
+
+	/// Reads `buf.len()` bytes starting at byte offset `offset`.
+	pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), FileError> {
+		if buf.is_empty() {
+			return Ok(());
+		}
+
+		let (first, last) = self.sector_range(offset, buf.len())?;
+		let skip = (offset - first * self.sector_size) as usize;
+		let mut dev = self.device()?.lock();
+
+		if skip == 0 && buf.len() as u64 % self.sector_size == 0 {
+			dev.read_sectors(self.start + first, buf)?;
+		} else {
+			let mut tmp = vec![0u8; ((last - first) * self.sector_size) as usize];
+			dev.read_sectors(self.start + first, &mut tmp)?;
+			buf.copy_from_slice(&tmp[skip..skip + buf.len()]);
+		}
+
+		Ok(())
+	}
+
+	/// Writes `buf` starting at byte offset `offset`.
+	pub fn write_at(&self, offset: u64, buf: &[u8]) -> Result<(), FileError> {
+		if self.read_only {
+			return Err(FileError::EROFS);
+		}
+		if buf.is_empty() {
+			return Ok(());
+		}
+
+		let (first, last) = self.sector_range(offset, buf.len())?;
+		let skip = (offset - first * self.sector_size) as usize;
+		let mut dev = self.device()?.lock();
+
+		if skip == 0 && buf.len() as u64 % self.sector_size == 0 {
+			dev.write_sectors(self.start + first, buf)?;
+		} else {
+			// read-modify-write of the sectors at the boundaries
+			let sector_size = self.sector_size as usize;
+			let mut tmp = vec![0u8; ((last - first) * self.sector_size) as usize];
+			let tail = tmp.len() - sector_size;
+			dev.read_sectors(self.start + first, &mut tmp[..sector_size])?;
+			if last - first > 1 {
+				dev.read_sectors(self.start + last - 1, &mut tmp[tail..])?;
+			}
+			tmp[skip..skip + buf.len()].copy_from_slice(buf);
+			dev.write_sectors(self.start + first, &tmp)?;
+		}
+
+		Ok(())
+	}
+
+	/// Makes sure that all writes reached persistent storage.
+	pub fn flush(&self) -> Result<(), FileError> {
+		if self.read_only {
+			return Ok(());
+		}
+
+		self.device()?.lock().flush()?;
+		Ok(())
+	}
+}
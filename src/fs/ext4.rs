@@ -0,0 +1,1146 @@
+//! A read-only implementation of the ext2, ext3 and ext4 file systems.
+//!
+//! Files are mapped either by the classic block maps or by extent trees.
+//! Lookups in hashed directories use the htree index, otherwise directories
+//! are scanned linearly. The journal is ignored, so a volume, which was not
+//! cleanly unmounted, may appear inconsistent.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use crate::fs::disk::Disk;
+use crate::syscalls::fs::{
+	DirEntry, FileError, FilePerms, FileType, PosixFile, PosixFileSystem, SeekWhence,
+};
+
+/// Byte offset of the superblock
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT4_MAGIC: u16 = 0xef53;
+const ROOT_INODE: u32 = 2;
+/// Maximum number of symbolic links, which are followed while resolving a path
+const MAX_SYMLINKS: usize = 40;
+
+const COMPAT_SPARSE_SUPER2: u32 = 0x0200;
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+const INCOMPAT_COMPRESSION: u32 = 0x0001;
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_RECOVER: u32 = 0x0004;
+const INCOMPAT_JOURNAL_DEV: u32 = 0x0008;
+const INCOMPAT_META_BG: u32 = 0x0010;
+const INCOMPAT_64BIT: u32 = 0x0080;
+const INCOMPAT_DIRDATA: u32 = 0x1000;
+const INCOMPAT_INLINE_DATA: u32 = 0x8000;
+const INCOMPAT_ENCRYPT: u32 = 0x10000;
+const INCOMPAT_CASEFOLD: u32 = 0x20000;
+/// Incompatible features, which prevent reading the file system
+const INCOMPAT_UNSUPPORTED: u32 = INCOMPAT_COMPRESSION
+	| INCOMPAT_JOURNAL_DEV
+	| INCOMPAT_DIRDATA
+	| INCOMPAT_INLINE_DATA
+	| INCOMPAT_ENCRYPT
+	| INCOMPAT_CASEFOLD;
+/// Directory hashes are computed with unsigned chars
+const FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+
+const INODE_FLAG_INDEX: u32 = 0x1000;
+const INODE_FLAG_EXTENTS: u32 = 0x80000;
+const INODE_FLAG_INLINE_DATA: u32 = 0x1000_0000;
+
+const S_IFMT: u16 = 0xf000;
+const S_IFLNK: u16 = 0xa000;
+const S_IFREG: u16 = 0x8000;
+const S_IFDIR: u16 = 0x4000;
+
+/// File types stored in directory entries
+const FT_DIR: u8 = 2;
+const FT_SYMLINK: u8 = 7;
+
+/// Number of direct block pointers in an inode
+const DIRECT_BLOCKS: u64 = 12;
+
+const EXTENT_MAGIC: u16 = 0xf30a;
+/// Extents longer than this value are uninitialized and read as zeros
+const EXTENT_INIT_MAX_LEN: u16 = 32768;
+
+const DX_HASH_LEGACY: u8 = 0;
+const DX_HASH_HALF_MD4: u8 = 1;
+const DX_HASH_TEA: u8 = 2;
+const DX_HASH_LEGACY_UNSIGNED: u8 = 3;
+const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+const DX_HASH_TEA_UNSIGNED: u8 = 5;
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Hash functions of the htree directory index, see `fs/ext4/hash.c` of Linux.
+mod dx_hash {
+	use core::cmp::min;
+
+	/// `dx_hack_hash`, which is used by the legacy hash version
+	pub fn legacy(name: &[u8], unsigned: bool) -> u32 {
+		let (mut hash0, mut hash1) = (0x12a3_fe2du32, 0x37ab_e8f9u32);
+		for c in name {
+			let c = if unsigned {
+				u32::from(*c)
+			} else {
+				*c as i8 as i32 as u32
+			};
+			let mut hash = hash1.wrapping_add(hash0 ^ c.wrapping_mul(7_152_373));
+			if hash & 0x8000_0000 != 0 {
+				hash = hash.wrapping_sub(0x7fff_ffff);
+			}
+			hash1 = hash0;
+			hash0 = hash;
+		}
+		hash0 << 1
+	}
+
+	/// Converts the next chunk of a name into `out.len()` words of input for the hash functions.
+	fn str2hashbuf(name: &[u8], out: &mut [u32], unsigned: bool) {
+		let len = name.len() as u32;
+		let mut pad = len | (len << 8);
+		pad |= pad << 16;
+
+		let mut val = pad;
+		let mut words = out.iter_mut();
+		for (i, c) in name.iter().take(out.len() * 4).enumerate() {
+			let c = if unsigned {
+				u32::from(*c)
+			} else {
+				*c as i8 as i32 as u32
+			};
+			val = c.wrapping_add(val << 8);
+			if i % 4 == 3 {
+				*words.next().unwrap() = val;
+				val = pad;
+			}
+		}
+		if let Some(word) = words.next() {
+			*word = val;
+		}
+		for word in words {
+			*word = pad;
+		}
+	}
+
+	fn f(x: u32, y: u32, z: u32) -> u32 {
+		z ^ (x & (y ^ z))
+	}
+
+	fn g(x: u32, y: u32, z: u32) -> u32 {
+		(x & y).wrapping_add((x ^ y) & z)
+	}
+
+	fn h(x: u32, y: u32, z: u32) -> u32 {
+		x ^ y ^ z
+	}
+
+	fn round(f: fn(u32, u32, u32) -> u32, a: &mut u32, b: u32, c: u32, d: u32, x: u32, s: u32) {
+		*a = a.wrapping_add(f(b, c, d)).wrapping_add(x).rotate_left(s);
+	}
+
+	fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+		const K1: u32 = 0;
+		const K2: u32 = 0x5a82_7999;
+		const K3: u32 = 0x6ed9_eba1;
+
+		let [mut a, mut b, mut c, mut d] = *buf;
+
+		round(f, &mut a, b, c, d, input[0].wrapping_add(K1), 3);
+		round(f, &mut d, a, b, c, input[1].wrapping_add(K1), 7);
+		round(f, &mut c, d, a, b, input[2].wrapping_add(K1), 11);
+		round(f, &mut b, c, d, a, input[3].wrapping_add(K1), 19);
+		round(f, &mut a, b, c, d, input[4].wrapping_add(K1), 3);
+		round(f, &mut d, a, b, c, input[5].wrapping_add(K1), 7);
+		round(f, &mut c, d, a, b, input[6].wrapping_add(K1), 11);
+		round(f, &mut b, c, d, a, input[7].wrapping_add(K1), 19);
+
+		round(g, &mut a, b, c, d, input[1].wrapping_add(K2), 3);
+		round(g, &mut d, a, b, c, input[3].wrapping_add(K2), 5);
+		round(g, &mut c, d, a, b, input[5].wrapping_add(K2), 9);
+		round(g, &mut b, c, d, a, input[7].wrapping_add(K2), 13);
+		round(g, &mut a, b, c, d, input[0].wrapping_add(K2), 3);
+		round(g, &mut d, a, b, c, input[2].wrapping_add(K2), 5);
+		round(g, &mut c, d, a, b, input[4].wrapping_add(K2), 9);
+		round(g, &mut b, c, d, a, input[6].wrapping_add(K2), 13);
+
+		round(h, &mut a, b, c, d, input[3].wrapping_add(K3), 3);
+		round(h, &mut d, a, b, c, input[7].wrapping_add(K3), 9);
+		round(h, &mut c, d, a, b, input[2].wrapping_add(K3), 11);
+		round(h, &mut b, c, d, a, input[6].wrapping_add(K3), 15);
+		round(h, &mut a, b, c, d, input[1].wrapping_add(K3), 3);
+		round(h, &mut d, a, b, c, input[5].wrapping_add(K3), 9);
+		round(h, &mut c, d, a, b, input[0].wrapping_add(K3), 11);
+		round(h, &mut b, c, d, a, input[4].wrapping_add(K3), 15);
+
+		buf[0] = buf[0].wrapping_add(a);
+		buf[1] = buf[1].wrapping_add(b);
+		buf[2] = buf[2].wrapping_add(c);
+		buf[3] = buf[3].wrapping_add(d);
+	}
+
+	fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+		const DELTA: u32 = 0x9e37_79b9;
+
+		let (mut b0, mut b1) = (buf[0], buf[1]);
+		let [a, b, c, d] = *input;
+		let mut sum = 0u32;
+
+		for _ in 0..16 {
+			sum = sum.wrapping_add(DELTA);
+			b0 = b0.wrapping_add(
+				(b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+			);
+			b1 = b1.wrapping_add(
+				(b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+			);
+		}
+
+		buf[0] = buf[0].wrapping_add(b0);
+		buf[1] = buf[1].wrapping_add(b1);
+	}
+
+	/// Computes the hash of `name`, which is used to search the htree index.
+	/// Returns `None` for unknown hash versions.
+	pub fn hash(name: &[u8], version: u8, seed: [u32; 4]) -> Option<u32> {
+		let mut buf = if seed == [0; 4] {
+			[0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476]
+		} else {
+			seed
+		};
+
+		let hash = match version {
+			super::DX_HASH_LEGACY | super::DX_HASH_LEGACY_UNSIGNED => {
+				legacy(name, version == super::DX_HASH_LEGACY_UNSIGNED)
+			}
+			super::DX_HASH_HALF_MD4 | super::DX_HASH_HALF_MD4_UNSIGNED => {
+				let unsigned = version == super::DX_HASH_HALF_MD4_UNSIGNED;
+				let mut input = [0u32; 8];
+				let mut chunk = name;
+				while !chunk.is_empty() {
+					str2hashbuf(chunk, &mut input, unsigned);
+					half_md4_transform(&mut buf, &input);
+					chunk = &chunk[min(chunk.len(), 32)..];
+				}
+				buf[1]
+			}
+			super::DX_HASH_TEA | super::DX_HASH_TEA_UNSIGNED => {
+				let unsigned = version == super::DX_HASH_TEA_UNSIGNED;
+				let mut input = [0u32; 4];
+				let mut chunk = name;
+				while !chunk.is_empty() {
+					str2hashbuf(chunk, &mut input, unsigned);
+					tea_transform(&mut buf, &input);
+					chunk = &chunk[min(chunk.len(), 16)..];
+				}
+				buf[0]
+			}
+			_ => return None,
+		};
+
+		// the largest hash value is reserved as end-of-directory marker
+		let hash = hash & !1;
+		Some(if hash == 0x7fff_ffff << 1 {
+			0x7fff_fffe << 1
+		} else {
+			hash
+		})
+	}
+}
+
+/// The parts of an inode, which are needed to read it
+#[derive(Debug, Clone)]
+struct Inode {
+	mode: u16,
+	size: u64,
+	/// Number of allocated 512-byte sectors
+	sectors: u64,
+	/// Block containing the extended attributes
+	file_acl: u64,
+	flags: u32,
+	/// Block map or extent tree
+	block: [u8; 60],
+}
+
+impl Inode {
+	fn is_dir(&self) -> bool {
+		self.mode & S_IFMT == S_IFDIR
+	}
+
+	fn is_symlink(&self) -> bool {
+		self.mode & S_IFMT == S_IFLNK
+	}
+
+	fn file_type(&self) -> FileType {
+		match self.mode & S_IFMT {
+			S_IFDIR => FileType::Directory,
+			S_IFLNK => FileType::Symlink,
+			_ => FileType::File,
+		}
+	}
+}
+
+/// Result of the search of a logical block in a node of an extent tree
+#[derive(Debug, PartialEq, Eq)]
+enum ExtentSearch {
+	/// The physical block, if the logical block is mapped and initialized,
+	/// and the number of following logical blocks with the same state
+	Mapped(Option<u64>, u64),
+	/// The logical block is covered by the node in the given block
+	Child(u64),
+}
+
+/// An entry of a directory block
+struct RawDirEntry<'a> {
+	inode: u32,
+	file_type: u8,
+	name: &'a [u8],
+}
+
+struct Volume {
+	disk: Disk,
+	block_size: u64,
+	inode_size: u64,
+	inodes_per_group: u32,
+	/// Number of inodes in the file system
+	inodes: u32,
+	/// Block numbers of the inode tables of all block groups
+	inode_tables: Vec<u64>,
+	/// Directory entries contain the type of the file
+	filetype: bool,
+	hash_seed: [u32; 4],
+	unsigned_hash: bool,
+}
+
+impl Volume {
+	fn mount(disk: Disk) -> Result<Self, FileError> {
+		let mut sb = [0u8; 1024];
+		disk.read_at(SUPERBLOCK_OFFSET, &mut sb)?;
+
+		if u16_at(&sb, 56) != EXT4_MAGIC {
+			error!("No ext2/ext3/ext4 file system found");
+			return Err(FileError::EINVAL);
+		}
+
+		let inodes = u32_at(&sb, 0);
+		let blocks = u64::from(u32_at(&sb, 4));
+		let first_data_block = u64::from(u32_at(&sb, 20));
+		let log_block_size = u32_at(&sb, 24);
+		let blocks_per_group = u64::from(u32_at(&sb, 32));
+		let inodes_per_group = u32_at(&sb, 40);
+		let rev_level = u32_at(&sb, 76);
+		let feature_compat = u32_at(&sb, 92);
+		let feature_incompat = u32_at(&sb, 96);
+		let feature_ro_compat = u32_at(&sb, 100);
+		let flags = u32_at(&sb, 352);
+
+		if log_block_size > 6 || blocks_per_group == 0 || inodes_per_group == 0 {
+			error!("Invalid ext2/ext3/ext4 superblock");
+			return Err(FileError::EINVAL);
+		}
+		if feature_incompat & INCOMPAT_UNSUPPORTED != 0 {
+			error!(
+				"ext2/ext3/ext4 file system uses unsupported features {:#x}",
+				feature_incompat & INCOMPAT_UNSUPPORTED
+			);
+			return Err(FileError::EINVAL);
+		}
+		if feature_incompat & INCOMPAT_RECOVER != 0 {
+			warn!("The journal of the ext3/ext4 file system needs recovery, files may be inconsistent");
+		}
+
+		let block_size = 1024u64 << log_block_size;
+		let (inode_size, desc_size) = if rev_level == 0 {
+			(128, 32)
+		} else {
+			let desc_size = if feature_incompat & INCOMPAT_64BIT != 0 {
+				u64::from(u16_at(&sb, 254)).max(32)
+			} else {
+				32
+			};
+			(u64::from(u16_at(&sb, 88)), desc_size)
+		};
+		let blocks = if feature_incompat & INCOMPAT_64BIT != 0 {
+			blocks | u64::from(u32_at(&sb, 336)) << 32
+		} else {
+			blocks
+		};
+		if inode_size < 128 || !inode_size.is_power_of_two() || inode_size > block_size {
+			error!("Invalid inode size {}", inode_size);
+			return Err(FileError::EINVAL);
+		}
+
+		let mut volume = Self {
+			disk,
+			block_size,
+			inode_size,
+			inodes_per_group,
+			inodes,
+			inode_tables: Vec::new(),
+			filetype: feature_incompat & INCOMPAT_FILETYPE != 0,
+			hash_seed: [
+				u32_at(&sb, 236),
+				u32_at(&sb, 240),
+				u32_at(&sb, 244),
+				u32_at(&sb, 248),
+			],
+			unsigned_hash: flags & FLAGS_UNSIGNED_HASH != 0,
+		};
+
+		// read the location of the inode table from all group descriptors
+		let groups = (blocks - first_data_block + blocks_per_group - 1) / blocks_per_group;
+		let descs_per_block = block_size / desc_size;
+		let first_meta_bg = if feature_incompat & INCOMPAT_META_BG != 0 {
+			u64::from(u32_at(&sb, 260))
+		} else {
+			u64::MAX
+		};
+		let sparse_super = feature_ro_compat & RO_COMPAT_SPARSE_SUPER != 0
+			|| feature_compat & COMPAT_SPARSE_SUPER2 != 0;
+
+		let mut buf = vec![0u8; block_size as usize];
+		for desc_block in 0..(groups + descs_per_block - 1) / descs_per_block {
+			let block = if desc_block < first_meta_bg {
+				first_data_block + 1 + desc_block
+			} else {
+				// with meta block groups, the descriptors are stored in the first group of each meta group
+				let group = desc_block * descs_per_block;
+				let backup = u64::from(Self::has_superblock(group, sparse_super));
+				first_data_block + group * blocks_per_group + backup
+			};
+			volume.read_block(block, &mut buf)?;
+
+			for desc in buf
+				.chunks_exact(desc_size as usize)
+				.take((groups - desc_block * descs_per_block) as usize)
+			{
+				let lo = u64::from(u32_at(desc, 8));
+				let hi = if desc_size >= 64 {
+					u64::from(u32_at(desc, 40))
+				} else {
+					0
+				};
+				volume.inode_tables.push(hi << 32 | lo);
+			}
+		}
+
+		Ok(volume)
+	}
+
+	/// Returns true, if the block group contains a copy of the superblock and the group descriptors.
+	fn has_superblock(group: u64, sparse_super: bool) -> bool {
+		let is_power_of = |mut n: u64, base: u64| {
+			while n > 1 && n % base == 0 {
+				n /= base;
+			}
+			n == 1
+		};
+
+		!sparse_super
+			|| group <= 1
+			|| is_power_of(group, 3)
+			|| is_power_of(group, 5)
+			|| is_power_of(group, 7)
+	}
+
+	fn read_block(&self, block: u64, buf: &mut [u8]) -> Result<(), FileError> {
+		self.disk.read_at(block * self.block_size, buf)
+	}
+
+	fn read_inode(&self, ino: u32) -> Result<Inode, FileError> {
+		if ino == 0 || ino > self.inodes {
+			error!("Invalid inode number {}", ino);
+			return Err(FileError::EIO);
+		}
+
+		let group = ((ino - 1) / self.inodes_per_group) as usize;
+		let index = u64::from((ino - 1) % self.inodes_per_group);
+		let table = *self.inode_tables.get(group).ok_or(FileError::EIO)?;
+
+		let mut raw = [0u8; 128];
+		self.disk
+			.read_at(table * self.block_size + index * self.inode_size, &mut raw)?;
+
+		let mode = u16_at(&raw, 0);
+		let size_lo = u64::from(u32_at(&raw, 4));
+		let size_hi = u64::from(u32_at(&raw, 108));
+		Ok(Inode {
+			mode,
+			size: size_hi << 32 | size_lo,
+			sectors: u64::from(u32_at(&raw, 28)),
+			file_acl: u64::from(u16_at(&raw, 118)) << 32 | u64::from(u32_at(&raw, 104)),
+			flags: u32_at(&raw, 32),
+			block: raw[40..100].try_into().unwrap(),
+		})
+	}
+
+	/// Maps the logical block `lblock` of a file onto the disk.
+	///
+	/// Returns the physical block and the number of following blocks, which are
+	/// contiguous on the disk, or `None` together with the length of the hole.
+	fn map_block(&self, inode: &Inode, lblock: u64) -> Result<(Option<u64>, u64), FileError> {
+		if inode.flags & INODE_FLAG_INLINE_DATA != 0 {
+			error!("Inline data is not supported");
+			Err(FileError::EIO)
+		} else if inode.flags & INODE_FLAG_EXTENTS != 0 {
+			self.map_extent(inode, lblock)
+		} else {
+			Ok((self.map_indirect(inode, lblock)?, 1))
+		}
+	}
+
+	/// Walks the extent tree of an inode.
+	fn map_extent(&self, inode: &Inode, lblock: u64) -> Result<(Option<u64>, u64), FileError> {
+		let mut node = inode.block.to_vec();
+		let mut buf = vec![0u8; self.block_size as usize];
+
+		loop {
+			match Self::search_extent_node(&node, lblock)? {
+				ExtentSearch::Mapped(block, count) => return Ok((block, count)),
+				ExtentSearch::Child(leaf) => {
+					self.read_block(leaf, &mut buf)?;
+					node.clear();
+					node.extend_from_slice(&buf);
+				}
+			}
+		}
+	}
+
+	/// Searches `lblock` in a single node of an extent tree.
+	fn search_extent_node(node: &[u8], lblock: u64) -> Result<ExtentSearch, FileError> {
+		if u16_at(node, 0) != EXTENT_MAGIC {
+			error!("Invalid extent header");
+			return Err(FileError::EIO);
+		}
+		let entries = usize::from(u16_at(node, 2));
+		let depth = u16_at(node, 6);
+		if 12 * (entries + 1) > node.len() {
+			error!("Invalid number of extents");
+			return Err(FileError::EIO);
+		}
+
+		// find the last entry, which starts at or before lblock
+		let entry = (0..entries)
+			.map(|i| &node[12 * (i + 1)..12 * (i + 2)])
+			.take_while(|entry| u64::from(u32_at(entry, 0)) <= lblock)
+			.last();
+		let next = (0..entries)
+			.map(|i| u64::from(u32_at(node, 12 * (i + 1))))
+			.find(|start| *start > lblock)
+			.unwrap_or(u64::from(u32::MAX) + 1);
+
+		let Some(entry) = entry else {
+			return Ok(ExtentSearch::Mapped(None, next - lblock));
+		};
+		let start = u64::from(u32_at(entry, 0));
+
+		if depth > 0 {
+			let leaf = u64::from(u16_at(entry, 8)) << 32 | u64::from(u32_at(entry, 4));
+			return Ok(ExtentSearch::Child(leaf));
+		}
+
+		let len = u16_at(entry, 4);
+		let (len, initialized) = if len > EXTENT_INIT_MAX_LEN {
+			(u64::from(len - EXTENT_INIT_MAX_LEN), false)
+		} else {
+			(u64::from(len), true)
+		};
+		let phys = u64::from(u16_at(entry, 6)) << 32 | u64::from(u32_at(entry, 8));
+
+		Ok(if lblock >= start + len {
+			ExtentSearch::Mapped(None, next - lblock)
+		} else if !initialized {
+			ExtentSearch::Mapped(None, start + len - lblock)
+		} else {
+			ExtentSearch::Mapped(Some(phys + lblock - start), start + len - lblock)
+		})
+	}
+
+	/// Walks the direct and indirect block maps of an inode.
+	fn map_indirect(&self, inode: &Inode, lblock: u64) -> Result<Option<u64>, FileError> {
+		let per_block = self.block_size / 4;
+
+		if lblock < DIRECT_BLOCKS {
+			return Ok(match u32_at(&inode.block, 4 * lblock as usize) {
+				0 => None,
+				block => Some(u64::from(block)),
+			});
+		}
+
+		// determine the number of indirections and the index within the indirect blocks
+		let mut index = lblock - DIRECT_BLOCKS;
+		let mut levels = 1;
+		let mut span = per_block;
+		while index >= span {
+			index -= span;
+			levels += 1;
+			span *= per_block;
+			if levels > 3 {
+				return Err(FileError::EIO);
+			}
+		}
+
+		let mut block = u64::from(u32_at(
+			&inode.block,
+			4 * (DIRECT_BLOCKS as usize + levels - 1),
+		));
+		let mut buf = vec![0u8; self.block_size as usize];
+		for _ in 0..levels {
+			if block == 0 {
+				return Ok(None);
+			}
+			span /= per_block;
+			self.read_block(block, &mut buf)?;
+			block = u64::from(u32_at(&buf, 4 * (index / span) as usize));
+			index %= span;
+		}
+
+		Ok(if block == 0 { None } else { Some(block) })
+	}
+
+	/// Reads up to `len` bytes at position `pos` of a file.
+	fn read_data(&self, inode: &Inode, pos: u64, len: usize) -> Result<Vec<u8>, FileError> {
+		if pos >= inode.size {
+			return Ok(Vec::new());
+		}
+
+		let len = min(len as u64, inode.size - pos) as usize;
+		let mut data = vec![0u8; len];
+		let mut done = 0;
+
+		while done < len {
+			let current = pos + done as u64;
+			let lblock = current / self.block_size;
+			let within = current % self.block_size;
+			let (block, count) = self.map_block(inode, lblock)?;
+			let n = min((len - done) as u64, count.max(1) * self.block_size - within) as usize;
+
+			// holes are already filled with zeros
+			if let Some(block) = block {
+				self.disk
+					.read_at(block * self.block_size + within, &mut data[done..done + n])?;
+			}
+			done += n;
+		}
+
+		Ok(data)
+	}
+
+	/// Reads the logical block `lblock` of a directory.
+	fn read_dir_block(&self, dir: &Inode, lblock: u64) -> Result<Vec<u8>, FileError> {
+		let mut buf = vec![0u8; self.block_size as usize];
+		if let (Some(block), _) = self.map_block(dir, lblock)? {
+			self.read_block(block, &mut buf)?;
+		}
+
+		Ok(buf)
+	}
+
+	/// Parses the entries of a directory block. Unused entries are skipped.
+	/// `filetype` specifies, if the entries contain the type of the file.
+	fn parse_dir_block(block: &[u8], filetype: bool) -> Result<Vec<RawDirEntry<'_>>, FileError> {
+		let mut entries = Vec::new();
+		let mut offset = 0;
+
+		while offset + 8 <= block.len() {
+			let inode = u32_at(block, offset);
+			let rec_len = match usize::from(u16_at(block, offset + 4)) {
+				// large blocks encode their size as 0 or 65535
+				0 | 65535 if block.len() >= 65536 => block.len() - offset,
+				rec_len => rec_len,
+			};
+			let (name_len, file_type) = if filetype {
+				(usize::from(block[offset + 6]), block[offset + 7])
+			} else {
+				(usize::from(u16_at(block, offset + 6)), 0)
+			};
+
+			if rec_len < 8 || offset + rec_len > block.len() || 8 + name_len > rec_len {
+				error!("Corrupted directory entry");
+				return Err(FileError::EIO);
+			}
+
+			if inode != 0 {
+				entries.push(RawDirEntry {
+					inode,
+					file_type,
+					name: &block[offset + 8..offset + 8 + name_len],
+				});
+			}
+			offset += rec_len;
+		}
+
+		Ok(entries)
+	}
+
+	/// Searches a single directory block for `name`.
+	fn find_in_block(
+		&self,
+		dir: &Inode,
+		lblock: u64,
+		name: &[u8],
+	) -> Result<Option<u32>, FileError> {
+		let block = self.read_dir_block(dir, lblock)?;
+		Ok(Self::parse_dir_block(&block, self.filetype)?
+			.iter()
+			.find(|entry| entry.name == name)
+			.map(|entry| entry.inode))
+	}
+
+	/// Searches `name` in a directory.
+	fn lookup(&self, dir: &Inode, name: &[u8]) -> Result<Option<u32>, FileError> {
+		if dir.flags & INODE_FLAG_INDEX != 0 {
+			match self.lookup_htree(dir, name) {
+				Ok(Some(result)) => return Ok(result),
+				Ok(None) => {}
+				Err(err) => warn!("Unable to use the directory index: {:?}", err),
+			}
+		}
+
+		let blocks = (dir.size + self.block_size - 1) / self.block_size;
+		for lblock in 0..blocks {
+			if let Some(ino) = self.find_in_block(dir, lblock, name)? {
+				return Ok(Some(ino));
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Parses the index entries (hash, block) of an htree node.
+	fn parse_dx_entries(block: &[u8], offset: usize) -> Result<Vec<(u32, u32)>, FileError> {
+		let limit = usize::from(u16_at(block, offset));
+		let count = usize::from(u16_at(block, offset + 2));
+		if count == 0 || count > limit || offset + 8 * count > block.len() {
+			error!("Corrupted directory index");
+			return Err(FileError::EIO);
+		}
+
+		// the first entry has no hash, as it covers all hashes below the second entry
+		Ok((0..count)
+			.map(|i| {
+				let entry = offset + 8 * i;
+				let hash = if i == 0 { 0 } else { u32_at(block, entry) };
+				(hash, u32_at(block, entry + 4) & 0x0fff_ffff)
+			})
+			.collect())
+	}
+
+	/// Searches `name` by the htree index of a directory.
+	///
+	/// Returns `None`, if the index cannot be used (e.g. unknown hash version).
+	fn lookup_htree(&self, dir: &Inode, name: &[u8]) -> Result<Option<Option<u32>>, FileError> {
+		let root = self.read_dir_block(dir, 0)?;
+		// the index root follows the entries "." (12 bytes) and ".." (12 bytes)
+		let version = root[24 + 4];
+		let info_len = usize::from(root[24 + 5]);
+		let levels = usize::from(root[24 + 6]);
+		if levels > 2 {
+			return Ok(None);
+		}
+
+		let version = if version <= DX_HASH_TEA && self.unsigned_hash {
+			version + 3
+		} else {
+			version
+		};
+		let Some(hash) = dx_hash::hash(name, version, self.hash_seed) else {
+			return Ok(None);
+		};
+
+		// descend to the leaf, remembering the path for hash collisions
+		let mut path: Vec<(Vec<(u32, u32)>, usize)> = Vec::new();
+		let mut entries = Self::parse_dx_entries(&root, 24 + info_len)?;
+		loop {
+			let at = entries.iter().rposition(|(h, _)| *h <= hash).unwrap_or(0);
+			let block = entries[at].1;
+			path.push((entries, at));
+			if path.len() > levels {
+				break;
+			}
+
+			// interior nodes start with an empty directory entry of 8 bytes
+			let node = self.read_dir_block(dir, u64::from(block))?;
+			entries = Self::parse_dx_entries(&node, 8)?;
+		}
+
+		loop {
+			let (entries, at) = path.last().unwrap();
+			if let Some(ino) = self.find_in_block(dir, u64::from(entries[*at].1), name)? {
+				return Ok(Some(Some(ino)));
+			}
+
+			// entries with colliding hashes may continue in the next block
+			let mut level = path.len();
+			loop {
+				if level == 0 {
+					return Ok(Some(None));
+				}
+				level -= 1;
+				let (entries, at) = &mut path[level];
+				if *at + 1 < entries.len() {
+					*at += 1;
+					break;
+				}
+			}
+
+			let (entries, at) = &path[level];
+			if entries[*at].0 & !1 != hash {
+				return Ok(Some(None));
+			}
+			for l in level + 1..path.len() {
+				let (entries, at) = &path[l - 1];
+				let node = self.read_dir_block(dir, u64::from(entries[*at].1))?;
+				path[l] = (Self::parse_dx_entries(&node, 8)?, 0);
+			}
+		}
+	}
+
+	/// Reads the target of a symbolic link.
+	fn read_link(&self, inode: &Inode) -> Result<String, FileError> {
+		// short targets are stored within the inode ("fast symlinks"), which has
+		// no data blocks besides an optional block of extended attributes
+		let ea_sectors = if inode.file_acl != 0 {
+			self.block_size / 512
+		} else {
+			0
+		};
+		let target = if inode.sectors == ea_sectors && inode.size < 60 {
+			inode.block[..inode.size as usize].to_vec()
+		} else {
+			self.read_data(inode, 0, inode.size as usize)?
+		};
+
+		String::from_utf8(target).map_err(|_| FileError::EINVAL)
+	}
+
+	/// Resolves `path` relative to the root directory and returns the inode number and the inode.
+	/// Symbolic links in the path are followed. The last component is only followed, if `follow` is set.
+	fn resolve(&self, path: &str, follow: bool) -> Result<(u32, Inode), FileError> {
+		// components, which still have to be resolved, in reverse order
+		let mut pending: Vec<String> = path
+			.split('/')
+			.rev()
+			.filter(|c| !c.is_empty() && *c != ".")
+			.map(String::from)
+			.collect();
+		// directories from the root to the current position
+		let mut stack: Vec<(u32, Inode)> = vec![(ROOT_INODE, self.read_inode(ROOT_INODE)?)];
+		let mut symlinks = 0;
+
+		while let Some(component) = pending.pop() {
+			let (_, dir) = stack.last().unwrap();
+			if !dir.is_dir() {
+				return Err(FileError::ENOTDIR);
+			}
+
+			if component == ".." {
+				if stack.len() > 1 {
+					stack.pop();
+				}
+				continue;
+			}
+
+			let ino = self
+				.lookup(dir, component.as_bytes())?
+				.ok_or(FileError::ENOENT)?;
+			let inode = self.read_inode(ino)?;
+
+			if inode.is_symlink() && (follow || !pending.is_empty()) {
+				symlinks += 1;
+				if symlinks > MAX_SYMLINKS {
+					return Err(FileError::ELOOP);
+				}
+
+				let target = self.read_link(&inode)?;
+				// absolute targets are interpreted relative to the root of this file system
+				if target.starts_with('/') {
+					stack.truncate(1);
+				}
+				pending.extend(
+					target
+						.split('/')
+						.rev()
+						.filter(|c| !c.is_empty() && *c != ".")
+						.map(String::from),
+				);
+			} else {
+				stack.push((ino, inode));
+			}
+		}
+
+		Ok(stack.pop().unwrap())
+	}
+}
+
+/// A read-only ext2, ext3 or ext4 file system on a block device
+pub struct Ext4 {
+	volume: Arc<Volume>,
+}
+
+impl Ext4 {
+	pub(crate) fn new(disk: Disk) -> Result<Self, FileError> {
+		let volume = Volume::mount(disk)?;
+		info!(
+			"Found ext2/ext3/ext4 file system with {} block groups and {} byte blocks",
+			volume.inode_tables.len(),
+			volume.block_size
+		);
+
+		Ok(Self {
+			volume: Arc::new(volume),
+		})
+	}
+}
+
+impl PosixFileSystem for Ext4 {
+	fn open(&self, path: &str, perms: FilePerms) -> Result<Box<dyn PosixFile + Send>, FileError> {
+		if perms.write || perms.creat || perms.trunc {
+			return Err(FileError::EROFS);
+		}
+
+		let (_, inode) = self.volume.resolve(path, true)?;
+		match inode.mode & S_IFMT {
+			S_IFREG => Ok(Box::new(Ext4File {
+				volume: self.volume.clone(),
+				inode,
+				offset: 0,
+			})),
+			S_IFDIR => Err(FileError::EISDIR),
+			_ => Err(FileError::EINVAL),
+		}
+	}
+
+	fn unlink(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS)
+	}
+
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::EROFS)
+	}
+
+	fn readdir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+		let (_, dir) = self.volume.resolve(path, true)?;
+		if !dir.is_dir() {
+			return Err(FileError::ENOTDIR);
+		}
+
+		let mut result = Vec::new();
+		let blocks = (dir.size + self.volume.block_size - 1) / self.volume.block_size;
+		for lblock in 0..blocks {
+			let block = self.volume.read_dir_block(&dir, lblock)?;
+			for entry in Volume::parse_dir_block(&block, self.volume.filetype)? {
+				if entry.name == b"." || entry.name == b".." {
+					continue;
+				}
+
+				let file_type = if self.volume.filetype {
+					match entry.file_type {
+						FT_DIR => FileType::Directory,
+						FT_SYMLINK => FileType::Symlink,
+						_ => FileType::File,
+					}
+				} else {
+					self.volume.read_inode(entry.inode)?.file_type()
+				};
+
+				result.push(DirEntry {
+					name: String::from_utf8_lossy(entry.name).into_owned(),
+					file_type,
+				});
+			}
+		}
+
+		Ok(result)
+	}
+}
+
+/// An open regular file on an ext2, ext3 or ext4 file system
+struct Ext4File {
+	volume: Arc<Volume>,
+	inode: Inode,
+	offset: u64,
+}
+
+impl PosixFile for Ext4File {
+	fn close(&mut self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&mut self, len: u32) -> Result<Vec<u8>, FileError> {
+		let data = self
+			.volume
+			.read_data(&self.inode, self.offset, len as usize)?;
+		self.offset += data.len() as u64;
+
+		Ok(data)
+	}
+
+	fn write(&mut self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EBADF)
+	}
+
+	fn lseek(&mut self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let base = match whence {
+			SeekWhence::Set => 0,
+			SeekWhence::Cur => self.offset as i64,
+			SeekWhence::End => self.inode.size as i64,
+			SeekWhence::Data | SeekWhence::Hole => return Err(FileError::EINVAL),
+		};
+
+		let offset = base
+			.checked_add(offset as i64)
+			.filter(|offset| *offset >= 0)
+			.ok_or(FileError::EINVAL)?;
+		self.offset = offset as u64;
+
+		Ok(offset as usize)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	/// Builds a node of an extent tree with the size of the block map of an inode.
+	fn extent_node(depth: u16, entries: &[[u8; 12]]) -> [u8; 60] {
+		let mut node = [0u8; 60];
+		node[0..2].copy_from_slice(&EXTENT_MAGIC.to_le_bytes());
+		node[2..4].copy_from_slice(&u16::try_from(entries.len()).unwrap().to_le_bytes());
+		node[4..6].copy_from_slice(&4u16.to_le_bytes());
+		node[6..8].copy_from_slice(&depth.to_le_bytes());
+		for (i, entry) in entries.iter().enumerate() {
+			node[12 * (i + 1)..12 * (i + 2)].copy_from_slice(entry);
+		}
+		node
+	}
+
+	fn leaf_entry(start: u32, len: u16, phys: u64) -> [u8; 12] {
+		let mut entry = [0u8; 12];
+		entry[0..4].copy_from_slice(&start.to_le_bytes());
+		entry[4..6].copy_from_slice(&len.to_le_bytes());
+		entry[6..8].copy_from_slice(&((phys >> 32) as u16).to_le_bytes());
+		entry[8..12].copy_from_slice(&(phys as u32).to_le_bytes());
+		entry
+	}
+
+	fn index_entry(start: u32, leaf: u64) -> [u8; 12] {
+		let mut entry = [0u8; 12];
+		entry[0..4].copy_from_slice(&start.to_le_bytes());
+		entry[4..8].copy_from_slice(&(leaf as u32).to_le_bytes());
+		entry[8..10].copy_from_slice(&((leaf >> 32) as u16).to_le_bytes());
+		entry
+	}
+
+	#[test]
+	fn extent_leaf() {
+		let node = extent_node(
+			0,
+			&[
+				leaf_entry(0, 4, 100),
+				leaf_entry(10, 2, 0x1_0000_0200),
+				leaf_entry(20, EXTENT_INIT_MAX_LEN + 3, 50),
+			],
+		);
+		let search = |lblock| Volume::search_extent_node(&node, lblock).unwrap();
+
+		assert_eq!(search(0), ExtentSearch::Mapped(Some(100), 4));
+		assert_eq!(search(2), ExtentSearch::Mapped(Some(102), 2));
+		// hole between two extents
+		assert_eq!(search(5), ExtentSearch::Mapped(None, 5));
+		// 48-bit physical block number
+		assert_eq!(search(11), ExtentSearch::Mapped(Some(0x1_0000_0201), 1));
+		// uninitialized extents are read as holes
+		assert_eq!(search(21), ExtentSearch::Mapped(None, 2));
+		// hole behind the last extent
+		assert_eq!(
+			search(30),
+			ExtentSearch::Mapped(None, u64::from(u32::MAX) + 1 - 30)
+		);
+	}
+
+	#[test]
+	fn extent_index() {
+		let node = extent_node(1, &[index_entry(10, 7), index_entry(100, 0x2_0000_0005)]);
+		let search = |lblock| Volume::search_extent_node(&node, lblock).unwrap();
+
+		assert_eq!(search(5), ExtentSearch::Mapped(None, 5));
+		assert_eq!(search(10), ExtentSearch::Child(7));
+		assert_eq!(search(99), ExtentSearch::Child(7));
+		assert_eq!(search(150), ExtentSearch::Child(0x2_0000_0005));
+	}
+
+	#[test]
+	fn invalid_extent_nodes() {
+		let mut node = extent_node(0, &[leaf_entry(0, 1, 1)]);
+		node[0] = 0;
+		assert!(Volume::search_extent_node(&node, 0).is_err());
+
+		// The node has only room for four entries.
+		let mut node = extent_node(0, &[leaf_entry(0, 1, 1)]);
+		node[2..4].copy_from_slice(&5u16.to_le_bytes());
+		assert!(Volume::search_extent_node(&node, 0).is_err());
+	}
+
+	/// Appends a directory entry with the given record length.
+	fn dir_entry(block: &mut Vec<u8>, inode: u32, rec_len: u16, file_type: u8, name: &[u8]) {
+		let start = block.len();
+		block.extend_from_slice(&inode.to_le_bytes());
+		block.extend_from_slice(&rec_len.to_le_bytes());
+		block.push(u8::try_from(name.len()).unwrap());
+		block.push(file_type);
+		block.extend_from_slice(name);
+		block.resize(start + usize::from(rec_len), 0);
+	}
+
+	#[test]
+	fn dir_block() {
+		let mut block = Vec::new();
+		dir_entry(&mut block, 2, 12, FT_DIR, b".");
+		// unused entry
+		dir_entry(&mut block, 0, 12, 1, b"x");
+		dir_entry(&mut block, 11, 40, 1, b"hello");
+
+		let entries = Volume::parse_dir_block(&block, true).unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].inode, 2);
+		assert_eq!(entries[0].file_type, FT_DIR);
+		assert_eq!(entries[0].name, b".");
+		assert_eq!(entries[1].inode, 11);
+		assert_eq!(entries[1].file_type, 1);
+		assert_eq!(entries[1].name, b"hello");
+
+		// Without file types, the name length occupies both bytes.
+		let mut block = Vec::new();
+		dir_entry(&mut block, 12, 24, 0, b"world");
+		let entries = Volume::parse_dir_block(&block, false).unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].file_type, 0);
+		assert_eq!(entries[0].name, b"world");
+		block[7] = FT_DIR;
+		assert!(Volume::parse_dir_block(&block, false).is_err());
+	}
+
+	#[test]
+	fn corrupted_dir_block() {
+		// The record exceeds the block.
+		let mut block = Vec::new();
+		dir_entry(&mut block, 2, 12, FT_DIR, b".");
+		block[4..6].copy_from_slice(&16u16.to_le_bytes());
+		assert!(Volume::parse_dir_block(&block, true).is_err());
+
+		// The name exceeds the record.
+		let mut block = Vec::new();
+		dir_entry(&mut block, 2, 12, FT_DIR, b".");
+		block[6] = 5;
+		assert!(Volume::parse_dir_block(&block, true).is_err());
+	}
+}
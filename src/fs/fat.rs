@@ -0,0 +1,1152 @@
+//! A FAT32 file system with support for long file names (VFAT).
+//!
+//! The on-disk format follows Microsoft's "FAT: General Overview of On-Disk Format".
+//! FAT12 and FAT16 volumes are rejected.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use hermit_sync::TicketMutex;
+use time::OffsetDateTime;
+
+use crate::arch;
+use crate::fs::disk::Disk;
+use crate::syscalls::fs::{
+	DirEntry, FileError, FilePerms, FileType, PosixFile, PosixFileSystem, SeekWhence,
+};
+
+/// Size of a directory entry in bytes
+const DIR_ENTRY_SIZE: usize = 32;
+/// Maximum number of entries in a directory
+const MAX_DIR_ENTRIES: usize = 65536;
+/// Maximum length of a long file name in UTF-16 code units
+const MAX_NAME_LEN: usize = 255;
+/// Number of UTF-16 code units stored in a long name entry
+const LFN_CHARS: usize = 13;
+/// Positions of the UTF-16 code units within a long name entry
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Marks the last (physically first) long name entry of a name
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// First byte of a free directory entry
+const ENTRY_FREE: u8 = 0xe5;
+/// First byte of the entry, which terminates the directory
+const ENTRY_END: u8 = 0x00;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+/// Flags in the `NTRes` field, which mark a lower case base name or extension
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+
+/// Only the lower 28 bit of a FAT32 entry are used
+const CLUSTER_MASK: u32 = 0x0fff_ffff;
+const CLUSTER_FREE: u32 = 0;
+/// All entries equal or above this value mark the end of a cluster chain
+const CLUSTER_EOC: u32 = 0x0fff_fff8;
+/// Number of the first data cluster
+const FIRST_CLUSTER: u32 = 2;
+
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUC_SIG: u32 = 0x6141_7272;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+
+/// Characters, which are allowed in short names besides upper case letters and digits
+const SHORT_NAME_SPECIAL: &[u8] = b"$%'-_@~`!(){}^#&";
+
+/// Returns the current date and time in the FAT format.
+fn timestamp() -> (u16, u16) {
+	let seconds = (arch::get_boot_time() + arch::processor::get_timer_ticks()) / 1_000_000;
+	match OffsetDateTime::from_unix_timestamp(seconds as i64) {
+		Ok(now) if (1980..2108).contains(&now.year()) => {
+			let date = ((now.year() - 1980) as u16) << 9
+				| u16::from(u8::from(now.month())) << 5
+				| u16::from(now.day());
+			let time = u16::from(now.hour()) << 11
+				| u16::from(now.minute()) << 5
+				| u16::from(now.second() / 2);
+			(date, time)
+		}
+		// 1980-01-01 00:00:00
+		_ => (1 << 5 | 1, 0),
+	}
+}
+
+/// Checksum of a short name, which is stored in the corresponding long name entries.
+fn checksum(short_name: &[u8]) -> u8 {
+	short_name
+		.iter()
+		.fold(0u8, |sum, c| sum.rotate_right(1).wrapping_add(*c))
+}
+
+/// Compares two file names case-insensitively, as FAT does not distinguish between cases.
+fn names_equal(a: &str, b: &str) -> bool {
+	a.eq_ignore_ascii_case(b) || (!a.is_ascii() && a.to_uppercase() == b.to_uppercase())
+}
+
+fn is_short_char(c: u8) -> bool {
+	c.is_ascii_uppercase() || c.is_ascii_digit() || SHORT_NAME_SPECIAL.contains(&c)
+}
+
+/// Returns the short name, if `name` is a valid 8.3 name in upper case.
+/// Such names are stored without long name entries.
+fn short_name(name: &str) -> Option<[u8; 11]> {
+	let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+	if base.is_empty()
+		|| base.len() > 8
+		|| ext.len() > 3
+		|| (name.contains('.') && ext.is_empty())
+		|| !base.bytes().chain(ext.bytes()).all(is_short_char)
+	{
+		return None;
+	}
+
+	let mut short = [b' '; 11];
+	short[..base.len()].copy_from_slice(base.as_bytes());
+	short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+	if short[0] == ENTRY_FREE {
+		short[0] = 0x05;
+	}
+	Some(short)
+}
+
+/// Generates a unique short name for a long file name (e.g. `LONGFI~1.TXT`).
+fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> Result<[u8; 11], FileError> {
+	let convert = |s: &str| -> Vec<u8> {
+		s.chars()
+			.filter(|c| *c != ' ' && *c != '.')
+			.map(|c| {
+				let c = c.to_ascii_uppercase();
+				if c.is_ascii() && is_short_char(c as u8) {
+					c as u8
+				} else {
+					b'_'
+				}
+			})
+			.collect()
+	};
+
+	let name = name.trim_start_matches('.');
+	let (base, ext) = match name.rsplit_once('.') {
+		Some((base, ext)) => (convert(base), convert(ext)),
+		None => (convert(name), Vec::new()),
+	};
+	let base = if base.is_empty() { vec![b'_'] } else { base };
+
+	let mut short = [b' '; 11];
+	let ext_len = min(ext.len(), 3);
+	short[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+
+	for n in 1..1_000_000 {
+		let tail = format!("~{n}");
+		let base_len = min(base.len(), 8 - tail.len());
+		short[..8].fill(b' ');
+		short[..base_len].copy_from_slice(&base[..base_len]);
+		short[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+
+		if !existing.contains(&short) {
+			return Ok(short);
+		}
+	}
+
+	Err(FileError::ENOSPC)
+}
+
+/// Checks, if `name` can be stored as a long file name.
+fn validate_name(name: &str) -> Result<(), FileError> {
+	if name.is_empty()
+		|| name == "."
+		|| name == ".."
+		|| name.encode_utf16().count() > MAX_NAME_LEN
+		|| name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+	{
+		return Err(FileError::EINVAL);
+	}
+
+	Ok(())
+}
+
+/// Raw short directory entry
+#[derive(Debug, Copy, Clone)]
+struct RawEntry([u8; DIR_ENTRY_SIZE]);
+
+impl RawEntry {
+	fn new(name: [u8; 11], attr: u8, cluster: u32) -> Self {
+		let mut entry = Self([0; DIR_ENTRY_SIZE]);
+		let (date, time) = timestamp();
+		entry.0[..11].copy_from_slice(&name);
+		entry.0[11] = attr;
+		entry.0[14..16].copy_from_slice(&time.to_le_bytes());
+		entry.0[16..18].copy_from_slice(&date.to_le_bytes());
+		entry.0[18..20].copy_from_slice(&date.to_le_bytes());
+		entry.0[22..24].copy_from_slice(&time.to_le_bytes());
+		entry.0[24..26].copy_from_slice(&date.to_le_bytes());
+		entry.set_first_cluster(cluster);
+		entry
+	}
+
+	fn name(&self) -> [u8; 11] {
+		self.0[..11].try_into().unwrap()
+	}
+
+	fn attr(&self) -> u8 {
+		self.0[11]
+	}
+
+	fn is_dir(&self) -> bool {
+		self.attr() & ATTR_DIRECTORY != 0
+	}
+
+	fn first_cluster(&self) -> u32 {
+		let hi = u16::from_le_bytes(self.0[20..22].try_into().unwrap());
+		let lo = u16::from_le_bytes(self.0[26..28].try_into().unwrap());
+		u32::from(hi) << 16 | u32::from(lo)
+	}
+
+	fn set_first_cluster(&mut self, cluster: u32) {
+		self.0[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+		self.0[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+	}
+
+	fn size(&self) -> u32 {
+		u32::from_le_bytes(self.0[28..32].try_into().unwrap())
+	}
+
+	fn set_size(&mut self, size: u32) {
+		self.0[28..32].copy_from_slice(&size.to_le_bytes());
+	}
+
+	/// Updates the modification time and sets the archive flag.
+	fn touch(&mut self) {
+		let (date, time) = timestamp();
+		self.0[11] |= ATTR_ARCHIVE;
+		self.0[18..20].copy_from_slice(&date.to_le_bytes());
+		self.0[22..24].copy_from_slice(&time.to_le_bytes());
+		self.0[24..26].copy_from_slice(&date.to_le_bytes());
+	}
+
+	/// Returns the short name in the form `NAME.EXT`.
+	fn short_name(&self) -> String {
+		let ntres = self.0[12];
+		let convert = |bytes: &[u8], lower: bool| -> String {
+			bytes
+				.iter()
+				.map(|c| {
+					let c = char::from(*c);
+					if lower {
+						c.to_ascii_lowercase()
+					} else {
+						c
+					}
+				})
+				.collect::<String>()
+				.trim_end()
+				.into()
+		};
+
+		let mut name = self.name();
+		if name[0] == 0x05 {
+			name[0] = ENTRY_FREE;
+		}
+		let base = convert(&name[..8], ntres & NTRES_LOWER_BASE != 0);
+		let ext = convert(&name[8..], ntres & NTRES_LOWER_EXT != 0);
+		if ext.is_empty() {
+			base
+		} else {
+			format!("{base}.{ext}")
+		}
+	}
+}
+
+/// A directory entry together with its location on the disk
+#[derive(Debug, Clone)]
+struct Entry {
+	/// Long name or, if the entry has no long name, the short name
+	name: String,
+	raw: RawEntry,
+	/// Byte offset of the short entry
+	offset: u64,
+	/// Byte offsets of all slots of the entry including the long name entries
+	slots: Vec<u64>,
+}
+
+/// Partially parsed long file name
+struct LongName {
+	chars: Vec<u16>,
+	checksum: u8,
+	/// Ordinal of the next expected long name entry
+	next: u8,
+	slots: Vec<u64>,
+}
+
+struct Volume {
+	disk: Disk,
+	sector_size: u64,
+	cluster_size: u64,
+	/// Byte offset of the FAT, which is used for reading
+	fat_start: u64,
+	/// Byte offsets of all FATs, which have to be updated
+	fats: Vec<u64>,
+	/// Byte offset of the first data cluster
+	data_start: u64,
+	/// Number of data clusters
+	clusters: u32,
+	root: u32,
+	/// Hint, where to start searching for free clusters
+	next_free: u32,
+	/// Most recently used sector of the FAT (sector index, content)
+	fat_cache: Option<(u64, Vec<u8>)>,
+}
+
+impl Volume {
+	fn mount(disk: Disk) -> Result<Self, FileError> {
+		let mut bs = [0u8; 512];
+		disk.read_at(0, &mut bs)?;
+
+		let u16_at = |offset: usize| u16::from_le_bytes(bs[offset..offset + 2].try_into().unwrap());
+		let u32_at = |offset: usize| u32::from_le_bytes(bs[offset..offset + 4].try_into().unwrap());
+
+		let sector_size = u64::from(u16_at(11));
+		let sectors_per_cluster = u64::from(bs[13]);
+		let reserved = u64::from(u16_at(14));
+		let num_fats = u64::from(bs[16]);
+		let root_entries = u16_at(17);
+		let fat_size16 = u16_at(22);
+		let total_sectors = match u16_at(19) {
+			0 => u64::from(u32_at(32)),
+			n => u64::from(n),
+		};
+		let fat_size = u64::from(u32_at(36));
+		let ext_flags = u16_at(40);
+		let root = u32_at(44);
+		let fs_info = u64::from(u16_at(48));
+
+		if bs[510..512] != [0x55, 0xaa]
+			|| ![512, 1024, 2048, 4096].contains(&sector_size)
+			|| !sectors_per_cluster.is_power_of_two()
+			|| num_fats == 0
+			|| reserved == 0
+		{
+			error!("No FAT file system found");
+			return Err(FileError::EINVAL);
+		}
+		if fat_size16 != 0 || root_entries != 0 || fat_size == 0 {
+			error!("Only FAT32 file systems are supported");
+			return Err(FileError::EINVAL);
+		}
+		if total_sectors * sector_size > disk.size() {
+			error!("FAT file system is larger than the disk");
+			return Err(FileError::EINVAL);
+		}
+
+		let data_sectors = total_sectors
+			.checked_sub(reserved + num_fats * fat_size)
+			.ok_or(FileError::EINVAL)?;
+		// the FAT has to be large enough for all clusters
+		let clusters = min(
+			data_sectors / sectors_per_cluster,
+			fat_size * sector_size / 4 - u64::from(FIRST_CLUSTER),
+		) as u32;
+
+		let fats = (0..num_fats)
+			.map(|i| (reserved + i * fat_size) * sector_size)
+			.collect::<Vec<_>>();
+		// bit 7 of ext_flags disables the mirroring of the FATs
+		let fats = if ext_flags & 0x80 != 0 {
+			let active = usize::from(ext_flags & 0xf);
+			vec![*fats.get(active).ok_or(FileError::EINVAL)?]
+		} else {
+			fats
+		};
+
+		let mut volume = Self {
+			disk,
+			sector_size,
+			cluster_size: sectors_per_cluster * sector_size,
+			fat_start: fats[0],
+			fats,
+			data_start: (reserved + num_fats * fat_size) * sector_size,
+			clusters,
+			root,
+			next_free: FIRST_CLUSTER,
+			fat_cache: None,
+		};
+
+		if !volume.is_valid_cluster(root) {
+			error!("Invalid root directory cluster {}", root);
+			return Err(FileError::EINVAL);
+		}
+		if fs_info != 0 && fs_info < reserved {
+			volume.read_fs_info(fs_info * sector_size)?;
+		}
+
+		Ok(volume)
+	}
+
+	/// Reads the hint for the next free cluster from the FSInfo sector.
+	///
+	/// The free cluster count is not maintained, so it is invalidated on writable
+	/// volumes. Other implementations will recompute it.
+	fn read_fs_info(&mut self, offset: u64) -> Result<(), FileError> {
+		let mut sector = [0u8; 512];
+		self.disk.read_at(offset, &mut sector)?;
+
+		let u32_at =
+			|offset: usize| u32::from_le_bytes(sector[offset..offset + 4].try_into().unwrap());
+		if u32_at(0) != FSINFO_LEAD_SIG || u32_at(484) != FSINFO_STRUC_SIG {
+			warn!("Invalid FSInfo sector");
+			return Ok(());
+		}
+
+		let next_free = u32_at(FSINFO_NEXT_FREE);
+		if self.is_valid_cluster(next_free) {
+			self.next_free = next_free;
+		}
+
+		if !self.disk.is_read_only() {
+			self.disk
+				.write_at(offset + FSINFO_FREE_COUNT as u64, &u32::MAX.to_le_bytes())?;
+		}
+
+		Ok(())
+	}
+
+	fn is_valid_cluster(&self, cluster: u32) -> bool {
+		(FIRST_CLUSTER..self.clusters + FIRST_CLUSTER).contains(&cluster)
+	}
+
+	/// Returns the byte offset of a data cluster.
+	fn cluster_offset(&self, cluster: u32) -> u64 {
+		self.data_start + u64::from(cluster - FIRST_CLUSTER) * self.cluster_size
+	}
+
+	/// Loads the FAT sector containing the entry of `cluster` into the cache
+	/// and returns the offset of the entry within the cached sector.
+	fn load_fat_sector(&mut self, cluster: u32) -> Result<usize, FileError> {
+		let offset = u64::from(cluster) * 4;
+		let sector = offset / self.sector_size;
+
+		if !matches!(self.fat_cache, Some((cached, _)) if cached == sector) {
+			let mut buf = vec![0u8; self.sector_size as usize];
+			self.disk
+				.read_at(self.fat_start + sector * self.sector_size, &mut buf)?;
+			self.fat_cache = Some((sector, buf));
+		}
+
+		Ok((offset % self.sector_size) as usize)
+	}
+
+	fn fat_entry(&mut self, cluster: u32) -> Result<u32, FileError> {
+		let index = self.load_fat_sector(cluster)?;
+		let (_, buf) = self.fat_cache.as_ref().unwrap();
+		Ok(u32::from_le_bytes(buf[index..index + 4].try_into().unwrap()) & CLUSTER_MASK)
+	}
+
+	fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), FileError> {
+		let index = self.load_fat_sector(cluster)?;
+		let (sector, buf) = self.fat_cache.as_mut().unwrap();
+
+		// the upper 4 bits are reserved and have to be preserved
+		let old = u32::from_le_bytes(buf[index..index + 4].try_into().unwrap());
+		let new = (old & !CLUSTER_MASK) | (value & CLUSTER_MASK);
+		buf[index..index + 4].copy_from_slice(&new.to_le_bytes());
+
+		let offset = *sector * self.sector_size;
+		for fat in self.fats.iter() {
+			self.disk.write_at(fat + offset, buf)?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns all clusters of the chain starting at `first`.
+	fn chain(&mut self, first: u32) -> Result<Vec<u32>, FileError> {
+		let mut chain = Vec::new();
+		let mut cluster = first;
+
+		while cluster != CLUSTER_FREE {
+			if !self.is_valid_cluster(cluster) || chain.len() >= self.clusters as usize {
+				error!("Corrupted cluster chain starting at cluster {}", first);
+				return Err(FileError::EIO);
+			}
+
+			chain.push(cluster);
+			cluster = match self.fat_entry(cluster)? {
+				next if next >= CLUSTER_EOC => CLUSTER_FREE,
+				next => next,
+			};
+		}
+
+		Ok(chain)
+	}
+
+	/// Allocates a free cluster and appends it to the chain ending with `prev`.
+	fn alloc_cluster(&mut self, prev: Option<u32>) -> Result<u32, FileError> {
+		for i in 0..self.clusters {
+			let cluster = (self.next_free - FIRST_CLUSTER + i) % self.clusters + FIRST_CLUSTER;
+			if self.fat_entry(cluster)? == CLUSTER_FREE {
+				self.set_fat_entry(cluster, CLUSTER_MASK)?;
+				if let Some(prev) = prev {
+					self.set_fat_entry(prev, cluster)?;
+				}
+				self.next_free = cluster;
+				return Ok(cluster);
+			}
+		}
+
+		Err(FileError::ENOSPC)
+	}
+
+	/// Releases all clusters of the chain starting at `first`.
+	fn free_chain(&mut self, first: u32) -> Result<(), FileError> {
+		for cluster in self.chain(first)? {
+			self.set_fat_entry(cluster, CLUSTER_FREE)?;
+		}
+
+		Ok(())
+	}
+
+	fn zero_cluster(&mut self, cluster: u32) -> Result<(), FileError> {
+		let zeros = vec![0u8; self.cluster_size as usize];
+		self.disk.write_at(self.cluster_offset(cluster), &zeros)
+	}
+
+	/// Splits `len` bytes at position `pos` of the cluster chain `clusters` into
+	/// contiguous regions on the disk (disk offset, length).
+	fn regions(&self, clusters: &[u32], pos: u64, len: usize) -> Vec<(u64, usize)> {
+		let mut regions: Vec<(u64, usize)> = Vec::new();
+		let mut done = 0;
+
+		while done < len {
+			let current = pos + done as u64;
+			let cluster = clusters[(current / self.cluster_size) as usize];
+			let within = current % self.cluster_size;
+			let n = min(len - done, (self.cluster_size - within) as usize);
+			let offset = self.cluster_offset(cluster) + within;
+
+			match regions.last_mut() {
+				Some((last, last_len)) if *last + *last_len as u64 == offset => *last_len += n,
+				_ => regions.push((offset, n)),
+			}
+			done += n;
+		}
+
+		regions
+	}
+
+	/// Returns the first cluster of a directory. Entries pointing to cluster 0 refer to the root directory.
+	fn dir_cluster(&self, entry: &RawEntry) -> u32 {
+		match entry.first_cluster() {
+			CLUSTER_FREE => self.root,
+			cluster => cluster,
+		}
+	}
+
+	/// Reads all entries of the directory starting at cluster `dir`.
+	/// Volume labels are skipped.
+	fn read_dir(&mut self, dir: u32) -> Result<Vec<Entry>, FileError> {
+		let mut entries = Vec::new();
+		let mut long_name: Option<LongName> = None;
+		let mut buf = vec![0u8; self.cluster_size as usize];
+
+		for cluster in self.chain(dir)? {
+			let base = self.cluster_offset(cluster);
+			self.disk.read_at(base, &mut buf)?;
+
+			for (i, slot) in buf.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+				let offset = base + (i * DIR_ENTRY_SIZE) as u64;
+				let attr = slot[11];
+
+				if slot[0] == ENTRY_END {
+					return Ok(entries);
+				} else if slot[0] == ENTRY_FREE {
+					long_name = None;
+				} else if attr & 0x3f == ATTR_LONG_NAME {
+					let ord = slot[0] & !LAST_LONG_ENTRY;
+					let chars = LFN_OFFSETS
+						.iter()
+						.map(|off| u16::from_le_bytes([slot[*off], slot[*off + 1]]));
+
+					if slot[0] & LAST_LONG_ENTRY != 0 && (1..=20).contains(&ord) {
+						let mut name = LongName {
+							chars: vec![0xffff; usize::from(ord) * LFN_CHARS],
+							checksum: slot[13],
+							next: ord - 1,
+							slots: vec![offset],
+						};
+						let start = usize::from(ord - 1) * LFN_CHARS;
+						for (dst, c) in name.chars[start..].iter_mut().zip(chars) {
+							*dst = c;
+						}
+						long_name = Some(name);
+					} else if let Some(name) = long_name
+						.as_mut()
+						.filter(|name| name.next == ord && ord > 0 && name.checksum == slot[13])
+					{
+						let start = usize::from(ord - 1) * LFN_CHARS;
+						for (dst, c) in name.chars[start..start + LFN_CHARS].iter_mut().zip(chars) {
+							*dst = c;
+						}
+						name.next -= 1;
+						name.slots.push(offset);
+					} else {
+						long_name = None;
+					}
+				} else if attr & ATTR_VOLUME_ID != 0 {
+					long_name = None;
+				} else {
+					let raw = RawEntry(slot.try_into().unwrap());
+					let mut slots = Vec::new();
+
+					let name = match long_name.take() {
+						Some(name) if name.next == 0 && name.checksum == checksum(&raw.name()) => {
+							slots = name.slots;
+							let len = name
+								.chars
+								.iter()
+								.position(|c| *c == 0)
+								.unwrap_or(name.chars.len());
+							char::decode_utf16(name.chars[..len].iter().copied())
+								.map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+								.collect()
+						}
+						_ => raw.short_name(),
+					};
+					slots.push(offset);
+
+					entries.push(Entry {
+						name,
+						raw,
+						offset,
+						slots,
+					});
+				}
+			}
+		}
+
+		Ok(entries)
+	}
+
+	/// Searches the directory starting at cluster `dir` for `name`.
+	fn find(&mut self, dir: u32, name: &str) -> Result<Option<Entry>, FileError> {
+		Ok(self.read_dir(dir)?.into_iter().find(|entry| {
+			names_equal(&entry.name, name) || names_equal(&entry.raw.short_name(), name)
+		}))
+	}
+
+	/// Resolves `path` relative to the root directory.
+	/// Returns `None`, if the path refers to the root directory itself.
+	fn walk(&mut self, path: &str) -> Result<Option<Entry>, FileError> {
+		let mut current: Option<Entry> = None;
+
+		for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+			let dir = match &current {
+				Some(entry) if entry.raw.is_dir() => self.dir_cluster(&entry.raw),
+				Some(_) => return Err(FileError::ENOTDIR),
+				None => self.root,
+			};
+
+			current = if component == ".." && dir == self.root {
+				None
+			} else {
+				match self.find(dir, component)? {
+					// `..` entries pointing to the root directory use cluster 0
+					Some(entry)
+						if component == ".." && entry.raw.first_cluster() == CLUSTER_FREE =>
+					{
+						None
+					}
+					Some(entry) => Some(entry),
+					None => return Err(FileError::ENOENT),
+				}
+			};
+		}
+
+		Ok(current)
+	}
+
+	/// Splits `path` into the first cluster of the parent directory and the name of the last component.
+	fn parent<'a>(&mut self, path: &'a str) -> Result<(u32, &'a str), FileError> {
+		let path = path.trim_end_matches('/');
+		let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+
+		let dir = match self.walk(parent)? {
+			Some(entry) if entry.raw.is_dir() => self.dir_cluster(&entry.raw),
+			Some(_) => return Err(FileError::ENOTDIR),
+			None => self.root,
+		};
+
+		Ok((dir, name))
+	}
+
+	/// Creates a new entry named `name` in the directory starting at cluster `dir`.
+	fn add_entry(
+		&mut self,
+		dir: u32,
+		name: &str,
+		attr: u8,
+		cluster: u32,
+	) -> Result<Entry, FileError> {
+		validate_name(name)?;
+
+		let existing = self.read_dir(dir)?;
+		let (short, long_name) = match short_name(name) {
+			Some(short) => (short, None),
+			None => {
+				let shorts = existing.iter().map(|e| e.raw.name()).collect::<Vec<_>>();
+				let short = generate_short_name(name, &shorts)?;
+				(short, Some(name.encode_utf16().collect::<Vec<_>>()))
+			}
+		};
+		let long_slots = long_name
+			.as_ref()
+			.map_or(0, |chars| (chars.len() + LFN_CHARS - 1) / LFN_CHARS);
+
+		let slots = self.find_free_slots(dir, long_slots + 1)?;
+
+		// long name entries are stored in reverse order in front of the short entry
+		if let Some(chars) = long_name {
+			let sum = checksum(&short);
+			for (i, offset) in slots[..long_slots].iter().enumerate() {
+				let ord = (long_slots - i) as u8;
+				let mut slot = [0u8; DIR_ENTRY_SIZE];
+				slot[0] = if i == 0 { ord | LAST_LONG_ENTRY } else { ord };
+				slot[11] = ATTR_LONG_NAME;
+				slot[13] = sum;
+
+				let start = usize::from(ord - 1) * LFN_CHARS;
+				for (j, off) in LFN_OFFSETS.iter().enumerate() {
+					let c = match chars.get(start + j) {
+						Some(c) => *c,
+						// the name is terminated by 0 and padded with 0xffff
+						None if start + j == chars.len() => 0,
+						None => 0xffff,
+					};
+					slot[*off..*off + 2].copy_from_slice(&c.to_le_bytes());
+				}
+
+				self.disk.write_at(*offset, &slot)?;
+			}
+		}
+
+		let raw = RawEntry::new(short, attr, cluster);
+		let offset = slots[long_slots];
+		self.disk.write_at(offset, &raw.0)?;
+
+		Ok(Entry {
+			name: name.into(),
+			raw,
+			offset,
+			slots,
+		})
+	}
+
+	/// Returns the offsets of `count` consecutive free slots in the directory starting at cluster `dir`.
+	/// The directory is extended, if it has not enough free slots.
+	fn find_free_slots(&mut self, dir: u32, count: usize) -> Result<Vec<u64>, FileError> {
+		let clusters = self.chain(dir)?;
+		let slots_per_cluster = self.cluster_size as usize / DIR_ENTRY_SIZE;
+		let mut free = Vec::new();
+		let mut buf = vec![0u8; self.cluster_size as usize];
+
+		for cluster in clusters.iter() {
+			let base = self.cluster_offset(*cluster);
+			self.disk.read_at(base, &mut buf)?;
+
+			for (i, slot) in buf.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+				if slot[0] == ENTRY_FREE || slot[0] == ENTRY_END {
+					free.push(base + (i * DIR_ENTRY_SIZE) as u64);
+					if free.len() == count {
+						return Ok(free);
+					}
+				} else {
+					free.clear();
+				}
+			}
+		}
+
+		let mut last = *clusters.last().unwrap();
+		let mut total = clusters.len() * slots_per_cluster;
+		while free.len() < count {
+			total += slots_per_cluster;
+			if total > MAX_DIR_ENTRIES {
+				return Err(FileError::ENOSPC);
+			}
+
+			last = self.alloc_cluster(Some(last))?;
+			self.zero_cluster(last)?;
+			let base = self.cluster_offset(last);
+			free.extend(
+				(0..slots_per_cluster)
+					.map(|i| base + (i * DIR_ENTRY_SIZE) as u64)
+					.take(count - free.len()),
+			);
+		}
+
+		Ok(free)
+	}
+
+	/// Marks all slots of an entry as free.
+	fn remove_entry(&mut self, entry: &Entry) -> Result<(), FileError> {
+		for slot in entry.slots.iter() {
+			self.disk.write_at(*slot, &[ENTRY_FREE])?;
+		}
+
+		Ok(())
+	}
+
+	fn read_entry(&self, offset: u64) -> Result<RawEntry, FileError> {
+		let mut raw = RawEntry([0; DIR_ENTRY_SIZE]);
+		self.disk.read_at(offset, &mut raw.0)?;
+		Ok(raw)
+	}
+
+	fn write_entry(&self, offset: u64, raw: &RawEntry) -> Result<(), FileError> {
+		self.disk.write_at(offset, &raw.0)
+	}
+
+	/// Reads up to `len` bytes at position `pos` of the file, whose short entry is located at `entry`.
+	fn read_file(&mut self, entry: u64, pos: u64, len: usize) -> Result<Vec<u8>, FileError> {
+		let raw = self.read_entry(entry)?;
+		let size = u64::from(raw.size());
+		if pos >= size {
+			return Ok(Vec::new());
+		}
+
+		let len = min(len as u64, size - pos) as usize;
+		let clusters = self.chain(raw.first_cluster())?;
+		if (clusters.len() as u64) * self.cluster_size < size {
+			error!("File size exceeds its cluster chain");
+			return Err(FileError::EIO);
+		}
+
+		let mut data = vec![0u8; len];
+		let mut done = 0;
+		for (offset, n) in self.regions(&clusters, pos, len) {
+			self.disk.read_at(offset, &mut data[done..done + n])?;
+			done += n;
+		}
+
+		Ok(data)
+	}
+
+	/// Writes `buf` at position `pos` of the file, whose short entry is located at `entry`.
+	/// Gaps behind the current end of the file are filled with zeros.
+	fn write_file(&mut self, entry: u64, pos: u64, buf: &[u8]) -> Result<(), FileError> {
+		let end = pos
+			.checked_add(buf.len() as u64)
+			.filter(|end| *end <= u64::from(u32::MAX))
+			.ok_or(FileError::ENOSPC)?;
+		let mut raw = self.read_entry(entry)?;
+		let size = u64::from(raw.size());
+
+		let mut clusters = self.chain(raw.first_cluster())?;
+		let needed = ((end + self.cluster_size - 1) / self.cluster_size) as usize;
+		while clusters.len() < needed {
+			match self.alloc_cluster(clusters.last().copied()) {
+				Ok(cluster) => {
+					if clusters.is_empty() {
+						raw.set_first_cluster(cluster);
+					}
+					clusters.push(cluster);
+				}
+				Err(err) => {
+					// keep the already allocated clusters reachable
+					self.write_entry(entry, &raw)?;
+					return Err(err);
+				}
+			}
+		}
+
+		if pos > size {
+			for (offset, n) in self.regions(&clusters, size, (pos - size) as usize) {
+				self.disk.write_at(offset, &vec![0u8; n])?;
+			}
+		}
+
+		let mut done = 0;
+		for (offset, n) in self.regions(&clusters, pos, buf.len()) {
+			self.disk.write_at(offset, &buf[done..done + n])?;
+			done += n;
+		}
+
+		if end > size {
+			raw.set_size(end as u32);
+		}
+		raw.touch();
+		self.write_entry(entry, &raw)
+	}
+
+	/// Truncates the file, whose short entry is located at `entry`, to zero length.
+	fn truncate(&mut self, entry: u64) -> Result<(), FileError> {
+		let mut raw = self.read_entry(entry)?;
+		let first = raw.first_cluster();
+
+		raw.set_first_cluster(CLUSTER_FREE);
+		raw.set_size(0);
+		raw.touch();
+		self.write_entry(entry, &raw)?;
+		self.free_chain(first)
+	}
+}
+
+/// A FAT32 volume on a block device
+pub struct Fat {
+	volume: Arc<TicketMutex<Volume>>,
+}
+
+impl Fat {
+	pub(crate) fn new(disk: Disk) -> Result<Self, FileError> {
+		let volume = Volume::mount(disk)?;
+		info!(
+			"Found FAT32 file system with {} clusters of {} bytes",
+			volume.clusters, volume.cluster_size
+		);
+
+		Ok(Self {
+			volume: Arc::new(TicketMutex::new(volume)),
+		})
+	}
+}
+
+impl PosixFileSystem for Fat {
+	fn open(&self, path: &str, perms: FilePerms) -> Result<Box<dyn PosixFile + Send>, FileError> {
+		let mut volume = self.volume.lock();
+		if (perms.write || perms.creat) && volume.disk.is_read_only() {
+			return Err(FileError::EROFS);
+		}
+
+		let (dir, name) = volume.parent(path)?;
+		if name.is_empty() || name == "." || name == ".." {
+			return Err(FileError::EISDIR);
+		}
+
+		let entry = match volume.find(dir, name)? {
+			Some(_) if perms.creat && perms.excl => return Err(FileError::EEXIST),
+			Some(entry) if entry.raw.is_dir() => return Err(FileError::EISDIR),
+			Some(entry) => {
+				if perms.trunc && perms.write && entry.raw.size() > 0 {
+					volume.truncate(entry.offset)?;
+				}
+				entry
+			}
+			None if perms.creat => volume.add_entry(dir, name, ATTR_ARCHIVE, CLUSTER_FREE)?,
+			None => return Err(FileError::ENOENT),
+		};
+
+		Ok(Box::new(FatFile {
+			volume: self.volume.clone(),
+			entry: entry.offset,
+			offset: 0,
+			write: perms.write,
+			append: perms.append,
+		}))
+	}
+
+	fn unlink(&self, path: &str) -> Result<(), FileError> {
+		let mut volume = self.volume.lock();
+
+		let entry = match volume.walk(path)? {
+			Some(entry) if entry.raw.is_dir() => return Err(FileError::EISDIR),
+			Some(entry) => entry,
+			None => return Err(FileError::EISDIR),
+		};
+
+		volume.remove_entry(&entry)?;
+		volume.free_chain(entry.raw.first_cluster())?;
+		volume.disk.flush()
+	}
+
+	fn mkdir(&self, path: &str, _mode: u32) -> Result<(), FileError> {
+		let mut volume = self.volume.lock();
+		if volume.disk.is_read_only() {
+			return Err(FileError::EROFS);
+		}
+
+		let (dir, name) = volume.parent(path)?;
+		if name.is_empty() || volume.find(dir, name)?.is_some() {
+			return Err(FileError::EEXIST);
+		}
+
+		let cluster = volume.alloc_cluster(None)?;
+		volume.zero_cluster(cluster)?;
+		if let Err(err) = volume.add_entry(dir, name, ATTR_DIRECTORY, cluster) {
+			volume.free_chain(cluster)?;
+			return Err(err);
+		}
+
+		// `..` refers to the root directory by cluster 0
+		let parent = if dir == volume.root {
+			CLUSTER_FREE
+		} else {
+			dir
+		};
+		let dot = RawEntry::new(*b".          ", ATTR_DIRECTORY, cluster);
+		let dotdot = RawEntry::new(*b"..         ", ATTR_DIRECTORY, parent);
+		let offset = volume.cluster_offset(cluster);
+		volume.write_entry(offset, &dot)?;
+		volume.write_entry(offset + DIR_ENTRY_SIZE as u64, &dotdot)?;
+
+		volume.disk.flush()
+	}
+
+	fn readdir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+		let mut volume = self.volume.lock();
+
+		let dir = match volume.walk(path)? {
+			Some(entry) if entry.raw.is_dir() => volume.dir_cluster(&entry.raw),
+			Some(_) => return Err(FileError::ENOTDIR),
+			None => volume.root,
+		};
+
+		Ok(volume
+			.read_dir(dir)?
+			.into_iter()
+			.filter(|entry| entry.name != "." && entry.name != "..")
+			.map(|entry| DirEntry {
+				file_type: if entry.raw.is_dir() {
+					FileType::Directory
+				} else {
+					FileType::File
+				},
+				name: entry.name,
+			})
+			.collect())
+	}
+}
+
+/// An open file on a FAT32 volume
+///
+/// The file is identified by the location of its short directory entry, which
+/// also holds the current size of the file.
+struct FatFile {
+	volume: Arc<TicketMutex<Volume>>,
+	/// Byte offset of the short directory entry
+	entry: u64,
+	offset: u64,
+	write: bool,
+	append: bool,
+}
+
+impl PosixFile for FatFile {
+	fn close(&mut self) -> Result<(), FileError> {
+		if self.write {
+			self.volume.lock().disk.flush()?;
+		}
+
+		Ok(())
+	}
+
+	fn read(&mut self, len: u32) -> Result<Vec<u8>, FileError> {
+		let data = self
+			.volume
+			.lock()
+			.read_file(self.entry, self.offset, len as usize)?;
+		self.offset += data.len() as u64;
+
+		Ok(data)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> Result<u64, FileError> {
+		if !self.write {
+			return Err(FileError::EBADF);
+		}
+
+		let mut volume = self.volume.lock();
+		if self.append {
+			self.offset = u64::from(volume.read_entry(self.entry)?.size());
+		}
+		volume.write_file(self.entry, self.offset, buf)?;
+		self.offset += buf.len() as u64;
+
+		Ok(buf.len() as u64)
+	}
+
+	fn lseek(&mut self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let base = match whence {
+			SeekWhence::Set => 0,
+			SeekWhence::Cur => self.offset as i64,
+			SeekWhence::End => i64::from(self.volume.lock().read_entry(self.entry)?.size()),
+			SeekWhence::Data | SeekWhence::Hole => return Err(FileError::EINVAL),
+		};
+
+		let offset = base
+			.checked_add(offset as i64)
+			.filter(|offset| *offset >= 0)
+			.ok_or(FileError::EINVAL)?;
+		self.offset = offset as u64;
+
+		Ok(offset as usize)
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn short_name_checksum() {
+		assert_eq!(checksum(b"README  TXT"), 0x73);
+		assert_eq!(checksum(b"LONGFI~1TXT"), 0xd4);
+	}
+
+	#[test]
+	fn valid_short_names() {
+		assert_eq!(short_name("README.TXT"), Some(*b"README  TXT"));
+		assert_eq!(short_name("A"), Some(*b"A          "));
+		assert_eq!(short_name("$FOO~1.@"), Some(*b"$FOO~1  @  "));
+
+		assert_eq!(short_name("readme.txt"), None);
+		assert_eq!(short_name("TOOLONGNAME"), None);
+		assert_eq!(short_name("FILE.TEXT"), None);
+		assert_eq!(short_name("FILE."), None);
+		assert_eq!(short_name(".FILE"), None);
+		assert_eq!(short_name("A B"), None);
+	}
+
+	#[test]
+	fn generated_short_names() {
+		let name = generate_short_name("Long File Name.txt", &[]).unwrap();
+		assert_eq!(&name, b"LONGFI~1TXT");
+
+		// Existing names are skipped.
+		let name = generate_short_name("Long File Name.txt", &[*b"LONGFI~1TXT"]).unwrap();
+		assert_eq!(&name, b"LONGFI~2TXT");
+
+		// Leading dots are removed and only the last dot separates the extension.
+		let name = generate_short_name(".bashrc", &[]).unwrap();
+		assert_eq!(&name, b"BASHRC~1   ");
+		let name = generate_short_name("a+b.tar.gzip", &[]).unwrap();
+		assert_eq!(&name, b"A_BTAR~1GZI");
+
+		// The tail grows with the number of existing names.
+		let existing: Vec<[u8; 11]> = (1..10)
+			.map(|n| {
+				let mut short = *b"FOO~1      ";
+				short[4] = b'0' + n;
+				short
+			})
+			.collect();
+		let name = generate_short_name("foo", &existing).unwrap();
+		assert_eq!(&name, b"FOO~10     ");
+	}
+
+	#[test]
+	fn long_names() {
+		assert!(validate_name("Long File Name.txt").is_ok());
+		assert!(validate_name("").is_err());
+		assert!(validate_name(".").is_err());
+		assert!(validate_name("..").is_err());
+		assert!(validate_name("a:b").is_err());
+		assert!(validate_name("a\u{1}b").is_err());
+		assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+	}
+}
@@ -1,7 +1,77 @@
+#[cfg(feature = "pci")]
+mod disk;
+#[cfg(feature = "pci")]
+mod ext4;
+#[cfg(feature = "pci")]
+mod fat;
 #[cfg(all(feature = "pci"))]
 pub mod fuse;
+#[cfg(feature = "pci")]
+pub mod ninep;
+#[cfg(feature = "pci")]
+mod vport;
+
+#[cfg(feature = "pci")]
+use alloc::boxed::Box;
+
+#[cfg(feature = "pci")]
+use crate::env;
+#[cfg(feature = "pci")]
+use crate::syscalls::fs::{self, FileError, PosixFileSystem};
 
 pub fn init() {
 	#[cfg(all(feature = "pci"))]
 	fuse::init();
+	#[cfg(feature = "pci")]
+	ninep::init();
+	#[cfg(feature = "pci")]
+	vport::init();
+	#[cfg(feature = "pci")]
+	mount_disks();
+}
+
+/// Mounts the disk-based file systems, which are given by the -mount command-line parameter.
+#[cfg(feature = "pci")]
+fn mount_disks() {
+	for spec in env::mounts() {
+		let Some((mount_point, source)) = spec.split_once('=') else {
+			error!("Invalid mount specification '{}'", spec);
+			continue;
+		};
+		let (kind, device) = source.split_once(':').unwrap_or((source, "0"));
+
+		match mount_disk(kind, device) {
+			Ok(filesystem) => {
+				info!(
+					"Mounting {} file system on disk {} at /{}",
+					kind, device, mount_point
+				);
+				if fs::FILESYSTEM
+					.lock()
+					.mount(mount_point, filesystem)
+					.is_err()
+				{
+					error!("Unable to mount disk {} at /{}", device, mount_point);
+				}
+			}
+			Err(err) => error!(
+				"Unable to mount {} file system on disk {}: {:?}",
+				kind, device, err
+			),
+		}
+	}
+}
+
+#[cfg(feature = "pci")]
+fn mount_disk(kind: &str, device: &str) -> Result<Box<dyn PosixFileSystem + Send>, FileError> {
+	let disk = disk::Disk::from_spec(device)?;
+
+	match kind {
+		"fat" | "vfat" | "fat32" => Ok(Box::new(fat::Fat::new(disk)?)),
+		"ext2" | "ext3" | "ext4" => Ok(Box::new(ext4::Ext4::new(disk)?)),
+		_ => {
+			error!("Unknown file system type '{}'", kind);
+			Err(FileError::EINVAL)
+		}
+	}
 }
@@ -0,0 +1,751 @@
+//! A 9P2000.L client, which exports the shares of virtio 9p devices as file systems.
+//!
+//! The protocol is described in the Plan 9 manual and the 9P2000.L extensions at
+//! <https://github.com/chaos/diod/blob/master/protocol.md>. Each share is mounted
+//! at its mount tag. Requests are processed synchronously, hence a single tag is
+//! sufficient.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use hermit_sync::InterruptTicketMutex;
+
+use crate::drivers::fs::virtio_9p::Virtio9pDriver;
+use crate::drivers::pci::get_9p_drivers;
+use crate::errno;
+use crate::syscalls::fs::{
+	self, DirEntry, FileError, FilePerms, FileType, PosixFile, PosixFileSystem, SeekWhence,
+};
+
+/// Protocol version negotiated with the server
+const VERSION: &str = "9P2000.L";
+/// Maximum message size requested by the client
+const MAX_MSIZE: u32 = 64 * 1024 + IO_HEADER_SIZE;
+/// Size of the header of Tread and Twrite, which precedes the payload
+const IO_HEADER_SIZE: u32 = 24;
+/// Size of the message header (size[4] type[1] tag[2])
+const HEADER_SIZE: usize = 7;
+/// Maximum number of path elements in a single walk
+const MAX_WELEM: usize = 16;
+
+/// Tag used for all requests except Tversion
+const TAG: u16 = 1;
+const NOTAG: u16 = !0;
+const NOFID: u32 = !0;
+/// Fid of the root directory of the share
+const ROOT_FID: u32 = 0;
+
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TGETATTR: u8 = 24;
+const TREADDIR: u8 = 40;
+const TMKDIR: u8 = 72;
+const TUNLINKAT: u8 = 76;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+
+/// Qid type of directories
+const QTDIR: u8 = 0x80;
+/// Size of a qid (type[1] version[4] path[8])
+const QID_SIZE: usize = 13;
+
+const GETATTR_SIZE: u64 = 0x0000_0200;
+
+/// Open flags, which are passed through to the server (Linux values)
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+const O_DIRECTORY: u32 = 0o200000;
+
+const DT_DIR: u8 = 4;
+const DT_LNK: u8 = 10;
+
+pub trait NinePInterface {
+	/// Sends a 9P request to the server and returns its response, which is
+	/// at most `max_response` bytes long.
+	fn send_request(&mut self, request: &[u8], max_response: usize) -> Result<Vec<u8>, FileError>;
+
+	fn get_mount_point(&self) -> String;
+}
+
+/// Maps a Linux error number of Rlerror to a [FileError].
+fn error_from_errno(ecode: u32) -> FileError {
+	match ecode as i32 {
+		errno::ENOENT => FileError::ENOENT,
+		errno::EBADF => FileError::EBADF,
+		errno::EEXIST => FileError::EEXIST,
+		errno::ENODEV => FileError::ENODEV,
+		errno::ENOTDIR => FileError::ENOTDIR,
+		errno::EISDIR => FileError::EISDIR,
+		errno::EINVAL => FileError::EINVAL,
+		errno::ENOSPC => FileError::ENOSPC,
+		errno::EROFS => FileError::EROFS,
+		errno::ENOSYS => FileError::ENOSYS,
+		errno::ENOTEMPTY => FileError::ENOTEMPTY,
+		errno::ELOOP => FileError::ELOOP,
+		_ => FileError::EIO,
+	}
+}
+
+/// Splits a path into the elements, which are passed to Twalk.
+fn path_elements(path: &str) -> Vec<&str> {
+	path.split('/')
+		.filter(|name| !name.is_empty() && *name != ".")
+		.collect()
+}
+
+/// Splits a path into the elements of its parent directory and the file name.
+fn split_parent(path: &str) -> Result<(Vec<&str>, &str), FileError> {
+	let mut names = path_elements(path);
+	match names.pop() {
+		Some("..") | None => Err(FileError::EINVAL),
+		Some(name) => Ok((names, name)),
+	}
+}
+
+/// A 9P message, which is built by the client.
+struct Request {
+	buf: Vec<u8>,
+}
+
+impl Request {
+	fn new(kind: u8, tag: u16) -> Self {
+		let mut buf = Vec::with_capacity(64);
+		// the size is filled in by `finish`
+		buf.extend_from_slice(&[0u8; 4]);
+		buf.push(kind);
+		buf.extend_from_slice(&tag.to_le_bytes());
+
+		Self { buf }
+	}
+
+	fn u16(mut self, val: u16) -> Self {
+		self.buf.extend_from_slice(&val.to_le_bytes());
+		self
+	}
+
+	fn u32(mut self, val: u32) -> Self {
+		self.buf.extend_from_slice(&val.to_le_bytes());
+		self
+	}
+
+	fn u64(mut self, val: u64) -> Self {
+		self.buf.extend_from_slice(&val.to_le_bytes());
+		self
+	}
+
+	fn str(mut self, val: &str) -> Self {
+		self.buf
+			.extend_from_slice(&(val.len() as u16).to_le_bytes());
+		self.buf.extend_from_slice(val.as_bytes());
+		self
+	}
+
+	fn data(mut self, val: &[u8]) -> Self {
+		self = self.u32(val.len() as u32);
+		self.buf.extend_from_slice(val);
+		self
+	}
+
+	fn finish(mut self) -> Vec<u8> {
+		let size = self.buf.len() as u32;
+		self.buf[..4].copy_from_slice(&size.to_le_bytes());
+		self.buf
+	}
+}
+
+/// Reader for the body of a 9P response.
+struct Response<'a> {
+	buf: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Response<'a> {
+	fn new(buf: &'a [u8]) -> Self {
+		Self { buf, pos: 0 }
+	}
+
+	fn is_empty(&self) -> bool {
+		self.pos >= self.buf.len()
+	}
+
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], FileError> {
+		let bytes = self
+			.buf
+			.get(self.pos..self.pos + len)
+			.ok_or(FileError::EIO)?;
+		self.pos += len;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8, FileError> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, FileError> {
+		Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+	}
+
+	fn u32(&mut self) -> Result<u32, FileError> {
+		Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+	}
+
+	fn u64(&mut self) -> Result<u64, FileError> {
+		Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+	}
+
+	fn str(&mut self) -> Result<&'a str, FileError> {
+		let len = self.u16()?;
+		core::str::from_utf8(self.bytes(len.into())?).map_err(|_| FileError::EIO)
+	}
+
+	/// Returns the type of a qid and skips the rest of it.
+	fn qid(&mut self) -> Result<u8, FileError> {
+		Ok(self.bytes(QID_SIZE)?[0])
+	}
+}
+
+/// Connection to the server behind a 9p device.
+struct Client {
+	/// Index of the device in the list of 9p devices
+	index: usize,
+	/// Negotiated maximum message size
+	msize: u32,
+	next_fid: AtomicU32,
+}
+
+impl Client {
+	fn new(index: usize) -> Self {
+		Self {
+			index,
+			msize: MAX_MSIZE,
+			next_fid: AtomicU32::new(ROOT_FID + 1),
+		}
+	}
+
+	fn device(&self) -> Result<&'static InterruptTicketMutex<Virtio9pDriver>, FileError> {
+		get_9p_drivers()
+			.get(self.index)
+			.copied()
+			.ok_or(FileError::ENODEV)
+	}
+
+	fn alloc_fid(&self) -> u32 {
+		self.next_fid.fetch_add(1, Ordering::Relaxed)
+	}
+
+	/// Sends a request and returns the body of the response, which has
+	/// to be of type `kind + 1`.
+	fn rpc(&self, request: Request) -> Result<Vec<u8>, FileError> {
+		let request = request.finish();
+		let kind = request[4];
+		let mut response = self
+			.device()?
+			.lock()
+			.send_request(&request, self.msize as usize)?;
+
+		if response.len() < HEADER_SIZE {
+			return Err(FileError::EIO);
+		}
+		let size = u32::from_le_bytes(response[..4].try_into().unwrap()) as usize;
+		if size < HEADER_SIZE || size > response.len() {
+			return Err(FileError::EIO);
+		}
+		response.truncate(size);
+
+		match response[4] {
+			RLERROR => {
+				let ecode = Response::new(&response[HEADER_SIZE..]).u32()?;
+				Err(error_from_errno(ecode))
+			}
+			rkind if rkind == kind + 1 => Ok(response.split_off(HEADER_SIZE)),
+			rkind => {
+				error!("Unexpected 9P response {} to request {}", rkind, kind);
+				Err(FileError::EIO)
+			}
+		}
+	}
+
+	/// Negotiates the protocol version and the message size.
+	fn version(&mut self) -> Result<(), FileError> {
+		let body = self.rpc(Request::new(TVERSION, NOTAG).u32(MAX_MSIZE).str(VERSION))?;
+		let mut rsp = Response::new(&body);
+		let msize = rsp.u32()?;
+		let version = rsp.str()?;
+
+		if version != VERSION {
+			error!("9P server does not support {}, but {}", VERSION, version);
+			return Err(FileError::ENOSYS);
+		}
+		if msize <= IO_HEADER_SIZE {
+			return Err(FileError::EIO);
+		}
+		self.msize = min(msize, MAX_MSIZE);
+
+		Ok(())
+	}
+
+	fn attach(&self, fid: u32) -> Result<(), FileError> {
+		self.rpc(
+			Request::new(TATTACH, TAG)
+				.u32(fid)
+				.u32(NOFID)
+				.str("")
+				.str("")
+				.u32(0),
+		)?;
+		Ok(())
+	}
+
+	/// Walks from `fid` along `names` and returns a new fid for the result
+	/// together with its qid type.
+	fn walk(&self, fid: u32, names: &[&str]) -> Result<(u32, u8), FileError> {
+		let newfid = self.alloc_fid();
+		let mut from = fid;
+		let mut qtype = QTDIR;
+
+		// an empty walk clones the fid
+		let mut chunks: Vec<&[&str]> = names.chunks(MAX_WELEM).collect();
+		if chunks.is_empty() {
+			chunks.push(&[]);
+		}
+
+		for chunk in chunks {
+			let mut request = Request::new(TWALK, TAG)
+				.u32(from)
+				.u32(newfid)
+				.u16(chunk.len() as u16);
+			for name in chunk {
+				request = request.str(name);
+			}
+
+			let result = self.rpc(request).and_then(|body| {
+				let mut rsp = Response::new(&body);
+				let nwqid = rsp.u16()?;
+				for _ in 0..nwqid {
+					qtype = rsp.qid()?;
+				}
+
+				// a partial walk means that an element does not exist
+				if usize::from(nwqid) == chunk.len() {
+					Ok(())
+				} else {
+					Err(FileError::ENOENT)
+				}
+			});
+
+			if let Err(err) = result {
+				// the new fid is only established by a complete walk
+				if from == newfid {
+					let _ = self.clunk(newfid);
+				}
+				return Err(err);
+			}
+			from = newfid;
+		}
+
+		Ok((newfid, qtype))
+	}
+
+	fn lopen(&self, fid: u32, flags: u32) -> Result<(), FileError> {
+		self.rpc(Request::new(TLOPEN, TAG).u32(fid).u32(flags))?;
+		Ok(())
+	}
+
+	/// Creates the file `name` in the directory `fid`, which then refers to the new file.
+	fn lcreate(&self, fid: u32, name: &str, flags: u32, mode: u32) -> Result<(), FileError> {
+		self.rpc(
+			Request::new(TLCREATE, TAG)
+				.u32(fid)
+				.str(name)
+				.u32(flags)
+				.u32(mode)
+				.u32(0),
+		)?;
+		Ok(())
+	}
+
+	fn read(&self, fid: u32, offset: u64, count: u32) -> Result<Vec<u8>, FileError> {
+		let count = min(count, self.msize - IO_HEADER_SIZE);
+		let body = self.rpc(Request::new(TREAD, TAG).u32(fid).u64(offset).u32(count))?;
+		let mut rsp = Response::new(&body);
+		let len = rsp.u32()?;
+
+		Ok(rsp.bytes(len as usize)?.to_vec())
+	}
+
+	fn write(&self, fid: u32, offset: u64, data: &[u8]) -> Result<u32, FileError> {
+		let len = min(data.len(), (self.msize - IO_HEADER_SIZE) as usize);
+		let body = self.rpc(
+			Request::new(TWRITE, TAG)
+				.u32(fid)
+				.u64(offset)
+				.data(&data[..len]),
+		)?;
+
+		Response::new(&body).u32()
+	}
+
+	fn clunk(&self, fid: u32) -> Result<(), FileError> {
+		self.rpc(Request::new(TCLUNK, TAG).u32(fid))?;
+		Ok(())
+	}
+
+	/// Returns the size of the file `fid`.
+	fn size(&self, fid: u32) -> Result<u64, FileError> {
+		let body = self.rpc(Request::new(TGETATTR, TAG).u32(fid).u64(GETATTR_SIZE))?;
+		let mut rsp = Response::new(&body);
+		// skip valid, qid, mode, uid, gid, nlink and rdev
+		rsp.bytes(8 + QID_SIZE + 4 + 4 + 4 + 8 + 8)?;
+
+		rsp.u64()
+	}
+
+	fn mkdir(&self, dfid: u32, name: &str, mode: u32) -> Result<(), FileError> {
+		self.rpc(
+			Request::new(TMKDIR, TAG)
+				.u32(dfid)
+				.str(name)
+				.u32(mode)
+				.u32(0),
+		)?;
+		Ok(())
+	}
+
+	fn unlinkat(&self, dfid: u32, name: &str, flags: u32) -> Result<(), FileError> {
+		self.rpc(Request::new(TUNLINKAT, TAG).u32(dfid).str(name).u32(flags))?;
+		Ok(())
+	}
+
+	/// Reads the entries of the opened directory `fid`.
+	fn readdir(&self, fid: u32) -> Result<Vec<DirEntry>, FileError> {
+		let mut entries = Vec::new();
+		let mut offset = 0;
+
+		loop {
+			let body = self.rpc(
+				Request::new(TREADDIR, TAG)
+					.u32(fid)
+					.u64(offset)
+					.u32(self.msize - IO_HEADER_SIZE),
+			)?;
+			let mut rsp = Response::new(&body);
+			let count = rsp.u32()? as usize;
+			let mut rsp = Response::new(rsp.bytes(count)?);
+			if rsp.is_empty() {
+				return Ok(entries);
+			}
+
+			while !rsp.is_empty() {
+				rsp.qid()?;
+				offset = rsp.u64()?;
+				let kind = rsp.u8()?;
+				let name = rsp.str()?;
+				if name == "." || name == ".." {
+					continue;
+				}
+
+				let file_type = match kind {
+					DT_DIR => FileType::Directory,
+					DT_LNK => FileType::Symlink,
+					_ => FileType::File,
+				};
+				entries.push(DirEntry {
+					name: name.into(),
+					file_type,
+				});
+			}
+		}
+	}
+
+	/// Walks to the parent directory of `path` and returns its fid together with the file name.
+	fn walk_parent<'a>(&self, path: &'a str) -> Result<(u32, &'a str), FileError> {
+		let (names, name) = split_parent(path)?;
+		let (fid, qtype) = self.walk(ROOT_FID, &names)?;
+		if qtype & QTDIR == 0 {
+			let _ = self.clunk(fid);
+			return Err(FileError::ENOTDIR);
+		}
+
+		Ok((fid, name))
+	}
+}
+
+/// A file system, which is exported by a 9P server.
+pub struct NineP {
+	client: Arc<Client>,
+}
+
+impl NineP {
+	/// Connects to the server behind the 9p device with the given index.
+	fn new(index: usize) -> Result<Self, FileError> {
+		let mut client = Client::new(index);
+		client.version()?;
+		client.attach(ROOT_FID)?;
+
+		Ok(Self {
+			client: Arc::new(client),
+		})
+	}
+
+	/// Opens an existing file, which is referred to by `fid`.
+	fn open_fid(&self, fid: u32, qtype: u8, perms: FilePerms) -> Result<(), FileError> {
+		if perms.creat && perms.excl {
+			return Err(FileError::EEXIST);
+		}
+		if qtype & QTDIR != 0 {
+			return Err(FileError::EISDIR);
+		}
+
+		self.client.lopen(fid, perms.raw & !(O_CREAT | O_EXCL))
+	}
+
+	/// Creates the file `path` and returns the fid of the opened file.
+	fn create(&self, path: &str, perms: FilePerms) -> Result<u32, FileError> {
+		let (fid, name) = self.client.walk_parent(path)?;
+		let mode = if perms.mode == 0 { 0o644 } else { perms.mode };
+
+		match self
+			.client
+			.lcreate(fid, name, perms.raw & !(O_CREAT | O_EXCL), mode)
+		{
+			Ok(()) => Ok(fid),
+			Err(err) => {
+				let _ = self.client.clunk(fid);
+				Err(err)
+			}
+		}
+	}
+}
+
+impl PosixFileSystem for NineP {
+	fn open(&self, path: &str, perms: FilePerms) -> Result<Box<dyn PosixFile + Send>, FileError> {
+		let fid = match self.client.walk(ROOT_FID, &path_elements(path)) {
+			Ok((fid, qtype)) => {
+				if let Err(err) = self.open_fid(fid, qtype, perms) {
+					let _ = self.client.clunk(fid);
+					return Err(err);
+				}
+				fid
+			}
+			Err(FileError::ENOENT) if perms.creat => self.create(path, perms)?,
+			Err(err) => return Err(err),
+		};
+
+		Ok(Box::new(NinePFile {
+			client: self.client.clone(),
+			fid,
+			offset: 0,
+			append: perms.append,
+		}))
+	}
+
+	fn unlink(&self, path: &str) -> Result<(), FileError> {
+		let (fid, name) = self.client.walk_parent(path)?;
+		let result = self.client.unlinkat(fid, name, 0);
+		let _ = self.client.clunk(fid);
+
+		result
+	}
+
+	fn mkdir(&self, path: &str, mode: u32) -> Result<(), FileError> {
+		let (fid, name) = self.client.walk_parent(path)?;
+		let mode = if mode == 0 { 0o755 } else { mode };
+		let result = self.client.mkdir(fid, name, mode);
+		let _ = self.client.clunk(fid);
+
+		result
+	}
+
+	fn readdir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+		let (fid, qtype) = self.client.walk(ROOT_FID, &path_elements(path))?;
+		let result = if qtype & QTDIR == 0 {
+			Err(FileError::ENOTDIR)
+		} else {
+			self.client
+				.lopen(fid, O_DIRECTORY)
+				.and_then(|_| self.client.readdir(fid))
+		};
+		let _ = self.client.clunk(fid);
+
+		result
+	}
+}
+
+struct NinePFile {
+	client: Arc<Client>,
+	fid: u32,
+	offset: u64,
+	append: bool,
+}
+
+impl PosixFile for NinePFile {
+	fn close(&mut self) -> Result<(), FileError> {
+		self.client.clunk(self.fid)
+	}
+
+	fn read(&mut self, len: u32) -> Result<Vec<u8>, FileError> {
+		let mut data = Vec::with_capacity(len as usize);
+
+		while data.len() < len as usize {
+			let chunk = self
+				.client
+				.read(self.fid, self.offset, len - data.len() as u32)?;
+			if chunk.is_empty() {
+				break;
+			}
+			self.offset += chunk.len() as u64;
+			data.extend_from_slice(&chunk);
+		}
+
+		Ok(data)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> Result<u64, FileError> {
+		if self.append {
+			self.offset = self.client.size(self.fid)?;
+		}
+
+		let mut written = 0;
+		while written < buf.len() {
+			let count = self.client.write(self.fid, self.offset, &buf[written..])?;
+			if count == 0 {
+				break;
+			}
+			self.offset += u64::from(count);
+			written += count as usize;
+		}
+
+		Ok(written as u64)
+	}
+
+	fn lseek(&mut self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let base = match whence {
+			SeekWhence::Set => 0,
+			SeekWhence::Cur => self.offset as i64,
+			SeekWhence::End => self.client.size(self.fid)? as i64,
+			SeekWhence::Data | SeekWhence::Hole => return Err(FileError::EINVAL),
+		};
+
+		let offset = base
+			.checked_add(offset as i64)
+			.filter(|offset| *offset >= 0)
+			.ok_or(FileError::EINVAL)?;
+		self.offset = offset as u64;
+
+		Ok(offset as usize)
+	}
+}
+
+/// Mounts the shares of all 9p devices at their mount tags.
+pub fn init() {
+	for (index, driver) in get_9p_drivers().iter().enumerate() {
+		let mount_point = driver.lock().get_mount_point();
+
+		match NineP::new(index) {
+			Ok(ninep) => {
+				info!("Mounting 9p share at /{}", mount_point);
+				if fs::FILESYSTEM
+					.lock()
+					.mount(mount_point.as_str(), Box::new(ninep))
+					.is_err()
+				{
+					error!("Unable to mount 9p share at /{}", mount_point);
+				}
+			}
+			Err(err) => error!("Unable to attach to 9p share {}: {:?}", mount_point, err),
+		}
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn encode_request() {
+		let request = Request::new(TVERSION, NOTAG)
+			.u32(MAX_MSIZE)
+			.str(VERSION)
+			.finish();
+
+		let mut expected = vec![21, 0, 0, 0, TVERSION, 0xff, 0xff];
+		expected.extend_from_slice(&MAX_MSIZE.to_le_bytes());
+		expected.extend_from_slice(&[8, 0]);
+		expected.extend_from_slice(b"9P2000.L");
+		assert_eq!(request, expected);
+
+		let request = Request::new(TWRITE, TAG)
+			.u32(3)
+			.u64(0x0102_0304_0506_0708)
+			.data(b"abc")
+			.finish();
+		assert_eq!(request.len(), HEADER_SIZE + 4 + 8 + 4 + 3);
+		assert_eq!(&request[..4], &(request.len() as u32).to_le_bytes());
+		assert_eq!(&request[4..7], &[TWRITE, 1, 0]);
+		assert_eq!(&request[11..19], &[8, 7, 6, 5, 4, 3, 2, 1]);
+		assert_eq!(&request[19..], &[3, 0, 0, 0, b'a', b'b', b'c']);
+	}
+
+	#[test]
+	fn decode_response() {
+		// body of Rversion
+		let mut body = Vec::new();
+		body.extend_from_slice(&8192u32.to_le_bytes());
+		body.extend_from_slice(&[8, 0]);
+		body.extend_from_slice(b"9P2000.L");
+
+		let mut rsp = Response::new(&body);
+		assert_eq!(rsp.u32().unwrap(), 8192);
+		assert_eq!(rsp.str().unwrap(), VERSION);
+		assert!(rsp.is_empty());
+		assert!(rsp.u8().is_err());
+
+		// entry of Rreaddir: qid[13] offset[8] type[1] name[s]
+		let mut entry = vec![QTDIR];
+		entry.extend_from_slice(&[0; QID_SIZE - 1]);
+		entry.extend_from_slice(&42u64.to_le_bytes());
+		entry.push(DT_DIR);
+		entry.extend_from_slice(&[3, 0]);
+		entry.extend_from_slice(b"dir");
+
+		let mut rsp = Response::new(&entry);
+		assert_eq!(rsp.qid().unwrap(), QTDIR);
+		assert_eq!(rsp.u64().unwrap(), 42);
+		assert_eq!(rsp.u8().unwrap(), DT_DIR);
+		assert_eq!(rsp.str().unwrap(), "dir");
+		assert!(rsp.is_empty());
+	}
+
+	#[test]
+	fn decode_truncated_response() {
+		// The string is longer than the message.
+		let mut rsp = Response::new(&[5, 0, b'a', b'b']);
+		assert!(rsp.str().is_err());
+
+		let mut rsp = Response::new(&[1, 2, 3]);
+		assert!(rsp.u32().is_err());
+		assert_eq!(rsp.u16().unwrap(), 0x0201);
+		assert!(rsp.u16().is_err());
+	}
+
+	#[test]
+	fn paths() {
+		assert_eq!(path_elements("/a//b/./c/"), ["a", "b", "c"]);
+		assert!(path_elements("/").is_empty());
+
+		let (parent, name) = split_parent("/a/b/c").unwrap();
+		assert_eq!(parent, ["a", "b"]);
+		assert_eq!(name, "c");
+		assert!(split_parent("/").is_err());
+		assert!(split_parent("/a/..").is_err());
+	}
+}
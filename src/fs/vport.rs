@@ -0,0 +1,124 @@
+//! Character devices for the named ports of the virtio console.
+//!
+//! Like `/dev/virtio-ports` on Linux, each port, which has been named by the
+//! host, is available as `/virtio-ports/<name>`. Port 0 is not listed, as it
+//! is already used for stdin, stdout and stderr.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::core_scheduler;
+use crate::drivers::console::ConsoleError;
+use crate::drivers::pci::get_console_driver;
+use crate::syscalls::fs::{
+	self, DirEntry, FileError, FilePerms, FileType, PosixFile, PosixFileSystem, SeekWhence,
+};
+
+/// Mount point of the ports
+const MOUNT_POINT: &str = "virtio-ports";
+
+impl From<ConsoleError> for FileError {
+	fn from(err: ConsoleError) -> Self {
+		match err {
+			ConsoleError::NoPort => FileError::ENODEV,
+			ConsoleError::Io => FileError::EIO,
+		}
+	}
+}
+
+/// The directory of all named ports.
+pub struct VirtioPorts;
+
+impl PosixFileSystem for VirtioPorts {
+	fn open(&self, path: &str, _perms: FilePerms) -> Result<Box<dyn PosixFile + Send>, FileError> {
+		let mut console = get_console_driver().ok_or(FileError::ENODEV)?.lock();
+		let port = console.find_port(path).ok_or(FileError::ENOENT)?;
+		console.set_guest_connected(port, true)?;
+
+		Ok(Box::new(PortFile { port }))
+	}
+
+	fn unlink(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS)
+	}
+
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::EROFS)
+	}
+
+	fn readdir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+		if !path.is_empty() {
+			return Err(FileError::ENOENT);
+		}
+
+		let mut console = get_console_driver().ok_or(FileError::ENODEV)?.lock();
+		Ok(console
+			.port_names()
+			.into_iter()
+			.map(|name| DirEntry {
+				name,
+				file_type: FileType::File,
+			})
+			.collect())
+	}
+}
+
+/// An opened port.
+struct PortFile {
+	port: u32,
+}
+
+impl PosixFile for PortFile {
+	fn close(&mut self) -> Result<(), FileError> {
+		let console = get_console_driver().ok_or(FileError::ENODEV)?;
+		console.lock().set_guest_connected(self.port, false)?;
+
+		Ok(())
+	}
+
+	/// Waits until data is available and returns at most `len` bytes.
+	/// Returns no data, if the host side of the port is not connected.
+	fn read(&mut self, len: u32) -> Result<Vec<u8>, FileError> {
+		let console = get_console_driver().ok_or(FileError::ENODEV)?;
+		let mut buf = vec![0u8; len as usize];
+
+		loop {
+			let (ret, connected) = {
+				let mut console = console.lock();
+				let ret = console.read(self.port, &mut buf)?;
+				(ret, console.is_host_connected(self.port))
+			};
+
+			if ret > 0 || len == 0 || !connected {
+				buf.truncate(ret);
+				return Ok(buf);
+			}
+			core_scheduler().reschedule();
+		}
+	}
+
+	fn write(&mut self, buf: &[u8]) -> Result<u64, FileError> {
+		let console = get_console_driver().ok_or(FileError::ENODEV)?;
+		let len = console.lock().write(self.port, buf)?;
+
+		Ok(len as u64)
+	}
+
+	fn lseek(&mut self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::EINVAL)
+	}
+}
+
+/// Mounts the named ports of the virtio console, if available.
+pub fn init() {
+	if get_console_driver().is_some() {
+		info!("Mounting virtio console ports at /{}", MOUNT_POINT);
+		if fs::FILESYSTEM
+			.lock()
+			.mount(MOUNT_POINT, Box::new(VirtioPorts))
+			.is_err()
+		{
+			error!("Unable to mount virtio console ports at /{}", MOUNT_POINT);
+		}
+	}
+}
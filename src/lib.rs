@@ -260,6 +260,8 @@ extern "C" fn initd(_arg: usize) {
 
 	// Initialize Drivers
 	arch::init_drivers();
+	#[cfg(feature = "pci")]
+	crate::drivers::balloon::init();
 	#[cfg(all(feature = "tcp", not(feature = "newlib")))]
 	crate::net::init();
 
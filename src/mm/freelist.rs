@@ -1,5 +1,7 @@
 use alloc::collections::linked_list::LinkedList;
 use core::alloc::AllocError;
+#[cfg(feature = "pci")]
+use core::cmp;
 use core::cmp::Ordering;
 
 use align_address::Align;
@@ -84,6 +86,41 @@ impl FreeList {
 		Err(AllocError)
 	}
 
+	/// Allocates a region like [FreeList::allocate], which starts at or above `min_address`.
+	#[cfg(feature = "pci")]
+	pub fn allocate_above(
+		&mut self,
+		size: usize,
+		alignment: Option<usize>,
+		min_address: usize,
+	) -> Result<usize, AllocError> {
+		let mut cursor = self.list.cursor_front_mut();
+		while let Some(node) = cursor.current() {
+			let (region_start, region_end) = (node.start, node.end);
+			let start = cmp::max(region_start, min_address);
+			let start = alignment.map_or(start, |align| start.align_up(align));
+
+			if start + size <= region_end {
+				if start + size < region_end {
+					node.start = start + size;
+					if start != region_start {
+						cursor.insert_before(FreeListEntry::new(region_start, start));
+					}
+				} else if start != region_start {
+					node.end = start;
+				} else {
+					cursor.remove_current();
+				}
+
+				return Ok(start);
+			}
+
+			cursor.move_next();
+		}
+
+		Err(AllocError)
+	}
+
 	#[cfg(not(feature = "pci"))]
 	pub fn reserve(&mut self, address: usize, size: usize) -> Result<(), AllocError> {
 		trace!(
@@ -183,6 +220,11 @@ impl FreeList {
 		self.list.push_back(new_element);
 	}
 
+	/// Returns the sum of the sizes of all free regions.
+	pub fn free_space(&self) -> usize {
+		self.list.iter().map(|node| node.end - node.start).sum()
+	}
+
 	pub fn print_information(&self, header: &str) {
 		infoheader!(header);
 
@@ -264,4 +306,59 @@ mod tests {
 			cursor.move_next();
 		}
 	}
+
+	#[test]
+	fn free_space() {
+		let mut freelist = FreeList::new();
+		freelist
+			.list
+			.push_back(FreeListEntry::new(0x10000, 0x20000));
+		freelist
+			.list
+			.push_back(FreeListEntry::new(0x30000, 0x100000));
+		assert_eq!(freelist.free_space(), 0xE0000);
+
+		let addr = freelist.allocate(0x1000, None).unwrap();
+		assert_eq!(freelist.free_space(), 0xDF000);
+
+		freelist.deallocate(addr, 0x1000);
+		assert_eq!(freelist.free_space(), 0xE0000);
+	}
+
+	#[cfg(feature = "pci")]
+	#[test]
+	fn allocate_above() {
+		let mut freelist = FreeList::new();
+		freelist
+			.list
+			.push_back(FreeListEntry::new(0x10000, 0x20000));
+		freelist
+			.list
+			.push_back(FreeListEntry::new(0x30000, 0x40000));
+
+		// The region is split around the allocation.
+		let addr = freelist.allocate_above(0x2000, Some(0x2000), 0x13000);
+		assert_eq!(addr.unwrap(), 0x14000);
+		let regions = freelist
+			.list
+			.iter()
+			.map(|node| (node.start, node.end))
+			.collect::<alloc::vec::Vec<_>>();
+		assert_eq!(
+			regions,
+			[(0x10000, 0x14000), (0x16000, 0x20000), (0x30000, 0x40000)]
+		);
+
+		// Regions below the address are skipped.
+		let addr = freelist.allocate_above(0x10000, None, 0x16000);
+		assert_eq!(addr.unwrap(), 0x30000);
+		assert_eq!(freelist.free_space(), 0xE000);
+
+		// The end of a region is allocated.
+		let addr = freelist.allocate_above(0x2000, None, 0x1E000);
+		assert_eq!(addr.unwrap(), 0x1E000);
+		assert_eq!(freelist.list.back().unwrap().end, 0x1E000);
+
+		assert!(freelist.allocate_above(0x1000, None, 0x1E000).is_err());
+	}
 }
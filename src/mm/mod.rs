@@ -226,6 +226,17 @@ pub fn print_information() {
 	arch::mm::virtualmem::print_information();
 }
 
+/// Tries to free `size` bytes of physical memory, after an allocation has failed.
+/// Returns `true`, if memory has been freed.
+pub(crate) fn reclaim_physical_memory(_size: usize) -> bool {
+	#[cfg(feature = "pci")]
+	if crate::drivers::balloon::deflate_on_oom(_size) {
+		return true;
+	}
+
+	false
+}
+
 pub fn allocate(sz: usize, no_execution: bool) -> VirtAddr {
 	let size = sz.align_up(BasePageSize::SIZE as usize);
 	let physical_address = arch::mm::physicalmem::allocate(size).unwrap();
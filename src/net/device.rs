@@ -1,37 +1,155 @@
 use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::slice;
-#[cfg(not(feature = "dhcpv4"))]
 use core::str::FromStr;
 
+use hermit_sync::InterruptTicketMutex;
 use smoltcp::iface::{Config, Interface, SocketSet};
-use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
-#[cfg(feature = "dhcpv4")]
-use smoltcp::socket::dhcpv4;
+use smoltcp::phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Medium};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress};
 #[cfg(not(feature = "dhcpv4"))]
-use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address};
+use smoltcp::wire::Ipv4Address;
+use smoltcp::wire::{
+	ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol, HardwareAddress,
+	Icmpv6Packet, IpAddress, IpCidr, IpProtocol, Ipv6Address, Ipv6Cidr, Ipv6Packet, NdiscRepr,
+};
 
-use crate::arch;
 #[cfg(not(feature = "pci"))]
 use crate::arch::kernel::mmio as hardware;
+use crate::drivers::net::checksum::fill_checksum;
+use crate::drivers::net::NetworkInterface as NetworkDriver;
 #[cfg(feature = "pci")]
 use crate::drivers::pci as hardware;
-#[cfg(not(feature = "dhcpv4"))]
-use crate::env;
-use crate::net::{NetworkInterface, NetworkState};
+use crate::net::slaac::eui64_addr;
+use crate::net::{Netif, NetworkInterface, NetworkState};
+use crate::{arch, env};
+
+/// MTU of the loopback device, if no network device is available
+const LOOPBACK_MTU: u16 = 65535;
+/// Locally administered MAC address of the loopback device, if no network device is available
+const LOOPBACK_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x00];
 
 /// Data type to determine the mac address
-#[derive(Debug, Copy, Clone)]
+///
+/// Besides the network device, it provides a loopback device, which delivers
+/// the packets addressed to the local host.
 #[repr(C)]
 pub(crate) struct HermitNet {
 	pub mtu: u16,
+	/// Checksums, which are not offloaded to the device
+	pub checksums: ChecksumCapabilities,
+	/// MAC address of the interface
+	mac: EthernetAddress,
+	/// Network device, which transmits the frames. Without a device,
+	/// all frames are delivered by the loopback device.
+	driver: Option<&'static InterruptTicketMutex<dyn NetworkDriver>>,
+	/// Frames, which have been sent to the loopback device
+	loopback: VecDeque<Vec<u8>>,
 }
 
 impl HermitNet {
-	pub(crate) const fn new(mtu: u16) -> Self {
-		Self { mtu }
+	pub(crate) const fn new(
+		mtu: u16,
+		checksums: ChecksumCapabilities,
+		mac: EthernetAddress,
+		driver: Option<&'static InterruptTicketMutex<dyn NetworkDriver>>,
+	) -> Self {
+		Self {
+			mtu,
+			checksums,
+			mac,
+			driver,
+			loopback: VecDeque::new(),
+		}
+	}
+
+	pub(crate) fn driver(&self) -> Option<&'static InterruptTicketMutex<dyn NetworkDriver>> {
+		self.driver
+	}
+
+	pub(crate) fn mac(&self) -> EthernetAddress {
+		self.mac
+	}
+
+	/// Checks, if the network stack leaves the TCP and UDP checksums of sent packets to the device.
+	fn checksums_offloaded(&self) -> bool {
+		!self.checksums.tcp.tx() || !self.checksums.udp.tx()
+	}
+}
+
+/// Returns the MTU, the MAC address and the checksum capabilities of the network device.
+/// Without a network device, only the loopback device is available.
+fn device_config(
+	driver: Option<&'static InterruptTicketMutex<dyn NetworkDriver>>,
+) -> (u16, [u8; 6], ChecksumCapabilities) {
+	if let Some(driver) = driver {
+		let guard = driver.lock();
+		(
+			guard.get_mtu(),
+			guard.get_mac_address(),
+			guard.get_checksums(),
+		)
+	} else {
+		(LOOPBACK_MTU, LOOPBACK_MAC, ChecksumCapabilities::default())
+	}
+}
+
+/// Assigns the loopback addresses 127.0.0.1/8 and ::1/128 to the interface.
+fn add_loopback_addrs(iface: &mut Interface) {
+	iface.update_ip_addrs(|ip_addrs| {
+		ip_addrs
+			.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+			.unwrap();
+		ip_addrs
+			.push(IpCidr::new(IpAddress::Ipv6(Ipv6Address::LOOPBACK), 128))
+			.unwrap();
+	});
+}
+
+/// Checks, if a frame is addressed to the local host. Such a frame is delivered
+/// by the loopback device and never reaches the network.
+fn is_loopback_frame(frame: &[u8], mac: EthernetAddress) -> bool {
+	let Ok(frame) = EthernetFrame::new_checked(frame) else {
+		return false;
+	};
+
+	if frame.dst_addr() == mac {
+		return true;
+	} else if !frame.dst_addr().is_multicast() {
+		return false;
+	}
+
+	// The neighbor discovery of the loopback addresses stays on the local host, too.
+	match frame.ethertype() {
+		EthernetProtocol::Arp => ArpPacket::new_checked(frame.payload())
+			.and_then(|packet| ArpRepr::parse(&packet))
+			.map_or(false, |repr| {
+				matches!(
+					repr,
+					ArpRepr::EthernetIpv4 { target_protocol_addr, .. }
+						if target_protocol_addr.is_loopback()
+				)
+			}),
+		EthernetProtocol::Ipv6 => {
+			let Ok(packet) = Ipv6Packet::new_checked(frame.payload()) else {
+				return false;
+			};
+			if packet.next_header() != IpProtocol::Icmpv6 {
+				return false;
+			}
+
+			Icmpv6Packet::new_checked(packet.payload())
+				.and_then(|packet| NdiscRepr::parse(&packet))
+				.map_or(false, |repr| {
+					matches!(
+						repr,
+						NdiscRepr::NeighborSolicit { target_addr, .. } if target_addr.is_loopback()
+					)
+				})
+		}
+		_ => false,
 	}
 }
 
@@ -62,25 +180,200 @@ macro_rules! hermit_var_or {
 	}};
 }
 
+/// Parses the IPv4 address `val` of the environment variable `var`.
+#[cfg(not(feature = "dhcpv4"))]
+fn parse_ipv4_var(var: &str, val: &str) -> Option<Ipv4Address> {
+	let addr = Ipv4Address::from_str(val).ok();
+	if addr.is_none() {
+		warn!("Invalid IPv4 address {} in {}", val, var);
+	}
+	addr
+}
+
+/// Configures the interface with the static IPv4 address of the environment and
+/// returns its gateway. The first interface uses `HERMIT_IP`, `HERMIT_GATEWAY` and
+/// `HERMIT_MASK`, while the remaining interfaces append their index, e.g. `HERMIT_IP1`.
+/// These interfaces stay unconfigured, if their address is not specified. Invalid
+/// values are ignored with a warning.
+#[cfg(not(feature = "dhcpv4"))]
+fn static_config(iface: &mut Interface, name: &str, index: usize) -> Option<Ipv4Address> {
+	let (myip, mygw, mymask) = if index == 0 {
+		(
+			parse_ipv4_var("HERMIT_IP", hermit_var_or!("HERMIT_IP", "10.0.5.3"))
+				.unwrap_or(Ipv4Address::new(10, 0, 5, 3)),
+			Some(
+				parse_ipv4_var(
+					"HERMIT_GATEWAY",
+					hermit_var_or!("HERMIT_GATEWAY", "10.0.5.1"),
+				)
+				.unwrap_or(Ipv4Address::new(10, 0, 5, 1)),
+			),
+			parse_ipv4_var(
+				"HERMIT_MASK",
+				hermit_var_or!("HERMIT_MASK", "255.255.255.0"),
+			)
+			.unwrap_or(Ipv4Address::new(255, 255, 255, 0)),
+		)
+	} else {
+		let var = |var: &str| {
+			let var = format!("{var}{index}");
+			env::var(&var).and_then(|val| parse_ipv4_var(&var, val))
+		};
+		let Some(myip) = var("HERMIT_IP") else {
+			info!("{}: no address configured", name);
+			return None;
+		};
+
+		(
+			myip,
+			var("HERMIT_GATEWAY"),
+			var("HERMIT_MASK").unwrap_or(Ipv4Address::new(255, 255, 255, 0)),
+		)
+	};
+
+	// calculate the netmask length
+	// => count the number of contiguous 1 bits,
+	// starting at the most significant bit in the first octet
+	let mut prefix_len = (!mymask.as_bytes()[0]).trailing_zeros();
+	if prefix_len == 8 {
+		prefix_len += (!mymask.as_bytes()[1]).trailing_zeros();
+	}
+	if prefix_len == 16 {
+		prefix_len += (!mymask.as_bytes()[2]).trailing_zeros();
+	}
+	if prefix_len == 24 {
+		prefix_len += (!mymask.as_bytes()[3]).trailing_zeros();
+	}
+
+	let ip_addr = IpCidr::new(IpAddress::Ipv4(myip), prefix_len.try_into().unwrap());
+
+	info!("{}: configure address {}", name, ip_addr);
+	iface.update_ip_addrs(|ip_addrs| {
+		ip_addrs.push(ip_addr).unwrap();
+	});
+
+	if let Some(mygw) = mygw {
+		info!("{}: configure gateway with address {}", name, mygw);
+		iface.routes_mut().add_default_ipv4_route(mygw).unwrap();
+	}
+
+	mygw
+}
+
+/// Returns the static IPv6 address of the environment and the gateway of the
+/// interface. The first interface uses `HERMIT_IPV6`, `HERMIT_IPV6_PREFIX` and
+/// `HERMIT_IPV6_GATEWAY`, while the remaining interfaces append their index,
+/// e.g. `HERMIT_IPV6_PREFIX1`. The prefix length defaults to 64 bits. Invalid
+/// values are ignored with a warning.
+fn static_ipv6_config(index: usize) -> Option<(Ipv6Cidr, Option<Ipv6Address>)> {
+	let var = |var: &str| {
+		if index == 0 {
+			env::var(var)
+		} else {
+			env::var(&format!("{var}{index}"))
+		}
+	};
+
+	let addr = var("HERMIT_IPV6")?;
+	let Ok(addr) = Ipv6Address::from_str(addr) else {
+		warn!("Invalid IPv6 address {}", addr);
+		return None;
+	};
+	let prefix_len = var("HERMIT_IPV6_PREFIX").map_or(64, |len| match len.parse() {
+		Ok(len) if len <= 128 => len,
+		_ => {
+			warn!("Invalid IPv6 prefix length {}", len);
+			64
+		}
+	});
+	let gateway = var("HERMIT_IPV6_GATEWAY").and_then(|gw| {
+		let gateway = Ipv6Address::from_str(gw).ok();
+		if gateway.is_none() {
+			warn!("Invalid IPv6 gateway {}", gw);
+		}
+		gateway
+	});
+
+	Some((Ipv6Cidr::new(addr, prefix_len), gateway))
+}
+
+/// Returns the name servers of the environment variable `HERMIT_DNS`,
+/// which contains a comma-separated list of addresses.
+#[cfg(feature = "dns")]
+fn static_dns_servers() -> Vec<IpAddress> {
+	let Some(servers) = crate::env::var("HERMIT_DNS") else {
+		return Vec::new();
+	};
+
+	servers
+		.split(',')
+		.filter_map(|server| {
+			let addr = IpAddress::from_str(server.trim()).ok();
+			if addr.is_none() {
+				warn!("Invalid name server {}", server);
+			}
+			addr
+		})
+		.collect()
+}
+
 impl<'a> NetworkInterface<'a> {
-	#[cfg(feature = "dhcpv4")]
 	pub(crate) fn create() -> NetworkState<'a> {
-		let (mtu, mac) = if let Some(driver) = hardware::get_network_driver() {
-			let guard = driver.lock();
-			(guard.get_mtu(), guard.get_mac_address())
-		} else {
-			return NetworkState::InitializationFailed;
+		let mut netifs = hardware::get_network_drivers()
+			.enumerate()
+			.map(|(index, driver)| {
+				let mut netif = Netif::new(format!("eth{index}"), Some(driver));
+				netif.configure(index);
+				netif.configure_ipv6(index);
+				netif
+			})
+			.collect::<Vec<_>>();
+		if netifs.is_empty() {
+			info!("No network device found, only the loopback device is available");
+			netifs.push(Netif::new(String::from("lo"), None));
+		}
+
+		// The loopback addresses belong to the first interface.
+		add_loopback_addrs(&mut netifs[0].iface);
+		for netif in netifs.iter_mut() {
+			netif.sort_ip_addrs();
+			netif.update_multicast_filter();
+		}
+
+		let mut nic = Self {
+			netifs,
+			routes: Vec::new(),
+			handles: BTreeMap::new(),
+			next_handle: 0,
+			#[cfg(feature = "dns")]
+			dns_handle: None,
+			#[cfg(feature = "dns")]
+			static_dns_servers: static_dns_servers(),
+			config_version: 1,
+			config_wakers: Vec::new(),
+			multicast_groups: Vec::new(),
 		};
+		nic.update_routes();
+		#[cfg(feature = "dns")]
+		nic.update_dns();
+
+		NetworkState::Initialized(Box::new(nic))
+	}
+}
 
-		let mut device = HermitNet::new(mtu);
+impl<'a> Netif<'a> {
+	/// Creates the interface of a network device. Without a device, the
+	/// interface provides only the loopback device.
+	fn new(name: String, driver: Option<&'static InterruptTicketMutex<dyn NetworkDriver>>) -> Self {
+		let (mtu, mac, checksums) = device_config(driver);
 
-		let ethernet_addr = EthernetAddress([mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]]);
+		let ethernet_addr = EthernetAddress(mac);
 		let hardware_addr = HardwareAddress::Ethernet(ethernet_addr);
 
-		info!("MAC address {}", hardware_addr);
-		info!("MTU: {} bytes", mtu);
+		let mut device = HermitNet::new(mtu, checksums, ethernet_addr, driver);
 
-		let dhcp = dhcpv4::Socket::new();
+		info!("{}: MAC address {}", name, hardware_addr);
+		info!("{}: MTU {} bytes", name, mtu);
 
 		// use the current time based on the wall-clock time as seed
 		let mut config = Config::new();
@@ -90,129 +383,158 @@ impl<'a> NetworkInterface<'a> {
 		}
 
 		let iface = Interface::new(config, &mut device);
-		let mut sockets = SocketSet::new(vec![]);
-		let dhcp_handle = sockets.add(dhcp);
 
-		NetworkState::Initialized(Box::new(Self {
+		let mut netif = Self {
+			name,
 			iface,
-			sockets,
+			sockets: SocketSet::new(vec![]),
 			device,
-			dhcp_handle,
-		}))
-	}
-
-	#[cfg(not(feature = "dhcpv4"))]
-	pub(crate) fn create() -> NetworkState<'a> {
-		let (mtu, mac) = if let Some(driver) = hardware::get_network_driver() {
-			let guard = driver.lock();
-			(guard.get_mtu(), guard.get_mac_address())
-		} else {
-			return NetworkState::InitializationFailed;
+			multicast_groups: Vec::new(),
+			promiscuous: false,
+			all_multicast: false,
+			link_up: true,
+			gateway: None,
+			gateway6: None,
+			slaac: None,
+			icmp_handles: Vec::new(),
+			#[cfg(feature = "dhcpv4")]
+			dhcp_handle: None,
+			#[cfg(feature = "dhcpv4")]
+			dns_servers: Vec::new(),
 		};
+		netif.add_icmp_sockets();
 
-		let mut device = HermitNet::new(mtu);
-
-		let myip = Ipv4Address::from_str(hermit_var_or!("HERMIT_IP", "10.0.5.3")).unwrap();
-		let mygw = Ipv4Address::from_str(hermit_var_or!("HERMIT_GATEWAY", "10.0.5.1")).unwrap();
-		let mymask = Ipv4Address::from_str(hermit_var_or!("HERMIT_MASK", "255.255.255.0")).unwrap();
+		netif
+	}
 
-		// calculate the netmask length
-		// => count the number of contiguous 1 bits,
-		// starting at the most significant bit in the first octet
-		let mut prefix_len = (!mymask.as_bytes()[0]).trailing_zeros();
-		if prefix_len == 8 {
-			prefix_len += (!mymask.as_bytes()[1]).trailing_zeros();
-		}
-		if prefix_len == 16 {
-			prefix_len += (!mymask.as_bytes()[2]).trailing_zeros();
-		}
-		if prefix_len == 24 {
-			prefix_len += (!mymask.as_bytes()[3]).trailing_zeros();
-		}
+	/// Requests the configuration of the network device by DHCP.
+	#[cfg(feature = "dhcpv4")]
+	fn configure(&mut self, _index: usize) {
+		self.start_dhcp();
+	}
 
-		let ethernet_addr = EthernetAddress([mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]]);
-		let hardware_addr = HardwareAddress::Ethernet(ethernet_addr);
-		let ip_addrs = [IpCidr::new(
-			IpAddress::v4(
-				myip.as_bytes()[0],
-				myip.as_bytes()[1],
-				myip.as_bytes()[2],
-				myip.as_bytes()[3],
-			),
-			prefix_len.try_into().unwrap(),
-		)];
+	/// Configures the network device with the static address of the environment.
+	#[cfg(not(feature = "dhcpv4"))]
+	fn configure(&mut self, index: usize) {
+		self.gateway = static_config(&mut self.iface, &self.name, index);
+	}
 
-		info!("MAC address {}", hardware_addr);
-		info!("Configure network interface with address {}", ip_addrs[0]);
-		info!("Configure gateway with address {}", mygw);
-		info!("MTU: {} bytes", mtu);
+	/// Assigns the link-local IPv6 address, which is derived from the MAC address,
+	/// and the static IPv6 address of the environment. Without a static address,
+	/// the addresses are configured by router advertisements.
+	fn configure_ipv6(&mut self, index: usize) {
+		let Some(driver) = self.device.driver() else {
+			return;
+		};
+		let mac = EthernetAddress(driver.lock().get_mac_address());
+		let link_local = Ipv6Cidr::new(
+			eui64_addr(&Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), &mac),
+			64,
+		);
+
+		info!("{}: configure IPv6 address {}", self.name, link_local);
+		self.iface.update_ip_addrs(|ip_addrs| {
+			ip_addrs.push(IpCidr::Ipv6(link_local)).unwrap();
+		});
 
-		// use the current time based on the wall-clock time as seed
-		let mut config = Config::new();
-		config.random_seed = (arch::get_boot_time() + arch::processor::get_timer_ticks()) / 1000000;
-		if device.capabilities().medium == Medium::Ethernet {
-			config.hardware_addr = Some(hardware_addr).into();
-		}
+		let Some((cidr, gateway)) = static_ipv6_config(index) else {
+			info!("{}: configure IPv6 addresses by SLAAC", self.name);
+			self.start_slaac();
+			return;
+		};
 
-		let mut iface = Interface::new(config, &mut device);
-		iface.update_ip_addrs(|ip_addrs| {
-			ip_addrs
-				.push(IpCidr::new(
-					IpAddress::v4(
-						myip.as_bytes()[0],
-						myip.as_bytes()[1],
-						myip.as_bytes()[2],
-						myip.as_bytes()[3],
-					),
-					prefix_len.try_into().unwrap(),
-				))
-				.unwrap();
+		info!("{}: configure IPv6 address {}", self.name, cidr);
+		self.iface.update_ip_addrs(|ip_addrs| {
+			ip_addrs.push(IpCidr::Ipv6(cidr)).unwrap();
 		});
-		iface.routes_mut().add_default_ipv4_route(mygw).unwrap();
 
-		NetworkState::Initialized(Box::new(Self {
-			iface,
-			sockets: SocketSet::new(vec![]),
-			device,
-		}))
+		if let Some(gateway) = gateway {
+			info!(
+				"{}: configure IPv6 gateway with address {}",
+				self.name, gateway
+			);
+			self.iface
+				.routes_mut()
+				.add_default_ipv6_route(gateway)
+				.unwrap();
+			self.gateway6 = Some(gateway);
+		}
 	}
 }
 
 impl Device for HermitNet {
 	type RxToken<'a> = RxToken;
-	type TxToken<'a> = TxToken;
+	type TxToken<'a> = TxToken<'a>;
 
 	fn capabilities(&self) -> DeviceCapabilities {
 		let mut cap = DeviceCapabilities::default();
 		cap.max_transmission_unit = self.mtu.into();
+		cap.checksum = self.checksums.clone();
 		cap
 	}
 
 	fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-		if let Some(driver) = hardware::get_network_driver() {
+		let rx_token = if let Some(frame) = self.loopback.pop_front() {
+			RxToken::Loopback(frame)
+		} else if let Some(driver) = self.driver {
 			match driver.lock().receive_rx_buffer() {
-				Ok(buffer) => Some((RxToken::new(buffer), TxToken::new())),
-				_ => None,
+				Ok((buffer, len, handle)) => RxToken::new(driver, buffer, len, handle),
+				_ => return None,
 			}
 		} else {
-			None
-		}
+			return None;
+		};
+
+		Some((
+			rx_token,
+			TxToken::new(
+				self.driver,
+				&mut self.loopback,
+				self.mac,
+				self.checksums_offloaded(),
+			),
+		))
 	}
 
 	fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
 		trace!("create TxToken to transfer data");
-		Some(TxToken::new())
+		Some(TxToken::new(
+			self.driver,
+			&mut self.loopback,
+			self.mac,
+			self.checksums_offloaded(),
+		))
 	}
 }
 
+/// A received packet, which is either lent by the network device or has been
+/// sent to the loopback device. A lent buffer is released, when the token is dropped.
 #[doc(hidden)]
-pub(crate) struct RxToken {
-	buffer: Vec<u8>,
+pub(crate) enum RxToken {
+	/// Packet, which has been received by the network device
+	Device {
+		driver: &'static InterruptTicketMutex<dyn NetworkDriver>,
+		buffer: *mut u8,
+		len: usize,
+		handle: usize,
+	},
+	/// Packet, which has been sent to the loopback device
+	Loopback(Vec<u8>),
 }
 
 impl RxToken {
-	pub(crate) fn new(buffer: Vec<u8>) -> Self {
-		Self { buffer }
+	pub(crate) fn new(
+		driver: &'static InterruptTicketMutex<dyn NetworkDriver>,
+		buffer: *mut u8,
+		len: usize,
+		handle: usize,
+	) -> Self {
+		Self::Device {
+			driver,
+			buffer,
+			len,
+			handle,
+		}
 	}
 }
 
@@ -221,36 +543,93 @@ impl phy::RxToken for RxToken {
 	where
 		F: FnOnce(&mut [u8]) -> R,
 	{
-		f(&mut self.buffer[..])
+		let rx_slice = match &mut self {
+			Self::Device { buffer, len, .. } => unsafe { slice::from_raw_parts_mut(*buffer, *len) },
+			Self::Loopback(frame) => frame.as_mut_slice(),
+		};
+		f(rx_slice)
+	}
+}
+
+impl Drop for RxToken {
+	fn drop(&mut self) {
+		if let Self::Device { driver, handle, .. } = self {
+			driver.lock().release_rx_buffer(*handle);
+		}
 	}
 }
 
 #[doc(hidden)]
-pub(crate) struct TxToken;
+pub(crate) struct TxToken<'a> {
+	driver: Option<&'static InterruptTicketMutex<dyn NetworkDriver>>,
+	loopback: &'a mut VecDeque<Vec<u8>>,
+	mac: EthernetAddress,
+	/// The checksums of sent packets are left to the device, so that they have
+	/// to be computed for packets, which are sent to the loopback device.
+	checksums_offloaded: bool,
+}
 
-impl TxToken {
-	pub(crate) fn new() -> Self {
-		Self {}
+impl<'a> TxToken<'a> {
+	pub(crate) fn new(
+		driver: Option<&'static InterruptTicketMutex<dyn NetworkDriver>>,
+		loopback: &'a mut VecDeque<Vec<u8>>,
+		mac: EthernetAddress,
+		checksums_offloaded: bool,
+	) -> Self {
+		Self {
+			driver,
+			loopback,
+			mac,
+			checksums_offloaded,
+		}
+	}
+
+	/// Delivers a frame, which has been sent by the network device, to the local host.
+	fn loop_back(&mut self, mut frame: Vec<u8>) {
+		if self.checksums_offloaded {
+			fill_checksum(&mut frame);
+		}
+		self.loopback.push_back(frame);
 	}
 }
 
-impl phy::TxToken for TxToken {
-	fn consume<R, F>(self, len: usize, f: F) -> R
+impl<'a> phy::TxToken for TxToken<'a> {
+	fn consume<R, F>(mut self, len: usize, f: F) -> R
 	where
 		F: FnOnce(&mut [u8]) -> R,
 	{
-		let (tx_buffer, handle) = hardware::get_network_driver()
-			.unwrap()
-			.lock()
-			.get_tx_buffer(len)
-			.unwrap();
+		let Some(driver) = self.driver else {
+			// Without a network device, all frames are delivered locally.
+			let mut frame = vec![0; len];
+			let result = f(&mut frame);
+			self.loopback.push_back(frame);
+			return result;
+		};
+
+		let tx_buffer = driver.lock().get_tx_buffer(len);
+		let Ok((tx_buffer, handle)) = tx_buffer else {
+			// Frames to the local host do not need a buffer of the device.
+			let mut frame = vec![0; len];
+			let result = f(&mut frame);
+			if is_loopback_frame(&frame, self.mac) {
+				self.loop_back(frame);
+			} else {
+				warn!(
+					"Drop packet of {} bytes, because no TX buffer is available",
+					len
+				);
+			}
+			return result;
+		};
+
 		let tx_slice: &'static mut [u8] = unsafe { slice::from_raw_parts_mut(tx_buffer, len) };
 		let result = f(tx_slice);
-		hardware::get_network_driver()
-			.unwrap()
-			.lock()
-			.send_tx_buffer(handle, len)
-			.expect("Unable to send TX buffer");
+		if is_loopback_frame(tx_slice, self.mac) {
+			self.loop_back(tx_slice.to_vec());
+			driver.lock().free_tx_buffer(handle);
+		} else if driver.lock().send_tx_buffer(handle, len).is_err() {
+			warn!("Unable to send packet of {} bytes", len);
+		}
 		result
 	}
 }
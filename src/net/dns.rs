@@ -0,0 +1,172 @@
+//! Resolver of host names, which is used by `getaddrinfo`.
+//!
+//! A name is looked up in the static hosts table, in the cache of previous
+//! answers and finally by the name servers, which are configured by DHCP or
+//! by the environment variable `HERMIT_DNS`.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use core::task::Poll;
+
+use futures_lite::future;
+use hermit_sync::InterruptTicketMutex;
+use smoltcp::socket::dns::{self, GetQueryResultError};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{DnsQueryType, IpAddress, Ipv4Address, Ipv6Address};
+
+use crate::env;
+use crate::net::{now, NIC};
+use crate::syscalls::net::{EAI_FAIL, EAI_FAMILY, EAI_NONAME};
+
+/// Lifetime of a cached answer
+const CACHE_TTL: Duration = Duration::from_secs(300);
+/// Maximum number of cached answers
+const CACHE_SIZE: usize = 32;
+
+/// Address family of a query
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Family {
+	Ipv4,
+	Ipv6,
+}
+
+impl Family {
+	fn matches(&self, addr: &IpAddress) -> bool {
+		matches!(
+			(self, addr),
+			(Family::Ipv4, IpAddress::Ipv4(_)) | (Family::Ipv6, IpAddress::Ipv6(_))
+		)
+	}
+}
+
+static CACHE: InterruptTicketMutex<BTreeMap<(String, Family), (Vec<IpAddress>, Instant)>> =
+	InterruptTicketMutex::new(BTreeMap::new());
+
+/// Looks up the static hosts table. Besides `localhost`, the environment variable
+/// `HERMIT_HOSTS` defines entries as comma-separated list of `name=address` pairs.
+fn lookup_hosts(name: &str, family: Family) -> Vec<IpAddress> {
+	let mut addrs = Vec::new();
+
+	if name.eq_ignore_ascii_case("localhost") {
+		addrs.push(IpAddress::Ipv4(Ipv4Address::new(127, 0, 0, 1)));
+		addrs.push(IpAddress::Ipv6(Ipv6Address::LOOPBACK));
+	}
+
+	if let Some(hosts) = env::var("HERMIT_HOSTS") {
+		for entry in hosts.split(',') {
+			let Some((host, addr)) = entry.split_once('=') else {
+				continue;
+			};
+			if !host.trim().eq_ignore_ascii_case(name) {
+				continue;
+			}
+			if let Ok(addr) = IpAddress::from_str(addr.trim()) {
+				addrs.push(addr);
+			}
+		}
+	}
+
+	addrs.retain(|addr| family.matches(addr));
+	addrs
+}
+
+fn lookup_cache(name: &str, family: Family) -> Option<Vec<IpAddress>> {
+	let mut cache = CACHE.lock();
+	let key = (name.to_ascii_lowercase(), family);
+	let (addrs, timestamp) = cache.get(&key)?;
+
+	if now() < *timestamp + CACHE_TTL {
+		Some(addrs.clone())
+	} else {
+		cache.remove(&key);
+		None
+	}
+}
+
+fn insert_cache(name: &str, family: Family, addrs: &[IpAddress]) {
+	let mut cache = CACHE.lock();
+
+	// evict the oldest answer, if the cache is full
+	if cache.len() >= CACHE_SIZE {
+		if let Some(key) = cache
+			.iter()
+			.min_by_key(|(_, (_, timestamp))| *timestamp)
+			.map(|(key, _)| key.clone())
+		{
+			cache.remove(&key);
+		}
+	}
+
+	cache.insert((name.to_ascii_lowercase(), family), (addrs.to_vec(), now()));
+}
+
+/// Sends a query to the name servers and waits for the answer.
+async fn query(name: &str, family: Family) -> Result<Vec<IpAddress>, i32> {
+	let query_type = match family {
+		Family::Ipv4 => DnsQueryType::A,
+		Family::Ipv6 => DnsQueryType::Aaaa,
+	};
+
+	let (handle, query) = {
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().map_err(|_| EAI_FAIL)?;
+		let handle = nic.dns_handle().ok_or(EAI_FAIL)?;
+		let (socket, cx) = nic.get_socket_and_context::<dns::Socket<'_>>(handle);
+		let query = socket
+			.start_query(cx, name, query_type)
+			.map_err(|_| EAI_FAIL)?;
+		nic.poll_common(now());
+
+		(handle, query)
+	};
+
+	future::poll_fn(|cx| {
+		let mut guard = NIC.lock();
+		let nic = guard.as_nic_mut().unwrap();
+		let socket = nic.get_mut_socket::<dns::Socket<'_>>(handle);
+		let result = match socket.get_query_result(query) {
+			Ok(addrs) => Poll::Ready(Ok(addrs.iter().copied().collect())),
+			Err(GetQueryResultError::Pending) => {
+				socket.register_query_waker(query, cx.waker());
+				Poll::Pending
+			}
+			Err(GetQueryResultError::Failed) => Poll::Ready(Err(EAI_NONAME)),
+		};
+		nic.poll_common(now());
+
+		result
+	})
+	.await
+}
+
+/// Resolves the host `name` to its addresses of the address family `family`.
+/// Returns one of the `EAI_*` errors on failure.
+pub(crate) async fn resolve(name: &str, family: Family) -> Result<Vec<IpAddress>, i32> {
+	if let Ok(addr) = IpAddress::from_str(name) {
+		return if family.matches(&addr) {
+			Ok(vec![addr])
+		} else {
+			Err(EAI_FAMILY)
+		};
+	}
+
+	let addrs = lookup_hosts(name, family);
+	if !addrs.is_empty() {
+		return Ok(addrs);
+	}
+
+	if let Some(addrs) = lookup_cache(name, family) {
+		return Ok(addrs);
+	}
+
+	let addrs = query(name, family).await?;
+	if addrs.is_empty() {
+		return Err(EAI_NONAME);
+	}
+	debug!("Resolved {} to {:?}", name, addrs);
+	insert_cache(name, family, &addrs);
+
+	Ok(addrs)
+}
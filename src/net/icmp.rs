@@ -0,0 +1,247 @@
+//! Errors of connections, which are reported by ICMP.
+//!
+//! Every interface receives the ICMP messages by raw sockets. If a destination is
+//! unreachable, the connection attempt of a TCP socket is aborted and a connected
+//! UDP socket reports the error by its next operation.
+
+use alloc::vec::Vec;
+use core::task::Waker;
+
+use smoltcp::phy::{ChecksumCapabilities, Device};
+use smoltcp::socket::{raw, tcp, Socket};
+use smoltcp::wire::{
+	Icmpv4DstUnreachable, Icmpv4Packet, Icmpv4Repr, Icmpv6DstUnreachable, Icmpv6Packet, Icmpv6Repr,
+	IpAddress, IpEndpoint, IpProtocol, IpVersion, Ipv4Packet, Ipv4Repr, Ipv6Packet, Ipv6Repr,
+};
+
+use crate::net::{Handle, Netif, NetworkInterface};
+
+/// Number of ICMP messages, which are buffered by a raw socket
+const ICMP_ERROR_COUNT: usize = 8;
+
+/// Unreachable destination of a connection, which has been reported by ICMP
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct IcmpError {
+	protocol: IpProtocol,
+	/// Endpoint of the local socket
+	local: IpEndpoint,
+	/// Endpoint of the unreachable peer
+	remote: IpEndpoint,
+	/// Error, which the socket reports
+	errno: i32,
+}
+
+/// Returns the endpoints of the packet, which has caused the error. The returned
+/// data of the packet starts with the ports of TCP and UDP.
+fn error_endpoints(
+	src_addr: IpAddress,
+	dst_addr: IpAddress,
+	data: &[u8],
+) -> Option<(IpEndpoint, IpEndpoint)> {
+	if data.len() < 4 {
+		return None;
+	}
+
+	let src_port = u16::from_be_bytes([data[0], data[1]]);
+	let dst_port = u16::from_be_bytes([data[2], data[3]]);
+
+	Some((
+		IpEndpoint::new(src_addr, src_port),
+		IpEndpoint::new(dst_addr, dst_port),
+	))
+}
+
+fn parse_icmpv4_error(buffer: &[u8], checksums: &ChecksumCapabilities) -> Option<IcmpError> {
+	let packet = Ipv4Packet::new_checked(buffer).ok()?;
+	let icmp = Icmpv4Packet::new_checked(packet.payload()).ok()?;
+	let Icmpv4Repr::DstUnreachable {
+		reason,
+		header: Ipv4Repr {
+			src_addr,
+			dst_addr,
+			next_header,
+			..
+		},
+		data,
+	} = Icmpv4Repr::parse(&icmp, checksums).ok()?
+	else {
+		return None;
+	};
+
+	let errno = match reason {
+		Icmpv4DstUnreachable::NetUnreachable => crate::errno::ENETUNREACH,
+		Icmpv4DstUnreachable::ProtoUnreachable => crate::errno::ENOPROTOOPT,
+		Icmpv4DstUnreachable::PortUnreachable => crate::errno::ECONNREFUSED,
+		_ => crate::errno::EHOSTUNREACH,
+	};
+	let (local, remote) = error_endpoints(src_addr.into(), dst_addr.into(), data)?;
+
+	Some(IcmpError {
+		protocol: next_header,
+		local,
+		remote,
+		errno: -errno,
+	})
+}
+
+fn parse_icmpv6_error(buffer: &[u8], checksums: &ChecksumCapabilities) -> Option<IcmpError> {
+	let packet = Ipv6Packet::new_checked(buffer).ok()?;
+	let ip_repr = Ipv6Repr::parse(&packet).ok()?;
+	let icmp = Icmpv6Packet::new_checked(packet.payload()).ok()?;
+	let Icmpv6Repr::DstUnreachable {
+		reason,
+		header: Ipv6Repr {
+			src_addr,
+			dst_addr,
+			next_header,
+			..
+		},
+		data,
+	} = Icmpv6Repr::parse(
+		&ip_repr.src_addr.into(),
+		&ip_repr.dst_addr.into(),
+		&icmp,
+		checksums,
+	)
+	.ok()?
+	else {
+		return None;
+	};
+
+	let errno = match reason {
+		Icmpv6DstUnreachable::NoRoute => crate::errno::ENETUNREACH,
+		Icmpv6DstUnreachable::AdminProhibit => crate::errno::EACCES,
+		Icmpv6DstUnreachable::PortUnreachable => crate::errno::ECONNREFUSED,
+		_ => crate::errno::EHOSTUNREACH,
+	};
+	let (local, remote) = error_endpoints(src_addr.into(), dst_addr.into(), data)?;
+
+	Some(IcmpError {
+		protocol: next_header,
+		local,
+		remote,
+		errno: -errno,
+	})
+}
+
+impl<'a> Netif<'a> {
+	/// Adds the raw sockets, which receive the ICMP messages of both address families.
+	pub(crate) fn add_icmp_sockets(&mut self) {
+		for (version, protocol) in [
+			(IpVersion::Ipv4, IpProtocol::Icmp),
+			(IpVersion::Ipv6, IpProtocol::Icmpv6),
+		] {
+			let rx_buffer = raw::PacketBuffer::new(
+				vec![raw::PacketMetadata::EMPTY; ICMP_ERROR_COUNT],
+				vec![0; ICMP_ERROR_COUNT * usize::from(self.device.mtu)],
+			);
+			let tx_buffer = raw::PacketBuffer::new(vec![], vec![]);
+			let socket = raw::Socket::new(version, protocol, rx_buffer, tx_buffer);
+
+			self.icmp_handles.push(self.sockets.add(socket));
+		}
+	}
+
+	/// Returns the unreachable destinations, which have been reported since the last call.
+	fn recv_icmp_errors(&mut self) -> Vec<IcmpError> {
+		let checksums = self.device.capabilities().checksum;
+		let mut errors = Vec::new();
+
+		for handle in self.icmp_handles.iter() {
+			let socket = self.sockets.get_mut::<raw::Socket<'_>>(*handle);
+			if !socket.can_recv() {
+				continue;
+			}
+
+			let mut buffer = vec![0; usize::from(self.device.mtu)];
+			while let Ok(len) = socket.recv_slice(&mut buffer) {
+				let error = match socket.ip_version() {
+					IpVersion::Ipv4 => parse_icmpv4_error(&buffer[..len], &checksums),
+					IpVersion::Ipv6 => parse_icmpv6_error(&buffer[..len], &checksums),
+				};
+				errors.extend(error);
+			}
+		}
+
+		errors
+	}
+}
+
+impl<'a> NetworkInterface<'a> {
+	/// Reports the unreachable destinations, which the interfaces have received, to the sockets.
+	pub(crate) fn poll_icmp_errors(&mut self) {
+		for netif in 0..self.netifs.len() {
+			for error in self.netifs[netif].recv_icmp_errors() {
+				debug!("{:?} reported by ICMP", error);
+				self.report_icmp_error(netif, error);
+			}
+		}
+	}
+
+	fn report_icmp_error(&mut self, netif: usize, error: IcmpError) {
+		let mut affected = Vec::new();
+
+		for (handle, socket) in self.netifs[netif].sockets.iter_mut() {
+			let matches = match socket {
+				// only the connection attempt fails, see RFC 1122, section 4.2.3.9
+				Socket::Tcp(socket) if error.protocol == IpProtocol::Tcp => {
+					socket.state() == tcp::State::SynSent
+						&& socket.local_endpoint() == Some(error.local)
+						&& socket.remote_endpoint() == Some(error.remote)
+				}
+				Socket::Udp(socket) if error.protocol == IpProtocol::Udp => {
+					let endpoint = socket.endpoint();
+					endpoint.port == error.local.port
+						&& endpoint.addr.map_or(true, |addr| addr == error.local.addr)
+				}
+				_ => false,
+			};
+
+			if matches {
+				if let Socket::Tcp(socket) = socket {
+					socket.abort();
+				}
+				affected.push(handle);
+			}
+		}
+
+		for entry in self
+			.handles
+			.values_mut()
+			.filter(|entry| entry.netif == netif && affected.contains(&entry.handle))
+		{
+			entry.error = Some((error.remote, error.errno));
+			if let Some(waker) = entry.error_waker.take() {
+				waker.wake();
+			}
+		}
+	}
+
+	/// Returns and clears the error, which ICMP has reported for the connection
+	/// of the socket to `remote`.
+	pub(crate) fn take_socket_error(&mut self, handle: Handle, remote: IpEndpoint) -> Option<i32> {
+		let entry = self.handles.get_mut(&handle)?;
+
+		match entry.error {
+			Some((endpoint, errno)) if endpoint == remote => {
+				entry.error = None;
+				Some(errno)
+			}
+			_ => None,
+		}
+	}
+
+	/// Returns and clears the error, which ICMP has reported for the socket, see `SO_ERROR`.
+	pub(crate) fn take_pending_error(&mut self, handle: Handle) -> Option<i32> {
+		let (_, errno) = self.handles.get_mut(&handle)?.error.take()?;
+
+		Some(errno)
+	}
+
+	/// Wakes the task up, if ICMP reports an error for the socket.
+	pub(crate) fn register_error_waker(&mut self, handle: Handle, waker: &Waker) {
+		if let Some(entry) = self.handles.get_mut(&handle) {
+			entry.error_waker = Some(waker.clone());
+		}
+	}
+}
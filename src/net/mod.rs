@@ -1,30 +1,58 @@
 mod device;
+#[cfg(all(feature = "dns", not(feature = "newlib")))]
+pub(crate) mod dns;
 pub(crate) mod executor;
+mod icmp;
+mod slaac;
 
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::iter;
 use core::ops::DerefMut;
 use core::str::FromStr;
 use core::sync::atomic::{AtomicU16, Ordering};
-use core::task::Poll;
+use core::task::{Poll, Waker};
 
 use futures_lite::future;
 use hermit_sync::InterruptTicketMutex;
 use smoltcp::iface::{self, SocketHandle, SocketSet};
+use smoltcp::phy::{Device, TxToken};
 #[cfg(feature = "dhcpv4")]
 use smoltcp::socket::dhcpv4;
-use smoltcp::socket::{tcp, udp, AnySocket};
+#[cfg(feature = "dns")]
+use smoltcp::socket::dns as dns_socket;
+use smoltcp::socket::{icmp as icmp_socket, tcp, udp, AnySocket, Socket};
 use smoltcp::time::{Duration, Instant};
-use smoltcp::wire::IpAddress;
 #[cfg(feature = "dhcpv4")]
-use smoltcp::wire::{IpCidr, Ipv4Address, Ipv4Cidr};
+use smoltcp::wire::Ipv4Cidr;
+use smoltcp::wire::{
+	ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+	EthernetRepr, Icmpv6Packet, Icmpv6Repr, IpAddress, IpCidr, IpEndpoint, IpProtocol, Ipv4Address,
+	Ipv6Address, Ipv6Packet, Ipv6Repr, NdiscNeighborFlags, NdiscRepr, RawHardwareAddress,
+};
 
 use crate::net::device::HermitNet;
 use crate::net::executor::spawn;
-use crate::{arch, DEFAULT_KEEP_ALIVE_INTERVAL};
+use crate::net::slaac::Slaac;
+use crate::{arch, DEFAULT_KEEP_ALIVE_INTERVAL, DEFAULT_TCP_BUFFER_SIZE, DEFAULT_UDP_BUFFER_SIZE};
+
+/// Number of datagrams, which the buffers of a UDP socket are able to hold
+const UDP_PACKET_COUNT: usize = 64;
+/// Maximum number of pending connections of a listening TCP socket per interface.
+/// Every pending connection needs a socket with its own buffers.
+const MAX_BACKLOG: usize = 16;
+/// Number of ICMP messages, which an ICMP socket buffers in each direction
+const ICMP_PACKET_COUNT: usize = 16;
+/// Size of the buffers of an ICMP socket, which limits the size of a message
+pub(crate) const ICMP_BUFFER_SIZE: usize = 16_384;
+/// Maximum number of name servers, see feature `dns-max-server-count-4` of smoltcp
+#[cfg(feature = "dns")]
+const DNS_SERVER_COUNT: usize = 4;
 
 pub(crate) enum NetworkState<'a> {
 	Missing,
-	InitializationFailed,
 	Initialized(Box<NetworkInterface<'a>>),
 }
 
@@ -37,18 +65,124 @@ impl<'a> NetworkState<'a> {
 	}
 }
 
-pub(crate) type Handle = SocketHandle;
+/// Handle of a socket, which stays valid, if the socket moves to another interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Handle(usize);
 
 static LOCAL_ENDPOINT: AtomicU16 = AtomicU16::new(0);
 pub(crate) static NIC: InterruptTicketMutex<NetworkState<'_>> =
 	InterruptTicketMutex::new(NetworkState::Missing);
 
-pub(crate) struct NetworkInterface<'a> {
+/// Location of a socket within the socket sets of the interfaces
+struct SocketEntry {
+	/// Index of the interface, which owns the socket
+	netif: usize,
+	/// Handle of the socket within the socket set of its interface
+	handle: SocketHandle,
+	/// The socket is bound to its interface and is never moved by the routing table.
+	bound: bool,
+	/// Port of a listening TCP socket, whose backlog consists of the socket and its listeners
+	listen_port: Option<u16>,
+	/// Additional sockets of the backlog, which listen on the same or the remaining interfaces
+	listeners: Vec<(usize, SocketHandle)>,
+	/// Error and peer of a connection, which has been reported by ICMP
+	error: Option<(IpEndpoint, i32)>,
+	/// Task, which waits for an error of ICMP
+	error_waker: Option<Waker>,
+}
+
+/// Entry of the routing table, which selects the interface of a destination
+#[derive(Debug, Copy, Clone)]
+struct Route {
+	cidr: IpCidr,
+	netif: usize,
+}
+
+impl Route {
+	/// Returns the interface of the route to `addr`. The longest matching prefix
+	/// wins and, among routes with the same prefix, the first one. Without any
+	/// matching route, the packets leave the first interface.
+	fn select(routes: &[Route], addr: &IpAddress) -> usize {
+		routes
+			.iter()
+			.rev()
+			.filter(|route| route.cidr.contains_addr(addr))
+			.max_by_key(|route| route.cidr.prefix_len())
+			.map_or(0, |route| route.netif)
+	}
+}
+
+/// Interface of a single network device
+pub(crate) struct Netif<'a> {
+	/// Name of the interface, e.g. `eth0`
+	name: String,
 	iface: smoltcp::iface::Interface,
 	sockets: SocketSet<'a>,
 	device: HermitNet,
+	/// Multicast groups, which have been joined
+	multicast_groups: Vec<IpAddress>,
+	/// The device receives all packets.
+	promiscuous: bool,
+	/// The device receives all multicast packets.
+	all_multicast: bool,
+	/// Last known state of the link
+	link_up: bool,
+	/// Default gateway of the interface
+	gateway: Option<Ipv4Address>,
+	/// Default IPv6 gateway of the interface
+	gateway6: Option<Ipv6Address>,
+	/// Autoconfiguration of the IPv6 addresses, if no static address is specified
+	slaac: Option<Slaac>,
+	/// Raw sockets, which receive the errors of ICMP
+	icmp_handles: Vec<SocketHandle>,
+	/// DHCP socket, if a network device is available
 	#[cfg(feature = "dhcpv4")]
-	dhcp_handle: SocketHandle,
+	dhcp_handle: Option<SocketHandle>,
+	/// Name servers, which have been provided by DHCP
+	#[cfg(feature = "dhcpv4")]
+	dns_servers: Vec<IpAddress>,
+}
+
+/// The network stack, which consists of the interfaces of all network devices.
+/// The first interface provides the loopback addresses, too.
+pub(crate) struct NetworkInterface<'a> {
+	netifs: Vec<Netif<'a>>,
+	/// Routing table, which selects the egress interface of a connection
+	routes: Vec<Route>,
+	/// Maps the handles of the sockets to their location
+	handles: BTreeMap<Handle, SocketEntry>,
+	next_handle: usize,
+	/// DNS socket, which resolves the host names of `getaddrinfo`
+	#[cfg(feature = "dns")]
+	dns_handle: Option<Handle>,
+	/// Name servers of the environment, which take precedence over DHCP
+	#[cfg(feature = "dns")]
+	static_dns_servers: Vec<IpAddress>,
+	/// Version of the configuration, which increases with every change
+	config_version: u32,
+	/// Tasks, which wait for a change of the configuration
+	config_wakers: Vec<Waker>,
+	/// Multicast groups, which sockets have joined, and the number of these sockets
+	multicast_groups: Vec<(IpAddress, usize)>,
+}
+
+/// Configuration and state of an interface, which is reported to the application
+pub(crate) struct NetifInfo {
+	pub name: String,
+	/// Index of the interface, starting at 1
+	pub index: u32,
+	/// The interface provides only the loopback device.
+	pub loopback: bool,
+	pub link_up: bool,
+	pub promiscuous: bool,
+	pub all_multicast: bool,
+	pub mtu: u16,
+	pub mac: EthernetAddress,
+	pub addrs: Vec<IpCidr>,
+	pub gateway: Option<Ipv4Address>,
+	pub gateway6: Option<Ipv6Address>,
+	pub dns_servers: Vec<IpAddress>,
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -74,6 +208,122 @@ fn start_endpoint() -> u16 {
 	(value % (u16::MAX as u64)).try_into().unwrap()
 }
 
+/// Checks, if the address belongs to the loopback device.
+fn is_loopback(addr: &IpAddress) -> bool {
+	match addr {
+		IpAddress::Ipv4(addr) => addr.is_loopback(),
+		IpAddress::Ipv6(addr) => addr.is_loopback(),
+	}
+}
+
+/// Returns the scope of an address, from global (0) to unspecified (3).
+fn addr_scope(cidr: &IpCidr) -> u8 {
+	let addr = cidr.address();
+	let link_local = match addr {
+		IpAddress::Ipv4(addr) => addr.is_link_local(),
+		IpAddress::Ipv6(addr) => addr.is_link_local(),
+	};
+
+	if addr.is_unspecified() {
+		3
+	} else if is_loopback(&addr) {
+		2
+	} else if link_local {
+		1
+	} else {
+		0
+	}
+}
+
+/// Checks, if the address has been assigned by DHCP, i.e. it is the
+/// IPv4 address of the network device.
+#[cfg(feature = "dhcpv4")]
+fn is_dhcp_addr(cidr: &IpCidr) -> bool {
+	matches!(cidr, IpCidr::Ipv4(cidr) if !cidr.address().is_loopback())
+}
+
+/// Creates a TCP socket, whose buffers hold `rx_size` and `tx_size` bytes.
+fn tcp_socket<'a>(rx_size: usize, tx_size: usize) -> tcp::Socket<'a> {
+	let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; rx_size]);
+	let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; tx_size]);
+	tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
+}
+
+/// Creates a TCP socket with the options of `template`, whose buffers hold
+/// `rx_size` and `tx_size` bytes.
+pub(crate) fn copy_tcp_socket<'a>(
+	template: &tcp::Socket<'_>,
+	rx_size: usize,
+	tx_size: usize,
+) -> tcp::Socket<'a> {
+	let mut socket = tcp_socket(rx_size, tx_size);
+	socket.set_nagle_enabled(template.nagle_enabled());
+	socket.set_ack_delay(template.ack_delay());
+	socket.set_keep_alive(template.keep_alive());
+	socket.set_timeout(template.timeout());
+	socket.set_hop_limit(template.hop_limit());
+
+	socket
+}
+
+/// Creates a socket of a backlog, which listens on `port` with the options of `template`.
+fn listener_socket<'a>(template: &tcp::Socket<'_>, port: u16) -> Result<tcp::Socket<'a>, i32> {
+	let mut listener =
+		copy_tcp_socket(template, template.recv_capacity(), template.send_capacity());
+	listener.listen(port).map_err(|_| -crate::errno::EIO)?;
+
+	Ok(listener)
+}
+
+/// A connection of a backlog is established, if it is ready to be accepted.
+fn is_established(netifs: &[Netif<'_>], (netif, handle): (usize, SocketHandle)) -> bool {
+	matches!(
+		netifs[netif].sockets.get::<tcp::Socket<'_>>(handle).state(),
+		tcp::State::Established | tcp::State::CloseWait
+	)
+}
+
+/// Creates a UDP socket, whose buffers hold `rx_size` and `tx_size` bytes of payload.
+pub(crate) fn udp_socket<'a>(rx_size: usize, tx_size: usize) -> udp::Socket<'a> {
+	let udp_rx_buffer = udp::PacketBuffer::new(
+		vec![udp::PacketMetadata::EMPTY; UDP_PACKET_COUNT],
+		vec![0; rx_size],
+	);
+	let udp_tx_buffer = udp::PacketBuffer::new(
+		vec![udp::PacketMetadata::EMPTY; UDP_PACKET_COUNT],
+		vec![0; tx_size],
+	);
+	udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
+}
+
+/// Creates an unbound ICMP socket, which sends echo requests and receives their replies.
+pub(crate) fn echo_socket<'a>() -> icmp_socket::Socket<'a> {
+	let icmp_rx_buffer = icmp_socket::PacketBuffer::new(
+		vec![icmp_socket::PacketMetadata::EMPTY; ICMP_PACKET_COUNT],
+		vec![0; ICMP_BUFFER_SIZE],
+	);
+	let icmp_tx_buffer = icmp_socket::PacketBuffer::new(
+		vec![icmp_socket::PacketMetadata::EMPTY; ICMP_PACKET_COUNT],
+		vec![0; ICMP_BUFFER_SIZE],
+	);
+	icmp_socket::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
+}
+
+/// Returns the Ethernet address, to which packets of a multicast group are sent.
+fn multicast_mac(addr: &IpAddress) -> Option<[u8; 6]> {
+	match addr {
+		IpAddress::Ipv4(addr) if addr.is_multicast() => {
+			let bytes = addr.as_bytes();
+			Some([0x01, 0x00, 0x5e, bytes[1] & 0x7f, bytes[2], bytes[3]])
+		}
+		IpAddress::Ipv6(addr) if addr.is_multicast() => {
+			let bytes = addr.as_bytes();
+			Some([0x33, 0x33, bytes[12], bytes[13], bytes[14], bytes[15]])
+		}
+		_ => None,
+	}
+}
+
 #[inline]
 pub(crate) fn now() -> Instant {
 	let microseconds = arch::processor::get_timer_ticks() + arch::get_boot_time();
@@ -119,45 +369,543 @@ pub(crate) fn init() {
 }
 
 impl<'a> NetworkInterface<'a> {
+	/// Adds a socket to the first interface. The socket moves to another interface,
+	/// if it is bound to it or if the routing table selects it for a connection.
+	fn add_socket<T: AnySocket<'a>>(&mut self, socket: T) -> Handle {
+		let handle = Handle(self.next_handle);
+		self.next_handle += 1;
+
+		let entry = SocketEntry {
+			netif: 0,
+			handle: self.netifs[0].sockets.add(socket),
+			bound: false,
+			listen_port: None,
+			listeners: Vec::new(),
+			error: None,
+			error_waker: None,
+		};
+		self.handles.insert(handle, entry);
+
+		handle
+	}
+
 	pub(crate) fn create_udp_handle(&mut self) -> Result<Handle, ()> {
-		// Must fit mDNS payload of at least one packet
-		let udp_rx_buffer =
-			udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 1024]);
-		// Will not send mDNS
-		let udp_tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 0]);
-		let udp_socket = udp::Socket::new(udp_rx_buffer, udp_tx_buffer);
-		let udp_handle = self.sockets.add(udp_socket);
+		let udp_socket = udp_socket(DEFAULT_UDP_BUFFER_SIZE, DEFAULT_UDP_BUFFER_SIZE);
+
+		Ok(self.add_socket(udp_socket))
+	}
 
-		Ok(udp_handle)
+	pub(crate) fn create_icmp_handle(&mut self) -> Result<Handle, ()> {
+		Ok(self.add_socket(echo_socket()))
 	}
 
 	pub(crate) fn create_tcp_handle(&mut self) -> Result<Handle, ()> {
-		let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-		let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-		let mut tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
+		let mut tcp_socket = tcp_socket(DEFAULT_TCP_BUFFER_SIZE, DEFAULT_TCP_BUFFER_SIZE);
 		tcp_socket.set_nagle_enabled(true);
-		let tcp_handle = self.sockets.add(tcp_socket);
 
-		Ok(tcp_handle)
+		Ok(self.add_socket(tcp_socket))
 	}
 
 	pub(crate) fn poll_common(&mut self, timestamp: Instant) {
+		let mut changed = false;
+		for netif in self.netifs.iter_mut() {
+			changed |= netif.poll(timestamp);
+		}
+		self.poll_icmp_errors();
+
+		if changed {
+			self.update_routes();
+			#[cfg(feature = "dns")]
+			self.update_dns();
+
+			self.config_version = self.config_version.wrapping_add(1);
+			for waker in self.config_wakers.drain(..) {
+				waker.wake();
+			}
+		}
+	}
+
+	/// Builds the routing table from the networks of the interfaces. The first
+	/// interface with a gateway provides the default route of its address family.
+	fn update_routes(&mut self) {
+		self.routes.clear();
+
+		for (i, netif) in self.netifs.iter().enumerate() {
+			for cidr in netif.iface.ip_addrs() {
+				if !cidr.address().is_unspecified() {
+					self.routes.push(Route {
+						cidr: *cidr,
+						netif: i,
+					});
+				}
+			}
+		}
+
+		if let Some(i) = self.netifs.iter().position(|netif| netif.gateway.is_some()) {
+			self.routes.push(Route {
+				cidr: IpCidr::new(IpAddress::v4(0, 0, 0, 0), 0),
+				netif: i,
+			});
+		}
+		if let Some(i) = self
+			.netifs
+			.iter()
+			.position(|netif| netif.gateway6.is_some())
+		{
+			self.routes.push(Route {
+				cidr: IpCidr::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 0),
+				netif: i,
+			});
+		}
+
+		for route in self.routes.iter() {
+			debug!("Route {} via {}", route.cidr, self.netifs[route.netif].name);
+		}
+	}
+
+	/// Configures the DNS socket with the name servers of the environment variable
+	/// `HERMIT_DNS` or, if it is not set, with the name servers provided by DHCP.
+	#[cfg(feature = "dns")]
+	fn update_dns(&mut self) {
+		let mut servers = self.static_dns_servers.clone();
+		#[cfg(feature = "dhcpv4")]
+		if servers.is_empty() {
+			for server in self
+				.netifs
+				.iter()
+				.flat_map(|netif| netif.dns_servers.iter())
+			{
+				if !servers.contains(server) {
+					servers.push(*server);
+				}
+			}
+		}
+		servers.truncate(DNS_SERVER_COUNT);
+
+		let handle = if let Some(handle) = self.dns_handle {
+			handle
+		} else {
+			let handle = self.add_socket(dns_socket::Socket::new(&[], vec![]));
+			self.dns_handle = Some(handle);
+			handle
+		};
+
+		self.get_mut_socket::<dns_socket::Socket<'_>>(handle)
+			.update_servers(&servers);
+		// the queries leave the interface of the first name server
+		if let Some(server) = servers.first() {
+			self.route_socket(handle, *server);
+		}
+	}
+
+	/// Returns the handle of the DNS socket.
+	#[cfg(feature = "dns")]
+	pub(crate) fn dns_handle(&self) -> Option<Handle> {
+		self.dns_handle
+	}
+
+	/// Returns the name servers, which the interface `netif` uses.
+	fn dns_servers(&self, netif: usize) -> Vec<IpAddress> {
+		#[cfg(feature = "dns")]
+		if !self.static_dns_servers.is_empty() {
+			return self.static_dns_servers.clone();
+		}
+
+		self.netifs[netif].dhcp_dns_servers().to_vec()
+	}
+
+	/// Returns the configuration and the state of all interfaces.
+	pub(crate) fn netif_info(&self) -> Vec<NetifInfo> {
+		self.netifs
+			.iter()
+			.enumerate()
+			.map(|(i, netif)| NetifInfo {
+				name: netif.name.clone(),
+				index: (i + 1).try_into().unwrap(),
+				loopback: netif.device.driver().is_none(),
+				link_up: netif.link_up,
+				promiscuous: netif.promiscuous,
+				all_multicast: netif.all_multicast,
+				mtu: netif.device.mtu,
+				mac: netif.device.mac(),
+				addrs: netif
+					.iface
+					.ip_addrs()
+					.iter()
+					.copied()
+					.filter(|cidr| !cidr.address().is_unspecified())
+					.collect(),
+				gateway: netif.gateway,
+				gateway6: netif.gateway6,
+				dns_servers: self.dns_servers(i),
+			})
+			.collect()
+	}
+
+	/// Returns the version of the configuration, which changes with the addresses,
+	/// the gateways, the name servers or the link state of an interface.
+	pub(crate) fn config_version(&self) -> u32 {
+		self.config_version
+	}
+
+	/// Wakes the task up, if the configuration changes.
+	pub(crate) fn register_config_waker(&mut self, waker: &Waker) {
+		if !self.config_wakers.iter().any(|w| w.will_wake(waker)) {
+			self.config_wakers.push(waker.clone());
+		}
+	}
+
+	/// Returns the index of the interface, which reaches the destination `addr`.
+	fn route(&self, addr: &IpAddress) -> usize {
+		Route::select(&self.routes, addr)
+	}
+
+	/// Moves a socket to the interface `netif`. The socket must not be in use.
+	fn move_socket(&mut self, handle: Handle, netif: usize) {
+		let entry = self.handles.get_mut(&handle).unwrap();
+		if entry.netif == netif {
+			return;
+		}
+
+		let socket = self.netifs[entry.netif].sockets.remove(entry.handle);
+		let sockets = &mut self.netifs[netif].sockets;
+		entry.handle = match socket {
+			Socket::Tcp(socket) => sockets.add(socket),
+			Socket::Udp(socket) => sockets.add(socket),
+			Socket::Icmp(socket) => sockets.add(socket),
+			#[cfg(feature = "dns")]
+			Socket::Dns(socket) => sockets.add(socket),
+			#[allow(unreachable_patterns)]
+			_ => unreachable!("Only TCP, UDP and ICMP sockets are able to move"),
+		};
+		entry.netif = netif;
+	}
+
+	/// Moves an unbound socket to the interface, which the routing table selects
+	/// for the destination `addr`.
+	pub(crate) fn route_socket(&mut self, handle: Handle, addr: IpAddress) {
+		let entry = &self.handles[&handle];
+		if entry.bound || entry.listen_port.is_some() {
+			return;
+		}
+
+		let netif = self.route(&addr);
+		self.move_socket(handle, netif);
+	}
+
+	fn bind_netif(&mut self, handle: Handle, netif: usize) {
+		self.move_socket(handle, netif);
+		self.handles.get_mut(&handle).unwrap().bound = true;
+	}
+
+	/// Binds a socket to the interface, which owns the local address `addr`.
+	/// An unspecified address keeps the socket unbound.
+	pub(crate) fn bind_addr(&mut self, handle: Handle, addr: IpAddress) -> Result<(), i32> {
+		if addr.is_unspecified() {
+			return Ok(());
+		}
+
+		let netif = self
+			.netifs
+			.iter()
+			.position(|netif| netif.iface.has_ip_addr(addr))
+			.ok_or(-crate::errno::EADDRNOTAVAIL)?;
+		self.bind_netif(handle, netif);
+
+		Ok(())
+	}
+
+	/// Binds a socket to the interface with the name `name`, e.g. `eth1`.
+	pub(crate) fn bind_to_device(&mut self, handle: Handle, name: &str) -> Result<(), i32> {
+		let netif = self
+			.netifs
+			.iter()
+			.position(|netif| netif.name == name)
+			.ok_or(-crate::errno::ENODEV)?;
+		self.bind_netif(handle, netif);
+
+		Ok(())
+	}
+
+	/// Lets a TCP socket listen on `port`. The backlog of the socket consists of
+	/// `backlog` listening sockets on its interface or, if the socket is not bound
+	/// to an interface, on every interface.
+	pub(crate) fn listen(&mut self, handle: Handle, port: u16, backlog: usize) -> Result<(), i32> {
+		let backlog = backlog.clamp(1, MAX_BACKLOG);
+		let entry = self.handles.get_mut(&handle).unwrap();
+
+		// listening again only enlarges the backlog
+		if entry.listen_port.is_none() {
+			let socket = self.netifs[entry.netif]
+				.sockets
+				.get_mut::<tcp::Socket<'_>>(entry.handle);
+			if socket.is_open() {
+				return Err(-crate::errno::EIO);
+			}
+			socket.listen(port).map_err(|_| -crate::errno::EIO)?;
+			entry.listen_port = Some(port);
+		}
+		let port = entry.listen_port.unwrap();
+
+		let netifs = if entry.bound {
+			entry.netif..entry.netif + 1
+		} else {
+			0..self.netifs.len()
+		};
+		for netif in netifs {
+			let count = entry
+				.listeners
+				.iter()
+				.chain([(entry.netif, entry.handle)].iter())
+				.filter(|(i, _)| *i == netif)
+				.count();
+
+			for _ in count..backlog {
+				let template = self.netifs[entry.netif]
+					.sockets
+					.get::<tcp::Socket<'_>>(entry.handle);
+				let listener = listener_socket(template, port)?;
+				entry
+					.listeners
+					.push((netif, self.netifs[netif].sockets.add(listener)));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Takes an established connection from the backlog of a listening socket and
+	/// returns a new handle of the connection. The backlog is refilled by a new
+	/// listening socket. Returns `None`, if no connection has been established yet.
+	pub(crate) fn accept(&mut self, handle: Handle) -> Result<Option<Handle>, i32> {
+		let entry = self.handles.get_mut(&handle).unwrap();
+		let port = entry.listen_port.ok_or(-crate::errno::EINVAL)?;
+
+		let mut backlog = entry.listeners.clone();
+		backlog.insert(0, (entry.netif, entry.handle));
+
+		// connections, which have been reset before they are accepted, listen again
+		for (netif, handle) in backlog.iter() {
+			let socket = self.netifs[*netif]
+				.sockets
+				.get_mut::<tcp::Socket<'_>>(*handle);
+			if socket.state() == tcp::State::Closed {
+				socket.listen(port).map_err(|_| -crate::errno::EIO)?;
+			}
+		}
+
+		let Some(pos) = backlog
+			.iter()
+			.position(|listener| is_established(&self.netifs, *listener))
+		else {
+			return Ok(None);
+		};
+
+		let (netif, connection) = backlog.remove(pos);
+		let template = self.netifs[netif]
+			.sockets
+			.get::<tcp::Socket<'_>>(connection);
+		let listener = listener_socket(template, port)?;
+		backlog.push((netif, self.netifs[netif].sockets.add(listener)));
+
+		(entry.netif, entry.handle) = backlog.remove(0);
+		entry.listeners = backlog;
+
+		let connection_entry = SocketEntry {
+			netif,
+			handle: connection,
+			bound: true,
+			listen_port: None,
+			listeners: Vec::new(),
+			error: None,
+			error_waker: None,
+		};
+
+		let connection_handle = Handle(self.next_handle);
+		self.next_handle += 1;
+		self.handles.insert(connection_handle, connection_entry);
+
+		Ok(Some(connection_handle))
+	}
+
+	/// Removes the backlog of a listening socket. Its pending connections are dropped.
+	pub(crate) fn stop_listening(&mut self, handle: Handle) {
+		let entry = self.handles.get_mut(&handle).unwrap();
+		for (netif, handle) in entry.listeners.drain(..) {
+			self.netifs[netif].sockets.remove(handle);
+		}
+		entry.listen_port = None;
+	}
+
+	/// Registers a waker, which is woken, if a listening socket receives a connection
+	/// on any interface.
+	pub(crate) fn register_accept_waker(&mut self, handle: Handle, waker: &Waker) {
+		let entry = &self.handles[&handle];
+		for (netif, handle) in entry
+			.listeners
+			.iter()
+			.chain([(entry.netif, entry.handle)].iter())
+		{
+			self.netifs[*netif]
+				.sockets
+				.get_mut::<tcp::Socket<'_>>(*handle)
+				.register_recv_waker(waker);
+		}
+	}
+
+	/// Joins a multicast group on all interfaces, if it is the first socket in the group.
+	pub(crate) fn join_multicast_group(&mut self, addr: IpAddress) -> Result<(), i32> {
+		if let Some((_, count)) = self
+			.multicast_groups
+			.iter_mut()
+			.find(|(group, _)| *group == addr)
+		{
+			*count += 1;
+			return Ok(());
+		}
+
+		for netif in self.netifs.iter_mut() {
+			netif.join_multicast_group(addr)?;
+		}
+		self.multicast_groups.push((addr, 1));
+
+		Ok(())
+	}
+
+	/// Leaves a multicast group on all interfaces, if it is the last socket in the group.
+	pub(crate) fn leave_multicast_group(&mut self, addr: IpAddress) -> Result<(), i32> {
+		let pos = self
+			.multicast_groups
+			.iter()
+			.position(|(group, _)| *group == addr)
+			.ok_or(-crate::errno::EADDRNOTAVAIL)?;
+
+		self.multicast_groups[pos].1 -= 1;
+		if self.multicast_groups[pos].1 == 0 {
+			self.multicast_groups.remove(pos);
+			for netif in self.netifs.iter_mut() {
+				netif.leave_multicast_group(addr)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns the interface `index`, where the first interface has the index 1.
+	fn netif_mut(&mut self, index: u32) -> Result<&mut Netif<'a>, i32> {
+		usize::try_from(index)
+			.ok()
+			.and_then(|index| index.checked_sub(1))
+			.and_then(|index| self.netifs.get_mut(index))
+			.ok_or(-crate::errno::ENODEV)
+	}
+
+	/// Switches the promiscuous and the all-multicast mode of the interface `index`.
+	pub(crate) fn set_netif_modes(
+		&mut self,
+		index: u32,
+		promiscuous: bool,
+		all_multicast: bool,
+	) -> Result<(), i32> {
+		let netif = self.netif_mut(index)?;
+		netif.set_promiscuous(promiscuous)?;
+		netif.set_all_multicast(all_multicast)
+	}
+
+	/// Lets the interface `index` receive the packets of the VLAN `vid`.
+	pub(crate) fn add_vlan(&mut self, index: u32, vid: u16) -> Result<(), i32> {
+		self.netif_mut(index)?.set_vlan(vid, true)
+	}
+
+	/// Removes the VLAN `vid` from the filter of the interface `index`.
+	pub(crate) fn remove_vlan(&mut self, index: u32, vid: u16) -> Result<(), i32> {
+		self.netif_mut(index)?.set_vlan(vid, false)
+	}
+
+	pub(crate) fn poll_delay(&mut self, timestamp: Instant) -> Option<Duration> {
+		self.netifs
+			.iter_mut()
+			.flat_map(|netif| {
+				netif
+					.iface
+					.poll_delay(timestamp, &netif.sockets)
+					.into_iter()
+					.chain(netif.slaac_delay(timestamp))
+			})
+			.min()
+	}
+
+	#[allow(dead_code)]
+	pub(crate) fn get_socket<T: AnySocket<'a>>(&self, handle: Handle) -> &T {
+		let entry = &self.handles[&handle];
+		self.netifs[entry.netif].sockets.get(entry.handle)
+	}
+
+	pub(crate) fn get_mut_socket<T: AnySocket<'a>>(&mut self, handle: Handle) -> &mut T {
+		let entry = &self.handles[&handle];
+		self.netifs[entry.netif].sockets.get_mut(entry.handle)
+	}
+
+	pub(crate) fn get_socket_and_context<T: AnySocket<'a>>(
+		&mut self,
+		handle: Handle,
+	) -> (&mut T, &mut smoltcp::iface::Context) {
+		let entry = &self.handles[&handle];
+		let netif = &mut self.netifs[entry.netif];
+		(netif.sockets.get_mut(entry.handle), netif.iface.context())
+	}
+}
+
+impl<'a> Netif<'a> {
+	/// Polls the interface and returns `true`, if its configuration or its link state
+	/// has changed.
+	fn poll(&mut self, timestamp: Instant) -> bool {
+		// Even without a link, the loopback device delivers the local packets.
+		let mut changed = self.poll_link_state();
+
 		let _ = self
 			.iface
 			.poll(timestamp, &mut self.device, &mut self.sockets);
 
 		#[cfg(feature = "dhcpv4")]
+		{
+			changed |= self.poll_dhcp();
+		}
+		changed |= self.poll_slaac(timestamp);
+
+		if changed {
+			self.sort_ip_addrs();
+			self.update_multicast_filter();
+		}
+
+		changed
+	}
+
+	/// Orders the addresses by their scope. As source address, smoltcp selects the first
+	/// address of the destination's family, which must be neither a loopback nor a
+	/// link-local address to reach other networks.
+	fn sort_ip_addrs(&mut self) {
+		self.iface
+			.update_ip_addrs(|addrs| addrs.sort_by_key(addr_scope));
+	}
+
+	#[cfg(feature = "dhcpv4")]
+	fn poll_dhcp(&mut self) -> bool {
+		let Some(dhcp_handle) = self.dhcp_handle else {
+			return false;
+		};
+
 		match self
 			.sockets
-			.get_mut::<dhcpv4::Socket<'_>>(self.dhcp_handle)
+			.get_mut::<dhcpv4::Socket<'_>>(dhcp_handle)
 			.poll()
 		{
-			None => {}
+			None => false,
 			Some(dhcpv4::Event::Configured(config)) => {
-				info!("DHCP config acquired!");
+				info!("{}: DHCP config acquired!", self.name);
 				info!("IP address:      {}", config.address);
 				self.iface.update_ip_addrs(|addrs| {
-					if let Some(dest) = addrs.iter_mut().next() {
+					if let Some(dest) = addrs.iter_mut().find(|cidr| is_dhcp_addr(cidr)) {
 						*dest = IpCidr::Ipv4(config.address);
 					} else if addrs.push(IpCidr::Ipv4(config.address)).is_err() {
 						info!("Unable to update IP address");
@@ -173,42 +921,322 @@ impl<'a> NetworkInterface<'a> {
 					info!("Default gateway: None");
 					self.iface.routes_mut().remove_default_ipv4_route();
 				}
+				self.gateway = config.router;
 
 				for (i, s) in config.dns_servers.iter().enumerate() {
 					info!("DNS server {}:    {}", i, s);
 				}
+				self.dns_servers = config
+					.dns_servers
+					.iter()
+					.map(|server| IpAddress::Ipv4(*server))
+					.collect();
+
+				true
 			}
 			Some(dhcpv4::Event::Deconfigured) => {
-				info!("DHCP lost config!");
-				let cidr = Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0);
-				self.iface.update_ip_addrs(|addrs| {
-					if let Some(dest) = addrs.iter_mut().next() {
-						*dest = IpCidr::Ipv4(cidr);
-					}
-				});
-				self.iface.routes_mut().remove_default_ipv4_route();
+				info!("{}: DHCP lost config!", self.name);
+				self.drop_dhcp_config();
+
+				true
 			}
+		}
+	}
+
+	/// Starts the configuration of the interface by DHCP.
+	#[cfg(feature = "dhcpv4")]
+	fn start_dhcp(&mut self) {
+		if self.dhcp_handle.is_none() {
+			self.dhcp_handle = Some(self.sockets.add(dhcpv4::Socket::new()));
+		}
+	}
+
+	/// Stops DHCP and drops the leased address, the gateway and the name servers.
+	#[cfg(feature = "dhcpv4")]
+	fn stop_dhcp(&mut self) {
+		if let Some(dhcp_handle) = self.dhcp_handle.take() {
+			self.sockets.remove(dhcp_handle);
+			self.drop_dhcp_config();
+		}
+	}
+
+	/// Removes the configuration, which has been provided by DHCP.
+	#[cfg(feature = "dhcpv4")]
+	fn drop_dhcp_config(&mut self) {
+		let cidr = Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0);
+		self.iface.update_ip_addrs(|addrs| {
+			if let Some(dest) = addrs.iter_mut().find(|cidr| is_dhcp_addr(cidr)) {
+				*dest = IpCidr::Ipv4(cidr);
+			}
+		});
+		self.iface.routes_mut().remove_default_ipv4_route();
+		self.gateway = None;
+		self.dns_servers.clear();
+	}
+
+	/// Returns the name servers, which have been provided by DHCP.
+	#[cfg(feature = "dhcpv4")]
+	fn dhcp_dns_servers(&self) -> &[IpAddress] {
+		&self.dns_servers
+	}
+
+	#[cfg(not(feature = "dhcpv4"))]
+	fn dhcp_dns_servers(&self) -> &[IpAddress] {
+		&[]
+	}
+
+	/// Follows the link state of the network device and announces the addresses of
+	/// the interface, if the device requests it. Returns `true`, if the link state has changed.
+	fn poll_link_state(&mut self) -> bool {
+		let Some(driver) = self.device.driver() else {
+			return false;
 		};
+		let (link_up, announce) = {
+			let guard = driver.lock();
+			(guard.is_link_up(), guard.is_announce())
+		};
+
+		let changed = link_up != self.link_up;
+		if changed {
+			self.link_up = link_up;
+
+			if link_up {
+				info!("Link of {} is up", self.name);
+				// The interface may have been moved to another network.
+				#[cfg(feature = "dhcpv4")]
+				self.start_dhcp();
+				self.restart_slaac();
+				self.announce();
+			} else {
+				info!("Link of {} is down", self.name);
+				// The lease may not be valid anymore, when the link comes back.
+				#[cfg(feature = "dhcpv4")]
+				self.stop_dhcp();
+			}
+		}
+
+		if link_up && announce {
+			self.announce();
+			driver.lock().ack_announce();
+		}
+
+		changed
 	}
 
-	pub(crate) fn poll_delay(&mut self, timestamp: Instant) -> Option<Duration> {
-		self.iface.poll_delay(timestamp, &self.sockets)
+	/// Announces the addresses of the interface by gratuitous ARP requests and
+	/// unsolicited neighbor advertisements. Thereby, switches and neighbors learn
+	/// the new location of the interface, e.g. after live migration.
+	fn announce(&mut self) {
+		let Some(driver) = self.device.driver() else {
+			return;
+		};
+		let mac = EthernetAddress(driver.lock().get_mac_address());
+
+		let addrs = self
+			.iface
+			.ip_addrs()
+			.iter()
+			.map(|cidr| cidr.address())
+			.filter(|addr| !addr.is_unspecified() && !is_loopback(addr))
+			.collect::<Vec<_>>();
+		for addr in addrs {
+			debug!("Announce address {}", addr);
+			match addr {
+				IpAddress::Ipv4(addr) => self.send_gratuitous_arp(mac, addr),
+				IpAddress::Ipv6(addr) => self.send_neighbor_advert(mac, addr),
+			}
+		}
 	}
 
-	#[allow(dead_code)]
-	pub(crate) fn get_socket<T: AnySocket<'a>>(&self, handle: SocketHandle) -> &T {
-		self.sockets.get(handle)
+	fn send_gratuitous_arp(&mut self, mac: EthernetAddress, addr: Ipv4Address) {
+		let arp = ArpRepr::EthernetIpv4 {
+			operation: ArpOperation::Request,
+			source_hardware_addr: mac,
+			source_protocol_addr: addr,
+			target_hardware_addr: EthernetAddress([0; 6]),
+			target_protocol_addr: addr,
+		};
+
+		self.send_frame(
+			mac,
+			EthernetAddress::BROADCAST,
+			EthernetProtocol::Arp,
+			arp.buffer_len(),
+			|payload| arp.emit(&mut ArpPacket::new_unchecked(payload)),
+		);
 	}
 
-	pub(crate) fn get_mut_socket<T: AnySocket<'a>>(&mut self, handle: SocketHandle) -> &mut T {
-		self.sockets.get_mut(handle)
+	fn send_neighbor_advert(&mut self, mac: EthernetAddress, addr: Ipv6Address) {
+		let dst_addr = Ipv6Address::LINK_LOCAL_ALL_NODES;
+		let icmp = Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert {
+			flags: NdiscNeighborFlags::OVERRIDE,
+			target_addr: addr,
+			lladdr: Some(RawHardwareAddress::from_bytes(mac.as_bytes())),
+		});
+		let ip = Ipv6Repr {
+			src_addr: addr,
+			dst_addr,
+			next_header: IpProtocol::Icmpv6,
+			payload_len: icmp.buffer_len(),
+			hop_limit: 255,
+		};
+		let checksums = self.device.capabilities().checksum;
+
+		self.send_frame(
+			mac,
+			EthernetAddress([0x33, 0x33, 0, 0, 0, 1]),
+			EthernetProtocol::Ipv6,
+			ip.buffer_len() + icmp.buffer_len(),
+			|payload| {
+				let mut packet = Ipv6Packet::new_unchecked(payload);
+				ip.emit(&mut packet);
+				icmp.emit(
+					&addr.into(),
+					&dst_addr.into(),
+					&mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+					&checksums,
+				);
+			},
+		);
 	}
 
-	pub(crate) fn get_socket_and_context<T: AnySocket<'a>>(
+	/// Sends an Ethernet frame, whose payload is written by `emit`, bypassing the interface.
+	fn send_frame(
 		&mut self,
-		handle: SocketHandle,
-	) -> (&mut T, &mut smoltcp::iface::Context) {
-		(self.sockets.get_mut(handle), self.iface.context())
+		src_addr: EthernetAddress,
+		dst_addr: EthernetAddress,
+		ethertype: EthernetProtocol,
+		payload_len: usize,
+		emit: impl FnOnce(&mut [u8]),
+	) {
+		let repr = EthernetRepr {
+			src_addr,
+			dst_addr,
+			ethertype,
+		};
+
+		if let Some(token) = self.device.transmit(now()) {
+			token.consume(repr.buffer_len() + payload_len, |buffer| {
+				let mut frame = EthernetFrame::new_unchecked(buffer);
+				repr.emit(&mut frame);
+				emit(frame.payload_mut());
+			});
+		}
+	}
+
+	/// Joins a multicast group and lets the network device receive its packets.
+	fn join_multicast_group(&mut self, addr: IpAddress) -> Result<(), i32> {
+		self.iface
+			.join_multicast_group(&mut self.device, addr, now())
+			.map_err(|_| -crate::errno::EINVAL)?;
+
+		if !self.multicast_groups.contains(&addr) {
+			self.multicast_groups.push(addr);
+			self.update_multicast_filter();
+		}
+
+		Ok(())
+	}
+
+	/// Leaves a multicast group, which has been joined before.
+	fn leave_multicast_group(&mut self, addr: IpAddress) -> Result<(), i32> {
+		self.iface
+			.leave_multicast_group(&mut self.device, addr, now())
+			.map_err(|_| -crate::errno::EINVAL)?;
+
+		if let Some(pos) = self
+			.multicast_groups
+			.iter()
+			.position(|group| *group == addr)
+		{
+			self.multicast_groups.remove(pos);
+			self.update_multicast_filter();
+		}
+
+		Ok(())
+	}
+
+	/// Switches the reception of all packets on or off.
+	fn set_promiscuous(&mut self, value: bool) -> Result<(), i32> {
+		if self.promiscuous != value {
+			let driver = self.device.driver().ok_or(-crate::errno::EOPNOTSUPP)?;
+			driver
+				.lock()
+				.set_promiscuous(value)
+				.map_err(|_| -crate::errno::EOPNOTSUPP)?;
+			self.promiscuous = value;
+		}
+
+		Ok(())
+	}
+
+	/// Switches the reception of all multicast packets on or off.
+	fn set_all_multicast(&mut self, value: bool) -> Result<(), i32> {
+		if self.all_multicast != value {
+			let driver = self.device.driver().ok_or(-crate::errno::EOPNOTSUPP)?;
+			driver
+				.lock()
+				.set_all_multicast(value)
+				.map_err(|_| -crate::errno::EOPNOTSUPP)?;
+			self.all_multicast = value;
+			// Without a multicast filter, the joined groups still require all multicast packets.
+			self.update_multicast_filter();
+		}
+
+		Ok(())
+	}
+
+	/// Adds the VLAN `vid` to the filter of the network device or removes it.
+	fn set_vlan(&mut self, vid: u16, value: bool) -> Result<(), i32> {
+		// VLAN ids consist of 12 bits.
+		if vid >= 4096 {
+			return Err(-crate::errno::EINVAL);
+		}
+		let driver = self.device.driver().ok_or(-crate::errno::EOPNOTSUPP)?;
+		let mut driver = driver.lock();
+		let result = if value {
+			driver.add_vlan(vid)
+		} else {
+			driver.remove_vlan(vid)
+		};
+
+		result.map_err(|_| -crate::errno::EOPNOTSUPP)
+	}
+
+	/// Programs the filter of the network device with the joined multicast groups.
+	/// If the device is not able to filter multicast packets, it receives all of them.
+	///
+	/// Besides, the neighbor discovery of IPv6 requires the group of all nodes and the
+	/// solicited-node groups of the IPv6 addresses.
+	fn update_multicast_filter(&self) {
+		let Some(driver) = self.device.driver() else {
+			return;
+		};
+
+		let ndisc_groups = self
+			.iface
+			.ip_addrs()
+			.iter()
+			.filter_map(|cidr| match cidr.address() {
+				IpAddress::Ipv6(addr) if !addr.is_loopback() && !addr.is_unspecified() => {
+					Some(IpAddress::Ipv6(addr.solicited_node()))
+				}
+				_ => None,
+			})
+			.chain(iter::once(IpAddress::Ipv6(
+				Ipv6Address::LINK_LOCAL_ALL_NODES,
+			)));
+		let addrs = self
+			.multicast_groups
+			.iter()
+			.copied()
+			.chain(ndisc_groups)
+			.filter_map(|addr| multicast_mac(&addr))
+			.collect::<Vec<_>>();
+		let mut driver = driver.lock();
+		if driver.set_multicast_filter(&addrs).is_err() {
+			let _ = driver.set_all_multicast(self.all_multicast || !addrs.is_empty());
+		}
 	}
 }
 
@@ -229,13 +1257,10 @@ impl AsyncSocket {
 		self.0
 	}
 
-	fn with<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>) -> R) -> R {
+	fn with_nic<R>(&self, f: impl FnOnce(&mut NetworkInterface<'_>) -> R) -> R {
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().unwrap();
-		let res = {
-			let s = nic.get_mut_socket::<tcp::Socket<'_>>(self.0);
-			f(s)
-		};
+		let res = f(nic);
 		let t = now();
 		if nic.poll_delay(t).map(|d| d.total_millis()).unwrap_or(0) == 0 {
 			nic.poll_common(t);
@@ -243,18 +1268,15 @@ impl AsyncSocket {
 		res
 	}
 
+	fn with<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>) -> R) -> R {
+		self.with_nic(|nic| f(nic.get_mut_socket::<tcp::Socket<'_>>(self.0)))
+	}
+
 	fn with_context<R>(&self, f: impl FnOnce(&mut tcp::Socket<'_>, &mut iface::Context) -> R) -> R {
-		let mut guard = NIC.lock();
-		let nic = guard.as_nic_mut().unwrap();
-		let res = {
+		self.with_nic(|nic| {
 			let (s, cx) = nic.get_socket_and_context::<tcp::Socket<'_>>(self.0);
 			f(s, cx)
-		};
-		let t = now();
-		if nic.poll_delay(t).map(|d| d.total_millis()).unwrap_or(0) == 0 {
-			nic.poll_common(t);
-		}
-		res
+		})
 	}
 
 	pub(crate) async fn connect(&self, ip: &[u8], port: u16) -> Result<Handle, i32> {
@@ -262,6 +1284,7 @@ impl AsyncSocket {
 			IpAddress::from_str(core::str::from_utf8(ip).map_err(|_| -crate::errno::EIO)?)
 				.map_err(|_| -crate::errno::EIO)?;
 
+		self.with_nic(|nic| nic.route_socket(self.0, address));
 		self.with_context(|socket, cx| {
 			socket.connect(
 				cx,
@@ -271,52 +1294,53 @@ impl AsyncSocket {
 		})
 		.map_err(|_| -crate::errno::EIO)?;
 
+		let remote = IpEndpoint::new(address, port);
 		future::poll_fn(|cx| {
-			self.with(|socket| match socket.state() {
-				tcp::State::Closed | tcp::State::TimeWait => {
-					Poll::Ready(Err(-crate::errno::EFAULT))
-				}
-				tcp::State::Listen => Poll::Ready(Err(-crate::errno::EIO)),
-				tcp::State::SynSent | tcp::State::SynReceived => {
-					socket.register_send_waker(cx.waker());
-					Poll::Pending
+			self.with_nic(|nic| {
+				let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.0);
+				match socket.state() {
+					// ICMP may have reported an unreachable destination
+					tcp::State::Closed | tcp::State::TimeWait => Poll::Ready(Err(nic
+						.take_socket_error(self.0, remote)
+						.unwrap_or(-crate::errno::EFAULT))),
+					tcp::State::Listen => Poll::Ready(Err(-crate::errno::EIO)),
+					tcp::State::SynSent | tcp::State::SynReceived => {
+						socket.register_send_waker(cx.waker());
+						Poll::Pending
+					}
+					_ => Poll::Ready(Ok(self.0)),
 				}
-				_ => Poll::Ready(Ok(self.0)),
 			})
 		})
 		.await
 	}
 
-	pub(crate) async fn accept(&self, port: u16) -> Result<(IpAddress, u16), i32> {
-		self.with(|socket| socket.listen(port).map_err(|_| -crate::errno::EIO))?;
+	pub(crate) fn listen(&self, port: u16, backlog: usize) -> Result<(), i32> {
+		self.with_nic(|nic| nic.listen(self.0, port, backlog))
+	}
 
-		future::poll_fn(|cx| {
-			self.with(|socket| {
-				if socket.is_active() {
-					Poll::Ready(Ok(()))
-				} else {
-					match socket.state() {
-						tcp::State::Closed
-						| tcp::State::Closing
-						| tcp::State::FinWait1
-						| tcp::State::FinWait2 => Poll::Ready(Err(-crate::errno::EIO)),
-						_ => {
-							socket.register_recv_waker(cx.waker());
-							Poll::Pending
-						}
-					}
+	/// Waits for an established connection of the listening socket and returns
+	/// its handle and remote endpoint. The socket continues to listen.
+	pub(crate) async fn accept(&self) -> Result<(Handle, IpAddress, u16), i32> {
+		let connection = future::poll_fn(|cx| {
+			self.with_nic(|nic| match nic.accept(self.0) {
+				Ok(Some(connection)) => Poll::Ready(Ok(connection)),
+				Ok(None) => {
+					nic.register_accept_waker(self.0, cx.waker());
+					Poll::Pending
 				}
+				Err(x) => Poll::Ready(Err(x)),
 			})
 		})
 		.await?;
 
 		let mut guard = NIC.lock();
 		let nic = guard.as_nic_mut().map_err(|_| -crate::errno::EIO)?;
-		let socket = nic.get_mut_socket::<tcp::Socket<'_>>(self.0);
+		let socket = nic.get_mut_socket::<tcp::Socket<'_>>(connection);
 		socket.set_keep_alive(Some(Duration::from_millis(DEFAULT_KEEP_ALIVE_INTERVAL)));
 		let endpoint = socket.remote_endpoint().ok_or(-crate::errno::EIO)?;
 
-		Ok((endpoint.addr, endpoint.port))
+		Ok((connection, endpoint.addr, endpoint.port))
 	}
 
 	pub(crate) async fn read(&self, buffer: &mut [u8]) -> Result<usize, i32> {
@@ -432,3 +1456,57 @@ impl From<Handle> for AsyncSocket {
 		AsyncSocket(handle)
 	}
 }
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	fn route(addr: IpAddress, prefix_len: u8, netif: usize) -> Route {
+		Route {
+			cidr: IpCidr::new(addr, prefix_len),
+			netif,
+		}
+	}
+
+	#[test]
+	fn longest_prefix() {
+		let routes = [
+			route(IpAddress::v4(10, 0, 0, 2), 8, 0),
+			route(IpAddress::v4(10, 0, 5, 3), 24, 1),
+			route(IpAddress::v4(0, 0, 0, 0), 0, 2),
+		];
+
+		assert_eq!(Route::select(&routes, &IpAddress::v4(10, 0, 5, 1)), 1);
+		assert_eq!(Route::select(&routes, &IpAddress::v4(10, 0, 6, 1)), 0);
+		assert_eq!(Route::select(&routes, &IpAddress::v4(192, 168, 0, 1)), 2);
+	}
+
+	#[test]
+	fn first_route_wins() {
+		let routes = [
+			route(IpAddress::v4(10, 0, 5, 3), 24, 1),
+			route(IpAddress::v4(10, 0, 5, 4), 24, 2),
+		];
+
+		assert_eq!(Route::select(&routes, &IpAddress::v4(10, 0, 5, 1)), 1);
+	}
+
+	#[test]
+	fn address_families() {
+		let routes = [
+			route(IpAddress::v4(0, 0, 0, 0), 0, 1),
+			route(IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 1), 64, 2),
+		];
+
+		assert_eq!(
+			Route::select(&routes, &IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 2)),
+			2
+		);
+		// Without an IPv6 default route, other IPv6 packets leave the first interface.
+		assert_eq!(
+			Route::select(&routes, &IpAddress::v6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+			0
+		);
+		assert_eq!(Route::select(&[], &IpAddress::v4(10, 0, 5, 1)), 0);
+	}
+}
@@ -0,0 +1,368 @@
+//! Stateless address autoconfiguration (SLAAC) of IPv6 addresses, see RFC 4862.
+//!
+//! The interface solicits router advertisements and derives its addresses from
+//! the advertised prefixes and its MAC address. The advertising router becomes
+//! the default IPv6 gateway of the interface.
+
+use alloc::vec::Vec;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::{ChecksumCapabilities, Device};
+use smoltcp::socket::raw;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{
+	EthernetAddress, EthernetProtocol, Icmpv6Packet, Icmpv6Repr, IpAddress, IpCidr, IpProtocol,
+	IpVersion, Ipv6Address, Ipv6Cidr, Ipv6Packet, Ipv6Repr, NdiscPrefixInfoFlags,
+	NdiscPrefixInformation, NdiscRepr, RawHardwareAddress,
+};
+
+use crate::net::{now, Netif};
+
+/// Maximum number of router solicitations, which are sent without an answer
+const MAX_RTR_SOLICITATIONS: u8 = 3;
+/// Interval between two router solicitations
+const RTR_SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+/// Number of router advertisements, which are buffered by the raw socket
+const RA_PACKET_COUNT: usize = 4;
+
+/// State of the address autoconfiguration of an interface
+pub(crate) struct Slaac {
+	/// Raw ICMPv6 socket, which receives the router advertisements
+	handle: SocketHandle,
+	/// Addresses, which have been configured, and the end of their valid lifetime
+	addrs: Vec<(Ipv6Cidr, Instant)>,
+	/// End of the lifetime of the default router
+	router_expiry: Option<Instant>,
+	/// Number of router solicitations, which have been sent
+	solicits: u8,
+	/// Time of the next router solicitation
+	next_solicit: Instant,
+}
+
+/// Router advertisement, which has been received by the raw socket
+struct Advert {
+	router: Ipv6Address,
+	router_lifetime: Duration,
+	prefix_info: Option<NdiscPrefixInformation>,
+}
+
+/// Builds the address of the prefix `prefix` with the modified EUI-64
+/// interface identifier of the MAC address `mac`.
+pub(crate) fn eui64_addr(prefix: &Ipv6Address, mac: &EthernetAddress) -> Ipv6Address {
+	let mac = mac.as_bytes();
+	let mut bytes = [0u8; 16];
+	bytes[..8].copy_from_slice(&prefix.as_bytes()[..8]);
+	bytes[8..].copy_from_slice(&[
+		mac[0] ^ 0x02,
+		mac[1],
+		mac[2],
+		0xff,
+		0xfe,
+		mac[3],
+		mac[4],
+		mac[5],
+	]);
+
+	Ipv6Address::from_bytes(&bytes)
+}
+
+/// Parses a router advertisement. Advertisements, which are not sent by a
+/// link-local address of the local link, are ignored.
+fn parse_advert(buffer: &[u8], checksums: &ChecksumCapabilities) -> Option<Advert> {
+	let packet = Ipv6Packet::new_checked(buffer).ok()?;
+	let ip_repr = Ipv6Repr::parse(&packet).ok()?;
+	if ip_repr.hop_limit != 255 || !ip_repr.src_addr.is_link_local() {
+		return None;
+	}
+
+	let icmp = Icmpv6Packet::new_checked(packet.payload()).ok()?;
+	let icmp_repr = Icmpv6Repr::parse(
+		&ip_repr.src_addr.into(),
+		&ip_repr.dst_addr.into(),
+		&icmp,
+		checksums,
+	)
+	.ok()?;
+
+	match icmp_repr {
+		Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+			router_lifetime,
+			prefix_info,
+			..
+		}) => Some(Advert {
+			router: ip_repr.src_addr,
+			router_lifetime,
+			prefix_info,
+		}),
+		_ => None,
+	}
+}
+
+impl<'a> Netif<'a> {
+	/// Starts the autoconfiguration of the interface by soliciting router advertisements.
+	pub(crate) fn start_slaac(&mut self) {
+		let rx_buffer = raw::PacketBuffer::new(
+			vec![raw::PacketMetadata::EMPTY; RA_PACKET_COUNT],
+			vec![0; RA_PACKET_COUNT * usize::from(self.device.mtu)],
+		);
+		let tx_buffer = raw::PacketBuffer::new(vec![], vec![]);
+		let socket = raw::Socket::new(IpVersion::Ipv6, IpProtocol::Icmpv6, rx_buffer, tx_buffer);
+
+		self.slaac = Some(Slaac {
+			handle: self.sockets.add(socket),
+			addrs: Vec::new(),
+			router_expiry: None,
+			solicits: 0,
+			next_solicit: now(),
+		});
+	}
+
+	/// Solicits router advertisements again, e.g. after the link has been restored.
+	pub(crate) fn restart_slaac(&mut self) {
+		if let Some(slaac) = self.slaac.as_mut() {
+			slaac.solicits = 0;
+			slaac.next_solicit = now();
+		}
+	}
+
+	/// Returns the duration until the next router solicitation or expiry of the configuration.
+	pub(crate) fn slaac_delay(&self, timestamp: Instant) -> Option<Duration> {
+		let slaac = self.slaac.as_ref()?;
+
+		slaac
+			.addrs
+			.iter()
+			.map(|(_, expiry)| *expiry)
+			.chain(slaac.router_expiry)
+			.chain((slaac.solicits < MAX_RTR_SOLICITATIONS).then_some(slaac.next_solicit))
+			.min()
+			.map(|deadline| {
+				if deadline > timestamp {
+					deadline - timestamp
+				} else {
+					Duration::ZERO
+				}
+			})
+	}
+
+	/// Processes the received router advertisements, removes expired addresses and
+	/// routers and solicits advertisements. Returns `true`, if the configuration has changed.
+	pub(crate) fn poll_slaac(&mut self, timestamp: Instant) -> bool {
+		let Some(handle) = self.slaac.as_ref().map(|slaac| slaac.handle) else {
+			return false;
+		};
+
+		let checksums = self.device.capabilities().checksum;
+		let mut adverts = Vec::new();
+		let socket = self.sockets.get_mut::<raw::Socket<'_>>(handle);
+		if socket.can_recv() {
+			let mut buffer = vec![0; usize::from(self.device.mtu)];
+			while let Ok(len) = socket.recv_slice(&mut buffer) {
+				adverts.extend(parse_advert(&buffer[..len], &checksums));
+			}
+		}
+
+		let mut changed = false;
+		for advert in adverts {
+			changed |= self.process_advert(advert, timestamp);
+		}
+		changed |= self.expire_slaac(timestamp);
+
+		let slaac = self.slaac.as_mut().unwrap();
+		if self.link_up && slaac.solicits < MAX_RTR_SOLICITATIONS && timestamp >= slaac.next_solicit
+		{
+			slaac.solicits += 1;
+			slaac.next_solicit = timestamp + RTR_SOLICITATION_INTERVAL;
+			self.send_router_solicit();
+		}
+
+		changed
+	}
+
+	fn process_advert(&mut self, advert: Advert, timestamp: Instant) -> bool {
+		let mut changed = false;
+		let slaac = self.slaac.as_mut().unwrap();
+		// The router answered => stop soliciting.
+		slaac.solicits = MAX_RTR_SOLICITATIONS;
+
+		if advert.router_lifetime == Duration::ZERO {
+			if self.gateway6 == Some(advert.router) {
+				info!("{}: IPv6 router {} has left", self.name, advert.router);
+				self.iface.routes_mut().remove_default_ipv6_route();
+				self.gateway6 = None;
+				slaac.router_expiry = None;
+				changed = true;
+			}
+		} else {
+			if self.gateway6 != Some(advert.router) {
+				info!("{}: configure IPv6 gateway {}", self.name, advert.router);
+				self.iface
+					.routes_mut()
+					.add_default_ipv6_route(advert.router)
+					.unwrap();
+				self.gateway6 = Some(advert.router);
+				changed = true;
+			}
+			slaac.router_expiry = Some(timestamp + advert.router_lifetime);
+		}
+
+		let Some(prefix_info) = advert.prefix_info else {
+			return changed;
+		};
+		// Only prefixes of 64 bits leave room for the interface identifier.
+		if !prefix_info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+			|| prefix_info.prefix_len != 64
+			|| prefix_info.prefix.is_link_local()
+		{
+			return changed;
+		}
+
+		let Some(driver) = self.device.driver() else {
+			return changed;
+		};
+		let mac = EthernetAddress(driver.lock().get_mac_address());
+		let cidr = Ipv6Cidr::new(eui64_addr(&prefix_info.prefix, &mac), 64);
+		let expiry = timestamp + prefix_info.valid_lifetime;
+
+		if let Some(pos) = slaac.addrs.iter().position(|(addr, _)| *addr == cidr) {
+			if prefix_info.valid_lifetime == Duration::ZERO {
+				slaac.addrs.remove(pos);
+				self.remove_ipv6_addr(cidr);
+				changed = true;
+			} else {
+				slaac.addrs[pos].1 = expiry;
+			}
+		} else if prefix_info.valid_lifetime != Duration::ZERO {
+			let mut added = false;
+			self.iface.update_ip_addrs(|addrs| {
+				added = addrs.push(IpCidr::Ipv6(cidr)).is_ok();
+			});
+			if added {
+				info!("{}: configure IPv6 address {}", self.name, cidr);
+				slaac.addrs.push((cidr, expiry));
+				changed = true;
+			} else {
+				info!("{}: unable to add IPv6 address {}", self.name, cidr);
+			}
+		}
+
+		changed
+	}
+
+	/// Removes the addresses and the router, whose lifetime has expired.
+	fn expire_slaac(&mut self, timestamp: Instant) -> bool {
+		let slaac = self.slaac.as_mut().unwrap();
+		let mut changed = false;
+
+		if slaac
+			.router_expiry
+			.map_or(false, |expiry| expiry <= timestamp)
+		{
+			info!("{}: IPv6 gateway has expired", self.name);
+			self.iface.routes_mut().remove_default_ipv6_route();
+			self.gateway6 = None;
+			slaac.router_expiry = None;
+			changed = true;
+		}
+
+		let (expired, valid) = slaac
+			.addrs
+			.drain(..)
+			.partition::<Vec<_>, _>(|(_, expiry)| *expiry <= timestamp);
+		slaac.addrs = valid;
+		for (cidr, _) in expired {
+			info!("{}: IPv6 address {} has expired", self.name, cidr);
+			self.remove_ipv6_addr(cidr);
+			changed = true;
+		}
+
+		changed
+	}
+
+	fn remove_ipv6_addr(&mut self, cidr: Ipv6Cidr) {
+		self.iface.update_ip_addrs(|addrs| {
+			if let Some(pos) = addrs.iter().position(|addr| *addr == IpCidr::Ipv6(cidr)) {
+				addrs.swap_remove(pos);
+			}
+		});
+	}
+
+	/// Sends a router solicitation from the link-local address of the interface.
+	fn send_router_solicit(&mut self) {
+		let Some(driver) = self.device.driver() else {
+			return;
+		};
+		let mac = EthernetAddress(driver.lock().get_mac_address());
+		let Some(src_addr) = self
+			.iface
+			.ip_addrs()
+			.iter()
+			.find_map(|cidr| match cidr.address() {
+				IpAddress::Ipv6(addr) if addr.is_link_local() => Some(addr),
+				_ => None,
+			})
+		else {
+			return;
+		};
+
+		debug!("{}: send router solicitation", self.name);
+		let dst_addr = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+		let icmp = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+			lladdr: Some(RawHardwareAddress::from_bytes(mac.as_bytes())),
+		});
+		let ip = Ipv6Repr {
+			src_addr,
+			dst_addr,
+			next_header: IpProtocol::Icmpv6,
+			payload_len: icmp.buffer_len(),
+			hop_limit: 255,
+		};
+		let checksums = self.device.capabilities().checksum;
+
+		self.send_frame(
+			mac,
+			EthernetAddress([0x33, 0x33, 0, 0, 0, 2]),
+			EthernetProtocol::Ipv6,
+			ip.buffer_len() + icmp.buffer_len(),
+			|payload| {
+				let mut packet = Ipv6Packet::new_unchecked(payload);
+				ip.emit(&mut packet);
+				icmp.emit(
+					&src_addr.into(),
+					&dst_addr.into(),
+					&mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+					&checksums,
+				);
+			},
+		);
+	}
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn eui64_link_local() {
+		let mac = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+		let prefix = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+
+		assert_eq!(
+			eui64_addr(&prefix, &mac),
+			Ipv6Address::new(0xfe80, 0, 0, 0, 0x5054, 0x00ff, 0xfe12, 0x3456)
+		);
+	}
+
+	#[test]
+	fn eui64_global() {
+		// The universal/local bit is inverted.
+		let mac = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+		// The interface identifier replaces the host bits of the prefix.
+		let prefix = Ipv6Address::new(0x2001, 0xdb8, 1, 2, 0xaaaa, 0, 0, 1);
+
+		assert_eq!(
+			eui64_addr(&prefix, &mac),
+			Ipv6Address::new(0x2001, 0xdb8, 1, 2, 0, 0x00ff, 0xfe00, 0x0001)
+		);
+	}
+}
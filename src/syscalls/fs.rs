@@ -42,6 +42,7 @@ use hermit_sync::TicketMutex;
 /// TODO:
 /// - FileDescriptor newtype
 use crate::env::is_uhyve;
+use crate::errno;
 
 // TODO: lazy static could be replaced with explicit init on OS boot.
 pub static FILESYSTEM: TicketMutex<Filesystem> = TicketMutex::new(Filesystem::new());
@@ -96,7 +97,7 @@ impl Filesystem {
 			pathsplit.next(); // empty, since first char is /
 
 			let mount = pathsplit.next().unwrap();
-			let internal_path = pathsplit.next().unwrap();
+			let internal_path = pathsplit.next().unwrap_or("");
 			if let Some(fs) = self.mounts.get(mount) {
 				return Ok((fs.deref(), internal_path));
 			}
@@ -160,6 +161,32 @@ impl Filesystem {
 		Ok(())
 	}
 
+	/// Creates a new directory given by path
+	pub fn mkdir(&mut self, path: &str, mode: u32) -> Result<(), FileError> {
+		debug!("Creating directory {}", path);
+		let (fs, internal_path) = self.parse_path(path)?;
+		fs.mkdir(internal_path, mode)
+	}
+
+	/// Returns the entries of the directory given by path.
+	/// The root directory contains the mount points.
+	pub fn readdir(&self, path: &str) -> Result<Vec<DirEntry>, FileError> {
+		debug!("Reading directory {}", path);
+		if path.trim_matches('/').is_empty() && path.starts_with('/') {
+			return Ok(self
+				.mounts
+				.keys()
+				.map(|mount| DirEntry {
+					name: mount.clone(),
+					file_type: FileType::Directory,
+				})
+				.collect());
+		}
+
+		let (fs, internal_path) = self.parse_path(path)?;
+		fs.readdir(internal_path)
+	}
+
 	/// Create new backing-fs at mountpoint mntpath
 	#[cfg(feature = "pci")]
 	pub fn mount(
@@ -200,15 +227,89 @@ impl Filesystem {
 #[derive(Debug)]
 pub enum FileError {
 	ENOENT,
-	#[cfg(feature = "pci")]
 	ENOSYS,
 	#[cfg(feature = "pci")]
 	EIO,
+	#[cfg(feature = "pci")]
+	EBADF,
+	#[cfg(feature = "pci")]
+	EEXIST,
+	#[cfg(feature = "pci")]
+	ENODEV,
+	#[cfg(feature = "pci")]
+	ENOTDIR,
+	#[cfg(feature = "pci")]
+	EISDIR,
+	#[cfg(feature = "pci")]
+	EINVAL,
+	#[cfg(feature = "pci")]
+	ENOSPC,
+	#[cfg(feature = "pci")]
+	EROFS,
+	#[cfg(feature = "pci")]
+	ENOTEMPTY,
+	#[cfg(feature = "pci")]
+	ELOOP,
+}
+
+impl FileError {
+	/// Returns the corresponding (positive) error number
+	pub fn errno(&self) -> i32 {
+		match self {
+			FileError::ENOENT => errno::ENOENT,
+			FileError::ENOSYS => errno::ENOSYS,
+			#[cfg(feature = "pci")]
+			FileError::EIO => errno::EIO,
+			#[cfg(feature = "pci")]
+			FileError::EBADF => errno::EBADF,
+			#[cfg(feature = "pci")]
+			FileError::EEXIST => errno::EEXIST,
+			#[cfg(feature = "pci")]
+			FileError::ENODEV => errno::ENODEV,
+			#[cfg(feature = "pci")]
+			FileError::ENOTDIR => errno::ENOTDIR,
+			#[cfg(feature = "pci")]
+			FileError::EISDIR => errno::EISDIR,
+			#[cfg(feature = "pci")]
+			FileError::EINVAL => errno::EINVAL,
+			#[cfg(feature = "pci")]
+			FileError::ENOSPC => errno::ENOSPC,
+			#[cfg(feature = "pci")]
+			FileError::EROFS => errno::EROFS,
+			#[cfg(feature = "pci")]
+			FileError::ENOTEMPTY => errno::ENOTEMPTY,
+			#[cfg(feature = "pci")]
+			FileError::ELOOP => errno::ELOOP,
+		}
+	}
+}
+
+/// Type of a directory entry
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileType {
+	File,
+	Directory,
+	Symlink,
+}
+
+/// An entry of a directory as it is returned by [PosixFileSystem::readdir]
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+	pub name: String,
+	pub file_type: FileType,
 }
 
 pub trait PosixFileSystem {
 	fn open(&self, _path: &str, _perms: FilePerms) -> Result<Box<dyn PosixFile + Send>, FileError>;
 	fn unlink(&self, _path: &str) -> Result<(), FileError>;
+
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::ENOSYS)
+	}
+
+	fn readdir(&self, _path: &str) -> Result<Vec<DirEntry>, FileError> {
+		Err(FileError::ENOSYS)
+	}
 }
 
 pub trait PosixFile {
@@ -11,6 +11,20 @@ use crate::{arch, env};
 mod generic;
 pub(crate) mod uhyve;
 
+/// Offset of the name in a directory entry, which is returned by `readdir`.
+/// The layout matches Linux' `struct dirent64`.
+#[cfg(target_arch = "x86_64")]
+const DIRENT_NAME_OFFSET: usize = 19;
+/// Directory entry type of a directory
+#[cfg(target_arch = "x86_64")]
+const DT_DIR: u8 = 4;
+/// Directory entry type of a regular file
+#[cfg(target_arch = "x86_64")]
+const DT_REG: u8 = 8;
+/// Directory entry type of a symbolic link
+#[cfg(target_arch = "x86_64")]
+const DT_LNK: u8 = 10;
+
 pub trait SyscallInterface: Send + Sync {
 	fn init(&self) {
 		// Interface-specific initialization steps.
@@ -69,8 +83,73 @@ pub trait SyscallInterface: Send + Sync {
 		fs::FILESYSTEM
 			.lock()
 			.unlink(name)
-			.expect("Unlinking failed!"); // TODO: error handling
-		0
+			.map_or_else(|e| -e.errno(), |_| 0)
+	}
+
+	#[cfg(not(target_arch = "x86_64"))]
+	fn mkdir(&self, _name: *const u8, _mode: u32) -> i32 {
+		debug!("mkdir is unimplemented, returning -ENOSYS");
+		-ENOSYS
+	}
+
+	#[cfg(target_arch = "x86_64")]
+	fn mkdir(&self, name: *const u8, mode: u32) -> i32 {
+		let name = unsafe { CStr::from_ptr(name as _) }.to_str().unwrap();
+		debug!("mkdir {}", name);
+
+		fs::FILESYSTEM
+			.lock()
+			.mkdir(name, mode)
+			.map_or_else(|e| -e.errno(), |_| 0)
+	}
+
+	#[cfg(not(target_arch = "x86_64"))]
+	fn readdir(&self, _name: *const u8, _pos: *mut usize, _buf: *mut u8, _len: usize) -> isize {
+		debug!("readdir is unimplemented, returning -ENOSYS");
+		(-ENOSYS).try_into().unwrap()
+	}
+
+	#[cfg(target_arch = "x86_64")]
+	fn readdir(&self, name: *const u8, pos: *mut usize, buf: *mut u8, len: usize) -> isize {
+		let name = unsafe { CStr::from_ptr(name as _) }.to_str().unwrap();
+		debug!("readdir {}", name);
+
+		let entries = match fs::FILESYSTEM.lock().readdir(name) {
+			Ok(entries) => entries,
+			Err(e) => return (-e.errno()).try_into().unwrap(),
+		};
+
+		let pos = unsafe { &mut *pos };
+		let buf = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+		let mut written = 0;
+		for (i, entry) in entries.iter().enumerate().skip(*pos) {
+			let reclen = (DIRENT_NAME_OFFSET + entry.name.len() + 1 + 7) & !7;
+			if written + reclen > len {
+				if written == 0 {
+					return (-EINVAL).try_into().unwrap();
+				}
+				break;
+			}
+
+			let d_type = match entry.file_type {
+				fs::FileType::File => DT_REG,
+				fs::FileType::Directory => DT_DIR,
+				fs::FileType::Symlink => DT_LNK,
+			};
+			let record = &mut buf[written..written + reclen];
+			record.fill(0);
+			record[0..8].copy_from_slice(&(i as u64 + 1).to_ne_bytes());
+			record[8..16].copy_from_slice(&(i as i64 + 1).to_ne_bytes());
+			record[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+			record[18] = d_type;
+			record[DIRENT_NAME_OFFSET..DIRENT_NAME_OFFSET + entry.name.len()]
+				.copy_from_slice(entry.name.as_bytes());
+
+			written += reclen;
+			*pos = i + 1;
+		}
+
+		written.try_into().unwrap()
 	}
 
 	fn stat(&self, _file: *const u8, _st: usize) -> i32 {
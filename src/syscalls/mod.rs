@@ -105,6 +105,27 @@ pub extern "C" fn sys_unlink(name: *const u8) -> i32 {
 	kernel_function!(__sys_unlink(name))
 }
 
+extern "C" fn __sys_mkdir(name: *const u8, mode: u32) -> i32 {
+	SYS.mkdir(name, mode)
+}
+
+#[no_mangle]
+pub extern "C" fn sys_mkdir(name: *const u8, mode: u32) -> i32 {
+	kernel_function!(__sys_mkdir(name, mode))
+}
+
+extern "C" fn __sys_readdir(name: *const u8, pos: *mut usize, buf: *mut u8, len: usize) -> isize {
+	SYS.readdir(name, pos, buf, len)
+}
+
+/// Reads the entries of the directory `name` into `buf`, starting with entry `*pos`.
+/// The entries are stored in the layout of Linux' `struct dirent64`.
+/// Returns the number of written bytes and advances `*pos`. Zero marks the end of the directory.
+#[no_mangle]
+pub extern "C" fn sys_readdir(name: *const u8, pos: *mut usize, buf: *mut u8, len: usize) -> isize {
+	kernel_function!(__sys_readdir(name, pos, buf, len))
+}
+
 extern "C" fn __sys_open(name: *const u8, flags: i32, mode: i32) -> FileDescriptor {
 	crate::fd::open(name, flags, mode).map_or_else(|e| e, |v| v)
 }
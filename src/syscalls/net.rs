@@ -1,7 +1,9 @@
 #![allow(dead_code)]
 #![allow(nonstandard_style)]
+use alloc::collections::BTreeMap;
 use core::ffi::c_void;
 
+use hermit_sync::InterruptTicketMutex;
 use smoltcp::socket::tcp;
 use smoltcp::time::Duration;
 use smoltcp::wire::IpAddress;
@@ -12,6 +14,14 @@ use crate::net::{AsyncSocket, Handle};
 use crate::syscalls::__sys_write;
 use crate::DEFAULT_KEEP_ALIVE_INTERVAL;
 
+/// Backlog of the listening sockets of `sys_tcp_listener_accept`
+const LISTENER_BACKLOG: usize = 8;
+
+/// Listening sockets of `sys_tcp_listener_accept` by port. A port keeps listening
+/// after the first call, so that connections are queued until the next call.
+static TCP_LISTENERS: InterruptTicketMutex<BTreeMap<u16, Handle>> =
+	InterruptTicketMutex::new(BTreeMap::new());
+
 #[no_mangle]
 pub fn sys_tcp_stream_connect(ip: &[u8], port: u16, timeout: Option<u64>) -> Result<Handle, ()> {
 	let socket = AsyncSocket::new();
@@ -139,15 +149,29 @@ pub fn sys_tcp_stream_peer_addr(handle: Handle) -> Result<(IpAddress, u16), ()>
 #[cfg(feature = "tcp")]
 #[no_mangle]
 pub fn sys_tcp_listener_accept(port: u16) -> Result<(Handle, IpAddress, u16), ()> {
-	let socket = AsyncSocket::new();
-	let (addr, port) = block_on(socket.accept(port), None).map_err(|_| ())?;
+	let handle = {
+		let mut listeners = TCP_LISTENERS.lock();
+		if let Some(handle) = listeners.get(&port) {
+			*handle
+		} else {
+			let socket = AsyncSocket::new();
+			socket.listen(port, LISTENER_BACKLOG).map_err(drop)?;
+			listeners.insert(port, socket.inner());
+			socket.inner()
+		}
+	};
 
-	Ok((socket.inner(), addr, port))
+	let socket = AsyncSocket::from(handle);
+	block_on(socket.accept(), None).map_err(drop)
 }
 
 pub const AF_INET: i32 = 0;
 pub const AF_INET6: i32 = 1;
+pub const AF_VSOCK: i32 = 2;
+pub const AF_UNSPEC: i32 = 3;
 pub const IPPROTO_IP: i32 = 0;
+pub const IPPROTO_ICMP: i32 = 1;
+pub const IPPROTO_ICMPV6: i32 = 58;
 pub const IPPROTO_IPV6: i32 = 41;
 pub const IPPROTO_TCP: i32 = 6;
 pub const IPPROTO_UDP: i32 = 17;
@@ -166,20 +190,40 @@ pub const SHUT_RDWR: i32 = 2;
 pub const SOCK_DGRAM: i32 = 2;
 pub const SOCK_STREAM: i32 = 1;
 pub const SOL_SOCKET: i32 = 4095;
+pub const SO_BINDTODEVICE: i32 = 25;
 pub const SO_BROADCAST: i32 = 32;
 pub const SO_ERROR: i32 = 4103;
+pub const SO_KEEPALIVE: i32 = 8;
 pub const SO_RCVTIMEO: i32 = 4102;
+pub const SO_RCVBUF: i32 = 4098;
 pub const SO_REUSEADDR: i32 = 4;
+pub const SO_SNDBUF: i32 = 4097;
 pub const SO_SNDTIMEO: i32 = 4101;
 pub const SO_LINGER: i32 = 128;
 pub const TCP_NODELAY: i32 = 1;
+pub const TCP_KEEPIDLE: i32 = 3;
+pub const TCP_KEEPINTVL: i32 = 4;
 pub const MSG_PEEK: i32 = 1;
 pub const FIONBIO: i32 = 0x8008667eu32 as i32;
+pub const AI_PASSIVE: i32 = 0x01;
 pub const EAI_NONAME: i32 = -2200;
 pub const EAI_SERVICE: i32 = -2201;
 pub const EAI_FAIL: i32 = -2202;
 pub const EAI_MEMORY: i32 = -2203;
 pub const EAI_FAMILY: i32 = -2204;
+pub const VMADDR_CID_ANY: u32 = u32::MAX;
+pub const VMADDR_CID_HYPERVISOR: u32 = 0;
+pub const VMADDR_CID_LOCAL: u32 = 1;
+pub const VMADDR_CID_HOST: u32 = 2;
+pub const VMADDR_PORT_ANY: u32 = u32::MAX;
+pub const IFNAMSIZ: usize = 16;
+pub const IFI_DNS_MAX: usize = 4;
+pub const IFF_UP: u32 = 0x1;
+pub const IFF_LOOPBACK: u32 = 0x8;
+pub const IFF_RUNNING: u32 = 0x40;
+pub const IFF_PROMISC: u32 = 0x100;
+pub const IFF_ALLMULTI: u32 = 0x200;
+pub const IFF_MULTICAST: u32 = 0x1000;
 pub type sa_family_t = u8;
 pub type socklen_t = u32;
 pub type in_addr_t = u32;
@@ -226,6 +270,17 @@ pub struct sockaddr_in6 {
 	pub sin6_scope_id: u32,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct sockaddr_vm {
+	pub svm_len: u8,
+	pub svm_family: sa_family_t,
+	pub svm_reserved1: u16,
+	pub svm_port: u32,
+	pub svm_cid: u32,
+	pub svm_zero: [u8; 4],
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct ip_mreq {
@@ -253,6 +308,35 @@ pub struct addrinfo {
 	pub ai_next: *mut addrinfo,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ifaddrs {
+	pub ifa_next: *mut ifaddrs,
+	pub ifa_name: *mut u8,
+	pub ifa_flags: u32,
+	pub ifa_addr: *mut sockaddr,
+	pub ifa_netmask: *mut sockaddr,
+	pub ifa_broadaddr: *mut sockaddr,
+	pub ifa_data: *mut c_void,
+}
+
+/// Configuration of an interface, which is returned by `sys_getifinfo`.
+/// Missing gateways are unspecified addresses. Name servers are stored as
+/// IPv6 addresses, IPv4 servers as IPv4-mapped IPv6 addresses.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ifinfo {
+	pub ifi_name: [u8; IFNAMSIZ],
+	pub ifi_index: u32,
+	pub ifi_flags: u32,
+	pub ifi_mtu: u32,
+	pub ifi_hwaddr: [u8; 6],
+	pub ifi_gateway: in_addr,
+	pub ifi_gateway6: in6_addr,
+	pub ifi_dns_count: u32,
+	pub ifi_dns: [in6_addr; IFI_DNS_MAX],
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct linger {
@@ -290,6 +374,11 @@ pub extern "C" fn sys_getsockname(s: i32, name: *mut sockaddr, namelen: *mut soc
 	kernel_function!(__sys_getsockname(s, name, namelen))
 }
 
+/// Sets an option of the socket `s`.
+///
+/// The sizes of `SO_RCVBUF` and `SO_SNDBUF` are clamped to 4 MiB. The buffers
+/// of a TCP socket are only resizable, before it connects or listens. Resizing
+/// the buffers of a UDP socket drops the datagrams, which are still buffered.
 #[no_mangle]
 pub extern "C" fn sys_setsockopt(
 	s: i32,
@@ -332,11 +421,74 @@ pub extern "C" fn sys_getaddrinfo(
 	kernel_function!(__sys_getaddrinfo(nodename, servname, hints, res))
 }
 
+#[no_mangle]
+pub extern "C" fn sys_getifaddrs(ifap: *mut *mut ifaddrs) -> i32 {
+	kernel_function!(__sys_getifaddrs(ifap))
+}
+
+#[no_mangle]
+pub extern "C" fn sys_freeifaddrs(ifa: *mut ifaddrs) {
+	kernel_function!(__sys_freeifaddrs(ifa))
+}
+
+/// Returns the configuration of the interface `index`, where the first interface
+/// has the index 1. Returns `-ENODEV`, if the interface does not exist.
+#[no_mangle]
+pub extern "C" fn sys_getifinfo(index: u32, info: *mut ifinfo) -> i32 {
+	kernel_function!(__sys_getifinfo(index, info))
+}
+
+/// Switches the promiscuous (`IFF_PROMISC`) and the all-multicast (`IFF_ALLMULTI`)
+/// mode of the interface `index`. Other flags are ignored. Returns `-EOPNOTSUPP`,
+/// if the device does not support the mode.
+#[no_mangle]
+pub extern "C" fn sys_setifflags(index: u32, flags: u32) -> i32 {
+	kernel_function!(__sys_setifflags(index, flags))
+}
+
+/// Lets the interface `index` receive the packets of the VLAN `vid`. Returns
+/// `-EOPNOTSUPP`, if the device does not filter VLANs.
+#[no_mangle]
+pub extern "C" fn sys_addifvlan(index: u32, vid: u16) -> i32 {
+	kernel_function!(__sys_addifvlan(index, vid))
+}
+
+/// Removes the VLAN `vid` from the filter of the interface `index`.
+#[no_mangle]
+pub extern "C" fn sys_delifvlan(index: u32, vid: u16) -> i32 {
+	kernel_function!(__sys_delifvlan(index, vid))
+}
+
+/// Waits until the version of the network configuration differs from `*version`
+/// and stores the new version in `*version`. The version changes, if an address,
+/// a gateway, a name server or the link state of an interface changes, e.g. by DHCP.
+/// A negative `timeout` in milliseconds waits forever.
+#[no_mangle]
+pub extern "C" fn sys_wait_netconfig(version: *mut u32, timeout: i32) -> i32 {
+	kernel_function!(__sys_wait_netconfig(version, timeout))
+}
+
 #[no_mangle]
 pub extern "C" fn sys_send(s: i32, mem: *const c_void, len: usize, _flags: i32) -> isize {
 	kernel_function!(__sys_write(s, mem as *const u8, len))
 }
 
+#[no_mangle]
+pub extern "C" fn sys_sendto(
+	s: i32,
+	mem: *const c_void,
+	len: usize,
+	flags: i32,
+	addr: *const sockaddr,
+	addrlen: socklen_t,
+) -> isize {
+	if flags == 0 {
+		kernel_function!(__sys_sendto(s, mem as *const u8, len, addr, addrlen))
+	} else {
+		(-crate::errno::EINVAL).try_into().unwrap()
+	}
+}
+
 #[no_mangle]
 pub extern "C" fn sys_shutdown_socket(s: i32, how: i32) -> i32 {
 	kernel_function!(__sys_shutdown_socket(s, how))
@@ -350,3 +502,19 @@ pub extern "C" fn sys_recv(fd: i32, buf: *mut u8, len: usize, flags: i32) -> isi
 		(-crate::errno::EINVAL).try_into().unwrap()
 	}
 }
+
+#[no_mangle]
+pub extern "C" fn sys_recvfrom(
+	fd: i32,
+	buf: *mut u8,
+	len: usize,
+	flags: i32,
+	addr: *mut sockaddr,
+	addrlen: *mut socklen_t,
+) -> isize {
+	if flags == 0 {
+		kernel_function!(__sys_recvfrom(fd, buf, len, addr, addrlen))
+	} else {
+		(-crate::errno::EINVAL).try_into().unwrap()
+	}
+}
@@ -0,0 +1,72 @@
+#![feature(test)]
+#![no_std]
+#![no_main]
+#![test_runner(common::test_case_runner)]
+#![feature(custom_test_frameworks)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate hermit;
+
+use hermit::{sys_join, sys_spawn2, sys_usleep};
+use smoltcp::wire::IpAddress;
+
+mod common;
+
+const USER_STACK_SIZE: usize = 1_048_576;
+const NORMAL_PRIO: u8 = 2;
+const PORT: u16 = 9975;
+
+/// Handle of a TCP socket, as the standard library sees it
+type Handle = usize;
+
+extern "Rust" {
+	fn sys_tcp_listener_accept(port: u16) -> Result<(Handle, IpAddress, u16), ()>;
+	fn sys_tcp_stream_connect(ip: &[u8], port: u16, timeout: Option<u64>) -> Result<Handle, ()>;
+	fn sys_tcp_stream_read(handle: Handle, buffer: &mut [u8]) -> Result<usize, ()>;
+	fn sys_tcp_stream_write(handle: Handle, buffer: &[u8]) -> Result<usize, ()>;
+	fn sys_tcp_stream_close(handle: Handle) -> Result<(), ()>;
+}
+
+extern "C" fn client_func(count: usize) {
+	// the first call of sys_tcp_listener_accept starts to listen
+	sys_usleep(100_000);
+
+	for i in 0..count {
+		let handle = unsafe { sys_tcp_stream_connect(b"127.0.0.1", PORT, None) }.unwrap();
+		let written = unsafe { sys_tcp_stream_write(handle, &[i as u8]) }.unwrap();
+		assert_eq!(written, 1);
+	}
+}
+
+fn read_byte(handle: Handle) -> u8 {
+	let mut buffer = [0u8; 1];
+	let len = unsafe { sys_tcp_stream_read(handle, &mut buffer) }.unwrap();
+	assert_eq!(len, 1);
+	buffer[0]
+}
+
+#[test_case]
+fn accept_queued_connections() {
+	let client = sys_spawn2(client_func, 2, NORMAL_PRIO, USER_STACK_SIZE, -1);
+
+	let (first, addr, _) = unsafe { sys_tcp_listener_accept(PORT) }.unwrap();
+	assert!(addr.is_loopback());
+	assert_eq!(read_byte(first), 0);
+
+	// the second connection waits in the backlog, until it is accepted
+	sys_join(client);
+	let (second, addr, _) = unsafe { sys_tcp_listener_accept(PORT) }.unwrap();
+	assert!(addr.is_loopback());
+	assert_eq!(read_byte(second), 1);
+
+	unsafe {
+		sys_tcp_stream_close(first).unwrap();
+		sys_tcp_stream_close(second).unwrap();
+	}
+}
+
+#[no_mangle]
+extern "C" fn runtime_entry(_argc: i32, _argv: *const *const u8, _env: *const *const u8) -> ! {
+	test_main();
+	common::exit(false)
+}